@@ -1,36 +1,28 @@
 //! One way to the contents of an entity, as you might do for debugging. A similar pattern could
 //! also be useful for serialization, or other row-oriented generic operations.
 
-type FormattingFunction = &'static dyn Fn(moss_hecs::EntityRef<'_>) -> Option<String>;
+use moss_hecs::{ComponentRegistry, DynamicComponent, EntityRef};
 
-fn format_entity(entity: moss_hecs::EntityRef<'_>) -> String {
-    fn fmt<T: moss_hecs::Component + std::fmt::Display>(entity: moss_hecs::EntityRef<'_>) -> Option<String> {
-        Some(entity.get::<&T>()?.to_string())
-    }
-
-    const FUNCTIONS: &[FormattingFunction] = &[&fmt::<i32>, &fmt::<bool>, &fmt::<f64>];
-
-    let mut out = String::new();
-    for f in FUNCTIONS {
-        if let Some(x) = f(entity) {
-            if out.is_empty() {
-                out.push('[');
-            } else {
-                out.push_str(", ");
-            }
-            out.push_str(&x);
+fn format_entity(registry: &ComponentRegistry, entity: EntityRef<'_>) -> String {
+    let mut fields = Vec::new();
+    entity.visit(&mut |component: DynamicComponent<'_>| {
+        if let Some(x) = registry
+            .get(component.type_info().id())
+            .and_then(|entry| entry.debug(component))
+        {
+            fields.push(format!("{:?}", x));
         }
-    }
-    if out.is_empty() {
-        out.push_str("[]");
-    } else {
-        out.push(']');
-    }
-    out
+    });
+    format!("[{}]", fields.join(", "))
 }
 
 fn main() {
+    let mut registry = ComponentRegistry::new();
+    registry.register::<i32>().debug();
+    registry.register::<bool>().debug();
+    registry.register::<f64>().debug();
+
     let mut frame = moss_hecs::Frame::new();
     let e = frame.spawn((42, true));
-    println!("{}", format_entity(frame.entity(e).unwrap()));
+    println!("{}", format_entity(&registry, frame.entity(e).unwrap()));
 }
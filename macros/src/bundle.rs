@@ -18,6 +18,7 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
         }
     };
     let (tys, field_members) = struct_fields(&data.fields);
+    check_duplicate_types(&tys, &field_members)?;
     let field_idents = member_as_idents(&field_members);
     let generics = add_additional_bounds_to_generic_params(input.generics);
 
@@ -32,6 +33,40 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
     Ok(ts)
 }
 
+/// Reject fields whose types are spelled identically, which would otherwise only be caught when
+/// the bundle is first spawned
+///
+/// Distinct spellings of the same type, e.g. through aliases or generic parameters, are still
+/// caught at runtime.
+fn check_duplicate_types(tys: &[&syn::Type], members: &[syn::Member]) -> Result<()> {
+    let spellings = tys
+        .iter()
+        .map(|ty| quote!(#ty).to_string())
+        .collect::<Vec<_>>();
+    let mut errors = None::<Error>;
+    for (i, spelling) in spellings.iter().enumerate() {
+        let Some(first) = spellings[..i].iter().position(|x| x == spelling) else {
+            continue;
+        };
+        let first = &members[first];
+        let member = &members[i];
+        let error = Error::new_spanned(
+            tys[i],
+            format!(
+                "field `{}` has the same type as field `{}`; \
+                 each component type may occur at most once in a bundle",
+                quote!(#member),
+                quote!(#first)
+            ),
+        );
+        match errors {
+            Some(ref mut errors) => errors.combine(error),
+            None => errors = Some(error),
+        }
+    }
+    errors.map_or(Ok(()), Err)
+}
+
 fn gen_dynamic_bundle_impl(
     ident: &syn::Ident,
     generics: &syn::Generics,
@@ -9,6 +9,7 @@ extern crate proc_macro;
 
 mod bundle;
 mod bundle_clone;
+mod map_entities;
 mod query;
 
 pub(crate) mod common;
@@ -63,12 +64,59 @@ pub fn derive_dynamic_bundle_clone(input: TokenStream) -> TokenStream {
     .into()
 }
 
-/// Implement `Query` for a struct
+/// Implement `MapEntities` for a struct or enum
+///
+/// Fields annotated with `#[map_entities]` are mapped with their own `MapEntities`
+/// implementations; all other fields are left unchanged.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// #[derive(MapEntities)]
+/// enum Target {
+///     Entity(#[map_entities] Entity),
+///     Point { x: f32, y: f32 },
+/// }
+///
+/// #[derive(MapEntities)]
+/// struct Follow {
+///     #[map_entities]
+///     target: Option<Entity>,
+///     distance: f32,
+/// }
+///
+/// let mut frame = Frame::new();
+/// let (old, new) = (frame.spawn(()), frame.spawn(()));
+/// let mut map = EntityMap::new();
+/// map.insert(old, new);
+/// let mut follow = Follow { target: Some(old), distance: 1.0 };
+/// follow.map_entities(&map);
+/// assert_eq!(follow.target, Some(new));
+/// ```
+#[proc_macro_derive(MapEntities, attributes(map_entities))]
+pub fn derive_map_entities(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match map_entities::derive(input) {
+        Ok(ts) => ts,
+        Err(e) => e.to_compile_error(),
+    }
+    .into()
+}
+
+/// Implement `Query` for a struct or enum
 ///
 /// Queries structs can be passed to the type parameter of `Frame::query`. They must have exactly
 /// one lifetime parameter, and all of their fields must be queries (e.g. references) using that
 /// lifetime.
 ///
+/// A field may be annotated with `#[query(with = Q)]` or `#[query(without = Q)]` to skip entities
+/// not satisfying, or satisfying, the query `Q`, as if the field's query were wrapped in `With` or
+/// `Without`.
+///
+/// Each variant of a query enum is an alternative set of queries. An entity yields the first
+/// variant whose fields it satisfies, making enums convenient for state machines stored as
+/// distinct component sets. Like `Or`, the enum may borrow the components of any variant.
+///
 /// # Example
 /// ```
 /// # use moss_hecs::*;
@@ -88,7 +136,28 @@ pub fn derive_dynamic_bundle_clone(input: TokenStream) -> TokenStream {
 ///     }
 /// );
 /// ```
-#[proc_macro_derive(Query)]
+///
+/// ```
+/// # use moss_hecs::*;
+/// struct Walking;
+/// struct Speed(f32);
+///
+/// #[derive(Query)]
+/// enum Movement<'a> {
+///     Walking(&'a Walking, &'a Speed),
+///     Sliding { speed: &'a mut Speed },
+///     Still,
+/// }
+///
+/// let mut frame = Frame::new();
+/// let a = frame.spawn((Walking, Speed(1.0)));
+/// let b = frame.spawn((Speed(2.0),));
+/// let c = frame.spawn((Walking,));
+/// assert!(matches!(frame.query_one_mut::<Movement>(a), Ok(Movement::Walking(_, Speed(x))) if *x == 1.0));
+/// assert!(matches!(frame.query_one_mut::<Movement>(b), Ok(Movement::Sliding { .. })));
+/// assert!(matches!(frame.query_one_mut::<Movement>(c), Ok(Movement::Still)));
+/// ```
+#[proc_macro_derive(Query, attributes(query))]
 pub fn derive_query(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     match query::derive(input) {
@@ -0,0 +1,83 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{DeriveInput, Error, Result};
+
+use crate::common::struct_fields;
+
+pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
+    let ident = input.ident;
+    let mut mapped_tys = Vec::new();
+    let arms = match input.data {
+        syn::Data::Struct(ref s) => vec![arm(quote! { Self }, &s.fields, &mut mapped_tys)?],
+        syn::Data::Enum(ref e) => e
+            .variants
+            .iter()
+            .map(|variant| {
+                let name = &variant.ident;
+                arm(quote! { Self::#name }, &variant.fields, &mut mapped_tys)
+            })
+            .collect::<Result<Vec<_>>>()?,
+        syn::Data::Union(_) => {
+            return Err(Error::new_spanned(
+                ident,
+                "derive(MapEntities) may only be applied to structs and enums",
+            ))
+        }
+    };
+
+    let mut generics = input.generics;
+    let where_clause = generics.make_where_clause();
+    for ty in mapped_tys {
+        where_clause
+            .predicates
+            .push(syn::parse_quote! { #ty: ::moss_hecs::MapEntities });
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::moss_hecs::MapEntities for #ident #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn map_entities(&mut self, map: &::moss_hecs::EntityMap) {
+                match *self {
+                    #(#arms)*
+                }
+            }
+        }
+    })
+}
+
+/// A match arm mapping the fields of `path` annotated with `#[map_entities]`
+fn arm(
+    path: TokenStream2,
+    fields: &syn::Fields,
+    mapped_tys: &mut Vec<syn::Type>,
+) -> Result<TokenStream2> {
+    let (tys, members) = struct_fields(fields);
+    let mut mapped = Vec::new();
+    for ((field, ty), member) in fields.iter().zip(tys).zip(members) {
+        if is_mapped(field)? {
+            mapped_tys.push(ty.clone());
+            mapped.push(member);
+        }
+    }
+    let bindings = (0..mapped.len())
+        .map(|i| format_ident!("field_{}", i))
+        .collect::<Vec<_>>();
+    Ok(quote! {
+        #path { #(#mapped: ref mut #bindings,)* .. } => {
+            #(::moss_hecs::MapEntities::map_entities(#bindings, map);)*
+        }
+    })
+}
+
+/// Whether `field` has a `#[map_entities]` attribute
+fn is_mapped(field: &syn::Field) -> Result<bool> {
+    let mut mapped = false;
+    for attr in &field.attrs {
+        if !attr.path().is_ident("map_entities") {
+            continue;
+        }
+        attr.meta.require_path_only()?;
+        mapped = true;
+    }
+    Ok(mapped)
+}
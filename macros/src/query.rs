@@ -1,65 +1,51 @@
 use proc_macro2::Span;
 use proc_macro2::TokenStream as TokenStream2;
-use quote::quote;
-use syn::{DeriveInput, Error, Ident, Lifetime, Result, Type};
+use quote::{format_ident, quote};
+use syn::{DeriveInput, Error, Generics, Ident, Lifetime, Result, Type, Visibility};
 
 pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
     let ident = input.ident;
     let vis = input.vis;
     let data = match input.data {
         syn::Data::Struct(s) => s,
-        _ => {
+        syn::Data::Enum(e) => return derive_enum(ident, vis, input.generics, e),
+        syn::Data::Union(_) => {
             return Err(Error::new_spanned(
                 ident,
-                "derive(Query) may only be applied to structs",
+                "derive(Query) may only be applied to structs and enums",
             ))
         }
     };
-    let lifetime = input
-        .generics
-        .lifetimes()
-        .next()
-        .map(|x| x.lifetime.clone());
-    let lifetime = match lifetime {
-        Some(x) => x,
-        None => {
-            return Err(Error::new_spanned(
-                input.generics,
-                "must have exactly one lifetime parameter",
-            ))
-        }
-    };
-    if input.generics.params.len() != 1 {
-        return Err(Error::new_spanned(
-            ident,
-            "must have exactly one lifetime parameter and no type parameters",
-        ));
-    }
+    let lifetime = query_lifetime(&ident, input.generics)?;
 
-    let (fields, queries) = match data.fields {
+    let (fields, queries): (Vec<_>, Vec<_>) = match data.fields {
         syn::Fields::Named(ref fields) => fields
             .named
             .iter()
             .map(|f| {
-                (
+                Ok((
                     syn::Member::Named(f.ident.clone().unwrap()),
-                    query_ty(&lifetime, &f.ty),
-                )
+                    field_query(&lifetime, f)?,
+                ))
             })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
             .unzip(),
         syn::Fields::Unnamed(ref fields) => fields
             .unnamed
             .iter()
             .enumerate()
             .map(|(i, f)| {
-                (
+                Ok((
                     syn::Member::Unnamed(syn::Index {
                         index: i as u32,
                         span: Span::call_site(),
                     }),
-                    query_ty(&lifetime, &f.ty),
-                )
+                    field_query(&lifetime, f)?,
+                ))
             })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
             .unzip(),
         syn::Fields::Unit => (Vec::new(), Vec::new()),
     };
@@ -130,6 +116,13 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
                     )*
                     #ident {#(#fields: #intermediates,)*}
                 }
+
+                const FILTERED: bool = false #(|| <#queries as ::moss_hecs::Query>::FILTERED)*;
+
+                #[allow(unused_variables)]
+                unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+                    true #(&& <#queries as ::moss_hecs::Query>::filter(&fetch.#fields, n))*
+                }
             }
 
             #state
@@ -188,11 +181,268 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
                         <#fetches as ::moss_hecs::Fetch>::for_each_borrow(&mut f);
                     )*
                 }
+
+                fn required() -> ::core::option::Option<::core::any::TypeId> {
+                    ::core::option::Option::None
+                        #(.or_else(<#fetches as ::moss_hecs::Fetch>::required))*
+                }
+
+                #[allow(unused_variables)]
+                fn explain(archetype: &::moss_hecs::Archetype, out: &mut ::moss_hecs::Explanation) {
+                    #(
+                        <#fetches as ::moss_hecs::Fetch>::explain(archetype, out);
+                    )*
+                }
             }
         };
     })
 }
 
+/// Implement `Query` for an enum whose variants are alternative sets of queries, matching the
+/// first variant that an archetype satisfies
+fn derive_enum(
+    ident: Ident,
+    vis: Visibility,
+    generics: Generics,
+    data: syn::DataEnum,
+) -> Result<TokenStream2> {
+    if data.variants.is_empty() {
+        return Err(Error::new_spanned(
+            ident,
+            "derive(Query) may not be applied to enums without variants",
+        ));
+    }
+    let lifetime = query_lifetime(&ident, generics)?;
+
+    let variants = data.variants.iter().map(|v| &v.ident).collect::<Vec<_>>();
+    let mut queries = Vec::new();
+    let mut patterns = Vec::new();
+    let mut constructors = Vec::new();
+    for variant in &data.variants {
+        let variant_ident = &variant.ident;
+        let tys = variant
+            .fields
+            .iter()
+            .map(|f| field_query(&lifetime, f))
+            .collect::<Result<Vec<_>>>()?;
+        let bindings = (0..tys.len())
+            .map(|i| format_ident!("field_{}", i))
+            .collect::<Vec<_>>();
+        constructors.push(match variant.fields {
+            syn::Fields::Named(ref fields) => {
+                let names = fields.named.iter().map(|f| f.ident.as_ref().unwrap());
+                quote! { #ident::#variant_ident { #(#names: #bindings,)* } }
+            }
+            syn::Fields::Unnamed(_) => quote! { #ident::#variant_ident(#(#bindings,)*) },
+            syn::Fields::Unit => quote! { #ident::#variant_ident },
+        });
+        queries.push(quote! { (#(#tys,)*) });
+        patterns.push(quote! { (#(#bindings,)*) });
+    }
+    let fetches = queries
+        .iter()
+        .map(|ty| quote! { <#ty as ::moss_hecs::Query>::Fetch })
+        .collect::<Vec<_>>();
+    let first_variant = variants[0];
+    let first_fetch = &fetches[0];
+    let fetch_ident = Ident::new(&format!("{}Fetch", ident), Span::call_site());
+    let state_ident = Ident::new(&format!("{}State", ident), Span::call_site());
+
+    Ok(quote! {
+        const _: () = {
+            #[derive(Clone)]
+            #vis enum #fetch_ident {
+                #(
+                    #variants(#fetches),
+                )*
+            }
+
+            #[derive(Clone, Copy)]
+            #vis enum #state_ident {
+                #(
+                    #variants(<#fetches as ::moss_hecs::Fetch>::State),
+                )*
+            }
+
+            impl<'a> ::moss_hecs::Query for #ident<'a> {
+                type Item<'q> = #ident<'q>;
+
+                type Fetch = #fetch_ident;
+
+                unsafe fn get<'q>(fetch: &Self::Fetch, n: usize) -> Self::Item<'q> {
+                    match fetch {
+                        #(
+                            #fetch_ident::#variants(fetch) => {
+                                let #patterns: <#queries as ::moss_hecs::Query>::Item<'q> =
+                                    <#queries as ::moss_hecs::Query>::get(fetch, n);
+                                #constructors
+                            }
+                        )*
+                    }
+                }
+
+                const FILTERED: bool = false #(|| <#queries as ::moss_hecs::Query>::FILTERED)*;
+
+                unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+                    match fetch {
+                        #(
+                            #fetch_ident::#variants(fetch) => <#queries as ::moss_hecs::Query>::filter(fetch, n),
+                        )*
+                    }
+                }
+            }
+
+            unsafe impl ::moss_hecs::Fetch for #fetch_ident {
+                type State = #state_ident;
+
+                fn dangling() -> Self {
+                    #fetch_ident::#first_variant(<#first_fetch as ::moss_hecs::Fetch>::dangling())
+                }
+
+                fn access(archetype: &::moss_hecs::Archetype) -> ::core::option::Option<::moss_hecs::Access> {
+                    #(
+                        if let ::core::option::Option::Some(access) = <#fetches as ::moss_hecs::Fetch>::access(archetype) {
+                            return ::core::option::Option::Some(access);
+                        }
+                    )*
+                    ::core::option::Option::None
+                }
+
+                fn borrow(archetype: &::moss_hecs::Archetype, state: Self::State) {
+                    match state {
+                        #(
+                            #state_ident::#variants(state) => <#fetches as ::moss_hecs::Fetch>::borrow(archetype, state),
+                        )*
+                    }
+                }
+
+                fn prepare(archetype: &::moss_hecs::Archetype) -> ::core::option::Option<Self::State> {
+                    #(
+                        if <#fetches as ::moss_hecs::Fetch>::access(archetype).is_some() {
+                            return <#fetches as ::moss_hecs::Fetch>::prepare(archetype).map(#state_ident::#variants);
+                        }
+                    )*
+                    ::core::option::Option::None
+                }
+
+                fn execute(archetype: &::moss_hecs::Archetype, state: Self::State) -> Self {
+                    match state {
+                        #(
+                            #state_ident::#variants(state) => #fetch_ident::#variants(<#fetches as ::moss_hecs::Fetch>::execute(archetype, state)),
+                        )*
+                    }
+                }
+
+                fn release(archetype: &::moss_hecs::Archetype, state: Self::State) {
+                    match state {
+                        #(
+                            #state_ident::#variants(state) => <#fetches as ::moss_hecs::Fetch>::release(archetype, state),
+                        )*
+                    }
+                }
+
+                fn for_each_borrow(mut f: impl ::core::ops::FnMut(::core::any::TypeId, bool)) {
+                    // Only one variant is borrowed at a time, so types shared between variants are
+                    // reported by a single variant, preferring one that borrows them uniquely. A
+                    // variant that aliases a unique borrow on its own always reports it.
+                    let variants: &[fn(&mut dyn ::core::ops::FnMut(::core::any::TypeId, bool))] = &[
+                        #(
+                            |f| <#fetches as ::moss_hecs::Fetch>::for_each_borrow(f),
+                        )*
+                    ];
+                    for (i, variant) in variants.iter().enumerate() {
+                        variant(&mut |ty, unique| {
+                            let (mut count, mut any_unique) = (0, false);
+                            variant(&mut |own_ty, own_unique| {
+                                if own_ty == ty {
+                                    count += 1;
+                                    any_unique |= own_unique;
+                                }
+                            });
+                            let mut shadowed = false;
+                            if !(any_unique && count > 1) {
+                                for (j, other) in variants.iter().enumerate() {
+                                    if i == j {
+                                        continue;
+                                    }
+                                    other(&mut |other_ty, other_unique| {
+                                        shadowed |= other_ty == ty
+                                            && (other_unique, ::core::cmp::Reverse(j))
+                                                > (any_unique, ::core::cmp::Reverse(i));
+                                    });
+                                }
+                            }
+                            if !shadowed {
+                                f(ty, unique);
+                            }
+                        });
+                    }
+                }
+
+                fn explain(archetype: &::moss_hecs::Archetype, out: &mut ::moss_hecs::Explanation) {
+                    // Every variant is relevant if none matches, otherwise only the one that does
+                    if <Self as ::moss_hecs::Fetch>::access(archetype).is_none() {
+                        #(
+                            <#fetches as ::moss_hecs::Fetch>::explain(archetype, out);
+                        )*
+                        return;
+                    }
+                    #(
+                        if <#fetches as ::moss_hecs::Fetch>::access(archetype).is_some() {
+                            <#fetches as ::moss_hecs::Fetch>::explain(archetype, out);
+                            return;
+                        }
+                    )*
+                }
+            }
+        };
+    })
+}
+
+/// Extract the single lifetime parameter a query type must have
+fn query_lifetime(ident: &Ident, generics: Generics) -> Result<Lifetime> {
+    let lifetime = match generics.lifetimes().next() {
+        Some(x) => x.lifetime.clone(),
+        None => {
+            return Err(Error::new_spanned(
+                generics,
+                "must have exactly one lifetime parameter",
+            ))
+        }
+    };
+    if generics.params.len() != 1 {
+        return Err(Error::new_spanned(
+            ident,
+            "must have exactly one lifetime parameter and no type parameters",
+        ));
+    }
+    Ok(lifetime)
+}
+
+/// The query for a field, filtered by any `#[query(with = Q)]` or `#[query(without = Q)]`
+/// attributes
+fn field_query(lifetime: &Lifetime, field: &syn::Field) -> Result<TokenStream2> {
+    let mut query = query_ty(lifetime, &field.ty);
+    for attr in &field.attrs {
+        if !attr.path().is_ident("query") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            let filter = if meta.path.is_ident("with") {
+                quote! { ::moss_hecs::With }
+            } else if meta.path.is_ident("without") {
+                quote! { ::moss_hecs::Without }
+            } else {
+                return Err(meta.error("expected `with` or `without`"));
+            };
+            let ty = query_ty(lifetime, &meta.value()?.parse::<Type>()?);
+            query = quote! { #filter<#query, #ty> };
+            Ok(())
+        })?;
+    }
+    Ok(query)
+}
+
 fn query_ty(lifetime: &Lifetime, ty: &Type) -> TokenStream2 {
     struct Visitor<'a> {
         replace: &'a Lifetime,
@@ -0,0 +1,105 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::num::NonZeroU32;
+
+use spin::Mutex;
+
+use crate::Entity;
+
+/// Source of entity IDs shared by several [`Frame`](crate::Frame)s
+///
+/// Frames created with [`Frame::with_allocator`](crate::Frame::with_allocator) from clones of the
+/// same allocator never hand out the same [`Entity`], so entities can be moved between them with
+/// [`Frame::transfer`](crate::Frame::transfer) without colliding. This allows a world to be
+/// sharded across several frames while each entity lives in exactly one of them.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// let allocator = SharedAllocator::new();
+/// let mut a = Frame::with_allocator(allocator.clone());
+/// let mut b = Frame::with_allocator(allocator.clone());
+/// let x = a.spawn((1,));
+/// let y = b.spawn((2,));
+/// assert_ne!(x.id(), y.id());
+/// a.transfer(x, &mut b).unwrap();
+/// assert!(b.contains(x) && allocator.contains(x));
+/// ```
+#[derive(Clone, Default)]
+pub struct SharedAllocator {
+    inner: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Current generation of each ID, and whether it's allocated
+    slots: Vec<(NonZeroU32, bool)>,
+    free: Vec<u32>,
+    len: u32,
+}
+
+impl SharedAllocator {
+    /// Create an allocator with no entities
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `entity` is allocated, i.e. lives in one of the frames using this allocator
+    pub fn contains(&self, entity: Entity) -> bool {
+        let state = self.inner.lock();
+        state
+            .slots
+            .get(entity.id() as usize)
+            .map_or(false, |&(generation, live)| {
+                live && generation == entity.generation
+            })
+    }
+
+    /// Number of allocated entities
+    pub fn len(&self) -> u32 {
+        self.inner.lock().len
+    }
+
+    /// Whether no entities are allocated
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `self` and `other` are clones of the same allocator
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    pub(crate) fn alloc(&self) -> Entity {
+        let mut state = self.inner.lock();
+        state.len += 1;
+        if let Some(id) = state.free.pop() {
+            let slot = &mut state.slots[id as usize];
+            slot.1 = true;
+            return Entity {
+                id,
+                generation: slot.0,
+            };
+        }
+        let id = u32::try_from(state.slots.len()).expect("too many entities");
+        let generation = NonZeroU32::new(1).unwrap();
+        state.slots.push((generation, true));
+        Entity { id, generation }
+    }
+
+    /// Release `entity`'s ID for reuse, if it's allocated
+    pub(crate) fn free(&self, entity: Entity) {
+        let mut state = self.inner.lock();
+        let Some(slot) = state.slots.get_mut(entity.id() as usize) else {
+            return;
+        };
+        if !slot.1 || slot.0 != entity.generation {
+            return;
+        }
+        slot.0 = NonZeroU32::new(u32::from(slot.0).wrapping_add(1))
+            .unwrap_or_else(|| NonZeroU32::new(1).unwrap());
+        slot.1 = false;
+        state.free.push(entity.id());
+        state.len -= 1;
+    }
+}
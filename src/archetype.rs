@@ -7,16 +7,22 @@
 
 use crate::alloc::alloc::{alloc, dealloc, Layout};
 use crate::alloc::boxed::Box;
+use crate::alloc::sync::Arc;
 use crate::alloc::{vec, vec::Vec};
-use core::any::{type_name, TypeId};
+use core::any::{type_name, Any, TypeId};
+use core::cell::Cell;
 use core::fmt;
 use core::hash::{BuildHasher, BuildHasherDefault, Hasher};
+use core::mem;
 use core::ops::{Deref, DerefMut};
 use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 
 use hashbrown::{hash_map::DefaultHashBuilder, HashMap};
 
 use crate::borrow::AtomicBorrow;
+use crate::dynamic_column::{ComponentColumn, ComponentColumnMut, DynamicColumn};
+use crate::extension::{self, TypeExtension};
 use crate::query::Fetch;
 use crate::{Access, Component, ComponentRef, Query};
 
@@ -32,30 +38,66 @@ pub struct Archetype {
     entities: Box<[u32]>,
     /// One allocation per type, in the same order as `types`
     data: Box<[Data]>,
+    /// Number of live [`ArchetypePin`]s
+    pins: Arc<AtomicUsize>,
+    /// Whether storage was reallocated since the last `take_grown`
+    grown: bool,
+    /// Incremented whenever entities are added, removed, or reordered
+    version: u32,
+    /// Tick stamped on components as they're added or changed, maintained by the frame
+    tick: u32,
+    /// Whether any column may be shared with another archetype
+    shares: bool,
+    /// User data attached by [`Frame::archetype_metadata_mut`](crate::Frame::archetype_metadata_mut)
+    metadata: TypeIdMap<Box<dyn Any + Send + Sync>>,
 }
 
 impl Archetype {
     fn assert_type_info(types: &[TypeInfo]) {
+        let mut duplicated = false;
         types.windows(2).for_each(|x| match x[0].cmp(&x[1]) {
             core::cmp::Ordering::Less => (),
-            #[cfg(debug_assertions)]
-            core::cmp::Ordering::Equal => panic!(
-                "attempted to allocate entity with duplicate {} components; \
-                 each type must occur at most once!",
-                x[0].type_name
-            ),
-            #[cfg(not(debug_assertions))]
-            core::cmp::Ordering::Equal => panic!(
-                "attempted to allocate entity with duplicate components; \
-                 each type must occur at most once!"
-            ),
+            core::cmp::Ordering::Equal => duplicated = true,
             core::cmp::Ordering::Greater => panic!("type info is unsorted"),
         });
+        if duplicated {
+            Self::duplicate_components(types);
+        }
+    }
+
+    /// Report every component type that occurs more than once in `types`
+    #[cold]
+    #[cfg(debug_assertions)]
+    fn duplicate_components(types: &[TypeInfo]) -> ! {
+        let mut names = Vec::new();
+        for x in types.windows(2) {
+            // Sorting makes duplicates adjacent
+            if x[0] == x[1] && names.last() != Some(&x[0].type_name) {
+                names.push(x[0].type_name);
+            }
+        }
+        panic!(
+            "attempted to allocate entity with duplicate {} components; \
+             each type must occur at most once!",
+            names.join(", ")
+        )
+    }
+
+    #[cold]
+    #[cfg(not(debug_assertions))]
+    fn duplicate_components(_: &[TypeInfo]) -> ! {
+        panic!(
+            "attempted to allocate entity with duplicate components; \
+             each type must occur at most once!"
+        )
     }
 
-    pub(crate) fn new(types: Vec<TypeInfo>) -> Self {
+    pub(crate) fn new(mut types: Vec<TypeInfo>) -> Self {
         let max_align = types.first().map_or(1, |ty| ty.layout.align());
         Self::assert_type_info(&types);
+        for ty in &mut types {
+            ty.extension = ty.extension.or_else(|| extension::lookup(ty.id));
+        }
         let component_count = types.len();
         Self {
             index: OrderedTypeIdMap::new(types.iter().enumerate().map(|(i, ty)| (ty.id, i))),
@@ -66,22 +108,39 @@ impl Archetype {
             data: (0..component_count)
                 .map(|_| Data {
                     state: AtomicBorrow::new(),
-                    storage: NonNull::new(max_align as *mut u8).unwrap(),
+                    storage: Cell::new(NonNull::new(max_align as *mut u8).unwrap()),
+                    shared: Cell::new(None),
+                    ticks: None,
+                    version: AtomicU32::new(0),
                 })
                 .collect(),
+            pins: Arc::new(AtomicUsize::new(0)),
+            grown: false,
+            version: 0,
+            tick: 0,
+            shares: false,
+            metadata: TypeIdMap::default(),
         }
     }
 
     pub(crate) fn clear(&mut self) {
         for (ty, data) in self.types.iter().zip(&*self.data) {
+            if !ty.needs_drop {
+                continue;
+            }
             for index in 0..self.len {
                 unsafe {
-                    let removed = data.storage.as_ptr().add(index as usize * ty.layout.size());
+                    let removed = data
+                        .storage
+                        .get()
+                        .as_ptr()
+                        .add(index as usize * ty.layout.size());
                     (ty.drop)(removed);
                 }
             }
         }
         self.len = 0;
+        self.touch();
     }
 
     /// Whether this archetype contains `T` components
@@ -99,12 +158,24 @@ impl Archetype {
         self.index.get(&TypeId::of::<T>()).copied()
     }
 
+    /// Find the state index associated with the type identified by `id`, if present
+    pub(crate) fn get_state_dynamic(&self, id: TypeId) -> Option<usize> {
+        self.index.get(&id).copied()
+    }
+
     /// Get the address of the first `T` component using an index from `get_state::<T>`
     pub(crate) fn get_base<T: Component>(&self, state: usize) -> NonNull<T> {
         assert_eq!(self.types[state].id, TypeId::of::<T>());
 
         unsafe {
-            NonNull::new_unchecked(self.data.get_unchecked(state).storage.as_ptr().cast::<T>())
+            NonNull::new_unchecked(
+                self.data
+                    .get_unchecked(state)
+                    .storage
+                    .get()
+                    .as_ptr()
+                    .cast::<T>(),
+            )
         }
     }
 
@@ -117,6 +188,24 @@ impl Archetype {
         T::get_column(self)
     }
 
+    /// Borrow all components of the type identified by `id`, if present, without knowing the type
+    /// statically
+    ///
+    /// Panics if the components are already borrowed uniquely.
+    pub fn dynamic_column(&self, id: TypeId) -> Option<Box<dyn ComponentColumn + '_>> {
+        let state = self.get_state_dynamic(id)?;
+        Some(Box::new(DynamicColumn::<false>::new(self, state)))
+    }
+
+    /// Uniquely borrow all components of the type identified by `id`, if present, without knowing
+    /// the type statically
+    ///
+    /// Panics if the components are already borrowed.
+    pub fn dynamic_column_mut(&self, id: TypeId) -> Option<Box<dyn ComponentColumnMut + '_>> {
+        let state = self.get_state_dynamic(id)?;
+        Some(Box::new(DynamicColumn::<true>::new(self, state)))
+    }
+
     pub(crate) fn borrow<T: Component>(&self, state: usize) {
         assert_eq!(self.types[state].id, TypeId::of::<T>());
 
@@ -137,6 +226,17 @@ impl Archetype {
         if !self.data[state].state.borrow_mut() {
             panic!("{} already borrowed", type_name::<T>());
         }
+        // The column was just borrowed uniquely
+        unsafe {
+            self.mark_written(state);
+        }
+    }
+
+    pub(crate) unsafe fn borrow_raw_mut(&self, state: usize) {
+        if !self.data[state].state.borrow_mut() {
+            panic!("state index {} already borrowed", state);
+        }
+        self.mark_written(state);
     }
 
     pub(crate) fn release<T: Component>(&self, state: usize) {
@@ -157,6 +257,334 @@ impl Archetype {
         self.data[state].state.release_mut();
     }
 
+    pub(crate) unsafe fn downgrade_raw(&self, state: usize) {
+        self.data[state].state.downgrade();
+    }
+
+    pub(crate) unsafe fn try_upgrade_raw(&self, state: usize) -> bool {
+        let upgraded = self.data[state].state.try_upgrade();
+        if upgraded {
+            self.mark_written(state);
+        }
+        upgraded
+    }
+
+    /// Record that the column at `state` may be written, first copying it if it's shared
+    ///
+    /// # Safety
+    ///
+    /// The column must be uniquely borrowed, or `self` uniquely accessible
+    pub(crate) unsafe fn mark_written(&self, state: usize) {
+        self.data[state].version.fetch_add(1, Ordering::Relaxed);
+        if self.shares {
+            self.unshare(state);
+        }
+    }
+
+    /// Replace the column at `state` with a private copy if it's shared with another archetype
+    ///
+    /// # Safety
+    ///
+    /// The column must be uniquely borrowed, or `self` uniquely accessible
+    unsafe fn unshare(&self, state: usize) {
+        let data = &self.data[state];
+        let Some(shared) = data.shared.take() else {
+            return;
+        };
+        let info = &self.types[state];
+        let layout = Layout::from_size_align(
+            info.layout.size() * self.entities.len(),
+            info.layout.align(),
+        )
+        .unwrap();
+        let mem =
+            NonNull::new(alloc(layout)).unwrap_or_else(|| alloc::alloc::handle_alloc_error(layout));
+        ptr::copy_nonoverlapping(
+            shared.storage.as_ptr(),
+            mem.as_ptr(),
+            info.layout.size() * self.len as usize,
+        );
+        data.storage.set(mem);
+    }
+
+    /// Give every shared column a private copy, before modifying storage wholesale
+    fn unshare_all(&mut self) {
+        if !mem::take(&mut self.shares) {
+            return;
+        }
+        for state in 0..self.data.len() {
+            // `&mut self` rules out outstanding borrows
+            unsafe {
+                self.unshare(state);
+            }
+        }
+    }
+
+    /// Let `target` use the storage of the column at `state` rather than a copy of it, until
+    /// either archetype writes to it
+    ///
+    /// Returns `false`, sharing nothing, if the column has no storage to share.
+    ///
+    /// # Safety
+    ///
+    /// Components of the column's type must be safe to duplicate bitwise, and must not need to be
+    /// dropped. `target` must have the same component type, no entities, and will take on
+    /// exactly this archetype's entities.
+    pub(crate) unsafe fn share_column(&mut self, state: usize, target: &mut Archetype) -> bool {
+        let info = self.types[state];
+        if info.layout.size() == 0 || self.len == 0 {
+            return false;
+        }
+        debug_assert_eq!(target.len, 0);
+        let data = &mut self.data[state];
+        let shared = data
+            .shared
+            .get_mut()
+            .get_or_insert_with(|| {
+                Arc::new(SharedColumn {
+                    storage: data.storage.get(),
+                    owner: Owner::Allocation(
+                        Layout::from_size_align(
+                            info.layout.size() * self.entities.len(),
+                            info.layout.align(),
+                        )
+                        .unwrap(),
+                    ),
+                })
+            })
+            .clone();
+        self.shares = true;
+
+        let target_state = target.get_state_dynamic(info.id).unwrap();
+        let dst = &mut target.data[target_state];
+        if dst.shared.get_mut().is_none() && !target.entities.is_empty() {
+            dealloc(
+                dst.storage.get().as_ptr(),
+                Layout::from_size_align(
+                    info.layout.size() * target.entities.len(),
+                    info.layout.align(),
+                )
+                .unwrap(),
+            );
+        }
+        dst.storage.set(shared.storage);
+        *dst.shared.get_mut() = Some(shared);
+        target.shares = true;
+        true
+    }
+
+    /// Record a change to the set or order of entities
+    fn touch(&mut self) {
+        self.version = self.version.wrapping_add(1);
+    }
+
+    /// Tick stamped on components as they're added or changed
+    #[inline]
+    pub(crate) fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub(crate) fn set_tick(&mut self, tick: u32) {
+        self.tick = tick;
+    }
+
+    /// Take ownership of components added outside of a frame, stamping them as added at `tick`
+    pub(crate) fn adopt(&mut self, tick: u32) {
+        self.tick = tick;
+        self.stamp(0..self.len);
+    }
+
+    /// Start recording when components in the column at `state` are added and changed
+    ///
+    /// Components already present count as neither added nor changed during the current tick.
+    pub(crate) fn track(&mut self, state: usize) {
+        if self.data[state].ticks.is_some() {
+            return;
+        }
+        let cap = self.entities.len();
+        let ticks = if cap == 0 {
+            NonNull::dangling()
+        } else {
+            let layout = Layout::array::<ComponentTicks>(cap).unwrap();
+            unsafe {
+                NonNull::new(alloc(layout))
+                    .unwrap_or_else(|| alloc::alloc::handle_alloc_error(layout))
+                    .cast::<ComponentTicks>()
+            }
+        };
+        let stale = self.tick.wrapping_sub(1);
+        for index in 0..self.len as usize {
+            unsafe {
+                *ticks.as_ptr().add(index) = ComponentTicks {
+                    added: stale,
+                    changed: stale,
+                };
+            }
+        }
+        self.data[state].ticks = Some(ticks);
+    }
+
+    /// Track changes to each of `types` this archetype has
+    pub(crate) fn track_all(&mut self, types: &[TypeId]) {
+        for &ty in types {
+            if let Some(state) = self.get_state_dynamic(ty) {
+                self.track(state);
+            }
+        }
+    }
+
+    /// Address of the ticks of the first component in the column at `state`, if they're tracked
+    #[inline]
+    pub(crate) fn get_ticks(&self, state: usize) -> Option<NonNull<ComponentTicks>> {
+        self.data[state].ticks
+    }
+
+    /// Ticks of the component at `index` in the column at `state`, if they're tracked
+    pub(crate) fn component_ticks(&self, state: usize, index: u32) -> Option<ComponentTicks> {
+        debug_assert!(index < self.len);
+        let ticks = self.data[state].ticks?;
+        unsafe { Some(*ticks.as_ptr().add(index as usize)) }
+    }
+
+    /// Overwrite the ticks of the component at `index` in the column at `state`
+    ///
+    /// # Safety
+    ///
+    /// The column must be tracked, and uniquely borrowed or `self` uniquely accessible
+    pub(crate) unsafe fn set_component_ticks(
+        &self,
+        state: usize,
+        index: u32,
+        ticks: ComponentTicks,
+    ) {
+        debug_assert!(index < self.len);
+        let column = self.data[state].ticks.unwrap_unchecked();
+        *column.as_ptr().add(index as usize) = ticks;
+    }
+
+    /// Record that the component at `index` in the column at `state` was changed
+    ///
+    /// # Safety
+    ///
+    /// The column must be uniquely borrowed, or `self` uniquely accessible
+    #[inline]
+    pub(crate) unsafe fn mark_changed(&self, state: usize, index: u32) {
+        debug_assert!(index < self.len);
+        if let Some(ticks) = self.data[state].ticks {
+            (*ticks.as_ptr().add(index as usize)).changed = self.tick;
+        }
+    }
+
+    /// Record that every component in the column at `state` was changed
+    ///
+    /// # Safety
+    ///
+    /// The column must be uniquely borrowed, or `self` uniquely accessible
+    pub(crate) unsafe fn mark_all_changed(&self, state: usize) {
+        let Some(ticks) = self.data[state].ticks else {
+            return;
+        };
+        for index in 0..self.len as usize {
+            (*ticks.as_ptr().add(index)).changed = self.tick;
+        }
+    }
+
+    /// Stamp every tracked component of the entities in `range` as added now
+    fn stamp(&mut self, range: core::ops::Range<u32>) {
+        let ticks = ComponentTicks {
+            added: self.tick,
+            changed: self.tick,
+        };
+        for column in self.data.iter().filter_map(|data| data.ticks) {
+            for index in range.clone() {
+                unsafe {
+                    *column.as_ptr().add(index as usize) = ticks;
+                }
+            }
+        }
+    }
+
+    /// Copy the ticks of each of this archetype's tracked components of the entity at
+    /// `source_index` in `source`, if it has that component, to the entity at `index`
+    pub(crate) fn copy_ticks(&mut self, index: u32, source: &Archetype, source_index: u32) {
+        debug_assert!(index < self.len && source_index < source.len);
+        for (ty, data) in self.types.iter().zip(&*self.data) {
+            let Some(column) = data.ticks else {
+                continue;
+            };
+            let ticks = source
+                .get_state_dynamic(ty.id)
+                .and_then(|state| source.component_ticks(state, source_index));
+            if let Some(ticks) = ticks {
+                unsafe {
+                    *column.as_ptr().add(index as usize) = ticks;
+                }
+            }
+        }
+    }
+
+    /// Counter incremented whenever entities are added to, removed from, or reordered within this
+    /// archetype
+    ///
+    /// Lets e.g. render extraction skip archetypes whose entities are unchanged since a previous
+    /// pass. Wraps on overflow, so compare versions for equality only.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Metadata of type `T` attached by
+    /// [`Frame::archetype_metadata_mut`](crate::Frame::archetype_metadata_mut), if any
+    pub fn metadata<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.metadata
+            .get(&TypeId::of::<T>())
+            .map(|x| x.downcast_ref().unwrap())
+    }
+
+    pub(crate) fn metadata_mut<T: Default + Send + Sync + 'static>(&mut self) -> &mut T {
+        self.metadata
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::<T>::default())
+            .downcast_mut()
+            .unwrap()
+    }
+
+    /// Counter incremented whenever the `T` components of this archetype may have been written,
+    /// or `None` if `T` isn't present
+    ///
+    /// Incremented when unique access is granted, e.g. by a query for `&mut T`, whether or not any
+    /// component is actually modified. Writes through unchecked accessors such as
+    /// [`Frame::get_unchecked`](crate::Frame::get_unchecked) aren't counted. Wraps on
+    /// overflow, so compare versions for equality only. Changes to the set of entities are
+    /// tracked by [`version`](Self::version) instead.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((1, true));
+    /// let index = frame.archetype_id(e).unwrap() as usize;
+    /// let versions = |frame: &Frame| {
+    ///     let archetype = frame.archetypes().nth(index).unwrap();
+    ///     (archetype.column_version::<i32>(), archetype.column_version::<bool>())
+    /// };
+    /// let (ints, bools) = versions(&frame);
+    /// for (_, x) in frame.query_mut::<&mut i32>() {
+    ///     *x += 1;
+    /// }
+    /// assert_ne!(versions(&frame).0, ints);
+    /// assert_eq!(versions(&frame).1, bools);
+    /// ```
+    pub fn column_version<T: Component>(&self) -> Option<u32> {
+        self.column_version_dynamic(TypeId::of::<T>())
+    }
+
+    /// Like [`column_version`](Self::column_version), but for the component type identified by
+    /// `id`
+    pub fn column_version_dynamic(&self, id: TypeId) -> Option<u32> {
+        let state = self.get_state_dynamic(id)?;
+        Some(self.data[state].version.load(Ordering::Relaxed))
+    }
+
     /// Number of entities in this archetype
     #[inline]
     pub fn len(&self) -> u32 {
@@ -181,6 +609,12 @@ impl Archetype {
     #[inline]
     pub(crate) fn set_entity_id(&mut self, index: usize, id: u32) {
         self.entities[index] = id;
+        self.touch();
+    }
+
+    /// Type information of each component type, in storage order
+    pub fn component_type_info(&self) -> &[TypeInfo] {
+        &self.types
     }
 
     pub(crate) fn types(&self) -> &[TypeInfo] {
@@ -216,44 +650,128 @@ impl Archetype {
         size: usize,
         index: u32,
     ) -> Option<NonNull<u8>> {
+        Some(self.get_dynamic_by_state(*self.index.get(&ty)?, size, index))
+    }
+
+    /// Replace the storage of the column at `state` with `len` components at `storage`, borrowed
+    /// until the column is first written
+    ///
+    /// # Safety
+    ///
+    /// `storage` must address `len` valid, aligned components of the column's type, which must be
+    /// safe to duplicate bitwise and must not need to be dropped. They must remain valid and
+    /// unchanged while `owner` lives. The archetype must be empty, and take on exactly `len`
+    /// entities.
+    pub(crate) unsafe fn borrow_column(
+        &mut self,
+        state: usize,
+        storage: NonNull<u8>,
+        owner: Arc<dyn Any + Send + Sync>,
+    ) {
+        debug_assert_eq!(self.len, 0);
+        let info = self.types[state];
+        let data = &mut self.data[state];
+        if info.layout.size() != 0 && data.shared.get_mut().is_none() && !self.entities.is_empty() {
+            dealloc(
+                data.storage.get().as_ptr(),
+                Layout::from_size_align(
+                    info.layout.size() * self.entities.len(),
+                    info.layout.align(),
+                )
+                .unwrap(),
+            );
+        }
+        data.storage.set(storage);
+        *data.shared.get_mut() = Some(Arc::new(SharedColumn {
+            storage,
+            owner: Owner::External(owner),
+        }));
+        self.shares = true;
+    }
+
+    /// Like `get_dynamic`, with a state index from `get_state_dynamic`
+    unsafe fn get_dynamic_by_state(&self, state: usize, size: usize, index: u32) -> NonNull<u8> {
         debug_assert!(index <= self.len);
-        Some(NonNull::new_unchecked(
+        NonNull::new_unchecked(
             self.data
-                .get_unchecked(*self.index.get(&ty)?)
+                .get_unchecked(state)
                 .storage
+                .get()
                 .as_ptr()
-                .add(size * index as usize)
-                .cast::<u8>(),
-        ))
+                .add(size * index as usize),
+        )
     }
 
     /// Every type must be written immediately after this call
     pub(crate) unsafe fn allocate(&mut self, id: u32) -> u32 {
+        self.unshare_all();
         if self.len as usize == self.entities.len() {
             self.grow(64);
         }
 
         self.entities[self.len as usize] = id;
         self.len += 1;
+        self.touch();
+        self.stamp(self.len - 1..self.len);
         self.len - 1
     }
 
     pub(crate) unsafe fn set_len(&mut self, len: u32) {
         debug_assert!(len <= self.capacity());
+        let old = self.len;
         self.len = len;
+        self.touch();
+        if len > old {
+            self.stamp(old..len);
+        }
     }
 
     pub(crate) fn reserve(&mut self, additional: u32) {
+        self.unshare_all();
         if additional > (self.capacity() - self.len()) {
             let increment = additional - (self.capacity() - self.len());
             self.grow(increment.max(64));
         }
     }
 
-    pub(crate) fn capacity(&self) -> u32 {
+    /// Number of entities this archetype can hold without reallocating
+    ///
+    /// [`Frame::replace_column`](crate::Frame::replace_column) takes ownership of vectors having
+    /// exactly this capacity rather than copying their contents.
+    pub fn capacity(&self) -> u32 {
         self.entities.len() as u32
     }
 
+    /// Exchange the `T` components of this archetype with `values`
+    ///
+    /// # Safety
+    ///
+    /// `T` must be present, and `values.len()` must equal `self.len()`
+    pub(crate) unsafe fn swap_column<T: Component>(&mut self, values: &mut Vec<T>) {
+        debug_assert_eq!(values.len(), self.len as usize);
+        let state = self.get_state::<T>().unwrap();
+        self.mark_written(state);
+        self.mark_all_changed(state);
+        let storage = &self.data[state].storage;
+        if mem::size_of::<T>() != 0 && values.capacity() == self.entities.len() {
+            // Columns are allocated with the same layout as a `Vec` of the same capacity
+            let mut replacement = mem::ManuallyDrop::new(mem::take(values));
+            *values = Vec::from_raw_parts(
+                storage.get().as_ptr().cast::<T>(),
+                self.len as usize,
+                self.entities.len(),
+            );
+            storage.set(NonNull::new_unchecked(
+                replacement.as_mut_ptr().cast::<u8>(),
+            ));
+        } else {
+            let base = storage.get().as_ptr().cast::<T>();
+            for (index, value) in values.iter_mut().enumerate() {
+                ptr::swap(base.add(index), value);
+            }
+        }
+    }
+
     /// Increase capacity by at least `min_increment`
     fn grow(&mut self, min_increment: u32) {
         // Double capacity or increase it by `min_increment`, whichever is larger.
@@ -285,16 +803,27 @@ impl Archetype {
                         let mem = NonNull::new(mem)
                             .unwrap_or_else(|| alloc::alloc::handle_alloc_error(layout));
                         ptr::copy_nonoverlapping(
-                            old.storage.as_ptr(),
+                            old.storage.get().as_ptr(),
                             mem.as_ptr(),
                             info.layout.size() * old_count,
                         );
                         mem
                     }
                 };
+                let ticks = old.ticks.map(|old| unsafe {
+                    let layout = Layout::array::<ComponentTicks>(new_cap).unwrap();
+                    let mem = NonNull::new(alloc(layout))
+                        .unwrap_or_else(|| alloc::alloc::handle_alloc_error(layout))
+                        .cast::<ComponentTicks>();
+                    ptr::copy_nonoverlapping(old.as_ptr(), mem.as_ptr(), old_count);
+                    mem
+                });
                 Data {
                     state: AtomicBorrow::new(), // &mut self guarantees no outstanding borrows
-                    storage,
+                    storage: Cell::new(storage),
+                    shared: Cell::new(None),
+                    ticks,
+                    version: AtomicU32::new(old.version.load(Ordering::Relaxed)),
                 }
             })
             .collect::<Box<[_]>>();
@@ -303,13 +832,22 @@ impl Archetype {
         // deallocate the old column data without risking `self.data` being left
         // partially deallocated on OOM.
         if old_cap > 0 {
-            for (info, data) in self.types.iter().zip(&*self.data) {
-                if info.layout.size() == 0 {
+            for (info, data) in self.types.iter().zip(&mut *self.data) {
+                if let Some(ticks) = data.ticks {
+                    unsafe {
+                        dealloc(
+                            ticks.as_ptr().cast(),
+                            Layout::array::<ComponentTicks>(old_cap).unwrap(),
+                        );
+                    }
+                }
+                // Shared storage is freed by the last archetype sharing it
+                if info.layout.size() == 0 || data.shared.get_mut().is_some() {
                     continue;
                 }
                 unsafe {
                     dealloc(
-                        data.storage.as_ptr(),
+                        data.storage.get().as_ptr(),
                         Layout::from_size_align(info.layout.size() * old_cap, info.layout.align())
                             .unwrap(),
                     );
@@ -318,22 +856,99 @@ impl Archetype {
         }
 
         self.data = new_data;
+        self.shares = false;
+        self.grown = true;
+    }
+
+    /// Reorder entities so that the entity at index `order[i]` moves to index `i`
+    ///
+    /// # Safety
+    ///
+    /// `order` must be a permutation of `0..self.len()`
+    pub(crate) unsafe fn permute(&mut self, order: &[u32]) {
+        debug_assert_eq!(order.len(), self.len as usize);
+        self.unshare_all();
+        self.touch();
+        let mut moved = vec![false; order.len()];
+        let mut scratch = Vec::new();
+        let columns = self
+            .types
+            .iter()
+            .zip(&*self.data)
+            .flat_map(|(ty, data)| {
+                let ticks = data.ticks.map(|ticks| {
+                    (
+                        ticks.as_ptr().cast::<u8>(),
+                        mem::size_of::<ComponentTicks>(),
+                    )
+                });
+                Some((data.storage.get().as_ptr(), ty.layout.size()))
+                    .into_iter()
+                    .chain(ticks)
+            })
+            .chain(Some((
+                self.entities.as_mut_ptr().cast::<u8>(),
+                mem::size_of::<u32>(),
+            )));
+        for (base, size) in columns {
+            if size == 0 {
+                continue;
+            }
+            moved.fill(false);
+            scratch.resize(size, 0u8);
+            // Rotate each cycle of the permutation through `scratch`
+            for start in 0..order.len() {
+                if moved[start] {
+                    continue;
+                }
+                ptr::copy_nonoverlapping(base.add(start * size), scratch.as_mut_ptr(), size);
+                let mut dest = start;
+                loop {
+                    moved[dest] = true;
+                    let src = order[dest] as usize;
+                    if src == start {
+                        ptr::copy_nonoverlapping(scratch.as_ptr(), base.add(dest * size), size);
+                        break;
+                    }
+                    ptr::copy_nonoverlapping(base.add(src * size), base.add(dest * size), size);
+                    dest = src;
+                }
+            }
+        }
+    }
+
+    /// Whether storage was reallocated since this was last called
+    pub(crate) fn take_grown(&mut self) -> bool {
+        mem::take(&mut self.grown)
     }
 
     /// Returns the ID of the entity moved into `index`, if any
     pub(crate) unsafe fn remove(&mut self, index: u32, drop: bool) -> Option<u32> {
+        self.unshare_all();
         let last = self.len - 1;
         for (ty, data) in self.types.iter().zip(&*self.data) {
-            let removed = data.storage.as_ptr().add(index as usize * ty.layout.size());
+            let removed = data
+                .storage
+                .get()
+                .as_ptr()
+                .add(index as usize * ty.layout.size());
             if drop {
                 (ty.drop)(removed);
             }
             if index != last {
-                let moved = data.storage.as_ptr().add(last as usize * ty.layout.size());
+                let moved = data
+                    .storage
+                    .get()
+                    .as_ptr()
+                    .add(last as usize * ty.layout.size());
                 ptr::copy_nonoverlapping(moved, removed, ty.layout.size());
+                if let Some(ticks) = data.ticks {
+                    *ticks.as_ptr().add(index as usize) = *ticks.as_ptr().add(last as usize);
+                }
             }
         }
         self.len = last;
+        self.touch();
         if index != last {
             self.entities[index as usize] = self.entities[last as usize];
             Some(self.entities[last as usize])
@@ -348,16 +963,29 @@ impl Archetype {
         index: u32,
         mut f: impl FnMut(*mut u8, TypeId, usize),
     ) -> Option<u32> {
+        self.unshare_all();
         let last = self.len - 1;
         for (ty, data) in self.types.iter().zip(&*self.data) {
-            let moved_out = data.storage.as_ptr().add(index as usize * ty.layout.size());
+            let moved_out = data
+                .storage
+                .get()
+                .as_ptr()
+                .add(index as usize * ty.layout.size());
             f(moved_out, ty.id(), ty.layout().size());
             if index != last {
-                let moved = data.storage.as_ptr().add(last as usize * ty.layout.size());
+                let moved = data
+                    .storage
+                    .get()
+                    .as_ptr()
+                    .add(last as usize * ty.layout.size());
                 ptr::copy_nonoverlapping(moved, moved_out, ty.layout.size());
+                if let Some(ticks) = data.ticks {
+                    *ticks.as_ptr().add(index as usize) = *ticks.as_ptr().add(last as usize);
+                }
             }
         }
         self.len -= 1;
+        self.touch();
         if index != last {
             self.entities[index as usize] = self.entities[last as usize];
             Some(self.entities[last as usize])
@@ -373,11 +1001,9 @@ impl Archetype {
         size: usize,
         index: u32,
     ) {
-        let ptr = self
-            .get_dynamic(ty, size, index)
-            .unwrap()
-            .as_ptr()
-            .cast::<u8>();
+        let state = self.get_state_dynamic(ty).unwrap();
+        self.mark_written(state);
+        let ptr = self.get_dynamic_by_state(state, size, index).as_ptr();
         ptr::copy_nonoverlapping(component, ptr, size);
     }
 
@@ -397,18 +1023,140 @@ impl Archetype {
     ///
     /// Component types must match exactly.
     pub(crate) unsafe fn merge(&mut self, mut other: Archetype) {
+        self.merge_from(&mut other);
+    }
+
+    /// Move all components out of `other`, leaving its storage allocated
+    ///
+    /// # Safety
+    ///
+    /// Component types must match exactly.
+    pub(crate) unsafe fn merge_from(&mut self, other: &mut Archetype) {
         self.reserve(other.len);
         for ((info, dst), src) in self.types.iter().zip(&*self.data).zip(&*other.data) {
             dst.storage
+                .get()
                 .as_ptr()
                 .add(self.len as usize * info.layout.size())
                 .copy_from_nonoverlapping(
-                    src.storage.as_ptr(),
+                    src.storage.get().as_ptr(),
                     other.len as usize * info.layout.size(),
                 )
         }
+        let base = self.len;
         self.len += other.len;
         other.len = 0;
+        self.touch();
+        other.touch();
+        self.stamp(base..self.len);
+    }
+
+    /// Move all entities into `target`, dropping components of types `target` lacks, and return
+    /// the index of the first moved entity in `target`
+    ///
+    /// # Safety
+    ///
+    /// `target`'s component types must be a subset of this archetype's.
+    pub(crate) unsafe fn move_all_to(&mut self, target: &mut Archetype) -> u32 {
+        let base = target.len;
+        target.reserve(self.len);
+        for (info, src) in self.types.iter().zip(&*self.data) {
+            let size = info.layout.size();
+            match target.get_state_dynamic(info.id) {
+                Some(state) => {
+                    let dst = &target.data[state];
+                    dst.storage
+                        .get()
+                        .as_ptr()
+                        .add(base as usize * size)
+                        .copy_from_nonoverlapping(
+                            src.storage.get().as_ptr(),
+                            self.len as usize * size,
+                        );
+                    match (dst.ticks, src.ticks) {
+                        (Some(dst), Some(src)) => dst
+                            .as_ptr()
+                            .add(base as usize)
+                            .copy_from_nonoverlapping(src.as_ptr(), self.len as usize),
+                        // Changes weren't tracked in the source, so count the components as added
+                        (Some(dst), None) => {
+                            let ticks = ComponentTicks {
+                                added: target.tick,
+                                changed: target.tick,
+                            };
+                            for index in 0..self.len as usize {
+                                *dst.as_ptr().add(base as usize + index) = ticks;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                None => {
+                    for index in 0..self.len as usize {
+                        (info.drop)(src.storage.get().as_ptr().add(index * size));
+                    }
+                }
+            }
+        }
+        target.entities[base as usize..(base + self.len) as usize]
+            .copy_from_slice(&self.entities[..self.len as usize]);
+        target.len += self.len;
+        self.len = 0;
+        self.touch();
+        target.touch();
+        base
+    }
+
+    /// Prevent components in this archetype from being moved while the returned guard is live
+    ///
+    /// Useful when external code, e.g. a GPU upload or a physics engine, retains raw pointers into
+    /// the archetype's columns beyond the lifetime of a borrow. While pinned, operations which would
+    /// move existing components or reallocate columns, such as despawning an entity, removing or
+    /// inserting one of its components, or spawning an entity that doesn't fit in the archetype's
+    /// existing capacity, panic rather than invalidate those pointers. Components may still be
+    /// modified in place.
+    ///
+    /// Pinning doesn't prevent the archetype from being dropped along with its [`Frame`](crate::Frame).
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((1.0f32,));
+    /// let pin = frame.archetypes().find(|a| a.has::<f32>()).unwrap().pin();
+    /// // ...hand pointers to an external system...
+    /// *frame.get::<&mut f32>(e).unwrap() = 2.0;
+    /// assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| frame.despawn(e))).is_err());
+    /// drop(pin);
+    /// frame.despawn(e).unwrap();
+    /// ```
+    pub fn pin(&self) -> ArchetypePin {
+        self.pins.fetch_add(1, Ordering::Relaxed);
+        ArchetypePin {
+            pins: self.pins.clone(),
+        }
+    }
+
+    /// Whether any [`ArchetypePin`] for this archetype is live
+    pub fn is_pinned(&self) -> bool {
+        self.pins.load(Ordering::Acquire) != 0
+    }
+
+    /// Check that entities may be removed from this archetype
+    ///
+    /// Called before making any changes, so that panicking leaves the frame consistent.
+    pub(crate) fn assert_unpinned(&self) {
+        assert!(
+            !self.is_pinned(),
+            "archetype is pinned; its components can't be moved"
+        );
+    }
+
+    /// Check that `additional` entities may be added to this archetype
+    pub(crate) fn assert_fits(&self, additional: u32) {
+        if self.capacity() - self.len < additional {
+            self.assert_unpinned();
+        }
     }
 
     /// Raw IDs of the entities in this archetype
@@ -422,17 +1170,38 @@ impl Archetype {
     }
 }
 
+/// Guard preventing an archetype's components from being moved
+///
+/// See [`Archetype::pin`].
+pub struct ArchetypePin {
+    pins: Arc<AtomicUsize>,
+}
+
+impl Drop for ArchetypePin {
+    fn drop(&mut self) {
+        self.pins.fetch_sub(1, Ordering::Release);
+    }
+}
+
 impl Drop for Archetype {
     fn drop(&mut self) {
         self.clear();
-        if self.entities.len() == 0 {
+        if self.entities.is_empty() {
             return;
         }
-        for (info, data) in self.types.iter().zip(&*self.data) {
-            if info.layout.size() != 0 {
+        for (info, data) in self.types.iter().zip(&mut *self.data) {
+            if let Some(ticks) = data.ticks {
+                unsafe {
+                    dealloc(
+                        ticks.as_ptr().cast(),
+                        Layout::array::<ComponentTicks>(self.entities.len()).unwrap(),
+                    );
+                }
+            }
+            if info.layout.size() != 0 && data.shared.get_mut().is_none() {
                 unsafe {
                     dealloc(
-                        data.storage.as_ptr(),
+                        data.storage.get().as_ptr(),
                         Layout::from_size_align_unchecked(
                             info.layout.size() * self.entities.len(),
                             info.layout.align(),
@@ -446,7 +1215,49 @@ impl Drop for Archetype {
 
 struct Data {
     state: AtomicBorrow,
+    storage: Cell<NonNull<u8>>,
+    /// Owner of `storage` if it's shared with other archetypes, which must copy it before writing
+    shared: Cell<Option<Arc<SharedColumn>>>,
+    /// When each component was added and last changed, if changes to its type are tracked
+    ticks: Option<NonNull<ComponentTicks>>,
+    /// Incremented whenever the column may be written
+    version: AtomicU32,
+}
+
+/// Column storage shared by archetypes in different frames, freed along with the last of them
+struct SharedColumn {
     storage: NonNull<u8>,
+    owner: Owner,
+}
+
+/// What keeps a [`SharedColumn`]'s storage alive
+enum Owner {
+    /// Storage allocated by an archetype with this layout
+    Allocation(Layout),
+    /// Storage borrowed from outside the frame, e.g. a memory-mapped file, valid while this lives
+    External(#[allow(dead_code)] Arc<dyn Any + Send + Sync>),
+}
+
+// Shared storage is only read, by frames that may live on different threads
+unsafe impl Send for SharedColumn {}
+unsafe impl Sync for SharedColumn {}
+
+impl Drop for SharedColumn {
+    fn drop(&mut self) {
+        if let Owner::Allocation(layout) = self.owner {
+            unsafe {
+                dealloc(self.storage.as_ptr(), layout);
+            }
+        }
+    }
+}
+
+/// Ticks at which a component was added and last changed, for [`Changed`](crate::Changed) and
+/// [`Added`](crate::Added)
+#[derive(Copy, Clone)]
+pub(crate) struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32,
 }
 
 /// A hasher optimized for hashing a single TypeId.
@@ -525,6 +1336,10 @@ pub struct TypeInfo {
     id: TypeId,
     layout: Layout,
     drop: unsafe fn(*mut u8),
+    /// Whether `drop` does anything, so that whole columns can be discarded without visiting them
+    needs_drop: bool,
+    /// Resolved from the registry when stored in an archetype
+    extension: Option<TypeExtension>,
     #[cfg(debug_assertions)]
     type_name: &'static str,
 }
@@ -540,6 +1355,8 @@ impl TypeInfo {
             id: TypeId::of::<T>(),
             layout: Layout::new::<T>(),
             drop: drop_ptr::<T>,
+            needs_drop: mem::needs_drop::<T>(),
+            extension: None,
             #[cfg(debug_assertions)]
             type_name: core::any::type_name::<T>(),
         }
@@ -554,6 +1371,8 @@ impl TypeInfo {
             id,
             layout,
             drop,
+            needs_drop: true,
+            extension: None,
             #[cfg(debug_assertions)]
             type_name: "<unknown> (TypeInfo constructed from parts)",
         }
@@ -564,11 +1383,29 @@ impl TypeInfo {
         self.id
     }
 
+    /// Name of this component type, if known
+    pub(crate) fn type_name(&self) -> Option<&'static str> {
+        #[cfg(debug_assertions)]
+        return Some(self.type_name);
+        #[cfg(not(debug_assertions))]
+        return None;
+    }
+
     /// Access the `Layout` of this component type.
     pub fn layout(&self) -> Layout {
         self.layout
     }
 
+    /// Access the extension attached to this component type by [`set_type_extension`], if any
+    ///
+    /// Cheap for the type information of an [`Archetype`]'s columns, which is resolved when the
+    /// archetype is created. Otherwise involves a lookup.
+    ///
+    /// [`set_type_extension`]: crate::set_type_extension
+    pub fn extension(&self) -> Option<TypeExtension> {
+        self.extension.or_else(|| extension::lookup(self.id))
+    }
+
     /// Directly call the destructor on a pointer to data of this component type.
     ///
     /// # Safety
@@ -620,9 +1457,9 @@ pub struct ArchetypeColumn<'a, T: Component> {
 impl<'a, T: Component> ArchetypeColumn<'a, T> {
     pub(crate) fn new(archetype: &'a Archetype) -> Option<Self> {
         let state = archetype.get_state::<T>()?;
+        archetype.borrow::<T>(state);
         let ptr = archetype.get_base::<T>(state);
         let column = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), archetype.len() as usize) };
-        archetype.borrow::<T>(state);
         Some(Self { archetype, column })
     }
 }
@@ -667,10 +1504,14 @@ pub struct ArchetypeColumnMut<'a, T: Component> {
 impl<'a, T: Component> ArchetypeColumnMut<'a, T> {
     pub(crate) fn new(archetype: &'a Archetype) -> Option<Self> {
         let state = archetype.get_state::<T>()?;
+        // Borrowing may move the column out of shared storage
+        archetype.borrow_mut::<T>(state);
         let ptr = archetype.get_base::<T>(state);
         let column =
             unsafe { core::slice::from_raw_parts_mut(ptr.as_ptr(), archetype.len() as usize) };
-        archetype.borrow_mut::<T>(state);
+        unsafe {
+            archetype.mark_all_changed(state);
+        }
         Some(Self { archetype, column })
     }
 }
@@ -1,5 +1,12 @@
-use crate::alloc::collections::BinaryHeap;
-use core::{any::TypeId, fmt, mem::MaybeUninit, slice};
+use crate::alloc::{collections::BinaryHeap, sync::Arc};
+use core::{
+    any::{Any, TypeId},
+    fmt,
+    mem::MaybeUninit,
+    ptr,
+    ptr::NonNull,
+    slice,
+};
 
 use crate::{
     archetype::{TypeIdMap, TypeInfo},
@@ -20,16 +27,77 @@ impl ColumnBatchType {
 
     /// Update to include `T` components
     pub fn add<T: Component>(&mut self) -> &mut Self {
-        self.types.push(TypeInfo::of::<T>());
-        self
+        self.add_dynamic(TypeInfo::of::<T>())
     }
 
     /// [Self::add()] but using type information determined at runtime via [TypeInfo::of()]
     pub fn add_dynamic(&mut self, id: TypeInfo) -> &mut Self {
-        self.types.push(id);
+        if !self.has_dynamic(id.id()) {
+            self.types.push(id);
+        }
         self
     }
 
+    /// Whether `T` components are included
+    pub fn has<T: Component>(&self) -> bool {
+        self.has_dynamic(TypeId::of::<T>())
+    }
+
+    /// Whether components of the type identified by `id` are included
+    pub fn has_dynamic(&self, id: TypeId) -> bool {
+        self.types.iter().any(|ty| ty.id() == id)
+    }
+
+    /// Iterate over the included component types, in no particular order
+    pub fn types(&self) -> impl ExactSizeIterator<Item = &TypeInfo> + '_ {
+        self.types.iter()
+    }
+
+    /// Number of included component types
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    /// Whether no component types are included
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// Component types included in either `self` or `other`
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut a = ColumnBatchType::new();
+    /// a.add::<i32>().add::<bool>();
+    /// let mut b = ColumnBatchType::new();
+    /// b.add::<bool>().add::<f32>();
+    /// let union = a.union(&b);
+    /// assert_eq!(union.len(), 3);
+    /// assert!(union.has::<i32>() && union.has::<bool>() && union.has::<f32>());
+    /// let intersection = a.intersection(&b);
+    /// assert_eq!(intersection.len(), 1);
+    /// assert!(intersection.has::<bool>());
+    /// ```
+    pub fn union(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for &ty in other.types() {
+            result.add_dynamic(ty);
+        }
+        result
+    }
+
+    /// Component types included in both `self` and `other`
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self {
+            types: self
+                .types()
+                .filter(|ty| other.has_dynamic(ty.id()))
+                .copied()
+                .collect(),
+        }
+    }
+
     /// Construct a [`ColumnBatchBuilder`] for *exactly* `size` entities with these components
     pub fn into_batch(self, size: u32) -> ColumnBatchBuilder {
         let mut types = self.types.into_sorted_vec();
@@ -67,15 +135,242 @@ impl ColumnBatchBuilder {
         let archetype = self.archetype.as_mut().unwrap();
         let state = archetype.get_state::<T>()?;
         let base = archetype.get_base::<T>(state);
+        let fill = self.fill.entry(TypeId::of::<T>()).or_insert(0);
+        let start = *fill as usize;
         Some(BatchWriter {
-            fill: self.fill.entry(TypeId::of::<T>()).or_insert(0),
+            fill,
             storage: unsafe {
-                slice::from_raw_parts_mut(base.as_ptr().cast(), self.target_fill as usize)
-                    .iter_mut()
+                slice::from_raw_parts_mut(
+                    base.as_ptr().cast::<MaybeUninit<T>>().add(start),
+                    self.target_fill as usize - start,
+                )
+                .iter_mut()
             },
         })
     }
 
+    /// Append `T` components produced by a parallel iterator, writing them from worker threads
+    ///
+    /// Components are written after any already present in the column. Returns `Err(BatchFull)`
+    /// without consuming `iter` if it yields more components than remain to be filled. Whether
+    /// every column was filled to the same length is checked by [`build()`](Self::build).
+    ///
+    /// Returns `None` if `T` was not in the [`ColumnBatchType`].
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// use rayon::prelude::*;
+    /// let mut ty = ColumnBatchType::new();
+    /// ty.add::<u32>().add::<u64>();
+    /// let mut builder = ty.into_batch(1000);
+    /// builder.par_fill((0..1000u32).into_par_iter()).unwrap().unwrap();
+    /// builder.par_fill((0..1000u32).into_par_iter().map(|x| u64::from(x) * 2)).unwrap().unwrap();
+    /// let mut frame = Frame::new();
+    /// frame.spawn_column_batch(builder.build().unwrap());
+    /// assert_eq!(frame.len(), 1000);
+    /// ```
+    #[cfg(feature = "parallel")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "parallel")))]
+    pub fn par_fill<T, I>(&mut self, iter: I) -> Option<Result<(), BatchFull>>
+    where
+        T: Component,
+        I: rayon::iter::IndexedParallelIterator<Item = T>,
+    {
+        use rayon::iter::ParallelIterator;
+
+        let archetype = self.archetype.as_mut().unwrap();
+        let state = archetype.get_state::<T>()?;
+        let base = archetype.get_base::<T>(state);
+        let fill = self.fill.entry(TypeId::of::<T>()).or_insert(0);
+        let count = iter.len();
+        if count > (self.target_fill - *fill) as usize {
+            return Some(Err(BatchFull { _opaque: () }));
+        }
+        // Raw pointers aren't `Send`; smuggle the column base across threads as an address.
+        let start = base.as_ptr() as usize + *fill as usize * core::mem::size_of::<T>();
+        iter.enumerate().for_each(|(i, x)| unsafe {
+            (start as *mut T).add(i).write(x);
+        });
+        // Only counted once every write has completed, so a panicking producer leaks rather than
+        // leaving uninitialized slots marked as filled.
+        *fill += count as u32;
+        Some(Ok(()))
+    }
+
+    /// Append `T` components by copying their bytes from `bytes`, e.g. a column of a memory-mapped
+    /// snapshot
+    ///
+    /// Loads a whole column with a single copy, so that together with
+    /// [`Frame::from_raw_parts`](crate::Frame::from_raw_parts) a snapshot can be loaded without any
+    /// per-entity work. `bytes` needn't be aligned for `T`. To avoid even the copy, see
+    /// [`borrow_bytes`](Self::borrow_bytes).
+    ///
+    /// Returns `Err(BatchFull)` without copying if `bytes` holds more components than remain to be
+    /// filled, or `None` if `T` was not in the [`ColumnBatchType`]. Panics if `T` is zero-sized or
+    /// `bytes.len()` isn't a multiple of its size.
+    ///
+    /// # Safety
+    ///
+    /// `bytes` must be the concatenated representations of valid `T` values.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let snapshot = [1u32, 2, 3].map(u32::to_ne_bytes).concat();
+    /// let mut ty = ColumnBatchType::new();
+    /// ty.add::<u32>();
+    /// let mut builder = ty.into_batch(3);
+    /// unsafe { builder.extend_from_bytes::<u32>(&snapshot).unwrap().unwrap() };
+    /// let mut frame = Frame::new();
+    /// let entities = frame.spawn_column_batch(builder.build().unwrap()).collect::<Vec<_>>();
+    /// assert_eq!(*frame.get::<&u32>(entities[2]).unwrap(), 3);
+    /// ```
+    pub unsafe fn extend_from_bytes<T: Component + Copy>(
+        &mut self,
+        bytes: &[u8],
+    ) -> Option<Result<(), BatchFull>> {
+        let size = core::mem::size_of::<T>();
+        assert!(size != 0, "zero-sized components have no bytes to copy");
+        assert_eq!(
+            bytes.len() % size,
+            0,
+            "{} bytes don't divide into components of {} bytes",
+            bytes.len(),
+            size
+        );
+        let archetype = self.archetype.as_mut().unwrap();
+        let state = archetype.get_state::<T>()?;
+        let base = archetype.get_base::<T>(state);
+        let fill = self.fill.entry(TypeId::of::<T>()).or_insert(0);
+        let count = bytes.len() / size;
+        if count > (self.target_fill - *fill) as usize {
+            return Some(Err(BatchFull { _opaque: () }));
+        }
+        ptr::copy_nonoverlapping(
+            bytes.as_ptr(),
+            base.as_ptr().add(*fill as usize).cast::<u8>(),
+            bytes.len(),
+        );
+        *fill += count as u32;
+        Some(Ok(()))
+    }
+
+    /// Fill the `T` column with components borrowed from `bytes`, e.g. a column of a memory-mapped
+    /// snapshot, rather than copied
+    ///
+    /// Frames read borrowed components in place, and copy the column only once it's first written
+    /// or entities are added to or removed from its archetype, so huge mostly-static snapshots
+    /// load without touching most of their pages. `owner`, e.g. the mapping, is kept alive until
+    /// no frame refers to `bytes`. Spawning the batch into a frame that already has an archetype
+    /// with the same component types copies the column into it.
+    ///
+    /// Falls back to copying, like [`extend_from_bytes`](Self::extend_from_bytes), unless `bytes`
+    /// is aligned for `T` and holds exactly the batch's components of type `T`. Returns
+    /// `Err(BatchFull)` or `None`, and panics, as `extend_from_bytes` does.
+    ///
+    /// # Safety
+    ///
+    /// `bytes` must be the concatenated representations of valid `T` values, and must remain valid
+    /// and unchanged while `owner` lives.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// # use std::sync::Arc;
+    /// let snapshot = Arc::new(vec![1u32, 2, 3]);
+    /// let bytes = unsafe { std::slice::from_raw_parts(snapshot.as_ptr().cast::<u8>(), 12) };
+    /// let mut ty = ColumnBatchType::new();
+    /// ty.add::<u32>();
+    /// let mut builder = ty.into_batch(3);
+    /// unsafe { builder.borrow_bytes::<u32>(bytes, snapshot.clone()).unwrap().unwrap() };
+    /// let mut frame = Frame::new();
+    /// let entities = frame.spawn_column_batch(builder.build().unwrap()).collect::<Vec<_>>();
+    /// assert_eq!(Arc::strong_count(&snapshot), 2);
+    /// // The first write copies the column, releasing the snapshot
+    /// *frame.get::<&mut u32>(entities[2]).unwrap() = 4;
+    /// assert_eq!(Arc::strong_count(&snapshot), 1);
+    /// assert_eq!(snapshot[2], 3);
+    /// ```
+    pub unsafe fn borrow_bytes<T: Component + Copy>(
+        &mut self,
+        bytes: &[u8],
+        owner: Arc<dyn Any + Send + Sync>,
+    ) -> Option<Result<(), BatchFull>> {
+        let size = core::mem::size_of::<T>();
+        assert!(size != 0, "zero-sized components have no bytes to copy");
+        let archetype = self.archetype.as_mut().unwrap();
+        let state = archetype.get_state::<T>()?;
+        let fill = self.fill.get(&TypeId::of::<T>()).copied().unwrap_or(0);
+        if fill != 0
+            || bytes.len() != self.target_fill as usize * size
+            || bytes.as_ptr() as usize % core::mem::align_of::<T>() != 0
+            || bytes.is_empty()
+        {
+            return self.extend_from_bytes::<T>(bytes);
+        }
+        let storage = NonNull::new_unchecked(bytes.as_ptr() as *mut u8);
+        archetype.borrow_column(state, storage, owner);
+        self.fill.insert(TypeId::of::<T>(), self.target_fill);
+        Some(Ok(()))
+    }
+
+    /// Append `count` components of type `ty` by copying their bytes from `src`
+    ///
+    /// Returns `false` without copying if `ty` isn't in the batch or too few components remain to be
+    /// filled.
+    ///
+    /// # Safety
+    ///
+    /// `src` must address `count` initialized values of type `ty` which may be bitwise duplicated,
+    /// e.g. because the type is `Copy`.
+    pub(crate) unsafe fn extend_raw(&mut self, ty: TypeInfo, src: NonNull<u8>, count: u32) -> bool {
+        let archetype = self.archetype.as_mut().unwrap();
+        if !archetype.has_dynamic(ty.id()) {
+            return false;
+        }
+        let fill = self.fill.entry(ty.id()).or_insert(0);
+        if count > self.target_fill - *fill {
+            return false;
+        }
+        let size = ty.layout().size();
+        // The archetype's length stays zero until the batch is built, so offset into the column
+        // manually
+        let dest = archetype
+            .get_dynamic(ty.id(), 0, 0)
+            .unwrap()
+            .as_ptr()
+            .add(size * *fill as usize);
+        ptr::copy_nonoverlapping(src.as_ptr(), dest, size * count as usize);
+        *fill += count;
+        true
+    }
+
+    /// Fill the column of type `ty` with `source`'s components by sharing its storage
+    ///
+    /// Returns `false` if nothing was shared, in which case the column should be filled otherwise.
+    ///
+    /// # Safety
+    ///
+    /// Values of type `ty` must be safe to duplicate bitwise, and must not need to be dropped
+    pub(crate) unsafe fn share_raw(&mut self, ty: TypeInfo, source: &mut Archetype) -> bool {
+        let archetype = self.archetype.as_mut().unwrap();
+        if !archetype.has_dynamic(ty.id())
+            || source.len() != self.target_fill
+            || self.fill.get(&ty.id()).map_or(false, |&fill| fill != 0)
+        {
+            return false;
+        }
+        let Some(state) = source.get_state_dynamic(ty.id()) else {
+            return false;
+        };
+        if !source.share_column(state, archetype) {
+            return false;
+        }
+        self.fill.insert(ty.id(), self.target_fill);
+        true
+    }
+
     /// Finish the batch, failing if any components are missing
     pub fn build(mut self) -> Result<ColumnBatch, BatchIncomplete> {
         let mut archetype = self.archetype.take().unwrap();
@@ -112,6 +407,47 @@ impl Drop for ColumnBatchBuilder {
 /// A collection of component data for entities with the same component types
 pub struct ColumnBatch(pub(crate) Archetype);
 
+unsafe impl Send for ColumnBatch {}
+unsafe impl Sync for ColumnBatch {}
+
+impl ColumnBatch {
+    /// Move the components of each of `bundles` into a batch
+    #[cfg(feature = "parallel")]
+    pub(crate) fn from_bundles<B: crate::Bundle>(bundles: crate::alloc::vec::Vec<B>) -> Self {
+        let mut ty = ColumnBatchType::new();
+        B::with_static_type_info(|info| {
+            for &x in info {
+                ty.add_dynamic(x);
+            }
+        });
+        let mut builder = ty.into_batch(bundles.len() as u32);
+        for bundle in bundles {
+            unsafe {
+                bundle.put(|ptr, ty| {
+                    builder.extend_raw(ty, NonNull::new_unchecked(ptr), 1);
+                });
+            }
+        }
+        builder.build().unwrap()
+    }
+
+    /// Drop any remaining components and reuse the batch's storage to build a batch of *exactly*
+    /// `size` entities with the same component types
+    ///
+    /// Allocates only if `size` exceeds the batch's capacity. See
+    /// [`Frame::spawn_column_batch_retaining`](crate::Frame::spawn_column_batch_retaining).
+    pub fn into_builder(self, size: u32) -> ColumnBatchBuilder {
+        let mut archetype = self.0;
+        archetype.clear();
+        archetype.reserve(size);
+        ColumnBatchBuilder {
+            fill: TypeIdMap::with_capacity_and_hasher(archetype.types().len(), Default::default()),
+            target_fill: size,
+            archetype: Some(archetype),
+        }
+    }
+}
+
 /// Handle for appending components
 pub struct BatchWriter<'a, T> {
     fill: &'a mut u32,
@@ -152,6 +488,21 @@ impl fmt::Display for BatchIncomplete {
     }
 }
 
+/// Error indicating that more components were supplied than a [`ColumnBatchBuilder`] has room for
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BatchFull {
+    _opaque: (),
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BatchFull {}
+
+impl fmt::Display for BatchFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("batch full")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -164,4 +515,181 @@ mod tests {
         let mut writer = builder.writer::<usize>().unwrap();
         assert!(writer.push(42).is_err());
     }
+
+    #[test]
+    fn writer_resumes_after_fill() {
+        let mut types = ColumnBatchType::new();
+        types.add::<usize>();
+        let mut builder = types.into_batch(2);
+        builder.writer::<usize>().unwrap().push(1).unwrap();
+        let mut writer = builder.writer::<usize>().unwrap();
+        writer.push(2).unwrap();
+        assert!(writer.push(3).is_err());
+        assert!(builder.build().is_ok());
+    }
+
+    #[test]
+    fn extend_from_unaligned_bytes() {
+        let mut bytes = alloc::vec![0u8];
+        bytes.extend_from_slice(&7u64.to_ne_bytes());
+        bytes.extend_from_slice(&9u64.to_ne_bytes());
+        let mut types = ColumnBatchType::new();
+        types.add::<u64>();
+        let mut builder = types.into_batch(2);
+        unsafe {
+            builder
+                .extend_from_bytes::<u64>(&bytes[1..9])
+                .unwrap()
+                .unwrap();
+            assert!(builder
+                .extend_from_bytes::<u64>(&bytes[1..])
+                .unwrap()
+                .is_err());
+            assert!(builder.extend_from_bytes::<u32>(&bytes[1..5]).is_none());
+            builder
+                .extend_from_bytes::<u64>(&bytes[9..])
+                .unwrap()
+                .unwrap();
+        }
+        let batch = builder.build().unwrap();
+        let column = batch.0.get::<&u64>().unwrap();
+        assert_eq!(&*column, &[7, 9]);
+    }
+
+    #[test]
+    fn borrow_bytes() {
+        let snapshot = Arc::new(alloc::vec![7u64, 9]);
+        let bytes = unsafe { slice::from_raw_parts(snapshot.as_ptr().cast::<u8>(), 16) };
+        let mut types = ColumnBatchType::new();
+        types.add::<u64>().add::<u32>();
+        let mut builder = types.into_batch(2);
+        unsafe {
+            builder
+                .borrow_bytes::<u64>(bytes, snapshot.clone())
+                .unwrap()
+                .unwrap();
+            // Partial columns are copied
+            builder
+                .borrow_bytes::<u32>(&bytes[..4], snapshot.clone())
+                .unwrap()
+                .unwrap();
+            builder
+                .borrow_bytes::<u32>(&bytes[4..8], snapshot.clone())
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(Arc::strong_count(&snapshot), 2);
+        let mut frame = crate::Frame::new();
+        let entities = frame
+            .spawn_column_batch(builder.build().unwrap())
+            .collect::<alloc::vec::Vec<_>>();
+        let column = frame.archetypes().last().unwrap().get::<&u64>().unwrap();
+        assert_eq!(column.as_ptr(), snapshot.as_ptr());
+        drop(column);
+
+        // Adding an entity copies the column, leaving the snapshot untouched
+        let e = frame.spawn((1u64, 2u32));
+        assert_eq!(Arc::strong_count(&snapshot), 1);
+        *frame.get::<&mut u64>(entities[0]).unwrap() = 3;
+        assert_eq!(&snapshot[..], &[7, 9]);
+        assert_eq!(*frame.get::<&u64>(entities[1]).unwrap(), 9);
+        assert_eq!(*frame.get::<&u64>(e).unwrap(), 1);
+    }
+
+    #[test]
+    fn reuse_storage() {
+        let mut frame = crate::Frame::new();
+        let mut types = ColumnBatchType::new();
+        types.add::<u32>().add::<alloc::string::String>();
+        let mut builder = types.into_batch(4);
+        let capacity = builder.archetype.as_ref().unwrap().capacity();
+        for tick in 0..3 {
+            let mut ints = builder.writer::<u32>().unwrap();
+            for i in 0..4 {
+                ints.push(tick * 4 + i).unwrap();
+            }
+            let mut strings = builder.writer::<alloc::string::String>().unwrap();
+            for _ in 0..4 {
+                strings.push("abc".into()).unwrap();
+            }
+            let mut batch = builder.build().unwrap();
+            let entities = frame
+                .spawn_column_batch_retaining(&mut batch)
+                .collect::<alloc::vec::Vec<_>>();
+            assert_eq!(*frame.get::<&u32>(entities[3]).unwrap(), tick * 4 + 3);
+            assert_eq!(batch.0.len(), 0);
+            builder = batch.into_builder(4);
+            assert_eq!(builder.archetype.as_ref().unwrap().capacity(), capacity);
+        }
+        assert_eq!(frame.len(), 12);
+        assert_eq!(frame.archetypes().filter(|x| !x.is_empty()).count(), 1);
+    }
+
+    #[test]
+    fn type_arithmetic() {
+        let mut a = ColumnBatchType::new();
+        a.add::<u8>().add::<u16>().add::<u8>();
+        assert_eq!(a.len(), 2);
+        let mut b = ColumnBatchType::new();
+        b.add::<u16>().add::<u32>();
+
+        let union = a.union(&b);
+        let mut ids = union
+            .types()
+            .map(|ty| ty.id())
+            .collect::<alloc::vec::Vec<_>>();
+        ids.sort_unstable();
+        let mut expected = [TypeId::of::<u8>(), TypeId::of::<u16>(), TypeId::of::<u32>()];
+        expected.sort_unstable();
+        assert_eq!(ids, expected);
+
+        let intersection = a.intersection(&b);
+        assert_eq!(intersection.len(), 1);
+        assert!(intersection.has::<u16>() && !intersection.has::<u8>());
+        assert!(a.intersection(&ColumnBatchType::new()).is_empty());
+
+        let mut builder = union.into_batch(1);
+        builder.writer::<u8>().unwrap().push(1).unwrap();
+        builder.writer::<u16>().unwrap().push(2).unwrap();
+        builder.writer::<u32>().unwrap().push(3).unwrap();
+        assert!(builder.build().is_ok());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn par_fill() {
+        use rayon::prelude::*;
+
+        let mut types = ColumnBatchType::new();
+        types.add::<usize>().add::<u16>();
+        let mut builder = types.into_batch(100);
+        builder
+            .par_fill((0..50usize).into_par_iter())
+            .unwrap()
+            .unwrap();
+        builder
+            .par_fill((50..100usize).into_par_iter())
+            .unwrap()
+            .unwrap();
+        assert!(builder
+            .par_fill((0..1usize).into_par_iter())
+            .unwrap()
+            .is_err());
+        assert!(builder.par_fill((0..1u8).into_par_iter()).is_none());
+        builder
+            .par_fill((0..99u16).into_par_iter())
+            .unwrap()
+            .unwrap();
+        assert!(builder.build().is_err());
+
+        let mut types = ColumnBatchType::new();
+        types.add::<usize>();
+        let mut builder = types.into_batch(100);
+        builder
+            .par_fill((0..100usize).into_par_iter())
+            .unwrap()
+            .unwrap();
+        let batch = builder.build().unwrap();
+        assert!(batch.0.get::<&usize>().unwrap().iter().copied().eq(0..100));
+    }
 }
@@ -5,12 +5,14 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(feature = "mutex-borrows"))]
+use core::sync::atomic::AtomicUsize as Flag;
+use core::sync::atomic::Ordering;
 
 /// A bit mask used to signal the `AtomicBorrow` has an active mutable borrow.
-const UNIQUE_BIT: usize = !(usize::max_value() >> 1);
+const UNIQUE_BIT: usize = !(usize::MAX >> 1);
 
-const COUNTER_MASK: usize = usize::max_value() >> 1;
+const COUNTER_MASK: usize = usize::MAX >> 1;
 
 /// An atomic integer used to dynamicaly enforce borrowing rules
 ///
@@ -22,11 +24,13 @@ const COUNTER_MASK: usize = usize::max_value() >> 1;
 ///  - `0b0_______...` the counter isn't mut borrowed, and currently borrowed
 ///  - `0b10000000...` the counter is mut borrowed
 ///  - `0b1_______...` the counter is mut borrowed, and some other thread is trying to borrow
-pub struct AtomicBorrow(AtomicUsize);
+///
+/// With the `mutex-borrows` feature, state changes are serialized by a mutex instead.
+pub struct AtomicBorrow(Flag);
 
 impl AtomicBorrow {
     pub const fn new() -> Self {
-        Self(AtomicUsize::new(0))
+        Self(Flag::new(0))
     }
 
     pub fn borrow(&self) -> bool {
@@ -60,12 +64,74 @@ impl AtomicBorrow {
         debug_assert!(value & UNIQUE_BIT == 0, "shared release of unique borrow");
     }
 
+    /// Convert a unique borrow into a single shared borrow without releasing it in between
+    pub fn downgrade(&self) {
+        // Any concurrent attempts to borrow will roll back their increments, leaving one borrow.
+        let value = self.0.fetch_sub(UNIQUE_BIT - 1, Ordering::Release);
+        debug_assert_ne!(value & UNIQUE_BIT, 0, "downgrade of shared borrow");
+    }
+
+    /// Convert a shared borrow into a unique borrow if it's the only one outstanding
+    pub fn try_upgrade(&self) -> bool {
+        self.0
+            .compare_exchange(1, UNIQUE_BIT, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
     pub fn release_mut(&self) {
         let value = self.0.fetch_and(!UNIQUE_BIT, Ordering::Release);
         debug_assert_ne!(value & UNIQUE_BIT, 0, "unique release of shared borrow");
     }
 }
 
+/// Drop-in replacement for `AtomicUsize` which performs every operation under a lock, for users
+/// who would rather trade speed for the simplest possible synchronization
+#[cfg(feature = "mutex-borrows")]
+struct Flag(std::sync::Mutex<usize>);
+
+#[cfg(feature = "mutex-borrows")]
+impl Flag {
+    const fn new(value: usize) -> Self {
+        Self(std::sync::Mutex::new(value))
+    }
+
+    /// Replace the value with `f(value)`, returning the previous value
+    fn update(&self, f: impl FnOnce(usize) -> usize) -> usize {
+        // Updates can't panic while the lock is held, so a poisoned value is still consistent
+        let mut value = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let prev = *value;
+        *value = f(prev);
+        prev
+    }
+
+    fn fetch_add(&self, x: usize, _: Ordering) -> usize {
+        self.update(|value| value.wrapping_add(x))
+    }
+
+    fn fetch_sub(&self, x: usize, _: Ordering) -> usize {
+        self.update(|value| value.wrapping_sub(x))
+    }
+
+    fn fetch_and(&self, x: usize, _: Ordering) -> usize {
+        self.update(|value| value & x)
+    }
+
+    fn compare_exchange(
+        &self,
+        current: usize,
+        new: usize,
+        _: Ordering,
+        _: Ordering,
+    ) -> Result<usize, usize> {
+        let prev = self.update(|value| if value == current { new } else { value });
+        if prev == current {
+            Ok(prev)
+        } else {
+            Err(prev)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -73,14 +139,14 @@ mod tests {
     #[test]
     #[should_panic(expected = "immutable borrow counter overflowed")]
     fn test_borrow_counter_overflow() {
-        let counter = AtomicBorrow(AtomicUsize::new(COUNTER_MASK));
+        let counter = AtomicBorrow(Flag::new(COUNTER_MASK));
         counter.borrow();
     }
 
     #[test]
     #[should_panic(expected = "immutable borrow counter overflowed")]
     fn test_mut_borrow_counter_overflow() {
-        let counter = AtomicBorrow(AtomicUsize::new(COUNTER_MASK | UNIQUE_BIT));
+        let counter = AtomicBorrow(Flag::new(COUNTER_MASK | UNIQUE_BIT));
         counter.borrow();
     }
 
@@ -98,4 +164,62 @@ mod tests {
         counter.release_mut();
         assert!(counter.borrow());
     }
+
+    #[test]
+    fn test_downgrade_upgrade() {
+        let counter = AtomicBorrow::new();
+        assert!(counter.borrow_mut());
+        counter.downgrade();
+        assert!(!counter.borrow_mut());
+        assert!(counter.borrow());
+        assert!(!counter.try_upgrade());
+        counter.release();
+        assert!(counter.try_upgrade());
+        assert!(!counter.borrow());
+        counter.release_mut();
+        assert!(counter.borrow_mut());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn contended_borrows_stay_exclusive() {
+        use core::sync::atomic::AtomicUsize;
+        use std::sync::Arc;
+
+        let counter = Arc::new(AtomicBorrow::new());
+        // Number of threads that believe they hold a shared borrow, or `usize::MAX` for unique
+        let holders = Arc::new(AtomicUsize::new(0));
+        let threads = (0..4)
+            .map(|thread| {
+                let counter = counter.clone();
+                let holders = holders.clone();
+                std::thread::spawn(move || {
+                    for i in 0..10_000 {
+                        if (i + thread) % 3 == 0 {
+                            if counter.borrow_mut() {
+                                assert_eq!(holders.swap(usize::MAX, Ordering::SeqCst), 0);
+                                if i % 2 == 0 {
+                                    holders.store(1, Ordering::SeqCst);
+                                    counter.downgrade();
+                                    holders.fetch_sub(1, Ordering::SeqCst);
+                                    counter.release();
+                                } else {
+                                    holders.store(0, Ordering::SeqCst);
+                                    counter.release_mut();
+                                }
+                            }
+                        } else if counter.borrow() {
+                            assert_ne!(holders.fetch_add(1, Ordering::SeqCst), usize::MAX);
+                            holders.fetch_sub(1, Ordering::SeqCst);
+                            counter.release();
+                        }
+                    }
+                })
+            })
+            .collect::<std::vec::Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(counter.borrow_mut());
+    }
 }
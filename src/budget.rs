@@ -0,0 +1,97 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use core::fmt;
+
+use crate::Entity;
+
+/// Limits on the contents of a [`Frame`](crate::Frame), enforced by
+/// [`Frame::set_budget`](crate::Frame::set_budget)
+///
+/// Every limit is unbounded by default.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Budget {
+    /// Maximum number of live entities
+    pub max_entities: Option<u32>,
+    /// Maximum number of entities in any single archetype
+    pub max_per_archetype: Option<u32>,
+    /// Maximum total size, in bytes, of every component of every entity
+    pub max_component_bytes: Option<usize>,
+}
+
+/// A limit of a [`Budget`] that a spawn or insert would exceed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BudgetExceeded {
+    /// More than [`Budget::max_entities`] entities are live
+    Entities {
+        /// The limit exceeded
+        limit: u32,
+    },
+    /// More than [`Budget::max_per_archetype`] entities are in an archetype
+    Archetype {
+        /// Position of the archetype in [`Frame::archetypes`](crate::Frame::archetypes)
+        archetype: u32,
+        /// The limit exceeded
+        limit: u32,
+    },
+    /// Components occupy more than [`Budget::max_component_bytes`] bytes
+    ComponentBytes {
+        /// The limit exceeded
+        limit: usize,
+    },
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            BudgetExceeded::Entities { limit } => {
+                write!(f, "more than {} entities are live", limit)
+            }
+            BudgetExceeded::Archetype { archetype, limit } => {
+                write!(
+                    f,
+                    "archetype {} holds more than {} entities",
+                    archetype, limit
+                )
+            }
+            BudgetExceeded::ComponentBytes { limit } => {
+                write!(f, "components occupy more than {} bytes", limit)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BudgetExceeded {}
+
+/// How a [`Frame`](crate::Frame) responds to a spawn or insert exceeding its [`Budget`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BudgetAction {
+    /// Go ahead regardless, e.g. after logging the violation
+    Allow,
+    /// Despawn the oldest entity counted by the exceeded limit, then check the budget again
+    DespawnOldest,
+    /// Fail the spawn or insert, leaving the frame unchanged
+    Reject,
+}
+
+/// Decides how to respond to each limit exceeded
+pub(crate) type BudgetPolicy = Box<dyn FnMut(&BudgetExceeded) -> BudgetAction + Send + Sync>;
+
+/// State of an enforced [`Budget`]
+pub(crate) struct Budgets {
+    pub limits: Budget,
+    pub policy: BudgetPolicy,
+    /// Entities spawned while the budget was enforced, oldest first, possibly including some
+    /// that have since been despawned
+    pub order: VecDeque<Entity>,
+}
+
+impl Budgets {
+    pub fn new(limits: Budget, policy: BudgetPolicy) -> Self {
+        Self {
+            limits,
+            policy,
+            order: VecDeque::new(),
+        }
+    }
+}
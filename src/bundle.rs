@@ -21,6 +21,51 @@ pub fn bundle_satisfies_query<B: Bundle, Q: crate::Query>() -> bool {
     Q::Fetch::access(&arch).is_some()
 }
 
+/// Describes why a query isn't satisfied by a bundle, if it isn't
+///
+/// See [`Frame::explain`](crate::Frame::explain) for the meaning of the result.
+pub fn bundle_query_mismatch<B: Bundle, Q: crate::Query>() -> Option<crate::QueryMismatch> {
+    let arch = B::with_static_type_info(|info| crate::Archetype::new(info.into()));
+    crate::QueryMismatch::explain::<Q>(&arch)
+}
+
+/// Asserts at runtime that entities spawned from a bundle type are matched by one or more queries
+///
+/// Guards against spawn code and the systems consuming its entities drifting apart. This is not a
+/// compile-time check: component types can't be compared during constant evaluation, and filters
+/// such as [`Without`](crate::Without) can't be expressed as trait bounds, so the check runs when
+/// the assertion is executed. Placing it in a unit test surfaces mismatches on every `cargo test`.
+/// On failure, the panic message names the missing and excluded component types.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// type Player = (i32, bool, &'static str);
+/// runtime_assert_bundle_satisfies!(Player, (&i32, &mut bool), With<&&str, &i32>);
+/// ```
+///
+/// ```should_panic
+/// # use moss_hecs::*;
+/// runtime_assert_bundle_satisfies!((i32,), (&i32, &bool));
+/// ```
+#[macro_export]
+macro_rules! runtime_assert_bundle_satisfies {
+    ($bundle:ty, $($query:ty),+ $(,)?) => {
+        $(
+            if let ::core::option::Option::Some(mismatch) =
+                $crate::bundle_query_mismatch::<$bundle, $query>()
+            {
+                ::core::panic!(
+                    "bundle `{}` doesn't satisfy query `{}`: {}",
+                    ::core::stringify!($bundle),
+                    ::core::stringify!($query),
+                    mismatch,
+                );
+            }
+        )+
+    };
+}
+
 /// Checks if a query is satisfied by a dynamic bundle. For static bundles, see [bundle_satisfies_query].
 /// This is primarily useful for unit tests.
 pub fn dynamic_bundle_satisfies_query<B: DynamicBundle, Q: crate::Query>(b: &B) -> bool {
@@ -134,6 +179,11 @@ impl MissingComponent {
     pub fn new<T: Component>() -> Self {
         Self(type_name::<T>())
     }
+
+    /// Construct an error representing a missing component of a type known only by its `TypeId`
+    pub(crate) fn dynamic() -> Self {
+        Self("dynamically identified")
+    }
 }
 
 impl fmt::Display for MissingComponent {
@@ -0,0 +1,135 @@
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::query::Fetch;
+use crate::{Archetype, Entity, Frame, Query, QueryShared};
+
+/// Entities selected from the results of a query, cached until the components they were selected
+/// by might have changed
+///
+/// [`refresh`](Self::refresh) runs the query, keeps the entities accepted by a predicate, and
+/// stamps the result with the [`Archetype::version`] and [`Archetype::column_version`]s of every
+/// archetype matching `Q`. Until one of those changes, or a new archetype matching `Q` is
+/// created, later calls return the cached entities without running the query. Suits derived
+/// lists like "enemies in range" that are expensive to compute but rarely change.
+///
+/// The predicate must depend only on the components fetched by `Q`; if it reads anything else,
+/// call [`invalidate`](Self::invalidate) when that changes. Writes through unchecked accessors
+/// aren't detected.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// struct Position(f32);
+/// struct Enemy;
+///
+/// let mut frame = Frame::new();
+/// let near = frame.spawn((Position(1.0), Enemy));
+/// frame.spawn((Position(50.0), Enemy));
+/// let mut in_range = CachedMatches::<With<&Position, &Enemy>>::new();
+/// let check = |_, p: &Position| p.0 < 10.0;
+/// assert_eq!(in_range.refresh(&frame, check), [near]);
+/// assert!(in_range.is_valid(&frame));
+///
+/// frame.spawn((Position(2.0), true));
+/// assert!(in_range.is_valid(&frame));
+/// frame.query_mut::<&mut Position>().into_iter().for_each(|(_, p)| p.0 += 1.0);
+/// assert!(!in_range.is_valid(&frame));
+/// assert_eq!(in_range.refresh(&frame, check), [near]);
+/// ```
+pub struct CachedMatches<Q: Query> {
+    entities: Vec<Entity>,
+    /// Frame ID and archetype generation at the last refresh, or zeroes if invalidated
+    memo: (u64, u32),
+    /// Index and version of every archetype matching `Q` at the last refresh
+    archetypes: Vec<(u32, u32)>,
+    /// Version of every column borrowed by `Q`, for each archetype in `archetypes`
+    columns: Vec<Option<u32>>,
+    _marker: PhantomData<fn() -> Q>,
+}
+
+impl<Q: Query + QueryShared> CachedMatches<Q> {
+    /// Create an empty cache, to be filled by the first [`refresh`](Self::refresh)
+    pub fn new() -> Self {
+        Self {
+            entities: Vec::new(),
+            memo: (0, 0),
+            archetypes: Vec::new(),
+            columns: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Whether the cached entities are exactly those that `refresh` would select from `frame`
+    pub fn is_valid(&self, frame: &Frame) -> bool {
+        if self.memo.0 != frame.memo().0 {
+            return false;
+        }
+        let archetypes = frame.archetypes_inner();
+        if archetypes[self.memo.1 as usize..]
+            .iter()
+            .any(|x| Q::Fetch::access(x).is_some())
+        {
+            return false;
+        }
+        let mut columns = self.columns.iter();
+        self.archetypes.iter().all(|&(index, version)| {
+            let archetype = &archetypes[index as usize];
+            let mut fresh = archetype.version() == version;
+            for_each_column::<Q>(archetype, |x| fresh &= columns.next() == Some(&x));
+            fresh
+        })
+    }
+
+    /// Entities accepted by `f` among the results of `Q` in `frame`, recomputed only if the
+    /// cache isn't [valid](Self::is_valid)
+    ///
+    /// Panics if a component fetched by `Q` is uniquely borrowed.
+    pub fn refresh(
+        &mut self,
+        frame: &Frame,
+        mut f: impl FnMut(Entity, Q::Item<'_>) -> bool,
+    ) -> &[Entity] {
+        if self.is_valid(frame) {
+            return &self.entities;
+        }
+        self.entities.clear();
+        for (entity, item) in frame.query::<Q>().iter() {
+            if f(entity, item) {
+                self.entities.push(entity);
+            }
+        }
+        self.memo = frame.memo();
+        self.archetypes.clear();
+        self.columns.clear();
+        for (index, archetype) in frame.archetypes_inner().iter().enumerate() {
+            if Q::Fetch::access(archetype).is_none() {
+                continue;
+            }
+            self.archetypes.push((index as u32, archetype.version()));
+            for_each_column::<Q>(archetype, |x| self.columns.push(x));
+        }
+        &self.entities
+    }
+
+    /// Entities selected by the last [`refresh`](Self::refresh), which may be out of date
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// Force the next [`refresh`](Self::refresh) to run the query
+    pub fn invalidate(&mut self) {
+        self.memo = (0, 0);
+    }
+}
+
+impl<Q: Query + QueryShared> Default for CachedMatches<Q> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pass the version of each column of `archetype` borrowed by `Q` to `f`
+fn for_each_column<Q: Query>(archetype: &Archetype, mut f: impl FnMut(Option<u32>)) {
+    Q::Fetch::for_each_borrow(|id, _| f(archetype.column_version_dynamic(id)));
+}
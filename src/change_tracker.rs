@@ -45,7 +45,7 @@ impl<T: Component> ChangeTracker<T> {
     {
         Changes {
             tracker: self,
-            frame: frame,
+            frame,
             added: false,
             changed: false,
             removed: false,
@@ -10,17 +10,25 @@ use core::mem;
 use core::ops::Range;
 use core::ptr::{self, NonNull};
 
+use spin::Mutex;
+
 use crate::alloc::alloc::{alloc, dealloc, Layout};
+use crate::alloc::boxed::Box;
+use crate::alloc::sync::Arc;
 use crate::alloc::vec::Vec;
 use crate::archetype::TypeInfo;
 use crate::{align, DynamicBundle};
 use crate::{Bundle, Entity};
 use crate::{Component, Frame};
+use hashbrown::HashMap;
 
 /// Records operations for future application to a [`Frame`]
 ///
 /// Useful when operations cannot be applied directly due to ordering concerns or borrow checking.
 ///
+/// Running or clearing a command buffer retains its storage, so keeping one around and reusing it
+/// every tick avoids reallocating, much like an [`EntityBuilder`](crate::EntityBuilder).
+///
 /// ```
 /// # use moss_hecs::*;
 /// let mut frame = Frame::new();
@@ -37,6 +45,12 @@ pub struct CommandBuffer {
     cursor: usize,
     components: Vec<ComponentInfo>,
     ids: Vec<TypeId>,
+    /// Component types named by `remove` commands
+    removed: Vec<TypeInfo>,
+    /// Entities named by `insert_batch` commands
+    batched: Vec<Entity>,
+    /// Receives commands recorded by `spawn_with` callbacks, retained for reuse
+    nested: Option<Box<CommandBuffer>>,
 }
 
 impl CommandBuffer {
@@ -83,6 +97,16 @@ impl CommandBuffer {
     ///
     /// When inserting a single component, see [`insert_one`](Self::insert_one) for convenience.
     pub fn insert(&mut self, entity: Entity, components: impl DynamicBundle) {
+        self.insert_inner(entity, components, false);
+    }
+
+    /// Like [`insert`](Self::insert), but spawns the reserved `entity` subject to the frame's
+    /// budget
+    pub(crate) fn spawn_reserved(&mut self, entity: Entity, components: impl DynamicBundle) {
+        self.insert_inner(entity, components, true);
+    }
+
+    fn insert_inner(&mut self, entity: Entity, components: impl DynamicBundle, reserved: bool) {
         let first_component = self.components.len();
         unsafe {
             components.put(|ptr, ty| self.add_inner(ptr, ty));
@@ -90,8 +114,104 @@ impl CommandBuffer {
         self.components[first_component..].sort_unstable_by_key(|c| c.ty);
         self.cmds.push(Cmd::SpawnOrInsert(EntityIndex {
             entity: Some(entity),
+            reserved,
+            components: first_component..self.components.len(),
+        }));
+    }
+
+    /// Add a bundle from `bundles` to each of `entities` that exists, pairing them up in order
+    ///
+    /// Recorded as a single command, and applied by moving entities that share an archetype one
+    /// after another, which is much cheaper than an [`insert`](Self::insert) per entity when
+    /// deferring the same components onto many entities.
+    ///
+    /// Panics if `bundles` doesn't yield exactly one bundle per entity.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let entities = (0..100).map(|i| frame.spawn((i,))).collect::<Vec<_>>();
+    /// let mut cmd = CommandBuffer::new();
+    /// cmd.insert_batch(&entities, (0..100).map(|i| (i % 2 == 0,)));
+    /// cmd.run_on(&mut frame);
+    /// assert!(*frame.get::<&bool>(entities[42]).unwrap());
+    /// ```
+    pub fn insert_batch<B: Bundle + 'static>(
+        &mut self,
+        entities: &[Entity],
+        bundles: impl IntoIterator<Item = B>,
+    ) {
+        let first_entity = self.batched.len();
+        let first_component = self.components.len();
+        let mut bundles = bundles.into_iter();
+        for &entity in entities {
+            let bundle = bundles
+                .next()
+                .expect("fewer bundles than entities passed to insert_batch");
+            let start = self.components.len();
+            unsafe {
+                bundle.put(|ptr, ty| self.add_inner(ptr, ty));
+            }
+            self.components[start..].sort_unstable_by_key(|c| c.ty);
+            self.batched.push(entity);
+        }
+        self.cmds.push(Cmd::InsertBatch(BatchIndex {
+            key: TypeId::of::<B>(),
+            entities: first_entity..self.batched.len(),
+            components: first_component..self.components.len(),
+            reserved: None,
+        }));
+        assert!(
+            bundles.next().is_none(),
+            "more bundles than entities passed to insert_batch"
+        );
+    }
+
+    /// Reserve `n` entities from `frame` and record spawning each with the bundle `f` returns for
+    /// its index, returning the reserved handles
+    ///
+    /// The handles are usable immediately, e.g. to record further commands referencing the new
+    /// entities, and the entities are spawned together like an [`insert_batch`](Self::insert_batch)
+    /// when the buffer is run on `frame`. If the buffer is instead cleared or dropped, the reserved
+    /// entities are despawned the next time `frame` is [`flush`](Frame::flush)ed.
+    ///
+    /// Panics if `frame` allocates IDs from a [`SharedAllocator`](crate::SharedAllocator).
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// struct Projectile { speed: f32 }
+    ///
+    /// let mut frame = Frame::new();
+    /// let mut cmd = CommandBuffer::new();
+    /// let volley = cmd.spawn_n(&frame, 8, |i| (Projectile { speed: i as f32 },)).to_vec();
+    /// cmd.run_on(&mut frame);
+    /// assert_eq!(frame.get::<&Projectile>(volley[3]).unwrap().speed, 3.0);
+    /// ```
+    pub fn spawn_n<B: Bundle + 'static>(
+        &mut self,
+        frame: &Frame,
+        n: u32,
+        mut f: impl FnMut(u32) -> B,
+    ) -> &[Entity] {
+        let first_entity = self.batched.len();
+        let first_component = self.components.len();
+        for (i, entity) in frame.reserve_entities(n).enumerate() {
+            let start = self.components.len();
+            unsafe {
+                f(i as u32).put(|ptr, ty| self.add_inner(ptr, ty));
+            }
+            self.components[start..].sort_unstable_by_key(|c| c.ty);
+            self.batched.push(entity);
+        }
+        self.cmds.push(Cmd::InsertBatch(BatchIndex {
+            key: TypeId::of::<B>(),
+            entities: first_entity..self.batched.len(),
             components: first_component..self.components.len(),
+            reserved: Some(frame.abandoned_reservations()),
         }));
+        &self.batched[first_entity..]
     }
 
     /// Add `component` to `entity`, if the entity exists
@@ -108,9 +228,12 @@ impl CommandBuffer {
         fn remove_bundle_and_ignore_result<T: Bundle + 'static>(frame: &mut Frame, ents: Entity) {
             let _ = frame.remove::<T>(ents);
         }
+        let first_type = self.removed.len();
+        T::with_static_type_info(|types| self.removed.extend_from_slice(types));
         self.cmds.push(Cmd::Remove(RemovedComps {
             remove: remove_bundle_and_ignore_result::<T>,
             entity: ent,
+            types: first_type..self.removed.len(),
         }));
     }
 
@@ -121,49 +244,126 @@ impl CommandBuffer {
         self.remove::<(T,)>(ent);
     }
 
+    /// Remove the component of the type identified by `ty` from `entity` if it exists
+    ///
+    /// See [`Frame::remove_dynamic`]. Recorded as a [`CommandKind::Remove`] without types, whose
+    /// [`RecordedCommand::removed_dynamic`] identifies the component type.
+    pub fn remove_dynamic(&mut self, entity: Entity, ty: TypeId) {
+        self.cmds.push(Cmd::RemoveDynamic(entity, ty));
+    }
+
     /// Despawn `entity` from Frame
     pub fn despawn(&mut self, entity: Entity) {
         self.cmds.push(Cmd::Despawn(entity));
     }
 
+    /// Drop every component of `entity`, keeping it alive
+    ///
+    /// See [`Frame::clear_entity`].
+    pub fn clear_entity(&mut self, entity: Entity) {
+        self.cmds.push(Cmd::Clear(entity));
+    }
+
     /// Spawn a new entity with `components`
     ///
     /// If the [`Entity`] is needed immediately, consider combining [`Frame::reserve_entity`] with
     /// [`insert`](CommandBuffer::insert) instead.
     pub fn spawn(&mut self, components: impl DynamicBundle) {
+        let entity = self.record_spawn(components);
+        self.cmds.push(Cmd::SpawnOrInsert(entity));
+    }
+
+    /// Spawn a new entity with `components`, then record further commands referencing it
+    ///
+    /// `f` is invoked when the buffer is run, immediately after the entity is spawned, with the
+    /// new entity's handle and an empty buffer. Commands recorded into that buffer are applied
+    /// before any command recorded after this one. This allows hierarchies to be built without
+    /// reserving handles up front.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// struct ChildOf(Entity);
+    ///
+    /// let mut frame = Frame::new();
+    /// let mut cmd = CommandBuffer::new();
+    /// cmd.spawn_with(("parent",), |parent, cmd| {
+    ///     cmd.spawn((ChildOf(parent), "child"));
+    /// });
+    /// cmd.run_on(&mut frame);
+    /// let (child, &ChildOf(parent)) = frame
+    ///     .query_mut::<&ChildOf>()
+    ///     .into_iter()
+    ///     .next()
+    ///     .unwrap();
+    /// assert_eq!(*frame.get::<&&str>(parent).unwrap(), "parent");
+    /// assert_eq!(*frame.get::<&&str>(child).unwrap(), "child");
+    /// ```
+    pub fn spawn_with(
+        &mut self,
+        components: impl DynamicBundle,
+        f: impl FnOnce(Entity, &mut CommandBuffer) + Send + Sync + 'static,
+    ) {
+        let entity = self.record_spawn(components);
+        self.cmds.push(Cmd::SpawnWith(entity, Box::new(f)));
+    }
+
+    fn record_spawn(&mut self, components: impl DynamicBundle) -> EntityIndex {
         let first_component = self.components.len();
         unsafe {
             components.put(|ptr, ty| self.add_inner(ptr, ty));
         }
         self.components[first_component..].sort_unstable_by_key(|c| c.ty);
-        self.cmds.push(Cmd::SpawnOrInsert(EntityIndex {
+        EntityIndex {
             entity: None,
+            reserved: false,
             components: first_component..self.components.len(),
-        }));
+        }
     }
 
     /// Run recorded commands on `frame`, clearing the command buffer
+    ///
+    /// Spawns and inserts rejected by the policy passed to [`Frame::set_budget`] are dropped, along
+    /// with the commands a [`spawn_with`](Self::spawn_with) callback would have recorded.
     pub fn run_on(&mut self, frame: &mut Frame) {
         for i in 0..self.cmds.len() {
             match mem::replace(&mut self.cmds[i], Cmd::Despawn(Entity::DANGLING)) {
-                Cmd::SpawnOrInsert(entity) => {
-                    let components = self.build(entity.components);
-                    match entity.entity {
+                Cmd::SpawnOrInsert(index) => {
+                    let components = self.build(index.components);
+                    match index.entity {
+                        Some(entity) if index.reserved => frame.spawn_reserved(entity, components),
                         Some(entity) => {
                             // If `entity` no longer exists, quietly drop the components.
-                            let _ = frame.insert(entity, components);
+                            let _ = frame.insert_within_budget(entity, components);
                         }
                         None => {
-                            frame.spawn(components);
+                            let _ = frame.try_spawn(components);
                         }
                     }
                 }
+                Cmd::SpawnWith(entity, f) => {
+                    let components = self.build(entity.components);
+                    let Ok(entity) = frame.try_spawn(components) else {
+                        continue;
+                    };
+                    let mut cmd = self.nested.take().unwrap_or_default();
+                    f(entity, &mut cmd);
+                    cmd.run_on(frame);
+                    self.nested = Some(cmd);
+                }
+                Cmd::InsertBatch(batch) => self.run_batch(frame, batch),
                 Cmd::Remove(remove) => {
                     (remove.remove)(frame, remove.entity);
                 }
+                Cmd::RemoveDynamic(entity, ty) => {
+                    let _ = frame.remove_dynamic(entity, ty);
+                }
                 Cmd::Despawn(entity) => {
                     let _ = frame.despawn(entity);
                 }
+                Cmd::Clear(entity) => {
+                    let _ = frame.clear_entity(entity);
+                }
             }
         }
         // Wipe out component references so `clear` doesn't try to double-free
@@ -172,6 +372,35 @@ impl CommandBuffer {
         self.clear();
     }
 
+    /// Apply an `insert_batch` command, grouping entities by archetype so each group shares one
+    /// lookup of the archetype it moves to
+    fn run_batch(&mut self, frame: &mut Frame, batch: BatchIndex) {
+        let count = batch.entities.len();
+        if count == 0 {
+            return;
+        }
+        let per_entity = batch.components.len() / count;
+        let meta = frame.entities_meta();
+        let mut order = (0..count).collect::<Vec<_>>();
+        // Stable, so repeated entities receive their bundles in the order recorded
+        order.sort_by_key(|&i| {
+            let entity = self.batched[batch.entities.start + i];
+            meta.get(entity.id() as usize).map(|x| x.location.archetype)
+        });
+        for i in order {
+            let entity = self.batched[batch.entities.start + i];
+            let start = batch.components.start + i * per_entity;
+            let mut components = self.build(start..start + per_entity);
+            components.key = Some(batch.key);
+            if batch.reserved.is_some() {
+                frame.spawn_reserved(entity, components);
+            } else {
+                // If `entity` no longer exists, quietly drop the components.
+                let _ = frame.insert_within_budget(entity, components);
+            }
+        }
+    }
+
     fn build(&mut self, components: Range<usize>) -> RecordedEntity<'_> {
         self.ids.clear();
         self.ids.extend(
@@ -182,12 +411,171 @@ impl CommandBuffer {
         RecordedEntity {
             cmd: self,
             components,
+            key: None,
+        }
+    }
+
+    /// Iterate over the recorded commands in the order they'll be applied, without applying them
+    ///
+    /// Each entity of an [`insert_batch`](Self::insert_batch) is listed as a separate insert.
+    /// Commands recorded by [`spawn_with`](Self::spawn_with) callbacks aren't known until the
+    /// buffer is run, so aren't included.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((1,));
+    /// let mut cmd = CommandBuffer::new();
+    /// cmd.insert(e, (true, 2.0f32));
+    /// cmd.despawn(e);
+    /// let mut recorded = cmd.iter();
+    /// let insert = recorded.next().unwrap();
+    /// assert_eq!((insert.kind(), insert.entity()), (CommandKind::Insert, Some(e)));
+    /// assert_eq!(insert.types().count(), 2);
+    /// assert_eq!(recorded.next().unwrap().kind(), CommandKind::Despawn);
+    /// assert!(recorded.next().is_none());
+    /// ```
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = RecordedCommand<'_>> + '_ {
+        let mut commands = Vec::with_capacity(self.cmds.len());
+        for cmd in &self.cmds {
+            let mut removed_dynamic = None;
+            let (kind, entity, added, removed) = match *cmd {
+                Cmd::SpawnOrInsert(ref index) => (
+                    match index.entity {
+                        Some(_) => CommandKind::Insert,
+                        None => CommandKind::Spawn,
+                    },
+                    index.entity,
+                    &self.components[index.components.clone()],
+                    &[][..],
+                ),
+                Cmd::SpawnWith(ref index, _) => (
+                    CommandKind::SpawnWith,
+                    None,
+                    &self.components[index.components.clone()],
+                    &[][..],
+                ),
+                Cmd::InsertBatch(ref batch) => {
+                    // Listed as an insert per entity
+                    let components = &self.components[batch.components.clone()];
+                    let per_entity = components.len() / batch.entities.len().max(1);
+                    let entities = &self.batched[batch.entities.clone()];
+                    commands.extend(entities.iter().enumerate().map(|(i, &entity)| {
+                        RecordedCommand {
+                            kind: CommandKind::Insert,
+                            entity: Some(entity),
+                            added: &components[i * per_entity..(i + 1) * per_entity],
+                            removed: &[],
+                            removed_dynamic: None,
+                        }
+                    }));
+                    continue;
+                }
+                Cmd::Remove(ref remove) => (
+                    CommandKind::Remove,
+                    Some(remove.entity),
+                    &[][..],
+                    &self.removed[remove.types.clone()],
+                ),
+                Cmd::RemoveDynamic(entity, ty) => {
+                    removed_dynamic = Some(ty);
+                    (CommandKind::Remove, Some(entity), &[][..], &[][..])
+                }
+                Cmd::Despawn(entity) => (CommandKind::Despawn, Some(entity), &[][..], &[][..]),
+                Cmd::Clear(entity) => (CommandKind::Clear, Some(entity), &[][..], &[][..]),
+            };
+            commands.push(RecordedCommand {
+                kind,
+                entity,
+                added,
+                removed,
+                removed_dynamic,
+            });
+        }
+        commands.into_iter()
+    }
+
+    /// Predict which recorded commands would fail if the buffer were run on `frame` now
+    ///
+    /// Returns the position of each such command in [`iter`](Self::iter) alongside the reason it
+    /// would fail. Accounts for the effects of earlier commands in the buffer. Failing commands are
+    /// otherwise quietly ignored by [`run_on`](Self::run_on).
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((1,));
+    /// let mut cmd = CommandBuffer::new();
+    /// cmd.remove_one::<i32>(e);
+    /// cmd.remove_one::<i32>(e);
+    /// cmd.despawn(e);
+    /// cmd.insert_one(e, true);
+    /// let failures = cmd.dry_run(&frame);
+    /// assert_eq!(failures.len(), 2);
+    /// assert!(matches!(failures[0], (1, CommandFailure::MissingComponent(ty)) if ty == TypeInfo::of::<i32>()));
+    /// assert!(matches!(failures[1], (3, CommandFailure::NoSuchEntity)));
+    /// ```
+    pub fn dry_run(&self, frame: &Frame) -> Vec<(usize, CommandFailure)> {
+        // Component types of entities affected by earlier commands, or `None` if despawned
+        let mut touched = HashMap::<Entity, Option<Vec<TypeId>>>::new();
+        let mut failures = Vec::new();
+        for (index, cmd) in self.iter().enumerate() {
+            let Some(entity) = cmd.entity() else {
+                continue;
+            };
+            let types = touched.entry(entity).or_insert_with(|| {
+                let entity = frame.entity(entity).ok()?;
+                Some(entity.component_types().collect())
+            });
+            let Some(current) = types else {
+                failures.push((index, CommandFailure::NoSuchEntity));
+                continue;
+            };
+            match cmd.kind() {
+                CommandKind::Insert => {
+                    for ty in cmd.added {
+                        if !current.contains(&ty.ty.id()) {
+                            current.push(ty.ty.id());
+                        }
+                    }
+                }
+                CommandKind::Remove => match cmd.removed_dynamic {
+                    Some(ty) if !current.contains(&ty) => {
+                        failures.push((index, CommandFailure::MissingDynamicComponent(ty)))
+                    }
+                    Some(ty) => current.retain(|&id| id != ty),
+                    None => match cmd.removed.iter().find(|ty| !current.contains(&ty.id())) {
+                        Some(&ty) => failures.push((index, CommandFailure::MissingComponent(ty))),
+                        None => current.retain(|id| cmd.removed.iter().all(|ty| ty.id() != *id)),
+                    },
+                },
+                CommandKind::Despawn => *types = None,
+                CommandKind::Clear => current.clear(),
+                CommandKind::Spawn | CommandKind::SpawnWith => {}
+            }
         }
+        failures
     }
 
     /// Drop all recorded commands
     pub fn clear(&mut self) {
+        for cmd in &self.cmds {
+            if let Cmd::InsertBatch(BatchIndex {
+                reserved: Some(ref abandoned),
+                ref entities,
+                ..
+            }) = *cmd
+            {
+                abandoned
+                    .lock()
+                    .extend_from_slice(&self.batched[entities.clone()]);
+            }
+        }
         self.ids.clear();
+        self.removed.clear();
+        self.batched.clear();
         self.cursor = 0;
         for info in self.components.drain(..) {
             unsafe {
@@ -198,6 +586,79 @@ impl CommandBuffer {
     }
 }
 
+/// Structural changes to a [`Frame`] recorded while it's shared, applied by
+/// [`Frame::apply_deferred`]
+///
+/// Obtained from [`Frame::defer`]. Each operation takes effect only once they're applied, and
+/// operations on entities that no longer exist by then are quietly ignored.
+#[derive(Copy, Clone)]
+pub struct DeferredOps<'a> {
+    frame: &'a Frame,
+}
+
+impl<'a> DeferredOps<'a> {
+    pub(crate) fn new(frame: &'a Frame) -> Self {
+        Self { frame }
+    }
+
+    /// Spawn a new entity with `components`
+    ///
+    /// The returned handle is reserved immediately, and may be used in further operations.
+    pub fn spawn(&self, components: impl DynamicBundle) -> Entity {
+        let entity = self.frame.reserve_entity();
+        self.frame
+            .deferred_commands()
+            .lock()
+            .spawn_reserved(entity, components);
+        entity
+    }
+
+    /// Add components from `bundle` to `entity`
+    ///
+    /// See [`CommandBuffer::insert`].
+    pub fn insert(&self, entity: Entity, components: impl DynamicBundle) {
+        self.frame
+            .deferred_commands()
+            .lock()
+            .insert(entity, components);
+    }
+
+    /// Add `component` to `entity`
+    pub fn insert_one(&self, entity: Entity, component: impl Component) {
+        self.insert(entity, (component,));
+    }
+
+    /// Remove components from `entity`
+    ///
+    /// See [`CommandBuffer::remove`].
+    pub fn remove<T: Bundle + 'static>(&self, entity: Entity) {
+        self.frame.deferred_commands().lock().remove::<T>(entity);
+    }
+
+    /// Remove a component from `entity`
+    pub fn remove_one<T: Component>(&self, entity: Entity) {
+        self.remove::<(T,)>(entity);
+    }
+
+    /// Remove the component of the type identified by `ty` from `entity`
+    pub fn remove_dynamic(&self, entity: Entity, ty: TypeId) {
+        self.frame
+            .deferred_commands()
+            .lock()
+            .remove_dynamic(entity, ty);
+    }
+
+    /// Despawn `entity`
+    pub fn despawn(&self, entity: Entity) {
+        self.frame.deferred_commands().lock().despawn(entity);
+    }
+
+    /// Drop every component of `entity`, keeping it alive
+    pub fn clear_entity(&self, entity: Entity) {
+        self.frame.deferred_commands().lock().clear_entity(entity);
+    }
+}
+
 unsafe impl Send for CommandBuffer {}
 unsafe impl Sync for CommandBuffer {}
 
@@ -222,6 +683,9 @@ impl Default for CommandBuffer {
             cursor: 0,
             components: Vec::new(),
             ids: Vec::new(),
+            removed: Vec::new(),
+            batched: Vec::new(),
+            nested: None,
         }
     }
 }
@@ -231,9 +695,15 @@ impl Default for CommandBuffer {
 struct RecordedEntity<'a> {
     cmd: &'a mut CommandBuffer,
     components: Range<usize>,
+    /// Identifies the component types, if they're those of a static bundle
+    key: Option<TypeId>,
 }
 
 unsafe impl DynamicBundle for RecordedEntity<'_> {
+    fn key(&self) -> Option<TypeId> {
+        self.key
+    }
+
     fn with_ids<T>(&self, f: impl FnOnce(&[TypeId]) -> T) -> T {
         f(&self.cmd.ids)
     }
@@ -277,6 +747,8 @@ struct ComponentInfo {
 /// Data of buffered 'entity' and its relative position in component data
 struct EntityIndex {
     entity: Option<Entity>,
+    /// Whether `entity` was reserved to be spawned by this command
+    reserved: bool,
     // Position of this entity's components in `CommandBuffer::info`
     //
     // We could store a single start point for the first initialized entity, rather than one for
@@ -284,17 +756,108 @@ struct EntityIndex {
     components: Range<usize>,
 }
 
+/// Data of buffered `insert_batch` commands
+struct BatchIndex {
+    /// `TypeId` of the bundle type
+    key: TypeId,
+    // Position of the entities in `CommandBuffer::batched`
+    entities: Range<usize>,
+    // Position of the components in `CommandBuffer::components`, with an equal number per entity
+    components: Range<usize>,
+    /// Where to release the entities if they were reserved by `spawn_n` and the batch is discarded
+    reserved: Option<Arc<Mutex<Vec<Entity>>>>,
+}
+
 /// Data required to remove components from 'entity'
 struct RemovedComps {
     remove: fn(&mut Frame, Entity),
     entity: Entity,
+    // Position of the removed types in `CommandBuffer::removed`
+    types: Range<usize>,
 }
 
+/// Records commands referencing a newly spawned entity
+type ChildCommands = Box<dyn FnOnce(Entity, &mut CommandBuffer) + Send + Sync>;
+
 /// A buffered command
 enum Cmd {
     SpawnOrInsert(EntityIndex),
+    SpawnWith(EntityIndex, ChildCommands),
+    InsertBatch(BatchIndex),
     Remove(RemovedComps),
+    RemoveDynamic(Entity, TypeId),
     Despawn(Entity),
+    Clear(Entity),
+}
+
+/// Operation performed by a command recorded in a [`CommandBuffer`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CommandKind {
+    /// Recorded by [`CommandBuffer::spawn`]
+    Spawn,
+    /// Recorded by [`CommandBuffer::spawn_with`]
+    SpawnWith,
+    /// Recorded by [`CommandBuffer::insert`]
+    Insert,
+    /// Recorded by [`CommandBuffer::remove`]
+    Remove,
+    /// Recorded by [`CommandBuffer::despawn`]
+    Despawn,
+    /// Recorded by [`CommandBuffer::clear_entity`]
+    Clear,
+}
+
+/// A command recorded in a [`CommandBuffer`], obtained from [`CommandBuffer::iter`]
+#[derive(Copy, Clone)]
+pub struct RecordedCommand<'a> {
+    kind: CommandKind,
+    entity: Option<Entity>,
+    added: &'a [ComponentInfo],
+    removed: &'a [TypeInfo],
+    /// Type removed by [`CommandBuffer::remove_dynamic`], whose `TypeInfo` isn't known
+    removed_dynamic: Option<TypeId>,
+}
+
+impl<'a> RecordedCommand<'a> {
+    /// The operation performed
+    pub fn kind(&self) -> CommandKind {
+        self.kind
+    }
+
+    /// The entity operated on, or `None` for spawns
+    pub fn entity(&self) -> Option<Entity> {
+        self.entity
+    }
+
+    /// Types of the components added or removed
+    ///
+    /// Empty for [`CommandBuffer::remove_dynamic`] commands; see
+    /// [`removed_dynamic`](Self::removed_dynamic).
+    pub fn types(&self) -> impl ExactSizeIterator<Item = TypeInfo> + 'a {
+        let added = self.added;
+        let removed = self.removed;
+        // At most one of these is non-empty
+        (0..added.len() + removed.len()).map(move |i| match added.get(i) {
+            Some(info) => info.ty,
+            None => removed[i - added.len()],
+        })
+    }
+
+    /// `TypeId` of the component removed by a [`CommandBuffer::remove_dynamic`] command
+    pub fn removed_dynamic(&self) -> Option<TypeId> {
+        self.removed_dynamic
+    }
+}
+
+/// Reason a recorded command would fail, as predicted by [`CommandBuffer::dry_run`]
+#[derive(Debug, Copy, Clone)]
+pub enum CommandFailure {
+    /// The entity wouldn't exist when the command is applied
+    NoSuchEntity,
+    /// The entity wouldn't have a component to be removed, so none would be
+    MissingComponent(TypeInfo),
+    /// As `MissingComponent`, for a component removed by [`CommandBuffer::remove_dynamic`]
+    MissingDynamicComponent(TypeId),
 }
 
 #[cfg(test)]
@@ -367,4 +930,184 @@ mod tests {
         cmd.run_on(&mut frame);
         assert_eq!(*frame.get::<&i32>(a).unwrap(), 42);
     }
+
+    #[test]
+    fn dry_run_tracks_earlier_commands() {
+        let mut frame = Frame::new();
+        let a = frame.spawn((1i32, true));
+        let reserved = frame.reserve_entity();
+        let mut cmd = CommandBuffer::new();
+        cmd.spawn((1u8,));
+        cmd.remove::<(i32, bool)>(a);
+        cmd.remove_one::<bool>(a);
+        cmd.insert_one(a, false);
+        cmd.remove_one::<bool>(a);
+        cmd.insert_one(reserved, 2i32);
+        cmd.remove_one::<i32>(reserved);
+        cmd.despawn(reserved);
+        cmd.despawn(reserved);
+
+        let kinds = cmd.iter().map(|c| c.kind()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds[..3],
+            [CommandKind::Spawn, CommandKind::Remove, CommandKind::Remove]
+        );
+        assert_eq!(cmd.iter().nth(1).unwrap().types().len(), 2);
+
+        let failures = cmd.dry_run(&frame);
+        assert_eq!(failures.len(), 2);
+        assert!(matches!(
+            failures[0],
+            (2, CommandFailure::MissingComponent(ty)) if ty == TypeInfo::of::<bool>()
+        ));
+        assert!(matches!(failures[1], (8, CommandFailure::NoSuchEntity)));
+
+        // Dry runs leave the buffer intact
+        cmd.run_on(&mut frame);
+        assert_eq!(frame.len(), 2);
+        assert!(!frame.satisfies::<&bool>(a).unwrap());
+    }
+
+    #[test]
+    fn dry_run_remove_dynamic() {
+        let mut frame = Frame::new();
+        let a = frame.spawn((1i32, true));
+        let dead = frame.spawn(());
+        frame.despawn(dead).unwrap();
+        let mut cmd = CommandBuffer::new();
+        cmd.remove_dynamic(a, TypeId::of::<bool>());
+        cmd.remove_dynamic(a, TypeId::of::<bool>());
+        cmd.remove_one::<bool>(a);
+        cmd.insert_one(a, 'x');
+        cmd.remove_dynamic(a, TypeId::of::<char>());
+        cmd.remove_dynamic(dead, TypeId::of::<i32>());
+
+        let recorded = cmd.iter().nth(4).unwrap();
+        assert_eq!(recorded.kind(), CommandKind::Remove);
+        assert_eq!(recorded.types().len(), 0);
+        assert_eq!(recorded.removed_dynamic(), Some(TypeId::of::<char>()));
+        assert_eq!(cmd.iter().nth(2).unwrap().removed_dynamic(), None);
+
+        let failures = cmd.dry_run(&frame);
+        assert_eq!(failures.len(), 3);
+        assert!(matches!(
+            failures[0],
+            (1, CommandFailure::MissingDynamicComponent(ty)) if ty == TypeId::of::<bool>()
+        ));
+        assert!(matches!(
+            failures[1],
+            (2, CommandFailure::MissingComponent(ty)) if ty == TypeInfo::of::<bool>()
+        ));
+        assert!(matches!(failures[2], (5, CommandFailure::NoSuchEntity)));
+
+        cmd.run_on(&mut frame);
+        assert_eq!(
+            frame
+                .entity(a)
+                .unwrap()
+                .component_types()
+                .collect::<Vec<_>>(),
+            [TypeId::of::<i32>()]
+        );
+    }
+
+    #[test]
+    fn insert_batch_across_archetypes() {
+        let mut frame = Frame::new();
+        let a = frame.spawn((1i32,));
+        let b = frame.spawn((2i32, 'b'));
+        let c = frame.spawn((3i32,));
+        let dead = frame.spawn(());
+        frame.despawn(dead).unwrap();
+
+        let mut cmd = CommandBuffer::new();
+        cmd.insert_batch(&[a, b, dead, c, a], [(10u8,), (20,), (30,), (40,), (50,)]);
+        assert_eq!(cmd.iter().len(), 5);
+        assert!(cmd
+            .iter()
+            .all(|x| x.kind() == CommandKind::Insert && x.types().len() == 1));
+        let failures = cmd.dry_run(&frame);
+        assert!(matches!(failures[..], [(2, CommandFailure::NoSuchEntity)]));
+
+        cmd.run_on(&mut frame);
+        assert_eq!(*frame.get::<&u8>(a).unwrap(), 50);
+        assert_eq!(*frame.get::<&u8>(b).unwrap(), 20);
+        assert_eq!(*frame.get::<&u8>(c).unwrap(), 40);
+        assert_eq!(*frame.get::<&char>(b).unwrap(), 'b');
+        assert_eq!(frame.len(), 3);
+    }
+
+    #[test]
+    fn spawn_n() {
+        let mut frame = Frame::new();
+        let existing = frame.spawn((0u8,));
+        let mut cmd = CommandBuffer::new();
+        let first = cmd.spawn_n(&frame, 3, |i| (i, i % 2 == 0)).to_vec();
+        let second = cmd.spawn_n(&frame, 2, |i| (i as u8,)).to_vec();
+        cmd.insert_one(first[1], 'x');
+        assert_eq!(cmd.iter().len(), 6);
+        assert!(first.iter().chain(&second).all(|&e| e != existing));
+
+        cmd.run_on(&mut frame);
+        assert_eq!(frame.len(), 6);
+        for (i, &e) in first.iter().enumerate() {
+            assert_eq!(*frame.get::<&u32>(e).unwrap(), i as u32);
+        }
+        assert!(*frame.get::<&bool>(first[2]).unwrap());
+        assert_eq!(*frame.get::<&char>(first[1]).unwrap(), 'x');
+        assert_eq!(*frame.get::<&u8>(second[1]).unwrap(), 1);
+    }
+
+    #[test]
+    fn spawn_n_discarded() {
+        let mut frame = Frame::new();
+        let mut cmd = CommandBuffer::new();
+        let cleared = cmd.spawn_n(&frame, 2, |i| (i,)).to_vec();
+        cmd.clear();
+        frame.flush();
+        assert_eq!(frame.len(), 0);
+        assert!(cleared.iter().all(|&e| !frame.contains(e)));
+
+        let mut cmd = CommandBuffer::new();
+        let dropped = cmd.spawn_n(&frame, 3, |i| (i,)).to_vec();
+        frame.flush();
+        assert_eq!(frame.len(), 3);
+        drop(cmd);
+        let kept = frame.spawn((7u32,));
+        assert_eq!(frame.len(), 1);
+        assert!(dropped.iter().all(|&e| !frame.contains(e)));
+
+        // Buffers that were run leave their entities alone
+        let mut cmd = CommandBuffer::new();
+        let run = cmd.spawn_n(&frame, 2, |i| (i,)).to_vec();
+        cmd.run_on(&mut frame);
+        drop(cmd);
+        frame.flush();
+        assert_eq!(frame.len(), 3);
+        assert!(run.iter().chain(Some(&kept)).all(|&e| frame.contains(e)));
+    }
+
+    #[test]
+    fn spawn_with_nested() {
+        struct ChildOf(Entity);
+
+        let mut frame = Frame::new();
+        let mut cmd = CommandBuffer::new();
+        cmd.spawn_with((0u32,), |root, cmd| {
+            cmd.spawn_with((1u32, ChildOf(root)), |child, cmd| {
+                cmd.spawn((2u32, ChildOf(child)));
+            });
+        });
+        cmd.spawn((3u32,));
+        cmd.run_on(&mut frame);
+        assert_eq!(frame.len(), 4);
+        for (_, (&depth, parent)) in frame.query::<(&u32, Option<&ChildOf>)>().iter() {
+            match parent {
+                Some(&ChildOf(parent)) => {
+                    assert_eq!(*frame.get::<&u32>(parent).unwrap() + 1, depth);
+                }
+                None => assert!(depth == 0 || depth == 3),
+            }
+        }
+    }
 }
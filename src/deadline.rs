@@ -0,0 +1,135 @@
+use core::time::Duration;
+
+#[cfg(all(feature = "std", debug_assertions))]
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(all(feature = "std", debug_assertions))]
+use spin::Mutex;
+#[cfg(all(feature = "std", debug_assertions))]
+use std::time::Instant;
+
+/// A [`Ref`](crate::Ref) or [`RefMut`](crate::RefMut) that was held for longer than the deadline
+/// set with [`set_borrow_deadline`]
+#[derive(Debug, Copy, Clone)]
+pub struct LongBorrow {
+    /// Name of the borrowed component type
+    pub type_name: &'static str,
+    /// Whether the borrow was unique
+    pub unique: bool,
+    /// How long the borrow was held
+    pub held: Duration,
+}
+
+/// Report [`Ref`](crate::Ref)s and [`RefMut`](crate::RefMut)s held for longer than `deadline`, or
+/// stop doing so if `None`
+///
+/// Long-lived borrows, e.g. guards accidentally kept across a frame boundary, often surface only as
+/// sporadic "already borrowed" panics elsewhere. Overruns are reported to the handler set with
+/// [`set_long_borrow_handler`], which by default prints a warning to stderr.
+///
+/// Applies to every [`Frame`](crate::Frame). Has no effect in release builds or without the `std`
+/// feature.
+pub fn set_borrow_deadline(deadline: Option<Duration>) {
+    #[cfg(all(feature = "std", debug_assertions))]
+    {
+        CONFIG.lock().deadline = deadline;
+        ENABLED.store(deadline.is_some(), Ordering::Relaxed);
+    }
+    #[cfg(not(all(feature = "std", debug_assertions)))]
+    let _ = deadline;
+}
+
+/// Call `handler` for each borrow that overruns the deadline set with [`set_borrow_deadline`]
+pub fn set_long_borrow_handler(handler: fn(&LongBorrow)) {
+    #[cfg(all(feature = "std", debug_assertions))]
+    {
+        CONFIG.lock().handler = handler;
+    }
+    #[cfg(not(all(feature = "std", debug_assertions)))]
+    let _ = handler;
+}
+
+#[cfg(all(feature = "std", debug_assertions))]
+struct Config {
+    deadline: Option<Duration>,
+    handler: fn(&LongBorrow),
+}
+
+#[cfg(all(feature = "std", debug_assertions))]
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(all(feature = "std", debug_assertions))]
+static CONFIG: Mutex<Config> = Mutex::new(Config {
+    deadline: None,
+    handler: warn,
+});
+
+#[cfg(all(feature = "std", debug_assertions))]
+fn warn(borrow: &LongBorrow) {
+    std::eprintln!(
+        "warning: {} borrow of {} held for {:?}",
+        if borrow.unique { "unique" } else { "shared" },
+        borrow.type_name,
+        borrow.held
+    );
+}
+
+/// Measures how long a component borrow is held, reporting it on drop if it overran the deadline
+///
+/// The default timer measures nothing.
+#[derive(Default)]
+pub(crate) struct BorrowTimer {
+    #[cfg(all(feature = "std", debug_assertions))]
+    started: Option<(Instant, &'static str, bool)>,
+}
+
+impl BorrowTimer {
+    #[allow(unused_variables)]
+    pub fn start(type_name: &'static str, unique: bool) -> Self {
+        Self {
+            #[cfg(all(feature = "std", debug_assertions))]
+            started: if ENABLED.load(Ordering::Relaxed) {
+                Some((Instant::now(), type_name, unique))
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Move the measurement into a new timer, e.g. when a borrow is upgraded or downgraded
+    pub fn take(&mut self) -> Self {
+        Self {
+            #[cfg(all(feature = "std", debug_assertions))]
+            started: self.started.take(),
+        }
+    }
+
+    /// Start timing another borrow of the same component
+    pub fn restart(&self) -> Self {
+        Self {
+            #[cfg(all(feature = "std", debug_assertions))]
+            started: self
+                .started
+                .map(|(_, type_name, unique)| (Instant::now(), type_name, unique)),
+        }
+    }
+}
+
+#[cfg(all(feature = "std", debug_assertions))]
+impl Drop for BorrowTimer {
+    fn drop(&mut self) {
+        let Some((start, type_name, unique)) = self.started else {
+            return;
+        };
+        let held = start.elapsed();
+        let config = CONFIG.lock();
+        if config.deadline.map_or(false, |x| held > x) {
+            let handler = config.handler;
+            drop(config);
+            handler(&LongBorrow {
+                type_name,
+                unique,
+                held,
+            });
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use core::ptr::NonNull;
+
+use crate::archetype::TypeInfo;
+use crate::{Archetype, DynamicComponent};
+
+/// Type-erased shared access to a column of components
+///
+/// Obtained from [`Archetype::dynamic_column`]. Lets code that only learns which component types
+/// to operate on at runtime, such as a visual scripting interpreter, walk columns without
+/// monomorphizing over every type. Operations on individual components, such as formatting or
+/// validation, can be looked up in a [`ComponentRegistry`](crate::ComponentRegistry) and applied
+/// to the [`DynamicComponent`]s yielded by [`get_raw`](Self::get_raw).
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// # use std::any::TypeId;
+/// let mut registry = ComponentRegistry::new();
+/// registry.register::<i32>().debug();
+/// let mut frame = Frame::new();
+/// frame.spawn((42, true));
+/// let mut out = Vec::new();
+/// for archetype in frame.archetypes() {
+///     let Some(column) = archetype.dynamic_column(TypeId::of::<i32>()) else {
+///         continue;
+///     };
+///     let entry = registry.get(column.type_info().id()).unwrap();
+///     for i in 0..column.len() {
+///         out.push(format!("{:?}", entry.debug(column.get_raw(i).unwrap()).unwrap()));
+///     }
+/// }
+/// assert_eq!(out, ["42"]);
+/// ```
+pub trait ComponentColumn {
+    /// Type of the components
+    fn type_info(&self) -> TypeInfo;
+
+    /// Number of components
+    fn len(&self) -> u32;
+
+    /// Whether the column holds no components
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The `index`th component, or `None` if out of bounds
+    fn get_raw(&self, index: u32) -> Option<DynamicComponent<'_>>;
+}
+
+/// Type-erased unique access to a column of components
+///
+/// Obtained from [`Archetype::dynamic_column_mut`].
+pub trait ComponentColumnMut: ComponentColumn {
+    /// Address of the `index`th component, or `None` if out of bounds
+    ///
+    /// The component may be read and written through this pointer until `self` is next used.
+    fn get_raw_mut(&mut self, index: u32) -> Option<NonNull<u8>>;
+}
+
+/// Dynamically borrowed column of an archetype
+pub(crate) struct DynamicColumn<'a, const UNIQUE: bool> {
+    archetype: &'a Archetype,
+    state: usize,
+}
+
+impl<'a, const UNIQUE: bool> DynamicColumn<'a, UNIQUE> {
+    pub fn new(archetype: &'a Archetype, state: usize) -> Self {
+        unsafe {
+            if UNIQUE {
+                archetype.borrow_raw_mut(state);
+                archetype.mark_all_changed(state);
+            } else {
+                archetype.borrow_raw(state);
+            }
+        }
+        Self { archetype, state }
+    }
+
+    fn address(&self, index: u32) -> Option<NonNull<u8>> {
+        if index >= self.archetype.len() {
+            return None;
+        }
+        let ty = self.archetype.types()[self.state];
+        unsafe {
+            self.archetype
+                .get_dynamic(ty.id(), ty.layout().size(), index)
+        }
+    }
+}
+
+impl<const UNIQUE: bool> ComponentColumn for DynamicColumn<'_, UNIQUE> {
+    fn type_info(&self) -> TypeInfo {
+        self.archetype.types()[self.state]
+    }
+
+    fn len(&self) -> u32 {
+        self.archetype.len()
+    }
+
+    fn get_raw(&self, index: u32) -> Option<DynamicComponent<'_>> {
+        let ptr = self.address(index)?;
+        Some(unsafe { DynamicComponent::new(self.type_info(), ptr) })
+    }
+}
+
+impl ComponentColumnMut for DynamicColumn<'_, true> {
+    fn get_raw_mut(&mut self, index: u32) -> Option<NonNull<u8>> {
+        self.address(index)
+    }
+}
+
+impl<const UNIQUE: bool> Drop for DynamicColumn<'_, UNIQUE> {
+    fn drop(&mut self) {
+        unsafe {
+            if UNIQUE {
+                self.archetype.release_raw_mut(self.state);
+            } else {
+                self.archetype.release_raw(self.state);
+            }
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use alloc::vec::Vec;
+use core::any::TypeId;
+use core::ptr::NonNull;
+
+use crate::{Archetype, Component, ComponentKey, ComponentRegistry, DynamicComponent};
+
+/// A query whose component access is specified at runtime
+///
+/// Useful for tools such as inspectors and scripting layers, which handle component types known
+/// only by [`TypeId`]. Run against a single entity with
+/// [`EntityRef::query_dynamic`](crate::EntityRef::query_dynamic).
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// # use std::any::TypeId;
+/// let mut frame = Frame::new();
+/// let e = frame.spawn((123, true));
+/// let mut query = DynamicQuery::new();
+/// query.write(TypeId::of::<i32>()).read(TypeId::of::<bool>());
+/// let entity = frame.entity(e).unwrap();
+/// let mut row = entity.query_dynamic(&query).unwrap();
+/// if *row.get(TypeId::of::<bool>()).unwrap().downcast_ref::<bool>().unwrap() {
+///     *row.get_mut::<i32>().unwrap() *= 2;
+/// }
+/// drop(row);
+/// assert_eq!(*entity.get::<&i32>().unwrap(), 246);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DynamicQuery {
+    access: Vec<(TypeId, Access)>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Access {
+    Read,
+    Write,
+    With,
+    Without,
+}
+
+impl DynamicQuery {
+    /// Create a query matching every entity and accessing nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require and borrow a component of type `ty` immutably
+    pub fn read(&mut self, ty: TypeId) -> &mut Self {
+        self.set(ty, Access::Read)
+    }
+
+    /// Require and borrow a component of type `ty` uniquely
+    pub fn write(&mut self, ty: TypeId) -> &mut Self {
+        self.set(ty, Access::Write)
+    }
+
+    /// Require a component of type `ty` without borrowing it
+    pub fn with(&mut self, ty: TypeId) -> &mut Self {
+        self.set(ty, Access::With)
+    }
+
+    /// Skip entities that have a component of type `ty`
+    pub fn without(&mut self, ty: TypeId) -> &mut Self {
+        self.set(ty, Access::Without)
+    }
+
+    /// Like [`read`](Self::read), naming the type by the [`ComponentKey`] it's assigned in
+    /// `registry`
+    ///
+    /// Lets plugins and scripts, whose [`TypeId`]s may not match the host's, build queries.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut registry = ComponentRegistry::new();
+    /// registry.register::<u32>().key("game::Health");
+    ///
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((10u32,));
+    /// let mut query = DynamicQuery::new();
+    /// query.write_key(&registry, ComponentKey::new("game::Health"));
+    /// let entity = frame.entity(e).unwrap();
+    /// *entity.query_dynamic(&query).unwrap().get_mut::<u32>().unwrap() -= 3;
+    /// assert_eq!(*entity.get::<&u32>().unwrap(), 7);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if no type in `registry` is assigned `key`.
+    pub fn read_key(&mut self, registry: &ComponentRegistry, key: ComponentKey) -> &mut Self {
+        self.set(resolve(registry, key), Access::Read)
+    }
+
+    /// Like [`write`](Self::write), naming the type by the [`ComponentKey`] it's assigned in
+    /// `registry`
+    ///
+    /// # Panics
+    ///
+    /// Panics if no type in `registry` is assigned `key`.
+    pub fn write_key(&mut self, registry: &ComponentRegistry, key: ComponentKey) -> &mut Self {
+        self.set(resolve(registry, key), Access::Write)
+    }
+
+    /// Like [`with`](Self::with), naming the type by the [`ComponentKey`] it's assigned in
+    /// `registry`
+    ///
+    /// # Panics
+    ///
+    /// Panics if no type in `registry` is assigned `key`.
+    pub fn with_key(&mut self, registry: &ComponentRegistry, key: ComponentKey) -> &mut Self {
+        self.set(resolve(registry, key), Access::With)
+    }
+
+    /// Like [`without`](Self::without), naming the type by the [`ComponentKey`] it's assigned in
+    /// `registry`
+    ///
+    /// # Panics
+    ///
+    /// Panics if no type in `registry` is assigned `key`.
+    pub fn without_key(&mut self, registry: &ComponentRegistry, key: ComponentKey) -> &mut Self {
+        self.set(resolve(registry, key), Access::Without)
+    }
+
+    /// Whether entities in `archetype` satisfy the query
+    pub fn matches(&self, archetype: &Archetype) -> bool {
+        self.access
+            .iter()
+            .all(|&(ty, access)| archetype.has_dynamic(ty) == (access != Access::Without))
+    }
+
+    /// Record `access` to `ty`, keeping the strongest access when a type is named repeatedly
+    fn set(&mut self, ty: TypeId, access: Access) -> &mut Self {
+        match self.access.iter_mut().find(|(x, _)| *x == ty) {
+            Some((_, existing)) => {
+                *existing = match (*existing, access) {
+                    (Access::Write, _) | (_, Access::Write) => Access::Write,
+                    (Access::Read, _) | (_, Access::Read) => Access::Read,
+                    _ => access,
+                }
+            }
+            None => self.access.push((ty, access)),
+        }
+        self
+    }
+
+    /// Borrow the components of the entity at `index` in `archetype`, if it matches
+    ///
+    /// # Safety
+    ///
+    /// `index` must be in bounds for `archetype`
+    pub(crate) unsafe fn borrow<'a>(
+        &self,
+        archetype: &'a Archetype,
+        index: u32,
+    ) -> Option<DynamicRow<'a>> {
+        if !self.matches(archetype) {
+            return None;
+        }
+        let mut row = DynamicRow {
+            archetype,
+            index,
+            borrows: Vec::new(),
+        };
+        for &(ty, access) in &self.access {
+            let unique = match access {
+                Access::Read => false,
+                Access::Write => true,
+                Access::With | Access::Without => continue,
+            };
+            let state = archetype.get_state_dynamic(ty).unwrap();
+            if unique {
+                archetype.borrow_raw_mut(state);
+                unsafe {
+                    archetype.mark_changed(state, index);
+                }
+            } else {
+                archetype.borrow_raw(state);
+            }
+            // Recorded immediately so that a later borrow failing releases this one
+            row.borrows.push((state, unique));
+        }
+        Some(row)
+    }
+}
+
+/// Type assigned `key` in `registry`
+fn resolve(registry: &ComponentRegistry, key: ComponentKey) -> TypeId {
+    match registry.find_by_key(key) {
+        Some(entry) => entry.type_info().id(),
+        None => panic!("no component type is assigned key {:?}", key),
+    }
+}
+
+/// Components of a single entity borrowed according to a [`DynamicQuery`]
+///
+/// Borrows are released when the row is dropped.
+pub struct DynamicRow<'a> {
+    archetype: &'a Archetype,
+    index: u32,
+    /// State index of each borrowed component, and whether it's borrowed uniquely
+    borrows: Vec<(usize, bool)>,
+}
+
+impl<'a> DynamicRow<'a> {
+    /// Access the component of type `ty`, if the query reads or writes it
+    pub fn get(&self, ty: TypeId) -> Option<DynamicComponent<'_>> {
+        let state = self.state(ty, false)?;
+        let info = self.archetype.types()[state];
+        unsafe { Some(DynamicComponent::new(info, self.ptr(state))) }
+    }
+
+    /// Uniquely access the component of type `ty`, if the query writes it
+    ///
+    /// The component may be read and written through the returned pointer while `self` is
+    /// uniquely borrowed.
+    pub fn get_mut_ptr(&mut self, ty: TypeId) -> Option<NonNull<u8>> {
+        let state = self.state(ty, true)?;
+        unsafe { Some(self.ptr(state)) }
+    }
+
+    /// Uniquely access the `T` component, if the query writes it
+    pub fn get_mut<T: Component>(&mut self) -> Option<&mut T> {
+        let ptr = self.get_mut_ptr(TypeId::of::<T>())?;
+        unsafe { Some(ptr.cast::<T>().as_mut()) }
+    }
+
+    /// Find the state index of the borrowed component of type `ty`
+    fn state(&self, ty: TypeId, unique: bool) -> Option<usize> {
+        let state = self.archetype.get_state_dynamic(ty)?;
+        self.borrows
+            .iter()
+            .any(|&(x, is_unique)| x == state && (is_unique || !unique))
+            .then_some(state)
+    }
+
+    /// # Safety
+    ///
+    /// `state` must be one of `self.borrows`
+    unsafe fn ptr(&self, state: usize) -> NonNull<u8> {
+        let ty = self.archetype.types()[state];
+        self.archetype
+            .get_dynamic(ty.id(), ty.layout().size(), self.index)
+            .unwrap()
+    }
+}
+
+impl Drop for DynamicRow<'_> {
+    fn drop(&mut self) {
+        for &(state, unique) in &self.borrows {
+            unsafe {
+                if unique {
+                    self.archetype.release_raw_mut(state);
+                } else {
+                    self.archetype.release_raw(state);
+                }
+            }
+        }
+    }
+}
+
+unsafe impl Send for DynamicRow<'_> {}
+unsafe impl Sync for DynamicRow<'_> {}
@@ -1,3 +1,6 @@
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::cmp;
 use core::convert::TryFrom;
@@ -73,6 +76,62 @@ impl Entity {
     pub const fn id(self) -> u32 {
         self.id
     }
+
+    /// Encode as a short, stable string suitable for human-facing contexts
+    ///
+    /// The token, e.g. `"42v1-74"`, contains the ID, the generation, and a checksum which catches
+    /// most typos. Decode it with `Entity::from_token`, or with `Frame::resolve_token` to also check
+    /// that the entity is still live.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn(());
+    /// let token = e.to_token();
+    /// assert_eq!(Entity::from_token(&token), Ok(e));
+    /// ```
+    pub fn to_token(self) -> String {
+        format!(
+            "{}v{}-{:02x}",
+            self.id,
+            self.generation,
+            self.token_checksum()
+        )
+    }
+
+    /// Decode a string produced by `Entity::to_token`
+    ///
+    /// Fails if the token is malformed or its checksum doesn't match. The entity need not exist.
+    pub fn from_token(token: &str) -> Result<Self, ParseEntityTokenError> {
+        use ParseEntityTokenError::*;
+        let (body, checksum) = token.split_once('-').ok_or(Malformed)?;
+        let (id, generation) = body.split_once('v').ok_or(Malformed)?;
+        let entity = Self {
+            id: parse_digits(id, 10).ok_or(Malformed)?,
+            generation: NonZeroU32::new(parse_digits(generation, 10).ok_or(Malformed)?)
+                .ok_or(Malformed)?,
+        };
+        if checksum.len() != 2 {
+            return Err(Malformed);
+        }
+        if parse_digits(checksum, 16).ok_or(Malformed)? != u32::from(entity.token_checksum()) {
+            return Err(ChecksumMismatch);
+        }
+        Ok(entity)
+    }
+
+    fn token_checksum(self) -> u8 {
+        (self.to_bits().get().wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 56) as u8
+    }
+}
+
+/// Parse a non-empty run of ASCII digits, rejecting signs and whitespace
+fn parse_digits(s: &str, radix: u32) -> Option<u32> {
+    if s.is_empty() || !s.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    u32::from_str_radix(s, radix).ok()
 }
 
 impl fmt::Debug for Entity {
@@ -119,6 +178,9 @@ pub struct ReserveEntitiesIterator<'a> {
 
     // New Entity IDs to hand out, outside the range of meta.len().
     id_range: core::ops::Range<u32>,
+
+    // Generation of the new Entity IDs.
+    generation: NonZeroU32,
 }
 
 impl<'a> Iterator for ReserveEntitiesIterator<'a> {
@@ -133,7 +195,7 @@ impl<'a> Iterator for ReserveEntitiesIterator<'a> {
             })
             .or_else(|| {
                 self.id_range.next().map(|id| Entity {
-                    generation: NonZeroU32::new(1).unwrap(),
+                    generation: self.generation,
                     id,
                 })
             })
@@ -147,6 +209,19 @@ impl<'a> Iterator for ReserveEntitiesIterator<'a> {
 
 impl<'a> ExactSizeIterator for ReserveEntitiesIterator<'a> {}
 
+impl ReserveEntitiesIterator<'_> {
+    /// IDs of the remaining entities as a contiguous range, if none were recycled
+    ///
+    /// Always `Some` for iterators returned by
+    /// [`Frame::reserve_entities_contiguous`](crate::Frame::reserve_entities_contiguous).
+    pub fn ids(&self) -> Option<Range<u32>> {
+        if self.id_iter.len() != 0 {
+            return None;
+        }
+        Some(self.id_range.clone())
+    }
+}
+
 #[derive(Default)]
 pub(crate) struct Entities {
     pub meta: Vec<EntityMeta>,
@@ -189,13 +264,22 @@ pub(crate) struct Entities {
     pending: Vec<u32>,
     free_cursor: AtomicIsize,
     len: u32,
+    /// IDs freed by `free_quarantined`, each with the value of `allocated` at which it may be
+    /// moved to the freelist
+    quarantine: VecDeque<(u64, u32)>,
+    /// Total number of entities allocated
+    allocated: u64,
+    /// Value set by `set_user_data` for each ID, allocated only as far as the highest ID set
+    user_data: Vec<u64>,
+    /// Highest generation of any ID dropped from `meta` by `compact`, which new IDs must exceed
+    generation_floor: u32,
 }
 
 impl Entities {
     /// Reserve entity IDs concurrently
     ///
     /// Storage for entity generation and location is lazily allocated by calling `flush`.
-    pub fn reserve_entities(&self, count: u32) -> ReserveEntitiesIterator {
+    pub fn reserve_entities(&self, count: u32) -> ReserveEntitiesIterator<'_> {
         // Use one atomic subtract to grab a range of new IDs. The range might be
         // entirely nonnegative, meaning all IDs come from the freelist, or entirely
         // negative, meaning they are all new IDs to allocate, or a mix of both.
@@ -233,7 +317,38 @@ impl Entities {
             meta: &self.meta[..],
             id_iter: self.pending[freelist_range].iter(),
             id_range: new_id_start..new_id_end,
+            generation: self.fresh_generation(),
+        }
+    }
+
+    /// Reserve a range of new entity IDs concurrently, if no freed IDs are awaiting reuse
+    pub fn reserve_entities_contiguous(&self, count: u32) -> Option<ReserveEntitiesIterator<'_>> {
+        let mut cursor = self.free_cursor.load(Ordering::Relaxed);
+        loop {
+            // A positive cursor means recycled IDs would be handed out first
+            if cursor > 0 {
+                return None;
+            }
+            match self.free_cursor.compare_exchange_weak(
+                cursor,
+                cursor - count as isize,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(actual) => cursor = actual,
+            }
         }
+
+        let base = self.meta.len() as isize;
+        let start = u32::try_from(base - cursor).expect("too many entities");
+        let end = u32::try_from(base - cursor + count as isize).expect("too many entities");
+        Some(ReserveEntitiesIterator {
+            meta: &self.meta[..],
+            id_iter: [].iter(),
+            id_range: start..end,
+            generation: self.fresh_generation(),
+        })
     }
 
     /// Reserve one entity ID concurrently
@@ -255,7 +370,7 @@ impl Entities {
             // As `self.free_cursor` goes more and more negative, we return IDs farther
             // and farther beyond `meta.len()`.
             Entity {
-                generation: NonZeroU32::new(1).unwrap(),
+                generation: self.fresh_generation(),
                 id: u32::try_from(self.meta.len() as isize - n).expect("too many entities"),
             }
         }
@@ -274,8 +389,10 @@ impl Entities {
     /// Location should be written immediately.
     pub fn alloc(&mut self) -> Entity {
         self.verify_flushed();
+        self.release_quarantined();
 
         self.len += 1;
+        self.allocated += 1;
         if let Some(id) = self.pending.pop() {
             let new_free_cursor = self.pending.len() as isize;
             *self.free_cursor.get_mut() = new_free_cursor;
@@ -285,19 +402,38 @@ impl Entities {
             }
         } else {
             let id = u32::try_from(self.meta.len()).expect("too many entities");
-            self.meta.push(EntityMeta::EMPTY);
+            let meta = self.fresh_meta();
+            self.meta.push(meta);
             Entity {
-                generation: NonZeroU32::new(1).unwrap(),
+                generation: meta.generation,
                 id,
             }
         }
     }
 
+    /// Allocate the next unused ID at `location`, ignoring the free list
+    ///
+    /// For frames whose entities are only ever freed all at once by `clear`, which leaves no IDs
+    /// to reuse.
+    pub fn alloc_bump(&mut self, location: Location) -> Entity {
+        debug_assert!(self.pending.is_empty() && self.quarantine.is_empty());
+        let id = u32::try_from(self.meta.len()).expect("too many entities");
+        let generation = self.fresh_generation();
+        self.meta.push(EntityMeta {
+            generation,
+            location,
+        });
+        self.len += 1;
+        self.allocated += 1;
+        Entity { generation, id }
+    }
+
     /// Allocate and set locations for many entity IDs laid out contiguously in an archetype
     ///
     /// `self.finish_alloc_many()` must be called after!
     pub fn alloc_many(&mut self, n: u32, archetype: u32, mut first_index: u32) -> AllocManyState {
         self.verify_flushed();
+        self.release_quarantined();
 
         let fresh = (n as usize).saturating_sub(self.pending.len()) as u32;
         assert!(
@@ -314,14 +450,16 @@ impl Entities {
         }
 
         let fresh_start = self.meta.len() as u32;
+        let generation = self.fresh_generation();
         self.meta.extend(
             (first_index..(first_index + fresh)).map(|index| EntityMeta {
-                generation: NonZeroU32::new(1).unwrap(),
+                generation,
                 location: Location { archetype, index },
             }),
         );
 
         self.len += n;
+        self.allocated += u64::from(n);
 
         AllocManyState {
             fresh: fresh_start..(fresh_start + fresh),
@@ -346,14 +484,27 @@ impl Entities {
             self.pending.extend((self.meta.len() as u32)..entity.id);
             let new_free_cursor = self.pending.len() as isize;
             *self.free_cursor.get_mut() = new_free_cursor;
-            self.meta.resize(entity.id as usize + 1, EntityMeta::EMPTY);
+            self.meta.resize(entity.id as usize + 1, self.fresh_meta());
             self.len += 1;
+            self.allocated += 1;
             None
         } else if let Some(index) = self.pending.iter().position(|item| *item == entity.id) {
             self.pending.swap_remove(index);
             let new_free_cursor = self.pending.len() as isize;
             *self.free_cursor.get_mut() = new_free_cursor;
             self.len += 1;
+            self.allocated += 1;
+            None
+        } else if self.meta[entity.id as usize].location.index == u32::MAX {
+            // Neither live nor in the freelist, so quarantined
+            let index = self
+                .quarantine
+                .iter()
+                .position(|&(_, id)| id == entity.id)
+                .unwrap();
+            self.quarantine.remove(index);
+            self.len += 1;
+            self.allocated += 1;
             None
         } else {
             Some(mem::replace(
@@ -363,14 +514,129 @@ impl Entities {
         };
 
         self.meta[entity.id as usize].generation = entity.generation;
+        self.set_user_data(entity.id, 0);
 
         loc
     }
 
+    /// Record `entity` as live at `location` without maintaining the freelist
+    ///
+    /// `rebuild_freelist` must be called after placing every entity.
+    pub fn place(&mut self, entity: Entity, location: Location) {
+        let id = entity.id as usize;
+        if id >= self.meta.len() {
+            self.meta.resize(id + 1, self.fresh_meta());
+        }
+        debug_assert_eq!(
+            self.meta[id].location.index,
+            u32::MAX,
+            "entity ID {} placed twice",
+            entity.id
+        );
+        self.meta[id] = EntityMeta {
+            generation: entity.generation,
+            location,
+        };
+        self.len += 1;
+        self.allocated += 1;
+    }
+
+    /// Free every ID below the largest placed one which wasn't placed
+    pub fn rebuild_freelist(&mut self) {
+        self.pending.clear();
+        self.pending.extend(
+            (0..self.meta.len() as u32)
+                .rev()
+                .filter(|&id| self.meta[id as usize].location.index == u32::MAX),
+        );
+        *self.free_cursor.get_mut() = self.pending.len() as isize;
+    }
+
+    /// Move live entities with IDs of at least `len` into the free IDs below it, truncating
+    /// storage to `len`, and return the old and new handle of each entity moved
+    ///
+    /// Quarantined IDs are reused, and IDs of at least `len` are later handed out with a generation
+    /// past any they had, so the old handles of moved entities stay invalid. Must not be called
+    /// while reserved entities are awaiting `flush()`.
+    pub fn compact(&mut self) -> Vec<(Entity, Entity, Location)> {
+        self.verify_flushed();
+        let len = self.len as usize;
+        let mut holes = (0..len as u32)
+            .filter(|&id| self.is_free(id))
+            .collect::<Vec<_>>();
+        holes.reverse();
+        let mut moved = Vec::new();
+        for id in len as u32..self.meta.len() as u32 {
+            let meta = self.meta[id as usize];
+            if meta.location.index == u32::MAX {
+                continue;
+            }
+            let new_id = holes.pop().unwrap();
+            // Freeing an ID already advanced its generation past any outstanding handle
+            let slot = &mut self.meta[new_id as usize];
+            slot.location = meta.location;
+            let new = Entity {
+                id: new_id,
+                generation: slot.generation,
+            };
+            let value = self.user_data(id);
+            self.set_user_data(new_id, value);
+            moved.push((
+                Entity {
+                    id,
+                    generation: meta.generation,
+                },
+                new,
+                meta.location,
+            ));
+        }
+        if let Some(max) = self.meta[len..]
+            .iter()
+            .map(|meta| meta.generation.get())
+            .max()
+        {
+            self.generation_floor = self.generation_floor.max(max);
+        }
+        self.meta.truncate(len);
+        self.meta.shrink_to_fit();
+        self.user_data.truncate(len);
+        self.user_data.shrink_to_fit();
+        self.quarantine.clear();
+        self.pending.clear();
+        *self.free_cursor.get_mut() = 0;
+        moved
+    }
+
     /// Destroy an entity, allowing it to be reused
     ///
     /// Must not be called while reserved entities are awaiting `flush()`.
     pub fn free(&mut self, entity: Entity) -> Result<Location, NoSuchEntity> {
+        let loc = self.retire(entity)?;
+
+        self.pending.push(entity.id);
+
+        let new_free_cursor = self.pending.len() as isize;
+        *self.free_cursor.get_mut() = new_free_cursor;
+
+        Ok(loc)
+    }
+
+    /// Destroy an entity, withholding its ID from reuse until `spawns` more entities are allocated
+    ///
+    /// Must not be called while reserved entities are awaiting `flush()`.
+    pub fn free_quarantined(
+        &mut self,
+        entity: Entity,
+        spawns: u32,
+    ) -> Result<Location, NoSuchEntity> {
+        let loc = self.retire(entity)?;
+        self.quarantine
+            .push_back((self.allocated + u64::from(spawns), entity.id));
+        Ok(loc)
+    }
+
+    /// Invalidate `entity`'s handle and release its location
+    fn retire(&mut self, entity: Entity) -> Result<Location, NoSuchEntity> {
         self.verify_flushed();
 
         let meta = self.meta.get_mut(entity.id as usize).ok_or(NoSuchEntity)?;
@@ -382,16 +648,33 @@ impl Entities {
             .unwrap_or_else(|| NonZeroU32::new(1).unwrap());
 
         let loc = mem::replace(&mut meta.location, EntityMeta::EMPTY.location);
-
-        self.pending.push(entity.id);
-
-        let new_free_cursor = self.pending.len() as isize;
-        *self.free_cursor.get_mut() = new_free_cursor;
         self.len -= 1;
+        self.set_user_data(entity.id, 0);
 
         Ok(loc)
     }
 
+    /// Move quarantined IDs whose time has come to the freelist
+    ///
+    /// Must not be called while reserved entities are awaiting `flush()`.
+    fn release_quarantined(&mut self) {
+        let mut released = false;
+        // Entries may be out of order if quarantines of differing lengths are mixed, in which case
+        // later entries are held back a little longer than necessary.
+        while let Some(&(release_at, id)) = self.quarantine.front() {
+            if release_at > self.allocated {
+                break;
+            }
+            self.quarantine.pop_front();
+            self.pending.push(id);
+            released = true;
+        }
+        if released {
+            let new_free_cursor = self.pending.len() as isize;
+            *self.free_cursor.get_mut() = new_free_cursor;
+        }
+    }
+
     /// Ensure at least `n` allocations can succeed without reallocating
     pub fn reserve(&mut self, additional: u32) {
         self.verify_flushed();
@@ -414,20 +697,48 @@ impl Entities {
             None => {
                 // Check if this could have been obtained from `reserve_entity`
                 let free = self.free_cursor.load(Ordering::Relaxed);
-                entity.generation.get() == 1
+                entity.generation == self.fresh_generation()
                     && free < 0
                     && (entity.id as isize) < (free.abs() + self.meta.len() as isize)
             }
         }
     }
 
+    /// Whether no live entity uses `id`
+    ///
+    /// Must not be called while reserved entities are awaiting `flush()`.
+    pub fn is_free(&self, id: u32) -> bool {
+        self.meta
+            .get(id as usize)
+            .map_or(true, |meta| meta.location.index == u32::MAX)
+    }
+
     pub fn clear(&mut self) {
         self.meta.clear();
+        self.user_data.clear();
         self.pending.clear();
+        self.quarantine.clear();
         *self.free_cursor.get_mut() = 0;
         self.len = 0;
     }
 
+    /// Value set by `set_user_data` for the entity with ID `id`, or 0
+    pub fn user_data(&self, id: u32) -> u64 {
+        self.user_data.get(id as usize).copied().unwrap_or(0)
+    }
+
+    /// Associate `value` with the entity with ID `id` until it's freed
+    pub fn set_user_data(&mut self, id: u32, value: u64) {
+        let id = id as usize;
+        if id >= self.user_data.len() {
+            if value == 0 {
+                return;
+            }
+            self.user_data.resize(id + 1, 0);
+        }
+        self.user_data[id] = value;
+    }
+
     /// Access the location storage of an entity
     ///
     /// Must not be called on pending entities.
@@ -440,18 +751,24 @@ impl Entities {
         }
     }
 
+    /// Location of the live, flushed entity with ID `id`, if any
+    pub fn live_location(&self, id: u32) -> Option<Location> {
+        let meta = self.meta.get(id as usize)?;
+        (meta.location.index != u32::MAX).then_some(meta.location)
+    }
+
     /// Returns `Ok(Location { archetype: 0, index: undefined })` for pending entities
     pub fn get(&self, entity: Entity) -> Result<Location, NoSuchEntity> {
         if self.meta.len() <= entity.id as usize {
             // Check if this could have been obtained from `reserve_entity`
             let free = self.free_cursor.load(Ordering::Relaxed);
-            if entity.generation.get() == 1
+            if entity.generation == self.fresh_generation()
                 && free < 0
                 && (entity.id as isize) < (free.abs() + self.meta.len() as isize)
             {
                 return Ok(Location {
                     archetype: 0,
-                    index: u32::max_value(),
+                    index: u32::MAX,
                 });
             } else {
                 return Err(NoSuchEntity);
@@ -485,7 +802,7 @@ impl Entities {
             if meta_len + num_pending > id as usize {
                 // Pending entities will have the first generation.
                 Entity {
-                    generation: NonZeroU32::new(1).unwrap(),
+                    generation: self.fresh_generation(),
                     id,
                 }
             } else {
@@ -494,7 +811,20 @@ impl Entities {
         }
     }
 
-    fn needs_flush(&mut self) -> bool {
+    /// Generation of IDs allocated beyond the end of `meta`
+    fn fresh_generation(&self) -> NonZeroU32 {
+        NonZeroU32::new(self.generation_floor.wrapping_add(1))
+            .unwrap_or_else(|| NonZeroU32::new(1).unwrap())
+    }
+
+    fn fresh_meta(&self) -> EntityMeta {
+        EntityMeta {
+            generation: self.fresh_generation(),
+            location: EntityMeta::EMPTY.location,
+        }
+    }
+
+    pub fn needs_flush(&mut self) -> bool {
         *self.free_cursor.get_mut() != self.pending.len() as isize
     }
 
@@ -508,9 +838,10 @@ impl Entities {
         } else {
             let old_meta_len = self.meta.len();
             let new_meta_len = old_meta_len + -free_cursor as usize;
-            self.meta.resize(new_meta_len, EntityMeta::EMPTY);
+            self.meta.resize(new_meta_len, self.fresh_meta());
 
             self.len += -free_cursor as u32;
+            self.allocated += -free_cursor as u64;
             for (id, meta) in self.meta.iter_mut().enumerate().skip(old_meta_len) {
                 init(id as u32, &mut meta.location);
             }
@@ -519,10 +850,13 @@ impl Entities {
             0
         };
 
-        self.len += (self.pending.len() - new_free_cursor) as u32;
+        let flushed = (self.pending.len() - new_free_cursor) as u32;
+        self.len += flushed;
+        self.allocated += u64::from(flushed);
         for id in self.pending.drain(new_free_cursor..) {
             init(id, &mut self.meta[id as usize].location);
         }
+        self.release_quarantined();
     }
 
     #[inline]
@@ -545,7 +879,7 @@ impl EntityMeta {
         },
         location: Location {
             archetype: 0,
-            index: u32::max_value(), // dummy value, to be filled in
+            index: u32::MAX, // dummy value, to be filled in
         },
     };
 }
@@ -556,6 +890,28 @@ pub(crate) struct Location {
     pub index: u32,
 }
 
+/// Error indicating that a string isn't a valid token from [`Entity::to_token`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ParseEntityTokenError {
+    /// The string isn't of the form produced by [`Entity::to_token`]
+    Malformed,
+    /// The token's checksum doesn't match its contents, e.g. due to a typo
+    ChecksumMismatch,
+}
+
+impl fmt::Display for ParseEntityTokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ParseEntityTokenError::*;
+        match *self {
+            Malformed => f.write_str("malformed entity token"),
+            ChecksumMismatch => f.write_str("entity token checksum mismatch"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for ParseEntityTokenError {}
+
 /// Error indicating that no entity with a particular ID exists
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct NoSuchEntity;
@@ -837,4 +1193,40 @@ mod tests {
             id: 0
         }));
     }
+
+    #[test]
+    fn quarantine() {
+        let mut e = Entities::default();
+        let a = e.alloc();
+        e.meta[a.id as usize].location.index = 0;
+        let b = e.alloc();
+        e.meta[b.id as usize].location.index = 0;
+        e.free_quarantined(a, 2).unwrap();
+        assert!(!e.contains(a));
+        assert_eq!(e.len(), 1);
+
+        // Reservations count towards the quarantine once flushed
+        let reserved = e.reserve_entity();
+        assert_ne!(reserved.id, a.id);
+        e.flush(|_, l| l.index = 0);
+        let c = e.alloc();
+        assert_ne!(c.id, a.id);
+        e.meta[c.id as usize].location.index = 0;
+        let d = e.alloc();
+        assert_eq!(d.id, a.id);
+        assert_ne!(d.generation, a.generation);
+        e.meta[d.id as usize].location.index = 0;
+
+        // Quarantined IDs may still be claimed explicitly
+        e.free_quarantined(b, 10).unwrap();
+        let b2 = Entity {
+            generation: NonZeroU32::new(7).unwrap(),
+            id: b.id,
+        };
+        assert!(e.alloc_at(b2).is_none());
+        e.meta[b.id as usize].location.index = 0;
+        assert!(e.contains(b2));
+        assert!(e.quarantine.is_empty());
+        assert_eq!(e.len(), 4);
+    }
 }
@@ -9,16 +9,18 @@ use crate::alloc::alloc::{alloc, dealloc, Layout};
 use crate::alloc::vec::Vec;
 use crate::bundle::{DynamicBundleClone, DynamicClone};
 use core::any::TypeId;
+use core::marker::PhantomData;
 use core::ptr::{self, NonNull};
 
 use hashbrown::hash_map::Entry;
 
 use crate::archetype::{TypeIdMap, TypeInfo};
-use crate::{align, Component, ComponentRef, ComponentRefShared, DynamicBundle};
+use crate::{align, Archetype, Component, ComponentRef, ComponentRefShared, DynamicBundle, Query};
 
 /// Helper for incrementally constructing a bundle of components with dynamic component types
 ///
-/// Prefer reusing the same builder over creating new ones repeatedly.
+/// Prefer reusing the same builder over creating new ones repeatedly. When several are needed at
+/// once, an [`EntityBuilderPool`] can recycle them.
 ///
 /// ```
 /// # use moss_hecs::*;
@@ -60,6 +62,15 @@ impl EntityBuilder {
         self
     }
 
+    /// Move a type-erased component into the entity, replacing any existing component of that type
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid value of the type described by `ty`, which must not be used
+    /// again afterwards.
+    pub(crate) unsafe fn add_dynamic(&mut self, ptr: *mut u8, ty: TypeInfo) {
+        self.inner.add(ptr, ty, ());
+    }
+
     /// Construct a `Bundle` suitable for spawning
     pub fn build(&mut self) -> BuiltEntity<'_> {
         self.inner.info.sort_unstable_by_key(|x| x.0);
@@ -95,6 +106,23 @@ impl EntityBuilder {
         self.inner.component_types()
     }
 
+    /// Whether an entity spawned from the builder's current components would match `Q`
+    ///
+    /// Useful for validating prefabs before they're spawned. See also
+    /// [`bundle_query_mismatch`](crate::bundle_query_mismatch) for statically typed bundles.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut builder = EntityBuilder::new();
+    /// builder.add(1.0f32).add(true);
+    /// assert!(builder.satisfies::<(&f32, Option<&i32>)>());
+    /// assert!(!builder.satisfies::<Without<&f32, &bool>>());
+    /// ```
+    pub fn satisfies<Q: Query>(&self) -> bool {
+        self.inner.satisfies::<Q>()
+    }
+
     /// Drop previously `add`ed components
     ///
     /// The builder is cleared implicitly when an entity is built, so this doesn't usually need to
@@ -104,6 +132,62 @@ impl EntityBuilder {
     }
 }
 
+/// A set of idle [`EntityBuilder`]s whose storage is recycled
+///
+/// Useful when several builders are needed at once, e.g. by a system that assembles entities every
+/// tick: builders obtained from `acquire` and handed back with `release` keep their allocations,
+/// so steady-state use doesn't allocate.
+///
+/// ```
+/// # use moss_hecs::*;
+/// let mut frame = Frame::new();
+/// let mut pool = EntityBuilderPool::new();
+/// for i in 0..3 {
+///     let mut builder = pool.acquire();
+///     builder.add(i).add("abc");
+///     frame.spawn(builder.build());
+///     pool.release(builder);
+/// }
+/// assert_eq!(pool.len(), 1);
+/// ```
+#[derive(Default)]
+pub struct EntityBuilderPool {
+    idle: Vec<EntityBuilder>,
+}
+
+impl EntityBuilderPool {
+    /// Create an empty pool
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take an empty builder from the pool, or create one if none is idle
+    pub fn acquire(&mut self) -> EntityBuilder {
+        self.idle.pop().unwrap_or_default()
+    }
+
+    /// Return `builder` to the pool, dropping any components it still holds
+    pub fn release(&mut self, mut builder: EntityBuilder) {
+        builder.clear();
+        self.idle.push(builder);
+    }
+
+    /// Number of idle builders
+    pub fn len(&self) -> usize {
+        self.idle.len()
+    }
+
+    /// Whether no builders are idle
+    pub fn is_empty(&self) -> bool {
+        self.idle.is_empty()
+    }
+
+    /// Free the storage of all idle builders
+    pub fn clear(&mut self) {
+        self.idle.clear();
+    }
+}
+
 /// The output of an [`EntityBuilder`], suitable for passing to
 /// [`Frame::spawn`](crate::Frame::spawn) or [`Frame::insert`](crate::Frame::insert)
 pub struct BuiltEntity<'a> {
@@ -194,6 +278,15 @@ impl EntityBuilderClone {
         self
     }
 
+    /// Add a clone of the component at `src`
+    ///
+    /// # Safety
+    ///
+    /// `src` must point to a live value of the type `clone` was constructed for
+    pub(crate) unsafe fn add_clone_of(&mut self, src: *const u8, clone: DynamicClone) {
+        (clone.func)(src, &mut |ptr, ty| self.inner.add(ptr, ty, clone));
+    }
+
     /// Convert into a value whose shared references are [`DynamicBundle`]s suitable for repeated
     /// spawning
     pub fn build(self) -> BuiltEntityClone {
@@ -224,6 +317,13 @@ impl EntityBuilderClone {
         self.inner.component_types()
     }
 
+    /// Whether an entity spawned from the builder's current components would match `Q`
+    ///
+    /// See [`EntityBuilder::satisfies`].
+    pub fn satisfies<Q: Query>(&self) -> bool {
+        self.inner.satisfies::<Q>()
+    }
+
     /// Drop previously `add`ed components
     ///
     /// The builder is cleared implicitly when an entity is built, so this doesn't usually need to
@@ -270,10 +370,65 @@ unsafe impl DynamicBundleClone for &'_ BuiltEntityClone {
     }
 }
 
+impl BuiltEntityClone {
+    /// Clone the components into a new [`EntityBuilderClone`], e.g. to customize a prefab
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut builder = EntityBuilderClone::new();
+    /// builder.add(123).add("abc");
+    /// let prefab = builder.build();
+    /// let mut variant = prefab.to_builder();
+    /// variant.add(456);
+    /// let variant = variant.build();
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn(&variant);
+    /// assert_eq!(*frame.get::<&i32>(e).unwrap(), 456);
+    /// assert_eq!(*frame.get::<&&str>(e).unwrap(), "abc");
+    /// ```
+    pub fn to_builder(&self) -> EntityBuilderClone {
+        self.clone().into()
+    }
+
+    /// Clone the components into a bundle with the new `T` component adjusted by `f`
+    ///
+    /// Handy for per-instance variation, e.g. jittering the position of each entity spawned from a
+    /// prefab, without building a new [`BuiltEntityClone`] per instance. If there's no `T`
+    /// component, `f` isn't called.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut builder = EntityBuilderClone::new();
+    /// builder.add(123).add("abc");
+    /// let prefab = builder.build();
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn(prefab.map(|x: &mut i32| *x += 1));
+    /// assert_eq!(*frame.get::<&i32>(e).unwrap(), 124);
+    /// ```
+    pub fn map<T, F>(&self, f: F) -> MappedEntityClone<'_, T, F>
+    where
+        T: Component,
+        F: FnOnce(&mut T),
+    {
+        MappedEntityClone {
+            built: self,
+            f,
+            _marker: PhantomData,
+        }
+    }
+}
+
 impl From<EntityBuilderClone> for BuiltEntityClone {
     fn from(mut x: EntityBuilderClone) -> Self {
         x.inner.info.sort_unstable_by_key(|y| y.0);
         x.inner.ids.extend(x.inner.info.iter().map(|y| y.0.id()));
+        // Sorting moved components, so refresh their indices in case we're turned back into a
+        // builder.
+        for (i, (ty, _, _)) in x.inner.info.iter().enumerate() {
+            x.inner.indices.insert(ty.id(), i);
+        }
         Self(x.inner)
     }
 }
@@ -285,6 +440,44 @@ impl From<BuiltEntityClone> for EntityBuilderClone {
     }
 }
 
+/// Clones of the components of a [`BuiltEntityClone`], with the `T` component adjusted
+///
+/// Constructed by [`BuiltEntityClone::map`].
+pub struct MappedEntityClone<'a, T, F> {
+    built: &'a BuiltEntityClone,
+    f: F,
+    _marker: PhantomData<fn(&mut T)>,
+}
+
+unsafe impl<T, F> DynamicBundle for MappedEntityClone<'_, T, F>
+where
+    T: Component,
+    F: FnOnce(&mut T),
+{
+    fn with_ids<U>(&self, f: impl FnOnce(&[TypeId]) -> U) -> U {
+        f(&self.built.0.ids)
+    }
+
+    fn type_info(&self) -> Vec<TypeInfo> {
+        self.built.type_info()
+    }
+
+    unsafe fn put(self, mut f: impl FnMut(*mut u8, TypeInfo)) {
+        let mut map = Some(self.f);
+        for &(_, offset, clone) in &self.built.0.info {
+            let ptr = self.built.0.storage.as_ptr().add(offset);
+            (clone.func)(ptr, &mut |src, ty| {
+                if ty.id() == TypeId::of::<T>() {
+                    if let Some(map) = map.take() {
+                        map(&mut *src.cast::<T>());
+                    }
+                }
+                f(src, ty)
+            });
+        }
+    }
+}
+
 struct Common<M> {
     storage: NonNull<u8>,
     layout: Layout,
@@ -321,6 +514,14 @@ impl<M> Common<M> {
         self.info.iter().map(|(info, _, _)| info.id())
     }
 
+    fn satisfies<Q: Query>(&self) -> bool {
+        use crate::Fetch;
+
+        let mut types = self.info.iter().map(|&(ty, _, _)| ty).collect::<Vec<_>>();
+        types.sort_unstable();
+        Q::Fetch::access(&Archetype::new(types)).is_some()
+    }
+
     unsafe fn grow(
         min_size: usize,
         cursor: usize,
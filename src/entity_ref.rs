@@ -1,13 +1,17 @@
+use alloc::sync::Arc;
 use core::any::TypeId;
 use core::fmt::{self, Debug, Display, Formatter};
 use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
 use core::ops::{Deref, DerefMut, FnOnce};
 use core::ptr::NonNull;
 
 use crate::archetype::Archetype;
+use crate::deadline::BorrowTimer;
 use crate::{
-    ArchetypeColumn, ArchetypeColumnMut, Component, Entity, Fetch, MissingComponent, Query,
-    QueryOne,
+    ArchetypeColumn, ArchetypeColumnMut, Bundle, Component, ComponentRegistry, ComponentVisitor,
+    DynamicComponent, DynamicQuery, DynamicRow, Entity, Fetch, MissingComponent, Query, QueryOne,
+    RefMany,
 };
 
 /// Handle to an entity with any component types
@@ -45,6 +49,13 @@ impl<'a> EntityRef<'a> {
         self.archetype.has::<T>()
     }
 
+    /// Determine whether this entity has every component in the bundle `B` without borrowing them
+    ///
+    /// `B` is a tuple of component types, e.g. `(A, B)`, or a type deriving [`Bundle`].
+    pub fn has_bundle<B: Bundle + 'static>(&self) -> bool {
+        B::with_static_ids(|ids| ids.iter().all(|&id| self.archetype.has_dynamic(id)))
+    }
+
     /// Borrow a single component, if it exists
     ///
     /// `T` must be a shared or unique reference to a component type.
@@ -85,6 +96,38 @@ impl<'a> EntityRef<'a> {
         unsafe { QueryOne::new(self.archetype, self.index) }
     }
 
+    /// Borrow several components of this entity at once
+    ///
+    /// Returns `None` if the entity doesn't satisfy `Q`. Unlike separate [`get`](Self::get) calls,
+    /// the components are located and borrowed together, and released when the guard is dropped.
+    ///
+    /// Panics if a component is already borrowed in a way that conflicts with `Q`.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((123, true, "abc"));
+    /// let entity = frame.entity(a).unwrap();
+    /// let mut guard = entity.get_many::<(&mut i32, &bool)>().unwrap();
+    /// let (number, flag) = guard.get();
+    /// if *flag { *number *= 2; }
+    /// assert_eq!(*guard.get().0, 246);
+    /// ```
+    pub fn get_many<Q: Query>(&self) -> Option<RefMany<'a, Q>> {
+        unsafe { RefMany::new(self.archetype, self.index) }
+    }
+
+    /// Run a query with runtime-specified access against this entity
+    ///
+    /// Returns `None` if the entity doesn't satisfy `query`. See [`DynamicQuery`] for an example.
+    ///
+    /// Panics if a component to be read is already uniquely borrowed, or a component to be
+    /// written is already borrowed.
+    pub fn query_dynamic(&self, query: &DynamicQuery) -> Option<DynamicRow<'a>> {
+        unsafe { query.borrow(self.archetype, self.index) }
+    }
+
     /// Enumerate the types of the entity's components
     ///
     /// Convenient for dispatching component-specific logic for a single entity. For example, this
@@ -95,6 +138,99 @@ impl<'a> EntityRef<'a> {
         self.archetype.types().iter().map(|ty| ty.id())
     }
 
+    /// Pass each of the entity's components to `visitor`, in arbitrary order
+    ///
+    /// Each component is borrowed for the duration of the `visitor` call it's passed to. Useful
+    /// for generic tooling such as inspectors or serializers, in combination with a
+    /// [`ComponentRegistry`](crate::ComponentRegistry).
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((42, true));
+    /// let mut total = 0;
+    /// frame.entity(e).unwrap().visit(&mut |component: DynamicComponent<'_>| {
+    ///     total += component.type_info().layout().size();
+    ///     if let Some(x) = component.downcast_ref::<i32>() {
+    ///         assert_eq!(*x, 42);
+    ///     }
+    /// });
+    /// assert_eq!(total, 5);
+    /// ```
+    ///
+    /// Panics if any component is already uniquely borrowed.
+    pub fn visit(&self, visitor: &mut dyn ComponentVisitor) {
+        for state in 0..self.archetype.types().len() {
+            self.visit_state(state, visitor);
+        }
+    }
+
+    /// Pass each of the entity's components of a type registered in `registry` to `visitor`,
+    /// ordered by registered [`name`](crate::RegisteredComponent::name)
+    ///
+    /// Unlike [`visit`](Self::visit), the order depends only on the registry, not on how the
+    /// entity's archetype happened to be constructed, so output derived from it such as save
+    /// files or hashes is reproducible. Types sharing a name are visited in the order they were
+    /// registered. Components of unregistered types are skipped.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut registry = ComponentRegistry::new();
+    /// registry.register::<bool>().name("b");
+    /// registry.register::<i32>().name("a");
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((true, 42, "unregistered"));
+    /// let mut names = Vec::new();
+    /// frame.entity(e).unwrap().visit_sorted(&registry, &mut |component: DynamicComponent<'_>| {
+    ///     names.push(registry.get(component.type_info().id()).unwrap().name());
+    /// });
+    /// assert_eq!(names, ["a", "b"]);
+    /// ```
+    ///
+    /// Panics if any visited component is already uniquely borrowed.
+    pub fn visit_sorted(&self, registry: &ComponentRegistry, visitor: &mut dyn ComponentVisitor) {
+        for (state, _) in registry.sorted_columns(self.archetype) {
+            self.visit_state(state, visitor);
+        }
+    }
+
+    fn visit_state(&self, state: usize, visitor: &mut dyn ComponentVisitor) {
+        let ty = self.archetype.types()[state];
+        unsafe {
+            self.archetype.borrow_raw(state);
+            let _borrow = ComponentBorrow {
+                archetype: self.archetype,
+                state,
+                index: self.index,
+                timer: BorrowTimer::default(),
+            };
+            let ptr = self
+                .archetype
+                .get_dynamic(ty.id(), ty.layout().size(), self.index)
+                .unwrap();
+            visitor.visit(DynamicComponent::new(ty, ptr));
+        }
+    }
+
+    /// Clone the components of types `T`, a tuple such as `(A, B)`, out of the entity
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((42, String::from("abc")));
+    /// let entity = frame.entity(e).unwrap();
+    /// assert_eq!(entity.cloned::<(i32, String)>(), Ok((42, String::from("abc"))));
+    /// assert!(entity.cloned::<(i32, bool)>().is_err());
+    /// ```
+    ///
+    /// Panics if any of the components is uniquely borrowed.
+    pub fn cloned<T: ClonedComponents>(&self) -> Result<T, MissingComponent> {
+        T::clone_from(*self)
+    }
+
     /// Number of components in this entity
     pub fn len(&self) -> usize {
         self.archetype.types().len()
@@ -111,8 +247,10 @@ unsafe impl<'a> Sync for EntityRef<'a> {}
 
 /// Shared borrow of an entity's component
 pub struct Ref<'a, T: ?Sized> {
-    borrow: ComponentBorrow<'a>,
+    borrow: SharedBorrow<'a>,
     target: NonNull<T>,
+    /// Whether `target` may be written through if the borrow becomes unique
+    upgradable: bool,
     _phantom: PhantomData<&'a T>,
 }
 
@@ -123,11 +261,97 @@ impl<'a, T: Component> Ref<'a, T> {
     ) -> Result<Self, MissingComponent> {
         let (target, borrow) = ComponentBorrow::for_component::<T>(archetype, index)?;
         Ok(Self {
-            borrow,
+            borrow: SharedBorrow::Shared(borrow),
             target,
+            upgradable: true,
             _phantom: PhantomData,
         })
     }
+
+    /// Convert into a unique borrow if no other borrows of this component type in the same
+    /// archetype are outstanding, or return the original `Ref`
+    ///
+    /// Always fails if the `Ref` was produced by [`Ref::map`] or similar. A `Ref` produced by
+    /// downgrading one half of a [`RefMut::map_split`] can be upgraded once the other half and any
+    /// clones have been dropped.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123,));
+    /// let entity = frame.entity(e).unwrap();
+    /// let x = entity.get::<&i32>().unwrap();
+    /// let y = x.clone();
+    /// let x = Ref::try_upgrade(x).unwrap_err();
+    /// drop(y);
+    /// let mut x = Ref::try_upgrade(x).unwrap();
+    /// *x = 456;
+    /// ```
+    pub fn try_upgrade(orig: Ref<'a, T>) -> Result<RefMut<'a, T>, Ref<'a, T>> {
+        let borrow = match orig.borrow {
+            _ if !orig.upgradable => return Err(orig),
+            SharedBorrow::Shared(ref borrow) => borrow,
+            SharedBorrow::Split(_) => return Self::try_upgrade_split(orig),
+        };
+        if !unsafe { borrow.archetype.try_upgrade_raw(borrow.state) } {
+            return Err(orig);
+        }
+        let (archetype, state, index) = (borrow.archetype, borrow.state, borrow.index);
+        // Upgrading may have moved a shared column into private storage
+        let target = unsafe {
+            archetype.mark_changed(state, index);
+            NonNull::new_unchecked(archetype.get_base::<T>(state).as_ptr().add(index as usize))
+        };
+        let mut orig = ManuallyDrop::new(orig);
+        let timer = match orig.borrow {
+            SharedBorrow::Shared(ref mut borrow) => borrow.timer.take(),
+            SharedBorrow::Split(_) => unreachable!(),
+        };
+        Ok(RefMut {
+            borrow: UniqueBorrow::Unique(ComponentBorrowMut {
+                archetype,
+                state,
+                index,
+                timer,
+            }),
+            target,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Reclaim the unique borrow held by a downgraded half of a [`RefMut::map_split`], if no
+    /// other `Ref` or `RefMut` shares it
+    fn try_upgrade_split(orig: Ref<'a, T>) -> Result<RefMut<'a, T>, Ref<'a, T>> {
+        let Ref {
+            borrow: SharedBorrow::Split(borrow),
+            target,
+            upgradable,
+            _phantom,
+        } = orig
+        else {
+            unreachable!()
+        };
+        match Arc::try_unwrap(borrow) {
+            Ok(borrow) => {
+                // The column has remained uniquely borrowed, so `target` is still valid
+                unsafe {
+                    borrow.archetype.mark_changed(borrow.state, borrow.index);
+                }
+                Ok(RefMut {
+                    borrow: UniqueBorrow::Unique(borrow),
+                    target,
+                    _phantom: PhantomData,
+                })
+            }
+            Err(borrow) => Err(Ref {
+                borrow: SharedBorrow::Split(borrow),
+                target,
+                upgradable,
+                _phantom,
+            }),
+        }
+    }
 }
 
 unsafe impl<T: ?Sized + Sync> Send for Ref<'_, T> {}
@@ -162,9 +386,72 @@ impl<'a, T: ?Sized> Ref<'a, T> {
         Ref {
             borrow: orig.borrow,
             target,
+            upgradable: false,
             _phantom: PhantomData,
         }
     }
+
+    /// Like [`map`](Self::map), but `f` may fail, in which case the original `Ref` is returned
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((vec![1, 2, 3],));
+    /// let entity = frame.entity(e).unwrap();
+    /// let v = entity.get::<&Vec<i32>>().unwrap();
+    /// let v = Ref::filter_map(v, |v| v.get(5)).unwrap_err();
+    /// let x = Ref::filter_map(v, |v| v.get(1)).unwrap();
+    /// assert_eq!(*x, 2);
+    /// ```
+    pub fn filter_map<U: ?Sized, F>(orig: Ref<'a, T>, f: F) -> Result<Ref<'a, U>, Ref<'a, T>>
+    where
+        F: FnOnce(&T) -> Option<&U>,
+    {
+        match f(&*orig).map(NonNull::from) {
+            Some(target) => Ok(Ref {
+                borrow: orig.borrow,
+                target,
+                upgradable: false,
+                _phantom: PhantomData,
+            }),
+            None => Err(orig),
+        }
+    }
+
+    /// Split into `Ref`s to different parts of the borrowed data, e.g. two struct fields
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn(((1, "abc"),));
+    /// let entity = frame.entity(e).unwrap();
+    /// let pair = entity.get::<&(i32, &str)>().unwrap();
+    /// let (x, y) = Ref::map_split(pair, |pair| (&pair.0, &pair.1));
+    /// assert_eq!((*x, *y), (1, "abc"));
+    /// ```
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(orig: Ref<'a, T>, f: F) -> (Ref<'a, U>, Ref<'a, V>)
+    where
+        F: FnOnce(&T) -> (&U, &V),
+    {
+        let (a, b) = f(&*orig);
+        let (a, b) = (NonNull::from(a), NonNull::from(b));
+        (
+            Ref {
+                borrow: orig.borrow.clone(),
+                target: a,
+                upgradable: false,
+                _phantom: PhantomData,
+            },
+            Ref {
+                borrow: orig.borrow,
+                target: b,
+                upgradable: false,
+                _phantom: PhantomData,
+            },
+        )
+    }
 }
 
 impl<'a, T: ?Sized> Deref for Ref<'a, T> {
@@ -191,6 +478,7 @@ impl<'a, T: ?Sized> Clone for Ref<'a, T> {
         Self {
             borrow: self.borrow.clone(),
             target: self.target,
+            upgradable: self.upgradable,
             _phantom: self._phantom,
         }
     }
@@ -198,7 +486,7 @@ impl<'a, T: ?Sized> Clone for Ref<'a, T> {
 
 /// Unique borrow of an entity's component
 pub struct RefMut<'a, T: ?Sized> {
-    borrow: ComponentBorrowMut<'a>,
+    borrow: UniqueBorrow<'a>,
     target: NonNull<T>,
     _phantom: PhantomData<&'a mut T>,
 }
@@ -210,7 +498,7 @@ impl<'a, T: Component> RefMut<'a, T> {
     ) -> Result<Self, MissingComponent> {
         let (target, borrow) = ComponentBorrowMut::for_component::<T>(archetype, index)?;
         Ok(Self {
-            borrow,
+            borrow: UniqueBorrow::Unique(borrow),
             target,
             _phantom: PhantomData,
         })
@@ -253,6 +541,120 @@ impl<'a, T: ?Sized> RefMut<'a, T> {
             _phantom: PhantomData,
         }
     }
+
+    /// Like [`map`](Self::map), but `f` may fail, in which case the original `RefMut` is returned
+    pub fn filter_map<U: ?Sized, F>(
+        mut orig: RefMut<'a, T>,
+        f: F,
+    ) -> Result<RefMut<'a, U>, RefMut<'a, T>>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(&mut *orig).map(NonNull::from) {
+            Some(target) => Ok(RefMut {
+                borrow: orig.borrow,
+                target,
+                _phantom: PhantomData,
+            }),
+            None => Err(orig),
+        }
+    }
+
+    /// Split into `RefMut`s to disjoint parts of the borrowed data, e.g. two struct fields
+    ///
+    /// The component remains uniquely borrowed until both halves are dropped.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn(((1, "abc"),));
+    /// let entity = frame.entity(e).unwrap();
+    /// let pair = entity.get::<&mut (i32, &str)>().unwrap();
+    /// let (mut x, mut y) = RefMut::map_split(pair, |pair| (&mut pair.0, &mut pair.1));
+    /// *x += 1;
+    /// *y = "def";
+    /// drop((x, y));
+    /// assert_eq!(*entity.get::<&(i32, &str)>().unwrap(), (2, "def"));
+    /// ```
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        mut orig: RefMut<'a, T>,
+        f: F,
+    ) -> (RefMut<'a, U>, RefMut<'a, V>)
+    where
+        F: FnOnce(&mut T) -> (&mut U, &mut V),
+    {
+        let (a, b) = f(&mut *orig);
+        let (a, b) = (NonNull::from(a), NonNull::from(b));
+        let borrow = match orig.borrow {
+            UniqueBorrow::Unique(borrow) => Arc::new(borrow),
+            UniqueBorrow::Split(borrow) => borrow,
+        };
+        (
+            RefMut {
+                borrow: UniqueBorrow::Split(borrow.clone()),
+                target: a,
+                _phantom: PhantomData,
+            },
+            RefMut {
+                borrow: UniqueBorrow::Split(borrow),
+                target: b,
+                _phantom: PhantomData,
+            },
+        )
+    }
+
+    /// Convert into a shared borrow, allowing other shared borrows of this component type to
+    /// proceed without any interval in which the component is unborrowed
+    ///
+    /// If this is one half of a [`map_split`](Self::map_split) whose other half is still alive,
+    /// the component remains uniquely borrowed until both are dropped.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123,));
+    /// let entity = frame.entity(e).unwrap();
+    /// let mut x = entity.get::<&mut i32>().unwrap();
+    /// *x = 456;
+    /// let x = RefMut::downgrade(x);
+    /// assert_eq!(*entity.get::<&i32>().unwrap(), 456);
+    /// assert_eq!(*x, 456);
+    /// ```
+    pub fn downgrade(orig: RefMut<'a, T>) -> Ref<'a, T> {
+        let borrow = match orig.borrow {
+            UniqueBorrow::Unique(borrow) => borrow,
+            UniqueBorrow::Split(borrow) => match Arc::try_unwrap(borrow) {
+                Ok(borrow) => borrow,
+                Err(borrow) => {
+                    return Ref {
+                        borrow: SharedBorrow::Split(borrow),
+                        target: orig.target,
+                        // Derived from a unique reference, so may be written through once the
+                        // other half is gone
+                        upgradable: true,
+                        _phantom: PhantomData,
+                    };
+                }
+            },
+        };
+        let mut borrow = ManuallyDrop::new(borrow);
+        unsafe {
+            borrow.archetype.downgrade_raw(borrow.state);
+        }
+        Ref {
+            borrow: SharedBorrow::Shared(ComponentBorrow {
+                archetype: borrow.archetype,
+                state: borrow.state,
+                index: borrow.index,
+                timer: borrow.timer.take(),
+            }),
+            target: orig.target,
+            upgradable: true,
+            _phantom: PhantomData,
+        }
+    }
 }
 
 impl<'a, T: ?Sized> Deref for RefMut<'a, T> {
@@ -362,10 +764,29 @@ pub trait ComponentRefShared<'a>: ComponentRef<'a> {}
 
 impl<'a, T: Component> ComponentRefShared<'a> for &'a T {}
 
+/// Dynamic borrow backing a [`Ref`]
+#[derive(Clone)]
+enum SharedBorrow<'a> {
+    Shared(ComponentBorrow<'a>),
+    /// A downgraded half of a split [`RefMut`], which keeps the component uniquely borrowed until
+    /// dropped or reclaimed by [`Ref::try_upgrade`]
+    Split(Arc<ComponentBorrowMut<'a>>),
+}
+
+/// Dynamic borrow backing a [`RefMut`]
+enum UniqueBorrow<'a> {
+    Unique(ComponentBorrowMut<'a>),
+    /// Shared by the halves of [`RefMut::map_split`]
+    Split(Arc<ComponentBorrowMut<'a>>),
+}
+
 struct ComponentBorrow<'a> {
     archetype: &'a Archetype,
     /// State index for the borrowed component in the `archetype`.
     state: usize,
+    /// Index of the borrowed component's entity in the `archetype`
+    index: u32,
+    timer: BorrowTimer,
 }
 
 impl<'a> ComponentBorrow<'a> {
@@ -380,12 +801,21 @@ impl<'a> ComponentBorrow<'a> {
             .get_state::<T>()
             .ok_or_else(MissingComponent::new::<T>)?;
 
+        // Borrow first, so the column can't concurrently be moved out of shared storage
+        archetype.borrow::<T>(state);
         let target =
             NonNull::new_unchecked(archetype.get_base::<T>(state).as_ptr().add(index as usize));
 
-        archetype.borrow::<T>(state);
-
-        Ok((target, Self { archetype, state }))
+        let timer = BorrowTimer::start(core::any::type_name::<T>(), false);
+        Ok((
+            target,
+            Self {
+                archetype,
+                state,
+                index,
+                timer,
+            },
+        ))
     }
 }
 
@@ -397,6 +827,8 @@ impl<'a> Clone for ComponentBorrow<'a> {
         Self {
             archetype: self.archetype,
             state: self.state,
+            index: self.index,
+            timer: self.timer.restart(),
         }
     }
 }
@@ -413,6 +845,9 @@ struct ComponentBorrowMut<'a> {
     archetype: &'a Archetype,
     /// State index for the borrowed component in the `archetype`.
     state: usize,
+    /// Index of the borrowed component's entity in the `archetype`
+    index: u32,
+    timer: BorrowTimer,
 }
 
 impl<'a> ComponentBorrowMut<'a> {
@@ -427,15 +862,29 @@ impl<'a> ComponentBorrowMut<'a> {
             .get_state::<T>()
             .ok_or_else(MissingComponent::new::<T>)?;
 
+        // Borrow first, as taking a unique borrow may move the column out of shared storage
+        archetype.borrow_mut::<T>(state);
+        archetype.mark_changed(state, index);
         let target =
             NonNull::new_unchecked(archetype.get_base::<T>(state).as_ptr().add(index as usize));
 
-        archetype.borrow_mut::<T>(state);
-
-        Ok((target, Self { archetype, state }))
+        let timer = BorrowTimer::start(core::any::type_name::<T>(), true);
+        Ok((
+            target,
+            Self {
+                archetype,
+                state,
+                index,
+                timer,
+            },
+        ))
     }
 }
 
+// Only used to release the borrow, which is atomic
+unsafe impl Send for ComponentBorrowMut<'_> {}
+unsafe impl Sync for ComponentBorrowMut<'_> {}
+
 impl<'a> Drop for ComponentBorrowMut<'a> {
     fn drop(&mut self) {
         unsafe {
@@ -443,3 +892,44 @@ impl<'a> Drop for ComponentBorrowMut<'a> {
         }
     }
 }
+
+/// A tuple of [`Clone`] component types that can be copied out of an entity together
+///
+/// The interface of this trait is a private implementation detail.
+pub trait ClonedComponents: Sized {
+    /// Clone each component out of `entity`
+    #[doc(hidden)]
+    fn clone_from(entity: EntityRef<'_>) -> Result<Self, MissingComponent>;
+}
+
+macro_rules! cloned_tuple_impl {
+    ($($name: ident),*) => {
+        impl<$($name: Component + Clone),*> ClonedComponents for ($($name,)*) {
+            #[allow(unused_variables, clippy::unused_unit)]
+            fn clone_from(entity: EntityRef<'_>) -> Result<Self, MissingComponent> {
+                Ok(($(
+                    $name::clone(&*entity.get::<&$name>().ok_or_else(MissingComponent::new::<$name>)?),
+                )*))
+            }
+        }
+    };
+}
+
+smaller_tuples_too!(
+    cloned_tuple_impl,
+    O,
+    N,
+    M,
+    L,
+    K,
+    J,
+    I,
+    H,
+    G,
+    F,
+    E,
+    D,
+    C,
+    B,
+    A
+);
@@ -0,0 +1,110 @@
+use crate::{
+    Bundle, ComponentError, ComponentRef, DynamicBundle, Entity, Frame, Query, QueryBorrow,
+    QueryMut, SpawnBatchIter,
+};
+
+/// A [`Frame`] for entities that live no longer than a single tick, such as particles or hit events
+///
+/// Entities can only be spawned, never despawned or restructured individually, so no free list or
+/// archetype transitions need be maintained: each entity takes the next ID, its components are
+/// appended to its archetype's columns, and none of a [`Frame`]'s hooks, observers, budgets or
+/// relation indexes are consulted. All entities are destroyed at once by [`reset`](Self::reset),
+/// which skips columns of types without drop glue entirely and retains every column's storage, so
+/// that the next tick's entities usually spawn without allocating.
+///
+/// Entity handles are reused after a reset, so they must not be retained across one.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// struct Particle { lifetime: f32 }
+/// let mut particles = EphemeralFrame::new();
+/// for _ in 0..2 {
+///     particles.spawn_batch((0..100).map(|_| (Particle { lifetime: 1.0 },)));
+///     for (_, p) in particles.query_mut::<&mut Particle>() {
+///         p.lifetime -= 0.5;
+///     }
+///     assert_eq!(particles.len(), 100);
+///     particles.reset();
+/// }
+/// assert!(particles.is_empty());
+/// ```
+#[derive(Default)]
+pub struct EphemeralFrame {
+    frame: Frame,
+}
+
+impl EphemeralFrame {
+    /// Create an empty frame
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an entity with certain components
+    ///
+    /// See [`Frame::spawn`].
+    pub fn spawn(&mut self, components: impl DynamicBundle) -> Entity {
+        self.frame.spawn_ephemeral(components)
+    }
+
+    /// Efficiently spawn a large number of entities with the same statically-typed components
+    ///
+    /// See [`Frame::spawn_batch`].
+    pub fn spawn_batch<I>(&mut self, iter: I) -> SpawnBatchIter<'_, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: Bundle + 'static,
+    {
+        self.frame.spawn_batch(iter)
+    }
+
+    /// Efficiently iterate over all entities that have certain components, using dynamic borrow
+    /// checking
+    ///
+    /// See [`Frame::query`].
+    pub fn query<Q: Query>(&self) -> QueryBorrow<'_, Q> {
+        self.frame.query()
+    }
+
+    /// Query a uniquely borrowed frame
+    ///
+    /// See [`Frame::query_mut`].
+    pub fn query_mut<Q: Query>(&mut self) -> QueryMut<'_, Q> {
+        self.frame.query_mut()
+    }
+
+    /// Borrow a single component of `entity`
+    ///
+    /// See [`Frame::get`].
+    pub fn get<'a, T: ComponentRef<'a>>(
+        &'a self,
+        entity: Entity,
+    ) -> Result<T::Ref, ComponentError> {
+        self.frame.get::<T>(entity)
+    }
+
+    /// Whether `entity` exists
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.frame.contains(entity)
+    }
+
+    /// Number of currently live entities
+    pub fn len(&self) -> u32 {
+        self.frame.len()
+    }
+
+    /// Whether no entities are live
+    pub fn is_empty(&self) -> bool {
+        self.frame.is_empty()
+    }
+
+    /// Destroy every entity, retaining allocated storage for reuse
+    pub fn reset(&mut self) {
+        self.frame.reset_ephemeral();
+    }
+
+    /// Access the underlying frame, e.g. for read-only APIs not mirrored here
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+}
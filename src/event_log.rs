@@ -0,0 +1,168 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Archetype, Entity, TypeInfo};
+
+/// Kind of structural operation recorded when the `event-log` feature is enabled
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum OpKind {
+    /// An entity was spawned
+    Spawn,
+    /// Entities were spawned in bulk, e.g. by [`Frame::spawn_batch`](crate::Frame::spawn_batch)
+    SpawnBatch,
+    /// Components were added to an entity
+    Insert,
+    /// Components were removed from an entity
+    Remove,
+    /// An entity was despawned
+    Despawn,
+    /// Every entity was despawned by [`Frame::clear`](crate::Frame::clear)
+    Clear,
+}
+
+/// A structural operation recorded when the `event-log` feature is enabled
+///
+/// Obtained from [`Frame::event_log`](crate::Frame::event_log).
+#[derive(Debug, Clone)]
+pub struct LoggedOp {
+    /// Value most recently passed to [`Frame::set_event_log_tick`](crate::Frame::set_event_log_tick)
+    pub tick: u64,
+    /// What happened
+    pub kind: OpKind,
+    /// The entity affected, if the operation concerned a single entity
+    pub entity: Option<Entity>,
+    /// Component types spawned, inserted, removed or despawned
+    pub types: Vec<TypeInfo>,
+}
+
+impl fmt::Display for LoggedOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            OpKind::Spawn => "spawn",
+            OpKind::SpawnBatch => "spawn batch",
+            OpKind::Insert => "insert",
+            OpKind::Remove => "remove",
+            OpKind::Despawn => "despawn",
+            OpKind::Clear => "clear",
+        };
+        write!(f, "[tick {}] {}", self.tick, kind)?;
+        if let Some(entity) = self.entity {
+            write!(f, " {:?}", entity)?;
+        }
+        for (i, ty) in self.types.iter().enumerate() {
+            f.write_str(if i == 0 { ": " } else { ", " })?;
+            match ty.type_name() {
+                Some(name) => f.write_str(name)?,
+                None => write!(f, "{:?}", ty.id())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Entry {
+    /// An entity moved between archetypes, with `None` standing for nonexistence
+    Transition {
+        entity: Entity,
+        from: Option<u32>,
+        to: Option<u32>,
+    },
+    SpawnBatch(u32),
+    Clear,
+}
+
+/// Bounded record of the most recent structural operations on a frame
+pub(crate) struct EventLog {
+    capacity: usize,
+    tick: u64,
+    entries: VecDeque<(u64, Entry)>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            tick: 0,
+            entries: VecDeque::new(),
+        }
+    }
+}
+
+impl EventLog {
+    pub fn is_enabled(&self) -> bool {
+        self.capacity != 0
+    }
+
+    pub fn set_tick(&mut self, tick: u64) {
+        self.tick = tick;
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    fn push(&mut self, entry: Entry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((self.tick, entry));
+    }
+
+    pub fn transition(&mut self, entity: Entity, from: Option<u32>, to: Option<u32>) {
+        self.push(Entry::Transition { entity, from, to });
+    }
+
+    pub fn spawn_batch(&mut self, archetype: u32) {
+        self.push(Entry::SpawnBatch(archetype));
+    }
+
+    pub fn clear(&mut self) {
+        self.push(Entry::Clear);
+    }
+
+    pub fn get(&self, archetypes: &[Archetype]) -> Vec<LoggedOp> {
+        let types = |id: u32| archetypes[id as usize].types();
+        let mut ops = Vec::with_capacity(self.entries.len());
+        for &(tick, entry) in &self.entries {
+            let mut op = |kind, entity, types: Vec<TypeInfo>| {
+                ops.push(LoggedOp {
+                    tick,
+                    kind,
+                    entity,
+                    types,
+                })
+            };
+            match entry {
+                Entry::Transition { entity, from, to } => match (from, to) {
+                    (None, Some(to)) => op(OpKind::Spawn, Some(entity), types(to).to_vec()),
+                    (Some(from), None) => op(OpKind::Despawn, Some(entity), types(from).to_vec()),
+                    (Some(from), Some(to)) => {
+                        let (old, new) = (types(from), types(to));
+                        let removed = old.iter().filter(|x| !new.contains(x));
+                        let added = new.iter().filter(|x| !old.contains(x));
+                        if removed.clone().next().is_some() {
+                            op(OpKind::Remove, Some(entity), removed.copied().collect());
+                        }
+                        if added.clone().next().is_some() {
+                            op(OpKind::Insert, Some(entity), added.copied().collect());
+                        }
+                    }
+                    (None, None) => {}
+                },
+                Entry::SpawnBatch(archetype) => {
+                    op(OpKind::SpawnBatch, None, types(archetype).to_vec())
+                }
+                Entry::Clear => op(OpKind::Clear, None, Vec::new()),
+            }
+        }
+        ops
+    }
+}
@@ -0,0 +1,58 @@
+use alloc::vec::Vec;
+use core::any::{Any, TypeId};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+
+use crate::Component;
+
+/// Metadata attached to a component type by [`set_type_extension`]
+pub type TypeExtension = &'static (dyn Any + Send + Sync);
+
+/// Attach `extension` to the component type `T`, making it available from every
+/// [`TypeInfo`](crate::TypeInfo) describing `T`
+///
+/// Allows frameworks to reach per-type data, e.g. reflection metadata or script bindings, directly
+/// from the type information exposed by archetypes, builders and command buffers, rather than
+/// maintaining a separate map keyed by [`TypeId`]. Applies to every [`Frame`](crate::Frame).
+///
+/// Panics if `T` already has an extension.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// struct Health(u32);
+/// struct ScriptName(&'static str);
+///
+/// set_type_extension::<Health>(&ScriptName("health"));
+/// let mut frame = Frame::new();
+/// frame.spawn((Health(10),));
+/// let archetype = frame.archetypes().find(|x| x.has::<Health>()).unwrap();
+/// let extension = archetype.component_type_info()[0].extension().unwrap();
+/// assert_eq!(extension.downcast_ref::<ScriptName>().unwrap().0, "health");
+/// ```
+pub fn set_type_extension<T: Component>(extension: TypeExtension) {
+    let id = TypeId::of::<T>();
+    let mut extensions = EXTENSIONS.lock();
+    match extensions.binary_search_by_key(&id, |&(x, _)| x) {
+        Ok(_) => panic!("{} already has an extension", core::any::type_name::<T>()),
+        Err(index) => extensions.insert(index, (id, extension)),
+    }
+    ANY.store(true, Ordering::Release);
+}
+
+/// Whether any extension was ever set, allowing lookups to be skipped entirely
+static ANY: AtomicBool = AtomicBool::new(false);
+
+/// Extension of each type, sorted by `TypeId`
+static EXTENSIONS: Mutex<Vec<(TypeId, TypeExtension)>> = Mutex::new(Vec::new());
+
+/// Find the extension of the type identified by `id`
+pub(crate) fn lookup(id: TypeId) -> Option<TypeExtension> {
+    if !ANY.load(Ordering::Acquire) {
+        return None;
+    }
+    let extensions = EXTENSIONS.lock();
+    let index = extensions.binary_search_by_key(&id, |&(x, _)| x).ok()?;
+    Some(extensions[index].1)
+}
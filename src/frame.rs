@@ -12,7 +12,9 @@ use core::convert::TryFrom;
 use core::hash::{BuildHasherDefault, Hasher};
 use spin::Mutex;
 
-use core::{fmt, ptr};
+use core::mem::ManuallyDrop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::{fmt, mem, ptr};
 
 #[cfg(feature = "std")]
 use std::error::Error;
@@ -20,12 +22,28 @@ use std::error::Error;
 use hashbrown::hash_map::{Entry, HashMap};
 
 use crate::alloc::boxed::Box;
+use crate::alloc::collections::VecDeque;
+use crate::alloc::sync::Arc;
 use crate::archetype::{Archetype, TypeIdMap, TypeInfo};
+use crate::budget::{Budget, BudgetAction, BudgetExceeded, Budgets};
 use crate::entities::{Entities, EntityMeta, Location, ReserveEntitiesIterator};
-use crate::query::{assert_borrow, assert_distinct};
+#[cfg(feature = "event-log")]
+use crate::event_log::{EventLog, LoggedOp};
+#[cfg(feature = "entity-history")]
+use crate::history::{ArchetypeTransition, History};
+use crate::hooks::{FrameEvent, HookId, Hooks};
+use crate::observer::{MatchEvent, ObserverId, Observers};
+use crate::previous::{Shadow, ShadowColumn};
+use crate::query::{assert_borrow, assert_distinct, get_filtered};
+use crate::relation;
+use crate::split::{assert_disjoint, ReadPartition, WritePartition};
 use crate::{
-    Bundle, ColumnBatch, ComponentRef, DynamicBundle, Entity, EntityRef, Fetch, MissingComponent,
-    NoSuchEntity, Query, QueryBorrow, QueryMut, QueryOne, TakenEntity, View, ViewBorrow,
+    BufferTooSmall, Bundle, ClonedComponents, ColumnBatch, CommandBuffer, ComponentRef,
+    ComponentRegistry, DeferredOps, DynamicBundle, Entity, EntityBuilder, EntityRef, Fetch,
+    FrameBuilder, FrameVisitor, InterleavedLayout, InvalidComponent, MissingComponent,
+    NoSuchEntity, ParseEntityTokenError, PreviousView, Query, QueryBorrow, QueryMismatch, QueryMut,
+    QueryOne, ReadTransaction, Relation, SharedAllocator, TakenEntity, UnregisteredComponent, View,
+    ViewBorrow, WriteTransaction,
 };
 
 /// An unordered collection of entities, each having any number of distinctly typed components
@@ -57,9 +75,50 @@ pub struct Frame {
     /// Maps source archetype and static bundle types to the archetype that an entity is moved to
     /// after removing the components from that bundle.
     remove_edges: IndexTypeIdMap<u32>,
+    hooks: Hooks,
+    observers: Observers,
+    /// Commands recorded through [`DeferredOps`], applied on [`Frame::apply_deferred`]
+    deferred: Mutex<CommandBuffer>,
+    /// Entities reserved by [`CommandBuffer::spawn_n`] whose buffer was cleared or dropped before
+    /// being run, despawned on the next [`Frame::flush`]
+    abandoned: Arc<Mutex<Vec<Entity>>>,
+    /// Number of live [`ReadTransaction`]s
+    readers: Arc<AtomicUsize>,
+    /// Previous values of component types registered with [`Frame::track_previous`]
+    shadows: TypeIdMap<Box<dyn Shadow>>,
+    /// Number of archetypes whose creation has been reported to hooks
+    reported_archetypes: u32,
+    /// Minimum column allocation, in bytes, reported by [`FrameEvent::ColumnGrown`]
+    growth_threshold: Option<usize>,
+    /// Source of entity IDs, if shared with other frames
+    allocator: Option<SharedAllocator>,
+    /// Validators applied to spawned and inserted components in debug builds
+    validator: Option<Arc<ComponentRegistry>>,
+    /// Number of spawns for which IDs freed by [`Frame::invalidate_handles`] aren't reused
+    handle_quarantine: u32,
+    /// Ordering applied by [`Frame::regroup`]
+    group_key: Option<GroupOrder>,
+    /// Component types removed by [`Frame::clear_transient`], sorted
+    transient: Vec<TypeInfo>,
+    /// Detaches holders of each relation type passed to [`Frame::register_relation`] from a
+    /// despawned target
+    relations: TypeIdMap<fn(&mut Frame, Entity)>,
+    /// Holders of each relation type passed to [`Frame::index_references`], by target
+    references: relation::References,
+    /// Limits enforced by [`Frame::spawn`]
+    budget: Option<Budgets>,
+    /// Recent archetype transitions of each entity
+    #[cfg(feature = "entity-history")]
+    history: History,
+    /// Recent structural operations
+    #[cfg(feature = "event-log")]
+    event_log: EventLog,
     id: u64,
 }
 
+/// Computes the order of an archetype's entities by a group key, if they're out of order
+type GroupOrder = fn(&Archetype) -> Option<Vec<u32>>;
+
 impl Frame {
     /// Create an empty frame
     pub fn new() -> Self {
@@ -78,10 +137,142 @@ impl Frame {
             bundle_to_archetype: HashMap::default(),
             insert_edges: HashMap::default(),
             remove_edges: HashMap::default(),
+            hooks: Hooks::default(),
+            observers: Observers::default(),
+            deferred: Mutex::new(CommandBuffer::new()),
+            abandoned: Arc::default(),
+            readers: Arc::new(AtomicUsize::new(0)),
+            shadows: HashMap::default(),
+            reported_archetypes: 1,
+            growth_threshold: None,
+            allocator: None,
+            validator: None,
+            handle_quarantine: 1024,
+            group_key: None,
+            transient: Vec::new(),
+            relations: HashMap::default(),
+            references: relation::References::default(),
+            budget: None,
+            #[cfg(feature = "entity-history")]
+            history: History::default(),
+            #[cfg(feature = "event-log")]
+            event_log: EventLog::default(),
             id,
         }
     }
 
+    /// Create an empty frame with room for `entities` entities in `archetypes` archetypes
+    ///
+    /// Component storage is allocated as archetypes are populated. Use
+    /// [`reserve`](Self::reserve) to preallocate it for a known bundle type.
+    pub fn with_capacity(entities: u32, archetypes: usize) -> Self {
+        let mut frame = Self::new();
+        frame.reserve_capacity(entities, archetypes);
+        frame
+    }
+
+    /// Configure a frame before creating it
+    pub fn builder() -> FrameBuilder {
+        FrameBuilder::new()
+    }
+
+    pub(crate) fn reserve_capacity(&mut self, entities: u32, archetypes: usize) {
+        self.entities.reserve(entities);
+        self.archetypes.archetypes.reserve(archetypes);
+        self.archetypes.index.reserve(archetypes);
+    }
+
+    /// Create an empty frame which allocates entity IDs from `allocator`
+    ///
+    /// Entities spawned by frames sharing an allocator never collide, so they can be moved between
+    /// those frames with [`transfer`](Self::transfer). Handles passed to [`spawn_at`](Self::spawn_at)
+    /// are not checked against the allocator.
+    ///
+    /// Such frames can't allocate IDs concurrently or in bulk, so
+    /// [`reserve_entity`](Self::reserve_entity), [`reserve_entities`](Self::reserve_entities),
+    /// spawning through [`defer`](Self::defer), [`spawn_batch`](Self::spawn_batch) and
+    /// [`spawn_column_batch`](Self::spawn_column_batch) panic.
+    pub fn with_allocator(allocator: SharedAllocator) -> Self {
+        Self {
+            allocator: Some(allocator),
+            ..Self::new()
+        }
+    }
+
+    /// Assemble a frame from prebuilt columns, each with the handles of the entities it stores
+    ///
+    /// Intended for loading frames baked offline, e.g. by a level editor: batches may be built in
+    /// parallel or directly from memory-mapped data, and assembling them does no per-entity work
+    /// beyond recording each entity's location. IDs below the largest one given that aren't
+    /// assigned to any entity are free for reuse.
+    ///
+    /// Panics if the number of handles for a batch doesn't match its length.
+    ///
+    /// # Safety
+    ///
+    /// Each handle's ID must appear at most once across all batches. This is only checked in debug
+    /// builds.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut ty = ColumnBatchType::new();
+    /// ty.add::<i32>();
+    /// let mut batch = ty.into_batch(2);
+    /// let mut writer = batch.writer::<i32>().unwrap();
+    /// writer.push(1).unwrap();
+    /// writer.push(2).unwrap();
+    /// let handles = [Entity::from_bits(1 << 32 | 3).unwrap(), Entity::from_bits(1 << 32).unwrap()];
+    /// let frame = unsafe { Frame::from_raw_parts([(batch.build().unwrap(), &handles[..])]) };
+    /// assert_eq!(*frame.get::<&i32>(handles[0]).unwrap(), 1);
+    /// assert_eq!(frame.len(), 2);
+    /// ```
+    pub unsafe fn from_raw_parts<'a>(
+        batches: impl IntoIterator<Item = (ColumnBatch, &'a [Entity])>,
+    ) -> Self {
+        let mut frame = Self::new();
+        for (batch, handles) in batches {
+            let archetype = batch.0;
+            assert_eq!(
+                handles.len(),
+                archetype.len() as usize,
+                "number of entity IDs {} must match number of entities {}",
+                handles.len(),
+                archetype.len()
+            );
+            frame.archetypes.assert_batch_fits(&archetype);
+            let (archetype_id, base) = frame.archetypes.insert_batch(archetype);
+            frame.report_growth(archetype_id);
+            let archetype = &mut frame.archetypes.archetypes[archetype_id as usize];
+            for (&handle, index) in handles.iter().zip(base..) {
+                archetype.set_entity_id(index as usize, handle.id());
+                frame.entities.place(
+                    handle,
+                    Location {
+                        archetype: archetype_id,
+                        index,
+                    },
+                );
+            }
+        }
+        frame.entities.rebuild_freelist();
+        frame
+    }
+
+    /// The allocator passed to [`with_allocator`](Self::with_allocator), if any
+    pub fn allocator(&self) -> Option<&SharedAllocator> {
+        self.allocator.as_ref()
+    }
+
+    /// Panic if entity IDs come from a [`SharedAllocator`], which `operation` doesn't support
+    fn assert_local_allocator(&self, operation: &str) {
+        assert!(
+            self.allocator.is_none(),
+            "{} is unsupported by frames with a shared allocator",
+            operation
+        );
+    }
+
     /// Create an entity with certain components
     ///
     /// Returns the ID of the newly created entity.
@@ -101,16 +292,62 @@ impl Frame {
     /// let a = frame.spawn((123, "abc"));
     /// let b = frame.spawn((456, true));
     /// ```
+    ///
+    /// Panics if the spawn is rejected by the policy passed to [`set_budget`](Self::set_budget).
     pub fn spawn(&mut self, components: impl DynamicBundle) -> Entity {
+        self.try_spawn(components)
+            .unwrap_or_else(|e| panic!("spawn rejected: {}", e))
+    }
+
+    /// Create an entity with certain components, then pass it to `f` before returning
+    ///
+    /// Allows initialization that depends on the new entity's handle, such as storing it in one
+    /// of its own components, without looking the entity up again.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// struct Owner(Option<Entity>);
+    ///
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn_with((Owner(None), 123), |entity| {
+    ///     entity.get::<&mut Owner>().unwrap().0 = Some(entity.entity());
+    /// });
+    /// assert_eq!(frame.get::<&Owner>(e).unwrap().0, Some(e));
+    /// ```
+    pub fn spawn_with(
+        &mut self,
+        components: impl DynamicBundle,
+        f: impl FnOnce(EntityRef<'_>),
+    ) -> Entity {
+        let entity = self.spawn(components);
+        f(self.entity(entity).unwrap());
+        entity
+    }
+
+    /// Like [`spawn`](Self::spawn), but fails rather than panicking if the spawn is rejected by
+    /// the policy passed to [`set_budget`](Self::set_budget)
+    pub fn try_spawn(&mut self, components: impl DynamicBundle) -> Result<Entity, BudgetExceeded> {
         // Ensure all entity allocations are accounted for so `self.entities` can realloc if
         // necessary
         self.flush();
 
-        let entity = self.entities.alloc();
+        let archetype_id = self.bundle_archetype(&components);
+        self.admit(self.spawn_growth(archetype_id, 1), None)?;
+        self.archetypes.archetypes[archetype_id as usize].assert_fits(1);
+        let entity = match self.allocator {
+            Some(ref allocator) => {
+                let entity = allocator.alloc();
+                self.entities.alloc_at(entity);
+                entity
+            }
+            None => self.entities.alloc(),
+        };
 
-        self.spawn_inner(entity, components);
+        self.spawn_inner(archetype_id, entity, components);
+        self.record_spawned([entity]);
 
-        entity
+        Ok(entity)
     }
 
     /// Create an entity with certain components and a specific [`Entity`] handle.
@@ -134,11 +371,138 @@ impl Frame {
     /// frame.spawn_at(a, (789, "ABC"));
     /// assert!(frame.contains(a));
     /// ```
+    ///
+    /// Panics if the spawn is rejected by the policy passed to [`set_budget`](Self::set_budget).
     pub fn spawn_at(&mut self, handle: Entity, components: impl DynamicBundle) {
         // Ensure all entity allocations are accounted for so `self.entities` can realloc if
         // necessary
         self.flush();
 
+        let archetype_id = self.bundle_archetype(&components);
+        let replaced = self.live_entity(handle.id()).map(|(entity, _)| entity);
+        self.admit(self.spawn_at_growth(archetype_id, &[handle]), replaced)
+            .unwrap_or_else(|e| panic!("spawn rejected: {}", e));
+        self.spawn_at_inner(archetype_id, handle, components);
+        self.record_spawned([handle]);
+    }
+
+    /// Spawn many entities with the same static component types at known handles
+    ///
+    /// Like calling [`spawn_at`](Self::spawn_at) for each item, but like
+    /// [`spawn_column_batch_at`](Self::spawn_column_batch_at) finds the archetype and reserves
+    /// storage for every entity up front, writes components straight into its columns, and
+    /// records every entity's location in one pass. Useful for loading saved entities.
+    ///
+    /// Panics if a handle's ID occurs more than once, or if the spawn is rejected by the policy
+    /// passed to [`set_budget`](Self::set_budget), which is consulted once for the whole batch.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut source = Frame::new();
+    /// let saved = (0..3)
+    ///     .map(|i| (source.spawn(()), (i, "abc")))
+    ///     .collect::<Vec<_>>();
+    /// let mut frame = Frame::new();
+    /// frame.spawn_batch_at(saved.iter().copied());
+    /// for &(entity, (i, _)) in &saved {
+    ///     assert_eq!(*frame.get::<&i32>(entity).unwrap(), i);
+    /// }
+    /// ```
+    pub fn spawn_batch_at<I, B>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (Entity, B)>,
+        B: Bundle + 'static,
+    {
+        self.begin_structural_change();
+        let (handles, bundles) = iter.into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
+        let mut ids = handles.iter().map(|x| x.id()).collect::<Vec<_>>();
+        ids.sort_unstable();
+        assert!(
+            ids.windows(2).all(|x| x[0] != x[1]),
+            "entity IDs passed to spawn_batch_at must be distinct"
+        );
+        let count = u32::try_from(handles.len()).expect("iterator too large");
+        let archetype_id = self.reserve_inner::<B>(count);
+        for &handle in &handles {
+            if let Some(loc) = self.entities.live_location(handle.id()) {
+                self.archetypes.archetypes[loc.archetype as usize].assert_unpinned();
+            }
+        }
+        self.admit(self.spawn_at_growth(archetype_id, &handles), None)
+            .unwrap_or_else(|e| panic!("spawn rejected: {}", e));
+
+        // Drop components of entities that will be replaced
+        for &handle in &handles {
+            let replaced = self.live_entity(handle.id());
+            if let Some(loc) = self.entities.alloc_at(handle) {
+                if let Some(moved) = unsafe {
+                    self.archetypes.archetypes[loc.archetype as usize].remove(loc.index, true)
+                } {
+                    self.entities.meta[moved as usize].location.index = loc.index;
+                }
+            }
+            if let Some((old, archetype)) = replaced {
+                self.notify_observers(old, Some(archetype), None);
+            }
+        }
+
+        // Move components straight into the reserved rows, then take ownership of them at once
+        let archetype = &mut self.archetypes.archetypes[archetype_id as usize];
+        let base = archetype.len();
+        unsafe {
+            let columns = archetype
+                .types()
+                .iter()
+                .enumerate()
+                .map(|(state, ty)| {
+                    archetype.mark_written(state);
+                    (
+                        archetype.get_dynamic(ty.id(), 0, 0).unwrap(),
+                        ty.layout().size(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            for ((index, &handle), components) in (base..).zip(&handles).zip(bundles) {
+                archetype.set_entity_id(index as usize, handle.id());
+                components.put(|ptr, ty| {
+                    let (column, size) = columns[archetype.get_state_dynamic(ty.id()).unwrap()];
+                    ptr::copy_nonoverlapping(ptr, column.as_ptr().add(size * index as usize), size);
+                });
+            }
+            archetype.set_len(base + count);
+        }
+        for (&handle, index) in handles.iter().zip(base..) {
+            self.entities.meta[handle.id() as usize].location = Location {
+                archetype: archetype_id,
+                index,
+            };
+        }
+
+        if self.tracks_transitions() {
+            for &handle in &handles {
+                self.notify_observers(handle, None, Some(archetype_id));
+            }
+        }
+        for &handle in &handles {
+            self.debug_validate(handle);
+        }
+        self.record_spawned(handles);
+    }
+
+    /// Spawn `components`, which must belong in archetype `archetype_id`, at `handle`
+    fn spawn_at_inner(
+        &mut self,
+        archetype_id: u32,
+        handle: Entity,
+        components: impl DynamicBundle,
+    ) {
+        if let Some(loc) = self.entities.live_location(handle.id()) {
+            self.archetypes.archetypes[loc.archetype as usize].assert_unpinned();
+        }
+        self.archetypes.archetypes[archetype_id as usize].assert_fits(1);
+
+        let replaced = self.live_entity(handle.id());
         let loc = self.entities.alloc_at(handle);
         if let Some(loc) = loc {
             if let Some(moved) = unsafe {
@@ -147,12 +511,16 @@ impl Frame {
                 self.entities.meta[moved as usize].location.index = loc.index;
             }
         }
+        if let Some((old, archetype)) = replaced {
+            self.notify_observers(old, Some(archetype), None);
+        }
 
-        self.spawn_inner(handle, components);
+        self.spawn_inner(archetype_id, handle, components);
     }
 
-    fn spawn_inner(&mut self, entity: Entity, components: impl DynamicBundle) {
-        let archetype_id = match components.key() {
+    /// Find or create the archetype for entities with exactly the components in `components`
+    fn bundle_archetype(&mut self, components: &impl DynamicBundle) -> u32 {
+        match components.key() {
             Some(k) => {
                 let archetypes = &mut self.archetypes;
                 *self.bundle_to_archetype.entry(k).or_insert_with(|| {
@@ -160,8 +528,10 @@ impl Frame {
                 })
             }
             None => components.with_ids(|ids| self.archetypes.get(ids, || components.type_info())),
-        };
+        }
+    }
 
+    fn spawn_inner(&mut self, archetype_id: u32, entity: Entity, components: impl DynamicBundle) {
         let archetype = &mut self.archetypes.archetypes[archetype_id as usize];
         unsafe {
             let index = archetype.allocate(entity.id);
@@ -173,6 +543,113 @@ impl Frame {
                 index,
             };
         }
+        self.report_growth(archetype_id);
+        self.notify_observers(entity, None, Some(archetype_id));
+        self.debug_validate(entity);
+    }
+
+    /// Spawn into a frame that's only ever emptied wholesale by `reset_ephemeral`, as for
+    /// [`EphemeralFrame`](crate::EphemeralFrame)
+    ///
+    /// Takes the next entity ID without consulting the free list, and skips the bookkeeping of
+    /// hooks, observers, budgets and relations, none of which an `EphemeralFrame` can configure.
+    pub(crate) fn spawn_ephemeral(&mut self, components: impl DynamicBundle) -> Entity {
+        let archetype_id = self.bundle_archetype(&components);
+        let archetype = &mut self.archetypes.archetypes[archetype_id as usize];
+        let id = self.entities.meta.len() as u32;
+        let index = unsafe {
+            let index = archetype.allocate(id);
+            components.put(|ptr, ty| {
+                archetype.put_dynamic(ptr, ty.id(), ty.layout().size(), index);
+            });
+            index
+        };
+        self.entities.alloc_bump(Location {
+            archetype: archetype_id,
+            index,
+        })
+    }
+
+    /// Drop every entity of a frame populated by `spawn_ephemeral` and `spawn_batch`, retaining
+    /// storage
+    ///
+    /// Columns of components without drop glue are discarded without visiting them.
+    pub(crate) fn reset_ephemeral(&mut self) {
+        for x in &mut self.archetypes.archetypes {
+            x.clear();
+        }
+        self.entities.clear();
+    }
+
+    /// The live entity with ID `id`, if any, and the archetype it's in
+    fn live_entity(&self, id: u32) -> Option<(Entity, u32)> {
+        let loc = self.entities.live_location(id)?;
+        let entity = Entity {
+            id,
+            generation: self.entities.meta[id as usize].generation,
+        };
+        Some((entity, loc.archetype))
+    }
+
+    /// Report `entity` moving from archetype `from` to archetype `to` to observers, with `None`
+    /// standing for nonexistence
+    fn notify_observers(&mut self, entity: Entity, from: Option<u32>, to: Option<u32>) {
+        #[cfg(feature = "entity-history")]
+        self.history.record(entity, from, to);
+        #[cfg(feature = "event-log")]
+        self.event_log.transition(entity, from, to);
+        if !self.references.is_empty() {
+            let index = self.entities.meta[entity.id as usize].location.index;
+            let row = to.map(|archetype| (&self.archetypes.archetypes[archetype as usize], index));
+            // Structural changes require unique access, so no columns are borrowed
+            unsafe {
+                self.references.update(entity, row);
+            }
+        }
+        if self.observers.is_empty() {
+            return;
+        }
+        self.observers.update(&self.archetypes.archetypes);
+        self.observers.transition(entity, from, to);
+    }
+
+    /// Whether `notify_observers` has any effect
+    fn tracks_transitions(&self) -> bool {
+        #[cfg(feature = "entity-history")]
+        if self.history.is_enabled() {
+            return true;
+        }
+        #[cfg(feature = "event-log")]
+        if self.event_log.is_enabled() {
+            return true;
+        }
+        !self.observers.is_empty() || !self.references.is_empty()
+    }
+
+    /// Report archetypes created since the last call, and reallocations of archetype
+    /// `archetype_id`'s storage, to hooks
+    fn report_growth(&mut self, archetype_id: u32) {
+        let grown = self.archetypes.archetypes[archetype_id as usize].take_grown();
+        let created = self.reported_archetypes..self.archetypes.generation();
+        self.reported_archetypes = created.end;
+        if self.hooks.is_empty() {
+            return;
+        }
+        for id in created {
+            let archetype = &self.archetypes.archetypes[id as usize];
+            self.hooks
+                .emit(&FrameEvent::ArchetypeCreated(archetype.types()));
+        }
+        let (true, Some(threshold)) = (grown, self.growth_threshold) else {
+            return;
+        };
+        let archetype = &self.archetypes.archetypes[archetype_id as usize];
+        for &ty in archetype.types() {
+            let bytes = ty.layout().size() * archetype.capacity() as usize;
+            if bytes >= threshold {
+                self.hooks.emit(&FrameEvent::ColumnGrown { ty, bytes });
+            }
+        }
     }
 
     /// Efficiently spawn a large number of entities with the same statically-typed components
@@ -180,6 +657,10 @@ impl Frame {
     /// Faster than calling [`spawn`](Self::spawn) repeatedly with the same components, but requires
     /// that component types are known at compile time.
     ///
+    /// Panics if the spawn is rejected by the policy passed to [`set_budget`](Self::set_budget),
+    /// which is consulted once, before any entity is spawned, with the number of entities given by
+    /// the lower bound of `iter`'s size hint.
+    ///
     /// # Example
     /// ```
     /// # use moss_hecs::*;
@@ -194,6 +675,7 @@ impl Frame {
         I: IntoIterator,
         I::Item: Bundle + 'static,
     {
+        self.assert_local_allocator("spawn_batch");
         // Ensure all entity allocations are accounted for so `self.entities` can realloc if
         // necessary
         self.flush();
@@ -203,10 +685,18 @@ impl Frame {
         let archetype_id = self.reserve_inner::<I::Item>(
             u32::try_from(upper.unwrap_or(lower)).expect("iterator too large"),
         );
+        self.admit(self.spawn_growth(archetype_id, lower as u32), None)
+            .unwrap_or_else(|e| panic!("spawn rejected: {}", e));
+        self.observers.update(&self.archetypes.archetypes);
+        #[cfg(feature = "event-log")]
+        self.event_log.spawn_batch(archetype_id);
 
         SpawnBatchIter {
             inner: iter,
             entities: &mut self.entities,
+            observers: &mut self.observers,
+            references: &mut self.references,
+            budgeted: self.budget.as_mut().map(|budget| &mut budget.order),
             archetype_id,
             archetype: &mut self.archetypes.archetypes[archetype_id as usize],
         }
@@ -216,13 +706,116 @@ impl Frame {
     ///
     /// The fastest, but most specialized, way to spawn large numbers of entities. Useful for high
     /// performance deserialization. Supports dynamic component types.
+    ///
+    /// Panics if the spawn is rejected by the policy passed to [`set_budget`](Self::set_budget),
+    /// which is consulted once for the whole batch.
     pub fn spawn_column_batch(&mut self, batch: ColumnBatch) -> SpawnColumnBatchIter<'_> {
+        self.assert_local_allocator("spawn_column_batch");
         self.flush();
 
         let archetype = batch.0;
         let entity_count = archetype.len();
+        self.archetypes.assert_batch_fits(&archetype);
+        self.admit_batch(&archetype, None);
         // Store component data
         let (archetype_id, base) = self.archetypes.insert_batch(archetype);
+        self.spawn_column_batch_inner(archetype_id, base, entity_count)
+    }
+
+    /// Spawn an entity for each bundle produced by a parallel iterator, constructing the bundles
+    /// on rayon's thread pool
+    ///
+    /// Each worker moves the bundles it produces into its own [`ColumnBatch`], and the batches are
+    /// then spawned one after another. Suits procedural generation of very large numbers of
+    /// entities, where constructing components dominates the cost of spawning them. Returns the new
+    /// entities in the iterator's order.
+    ///
+    /// Panics if the frame has a [`SharedAllocator`], or if a batch is rejected by the policy
+    /// passed to [`set_budget`](Self::set_budget), which is consulted once per batch.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// use rayon::prelude::*;
+    /// let mut frame = Frame::new();
+    /// let entities = frame.par_extend((0..10_000).into_par_iter().map(|i| (i, i as f32 * 0.5)));
+    /// assert_eq!(entities.len(), 10_000);
+    /// assert_eq!(*frame.get::<&f32>(entities[42]).unwrap(), 21.0);
+    /// ```
+    #[cfg(feature = "parallel")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "parallel")))]
+    pub fn par_extend<I>(&mut self, iter: I) -> Vec<Entity>
+    where
+        I: rayon::iter::IntoParallelIterator,
+        I::Item: Bundle + Send + 'static,
+    {
+        use rayon::iter::ParallelIterator;
+
+        self.assert_local_allocator("par_extend");
+        let batches = iter
+            .into_par_iter()
+            .fold(Vec::new, |mut bundles, bundle| {
+                bundles.push(bundle);
+                bundles
+            })
+            .filter(|bundles| !bundles.is_empty())
+            .map(ColumnBatch::from_bundles)
+            .collect::<Vec<_>>();
+        let mut entities = Vec::with_capacity(batches.iter().map(|x| x.0.len() as usize).sum());
+        for batch in batches {
+            entities.extend(self.spawn_column_batch(batch));
+        }
+        entities
+    }
+
+    /// Like [`spawn_column_batch`](Self::spawn_column_batch), but moves components out of `batch`
+    /// rather than consuming it
+    ///
+    /// `batch` is left empty with its storage intact, so that pipelines which build a batch every
+    /// tick can refill it with [`ColumnBatch::into_builder`] rather than reallocating its columns.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let mut ty = ColumnBatchType::new();
+    /// ty.add::<u32>();
+    /// let mut builder = ty.into_batch(100);
+    /// for tick in 0..3 {
+    ///     let mut writer = builder.writer::<u32>().unwrap();
+    ///     for i in 0..100 {
+    ///         writer.push(tick * 100 + i).unwrap();
+    ///     }
+    ///     let mut batch = builder.build().unwrap();
+    ///     frame.spawn_column_batch_retaining(&mut batch);
+    ///     builder = batch.into_builder(100);
+    /// }
+    /// assert_eq!(frame.len(), 300);
+    /// ```
+    pub fn spawn_column_batch_retaining(
+        &mut self,
+        batch: &mut ColumnBatch,
+    ) -> SpawnColumnBatchIter<'_> {
+        self.assert_local_allocator("spawn_column_batch_retaining");
+        self.flush();
+
+        let entity_count = batch.0.len();
+        self.archetypes.assert_batch_fits(&batch.0);
+        self.admit_batch(&batch.0, None);
+        let (archetype_id, base) = self.archetypes.insert_batch_from(&mut batch.0);
+        self.spawn_column_batch_inner(archetype_id, base, entity_count)
+    }
+
+    /// Allocate IDs for `entity_count` entities just stored in `archetype_id` starting at `base`
+    fn spawn_column_batch_inner(
+        &mut self,
+        archetype_id: u32,
+        base: u32,
+        entity_count: u32,
+    ) -> SpawnColumnBatchIter<'_> {
+        self.report_growth(archetype_id);
+        #[cfg(feature = "event-log")]
+        self.event_log.spawn_batch(archetype_id);
 
         let archetype = &mut self.archetypes.archetypes[archetype_id as usize];
         let id_alloc = self.entities.alloc_many(entity_count, archetype_id, base);
@@ -235,6 +828,37 @@ impl Frame {
             index += 1;
         }
 
+        if !self.observers.is_empty() {
+            self.observers.update(&self.archetypes.archetypes);
+            let mut id_alloc_clone = id_alloc.clone();
+            while let Some(id) = id_alloc_clone.next(&self.entities) {
+                let entity = unsafe { self.entities.resolve_unknown_gen(id) };
+                self.observers.transition(entity, None, Some(archetype_id));
+            }
+        }
+        if !self.references.is_empty() {
+            let archetype = &self.archetypes.archetypes[archetype_id as usize];
+            for index in base..archetype.len() {
+                // Entities spawned into `archetype` are live, and unique access to `self` rules
+                // out borrows
+                unsafe {
+                    let entity = self
+                        .entities
+                        .resolve_unknown_gen(archetype.ids()[index as usize]);
+                    self.references.update(entity, Some((archetype, index)));
+                }
+            }
+        }
+
+        if let Some(ref mut budget) = self.budget {
+            let mut id_alloc_clone = id_alloc.clone();
+            while let Some(id) = id_alloc_clone.next(&self.entities) {
+                budget
+                    .order
+                    .push_back(unsafe { self.entities.resolve_unknown_gen(id) });
+            }
+        }
+
         // Return iterator over new IDs
         SpawnColumnBatchIter {
             pending_end: id_alloc.pending_end,
@@ -245,6 +869,7 @@ impl Frame {
 
     /// Hybrid of [`spawn_column_batch`](Self::spawn_column_batch) and [`spawn_at`](Self::spawn_at)
     pub fn spawn_column_batch_at(&mut self, handles: &[Entity], batch: ColumnBatch) {
+        self.begin_structural_change();
         let archetype = batch.0;
         assert_eq!(
             handles.len(),
@@ -253,9 +878,17 @@ impl Frame {
             handles.len(),
             archetype.len()
         );
+        for &handle in handles {
+            if let Some(loc) = self.entities.live_location(handle.id()) {
+                self.archetypes.archetypes[loc.archetype as usize].assert_unpinned();
+            }
+        }
+        self.archetypes.assert_batch_fits(&archetype);
+        self.admit_batch(&archetype, Some(handles));
 
         // Drop components of entities that will be replaced
         for &handle in handles {
+            let replaced = self.live_entity(handle.id());
             let loc = self.entities.alloc_at(handle);
             if let Some(loc) = loc {
                 if let Some(moved) = unsafe {
@@ -264,10 +897,14 @@ impl Frame {
                     self.entities.meta[moved as usize].location.index = loc.index;
                 }
             }
+            if let Some((old, archetype)) = replaced {
+                self.notify_observers(old, Some(archetype), None);
+            }
         }
 
         // Store components
         let (archetype_id, base) = self.archetypes.insert_batch(archetype);
+        self.report_growth(archetype_id);
 
         // Fix up entity IDs
         let archetype = &mut self.archetypes.archetypes[archetype_id as usize];
@@ -278,6 +915,10 @@ impl Frame {
                 index: index as u32,
             };
         }
+        for &handle in handles {
+            self.notify_observers(handle, None, Some(archetype_id));
+        }
+        self.record_spawned(handles.iter().copied());
     }
 
     /// Allocate many entities ID concurrently
@@ -290,14 +931,38 @@ impl Frame {
     /// can also be done explicitly by calling [`flush`](Self::flush).
     ///
     /// Useful for reserving an ID that will later have components attached to it with `insert`.
-    pub fn reserve_entities(&self, count: u32) -> ReserveEntitiesIterator {
+    pub fn reserve_entities(&self, count: u32) -> ReserveEntitiesIterator<'_> {
+        self.assert_local_allocator("reserve_entities");
         self.entities.reserve_entities(count)
     }
 
+    /// Allocate many entity IDs concurrently, forming a contiguous range
+    ///
+    /// Like [`reserve_entities`](Self::reserve_entities), but rather than recycling the IDs of
+    /// despawned entities, hands out brand new IDs whose range is exposed by
+    /// [`ReserveEntitiesIterator::ids`]. Useful for indexing external per-entity storage, e.g. GPU
+    /// buffers, directly by [`Entity::id`]. Returns `None` if freed IDs are awaiting reuse; a
+    /// [`flush`](Self::flush) doesn't change that, so fall back to `reserve_entities` in that case.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// frame.spawn((1,));
+    /// let reserved = frame.reserve_entities_contiguous(3).unwrap();
+    /// assert_eq!(reserved.ids(), Some(1..4));
+    /// assert!(reserved.map(|e| e.id()).eq(1..4));
+    /// ```
+    pub fn reserve_entities_contiguous(&self, count: u32) -> Option<ReserveEntitiesIterator<'_>> {
+        self.assert_local_allocator("reserve_entities_contiguous");
+        self.entities.reserve_entities_contiguous(count)
+    }
+
     /// Allocate an entity ID concurrently
     ///
     /// See [`reserve_entities`](Self::reserve_entities).
     pub fn reserve_entity(&self) -> Entity {
+        self.assert_local_allocator("reserve_entity");
         self.entities.reserve_entity()
     }
 
@@ -305,305 +970,1531 @@ impl Frame {
     ///
     /// See also [`take`](Self::take).
     pub fn despawn(&mut self, entity: Entity) -> Result<(), NoSuchEntity> {
+        self.despawn_inner(entity, None)
+    }
+
+    /// Despawn `entity`, quarantining its ID for `quarantine` spawns if set
+    fn despawn_inner(
+        &mut self,
+        entity: Entity,
+        quarantine: Option<u32>,
+    ) -> Result<(), NoSuchEntity> {
         self.flush();
-        let loc = self.entities.free(entity)?;
+        let loc = self.entities.get(entity)?;
+        self.archetypes.archetypes[loc.archetype as usize].assert_unpinned();
+        match quarantine {
+            None => self.entities.free(entity)?,
+            Some(spawns) => self.entities.free_quarantined(entity, spawns)?,
+        };
+        self.notify_observers(entity, Some(loc.archetype), None);
+        if let Some(ref allocator) = self.allocator {
+            allocator.free(entity);
+        }
         if let Some(moved) =
             unsafe { self.archetypes.archetypes[loc.archetype as usize].remove(loc.index, true) }
         {
             self.entities.meta[moved as usize].location.index = loc.index;
         }
+        self.detach_relations(entity);
         Ok(())
     }
 
-    /// Ensure at least `additional` entities with exact components `T` can be spawned without reallocating
-    pub fn reserve<T: Bundle + 'static>(&mut self, additional: u32) {
-        self.reserve_inner::<T>(additional);
-    }
-
-    fn reserve_inner<T: Bundle + 'static>(&mut self, additional: u32) -> u32 {
-        self.flush();
-        self.entities.reserve(additional);
-
-        let archetypes = &mut self.archetypes;
-        let archetype_id = *self
-            .bundle_to_archetype
-            .entry(TypeId::of::<T>())
-            .or_insert_with(|| {
-                T::with_static_ids(|ids| {
-                    archetypes.get(ids, || T::with_static_type_info(|info| info.to_vec()))
-                })
-            });
-
-        self.archetypes.archetypes[archetype_id as usize].reserve(additional);
-        archetype_id
-    }
-
-    /// Despawn all entities
-    ///
-    /// Preserves allocated storage for reuse but clears metadata so that [`Entity`] values will repeat (in contrast to [`despawn`][Self::despawn]).
-    pub fn clear(&mut self) {
-        for x in &mut self.archetypes.archetypes {
-            x.clear();
+    /// Remove the relations of each type passed to [`register_relation`](Self::register_relation)
+    /// that refer to `target`
+    fn detach_relations(&mut self, target: Entity) {
+        if !self.relations.is_empty() {
+            let detach = self.relations.values().copied().collect::<Vec<_>>();
+            for detach in detach {
+                detach(self, target);
+            }
         }
-        self.entities.clear();
     }
 
-    /// Whether `entity` still exists
-    pub fn contains(&self, entity: Entity) -> bool {
-        self.entities.contains(entity)
+    /// Destroy every entity in `entities` that still exists, returning how many were despawned
+    ///
+    /// Hooks registered with [`add_hook`](Self::add_hook) are notified once with
+    /// [`FrameEvent::DespawnedBatch`] rather than per entity.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((123,));
+    /// let b = frame.spawn((456,));
+    /// frame.despawn(b).unwrap();
+    /// assert_eq!(frame.despawn_batch(&[a, b]), 1);
+    /// assert!(frame.is_empty());
+    /// ```
+    pub fn despawn_batch(&mut self, entities: &[Entity]) -> usize {
+        self.despawn_batch_inner(entities, None)
     }
 
-    /// Efficiently iterate over all entities that have certain components, using dynamic borrow
-    /// checking
+    /// Destroy every entity in `entities` that still exists, and withhold their IDs from reuse
+    /// until a number of further entities have been spawned
     ///
-    /// Prefer [`query_mut`](Self::query_mut) when concurrent access to the [`Frame`] is not required.
+    /// Handles to despawned entities are always invalidated, but an ID is normally recycled by the
+    /// next spawn, whose handle differs only in generation. Code that caches handles across many
+    /// frames and compares IDs alone may then mistake the new entity for the old. The IDs of
+    /// entities despawned here aren't reused until after [`handle_quarantine`](Self::handle_quarantine)
+    /// further entities are spawned, 1024 by default.
     ///
-    /// Calling `iter` on the returned value yields `(Entity, Q)` tuples, where `Q` is some query
-    /// type. A query type is any type for which an implementation of [`Query`] exists, e.g. `&T`,
-    /// `&mut T`, a tuple of query types, or an `Option` wrapping a query type, where `T` is any
-    /// component type. Components queried with `&mut` must only appear once. Entities which do not
-    /// have a component type referenced outside of an `Option` will be skipped.
-    ///
-    /// Entities are yielded in arbitrary order.
-    ///
-    /// The returned [`QueryBorrow`] can be further transformed with combinator methods; see its
-    /// documentation for details.
-    ///
-    /// Iterating a query will panic if it would violate an existing unique reference or construct
-    /// an invalid unique reference. This occurs when two simultaneously-active queries could expose
-    /// the same entity. Simultaneous queries can access the same component type if and only if the
-    /// frame contains no entities that have all components required by both queries, assuming no
-    /// other component borrows are outstanding.
-    ///
-    /// Iterating a query yields references with lifetimes bound to the [`QueryBorrow`] returned
-    /// here. To ensure those are invalidated, the return value of this method must be dropped for
-    /// its dynamic borrows from the frame to be released. Similarly, lifetime rules ensure that
-    /// references obtained from a query cannot outlive the [`QueryBorrow`].
+    /// Returns how many entities were despawned, and notifies hooks like
+    /// [`despawn_batch`](Self::despawn_batch).
     ///
     /// # Example
     /// ```
     /// # use moss_hecs::*;
     /// let mut frame = Frame::new();
-    /// let a = frame.spawn((123, true, "abc"));
-    /// let b = frame.spawn((456, false));
-    /// let c = frame.spawn((42, "def"));
-    /// let entities = frame.query::<(&i32, &bool)>()
-    ///     .iter()
-    ///     .map(|(e, (&i, &b))| (e, i, b)) // Copy out of the frame
-    ///     .collect::<Vec<_>>();
-    /// assert_eq!(entities.len(), 2);
-    /// assert!(entities.contains(&(a, 123, true)));
-    /// assert!(entities.contains(&(b, 456, false)));
+    /// frame.set_handle_quarantine(2);
+    /// let a = frame.spawn(());
+    /// assert_eq!(frame.invalidate_handles(&[a]), 1);
+    /// assert_ne!(frame.spawn(()).id(), a.id());
+    /// assert_ne!(frame.spawn(()).id(), a.id());
+    /// assert_eq!(frame.spawn(()).id(), a.id());
     /// ```
-    pub fn query<Q: Query>(&self) -> QueryBorrow<'_, Q> {
-        QueryBorrow::new(self)
+    ///
+    /// Panics if the frame allocates IDs from a [`SharedAllocator`].
+    pub fn invalidate_handles(&mut self, entities: &[Entity]) -> usize {
+        self.assert_local_allocator("invalidate_handles");
+        self.despawn_batch_inner(entities, Some(self.handle_quarantine))
     }
 
-    /// Provide random access to any entity for a given Query.
-    pub fn view<Q: Query>(&self) -> ViewBorrow<'_, Q> {
-        ViewBorrow::new(self)
+    /// Number of spawns for which IDs freed by [`invalidate_handles`](Self::invalidate_handles)
+    /// aren't reused
+    pub fn handle_quarantine(&self) -> u32 {
+        self.handle_quarantine
     }
 
-    /// Provide random access to any entity for a given Query on a uniquely
-    /// borrowed frame. Like [`view`](Self::view), but faster because dynamic borrow checks can be skipped.
-    pub fn view_mut<Q: Query>(&mut self) -> View<'_, Q> {
-        assert_borrow::<Q>();
-        unsafe { View::<Q>::new(self.entities_meta(), self.archetypes_inner()) }
+    /// Set the number of spawns for which IDs freed by later calls to
+    /// [`invalidate_handles`](Self::invalidate_handles) aren't reused
+    pub fn set_handle_quarantine(&mut self, spawns: u32) {
+        self.handle_quarantine = spawns;
     }
 
-    /// Query a uniquely borrowed frame
+    fn despawn_batch_inner(&mut self, entities: &[Entity], quarantine: Option<u32>) -> usize {
+        let notify = !self.hooks.is_empty();
+        let mut despawned = Vec::new();
+        let mut count = 0;
+        for &entity in entities {
+            if self.despawn_inner(entity, quarantine).is_ok() {
+                count += 1;
+                if notify {
+                    despawned.push(entity);
+                }
+            }
+        }
+        if count != 0 {
+            self.hooks.emit(&FrameEvent::DespawnedBatch(&despawned));
+        }
+        count
+    }
+
+    /// Register a callback to be invoked on wholesale structural changes to this frame
     ///
-    /// Like [`query`](Self::query), but faster because dynamic borrow checks can be skipped. Note
-    /// that, unlike [`query`](Self::query), this returns an `IntoIterator` which can be passed
-    /// directly to a `for` loop.
-    pub fn query_mut<Q: Query>(&mut self) -> QueryMut<'_, Q> {
-        QueryMut::new(self)
+    /// See [`FrameEvent`] for the changes reported.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// # use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+    /// let mut frame = Frame::new();
+    /// let cleared = Arc::new(AtomicBool::new(false));
+    /// let flag = cleared.clone();
+    /// frame.add_hook(move |event| {
+    ///     if let FrameEvent::Cleared = event {
+    ///         flag.store(true, Ordering::Relaxed);
+    ///     }
+    /// });
+    /// frame.clear();
+    /// assert!(cleared.load(Ordering::Relaxed));
+    /// ```
+    pub fn add_hook(
+        &mut self,
+        hook: impl FnMut(&FrameEvent<'_>) + Send + Sync + 'static,
+    ) -> HookId {
+        self.hooks.add(Box::new(hook))
     }
 
-    pub(crate) fn memo(&self) -> (u64, u32) {
-        (self.id, self.archetypes.generation())
+    /// Unregister a hook, returning whether it was registered
+    pub fn remove_hook(&mut self, hook: HookId) -> bool {
+        self.hooks.remove(hook)
     }
 
-    #[inline(always)]
-    pub(crate) fn entities_meta(&self) -> &[EntityMeta] {
-        &self.entities.meta
+    /// Bring the [`references`](Self::references) index up to date with relations written in
+    /// place
+    pub(crate) fn refresh_references(&mut self) {
+        if self.references.is_empty() {
+            return;
+        }
+        let entities = &self.entities;
+        // Every id stored in an archetype belongs to a live entity, and unique access to `self`
+        // rules out borrows
+        unsafe {
+            self.references.refresh(&self.archetypes.archetypes, |id| {
+                entities.resolve_unknown_gen(id)
+            });
+        }
     }
 
-    #[inline(always)]
-    pub(crate) fn archetypes_inner(&self) -> &[Archetype] {
-        &self.archetypes.archetypes
+    pub(crate) fn emit_event(&mut self, event: &FrameEvent<'_>) {
+        self.hooks.emit(event);
     }
 
-    /// Prepare a query against a single entity, using dynamic borrow checking
-    ///
-    /// Prefer [`query_one_mut`](Self::query_one_mut) when concurrent access to the [`Frame`] is not
-    /// required.
+    /// Register a callback to be invoked whenever an entity begins or ceases to match `Q`
     ///
-    /// Call [`get`](QueryOne::get) on the resulting [`QueryOne`] to actually execute the query. The
-    /// [`QueryOne`] value is responsible for releasing the dynamically-checked borrow made by
-    /// `get`, so it can't be dropped while references returned by `get` are live.
+    /// Entities begin to match when spawned or when inserting or removing components makes them
+    /// satisfy `Q`, and cease to match when despawned, taken, cleared, or changed such that they no
+    /// longer satisfy it. Whether each archetype matches is determined once, so the cost of an
+    /// observer is independent of the number of entities. Entities matching `Q` when the observer is
+    /// registered aren't reported.
     ///
-    /// Handy for accessing multiple components simultaneously.
+    /// The callback runs during the operation responsible, so can't access the frame; collect the
+    /// reported entities for processing afterwards if needed.
     ///
     /// # Example
     /// ```
     /// # use moss_hecs::*;
+    /// # use std::sync::{Arc, Mutex};
     /// let mut frame = Frame::new();
-    /// let a = frame.spawn((123, true, "abc"));
-    /// // The returned query must outlive the borrow made by `get`
-    /// let mut query = frame.query_one::<(&mut i32, &bool)>(a).unwrap();
-    /// let (number, flag) = query.get().unwrap();
-    /// if *flag { *number *= 2; }
-    /// assert_eq!(*number, 246);
+    /// let log = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = log.clone();
+    /// frame.observe::<(&i32, &bool)>(move |entity, event| sink.lock().unwrap().push((entity, event)));
+    /// let e = frame.spawn((1,));
+    /// frame.insert_one(e, true).unwrap();
+    /// frame.insert_one(e, "abc").unwrap();
+    /// frame.remove_one::<i32>(e).unwrap();
+    /// assert_eq!(*log.lock().unwrap(), [(e, MatchEvent::Entered), (e, MatchEvent::Exited)]);
     /// ```
-    pub fn query_one<Q: Query>(&self, entity: Entity) -> Result<QueryOne<'_, Q>, NoSuchEntity> {
-        let loc = self.entities.get(entity)?;
-        Ok(unsafe {
-            QueryOne::new(
-                &self.archetypes.archetypes[loc.archetype as usize],
-                loc.index,
-            )
-        })
+    pub fn observe<Q: Query>(
+        &mut self,
+        callback: impl FnMut(Entity, MatchEvent) + Send + Sync + 'static,
+    ) -> ObserverId {
+        self.observers
+            .add::<Q>(&self.archetypes.archetypes, Box::new(callback))
     }
 
-    /// Query a single entity in a uniquely borrowed frame
+    /// Unregister an observer, returning whether it was registered
+    pub fn remove_observer(&mut self, observer: ObserverId) -> bool {
+        self.observers.remove(observer)
+    }
+
+    /// Limit the contents of the frame, consulting `policy` whenever a spawn exceeds a limit of
+    /// `budget`
     ///
-    /// Like [`query_one`](Self::query_one), but faster because dynamic borrow checks can be
-    /// skipped. Note that, unlike [`query_one`](Self::query_one), on success this returns the
-    /// query's results directly.
-    pub fn query_one_mut<Q: Query>(
+    /// Checked before each spawn, whether by [`spawn`](Self::spawn), [`try_spawn`](Self::try_spawn),
+    /// [`spawn_at`](Self::spawn_at), a batch, or a [`CommandBuffer`], and before each insert that
+    /// moves an entity to another archetype. Batches are checked as a whole. `policy` is called
+    /// with each limit that would be exceeded, and may e.g. log the violation before returning
+    /// [`BudgetAction::Allow`]. If it returns [`BudgetAction::DespawnOldest`], the least recently
+    /// spawned entity counted by the limit is despawned and the budget checked again. Only
+    /// entities spawned since the budget was set are candidates; if none remain, the change goes
+    /// ahead. If it returns [`BudgetAction::Reject`], nothing is changed and the operation fails:
+    /// `try_spawn` returns an error, spawns and inserts recorded in a `CommandBuffer` or through
+    /// [`defer`](Self::defer) are dropped, and other operations panic.
+    ///
+    /// Checking [`Budget::max_component_bytes`] takes time proportional to the number of
+    /// archetypes.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let budget = Budget {
+    ///     max_entities: Some(2),
+    ///     ..Budget::default()
+    /// };
+    /// frame.set_budget(budget, |_| BudgetAction::DespawnOldest);
+    /// let a = frame.spawn((1,));
+    /// let b = frame.spawn((2,));
+    /// let c = frame.spawn((3,));
+    /// assert!(!frame.contains(a));
+    /// assert!(frame.contains(b) && frame.contains(c));
+    ///
+    /// frame.set_budget(budget, |_| BudgetAction::Reject);
+    /// assert_eq!(
+    ///     frame.try_spawn((4,)),
+    ///     Err(BudgetExceeded::Entities { limit: 2 })
+    /// );
+    /// assert_eq!(frame.len(), 2);
+    /// ```
+    pub fn set_budget(
         &mut self,
-        entity: Entity,
-    ) -> Result<Q::Item<'_>, QueryOneError> {
-        assert_borrow::<Q>();
+        budget: Budget,
+        policy: impl FnMut(&BudgetExceeded) -> BudgetAction + Send + Sync + 'static,
+    ) {
+        self.budget = Some(Budgets::new(budget, Box::new(policy)));
+    }
 
-        let loc = self.entities.get(entity)?;
-        let archetype = &self.archetypes.archetypes[loc.archetype as usize];
-        let state = Q::Fetch::prepare(archetype).ok_or(QueryOneError::Unsatisfied)?;
-        let fetch = Q::Fetch::execute(archetype, state);
-        unsafe { Ok(Q::get(&fetch, loc.index as usize)) }
+    /// Stop enforcing the budget passed to [`set_budget`](Self::set_budget)
+    pub fn clear_budget(&mut self) {
+        self.budget = None;
     }
 
-    /// Query a fixed number of distinct entities in a uniquely borrowed frame
-    ///
-    /// Like [`query_one_mut`](Self::query_one_mut), but for multiple entities, which would
-    /// otherwise be forbidden by the unique borrow. Panics if the same entity occurs more than
-    /// once.
-    pub fn query_many_mut<Q: Query, const N: usize>(
+    /// Apply the budget, if any, to `growth` before it's made, sparing `spare` from
+    /// [`BudgetAction::DespawnOldest`]
+    fn admit(&mut self, growth: Growth, spare: Option<Entity>) -> Result<(), BudgetExceeded> {
+        let Some(mut budget) = self.budget.take() else {
+            return Ok(());
+        };
+        let result = self.admit_inner(&mut budget, growth, spare);
+        self.budget = Some(budget);
+        result
+    }
+
+    fn admit_inner(
         &mut self,
-        entities: [Entity; N],
-    ) -> [Result<Q::Item<'_>, QueryOneError>; N] {
-        assert_borrow::<Q>();
-        assert_distinct(&entities);
+        budget: &mut Budgets,
+        growth: Growth,
+        spare: Option<Entity>,
+    ) -> Result<(), BudgetExceeded> {
+        while let Some(exceeded) = self.check_budget(&budget.limits, growth) {
+            match (budget.policy)(&exceeded) {
+                BudgetAction::Allow => break,
+                BudgetAction::Reject => return Err(exceeded),
+                BudgetAction::DespawnOldest => {
+                    let scope = match exceeded {
+                        BudgetExceeded::Archetype { archetype, .. } => Some(archetype),
+                        _ => None,
+                    };
+                    let oldest = budget.order.iter().position(|&x| {
+                        Some(x) != spare
+                            && self.entities.get(x).map_or(false, |loc| {
+                                scope.map_or(true, |arch| loc.archetype == arch)
+                            })
+                    });
+                    let Some(oldest) = oldest else {
+                        break;
+                    };
+                    let victim = budget.order.remove(oldest).unwrap();
+                    self.despawn(victim).unwrap();
+                }
+            }
+        }
+        Ok(())
+    }
 
-        entities.map(|entity| {
-            let loc = self.entities.get(entity)?;
-            let archetype = &self.archetypes.archetypes[loc.archetype as usize];
-            let state = Q::Fetch::prepare(archetype).ok_or(QueryOneError::Unsatisfied)?;
-            let fetch = Q::Fetch::execute(archetype, state);
-            unsafe { Ok(Q::get(&fetch, loc.index as usize)) }
-        })
+    /// Apply the budget, if any, to spawning the entities stored in `batch`, at `handles` if given
+    fn admit_batch(&mut self, batch: &Archetype, handles: Option<&[Entity]>) {
+        if self.budget.is_none() {
+            return;
+        }
+        let archetype_id = self
+            .archetypes
+            .get(batch.type_ids(), || batch.types().to_vec());
+        let growth = match handles {
+            Some(handles) => self.spawn_at_growth(archetype_id, handles),
+            None => self.spawn_growth(archetype_id, batch.len()),
+        };
+        self.admit(growth, None)
+            .unwrap_or_else(|e| panic!("spawn rejected: {}", e));
     }
 
-    /// Short-hand for [`entity`](Self::entity) followed by [`EntityRef::get`]
-    pub fn get<'a, T: ComponentRef<'a>>(
-        &'a self,
+    /// Apply the budget, if any, to moving `entity` from `loc` to the archetype reached by adding
+    /// `components` to `graph_origin`, returning its location afterwards
+    ///
+    /// If `spawning`, `entity` is a reserved entity being spawned, and is checked against
+    /// [`Budget::max_entities`] too.
+    fn admit_insert(
+        &mut self,
         entity: Entity,
-    ) -> Result<T::Ref, ComponentError> {
-        Ok(self
-            .entity(entity)?
-            .get::<T>()
-            .ok_or_else(MissingComponent::new::<T::Component>)?)
+        loc: Location,
+        graph_origin: u32,
+        components: &impl DynamicBundle,
+        spawning: bool,
+    ) -> Result<Location, BudgetExceeded> {
+        if self.budget.is_none() {
+            return Ok(loc);
+        }
+        let target = match components.key() {
+            None => {
+                self.archetypes
+                    .get_insert_target(graph_origin, components)
+                    .index
+            }
+            Some(key) => match self.insert_edges.entry((graph_origin, key)) {
+                Entry::Occupied(entry) => entry.get().index,
+                Entry::Vacant(entry) => {
+                    let target = self.archetypes.get_insert_target(graph_origin, components);
+                    entry.insert(target).index
+                }
+            },
+        };
+        if target == loc.archetype && !spawning {
+            return Ok(loc);
+        }
+        let growth = Growth {
+            archetype: target,
+            rows: u32::from(target != loc.archetype),
+            entities: spawning.then_some(0),
+            bytes_added: self.row_bytes(target),
+            bytes_freed: self.row_bytes(loc.archetype),
+        };
+        self.admit(growth, Some(entity))?;
+        Ok(self.entities.get(entity).unwrap())
     }
 
-    /// Short-hand for [`entity`](Self::entity) followed by [`EntityRef::satisfies`]
-    pub fn satisfies<Q: Query>(&self, entity: Entity) -> Result<bool, NoSuchEntity> {
-        Ok(self.entity(entity)?.satisfies::<Q>())
+    /// Make entities spawned while a budget is enforced candidates for
+    /// [`BudgetAction::DespawnOldest`]
+    fn record_spawned(&mut self, entities: impl IntoIterator<Item = Entity>) {
+        let Some(ref mut budget) = self.budget else {
+            return;
+        };
+        budget.order.extend(entities);
+        // Forget despawned entities once they outnumber live ones
+        if budget.order.len() > 2 * self.entities.len() as usize + 16 {
+            budget.order.retain(|&x| self.entities.contains(x));
+        }
     }
 
-    /// Access an entity regardless of its component types
-    ///
-    /// Does not immediately borrow any component.
-    pub fn entity(&self, entity: Entity) -> Result<EntityRef<'_>, NoSuchEntity> {
-        let loc = self.entities.get(entity)?;
-        unsafe {
-            Ok(EntityRef::new(
-                &self.archetypes.archetypes[loc.archetype as usize],
-                entity,
-                loc.index,
-            ))
+    /// The growth from spawning `count` new entities into `archetype`
+    fn spawn_growth(&self, archetype: u32, count: u32) -> Growth {
+        Growth {
+            archetype,
+            rows: count,
+            entities: Some(count),
+            bytes_added: count as usize * self.row_bytes(archetype),
+            bytes_freed: 0,
         }
     }
 
-    /// Given an id obtained from [`Entity::id`], reconstruct the still-live [`Entity`].
+    /// The growth from spawning entities into `archetype` at `handles`, replacing any live
+    /// entities with the same IDs
+    fn spawn_at_growth(&self, archetype: u32, handles: &[Entity]) -> Growth {
+        let mut growth = self.spawn_growth(archetype, handles.len() as u32);
+        for handle in handles {
+            let Some(loc) = self.entities.live_location(handle.id()) else {
+                continue;
+            };
+            *growth.entities.as_mut().unwrap() -= 1;
+            growth.rows -= u32::from(loc.archetype == archetype);
+            growth.bytes_freed += self.row_bytes(loc.archetype);
+        }
+        growth
+    }
+
+    /// Size of the components of an entity in `archetype`
+    fn row_bytes(&self, archetype: u32) -> usize {
+        self.archetypes.archetypes[archetype as usize]
+            .types()
+            .iter()
+            .map(|ty| ty.layout().size())
+            .sum()
+    }
+
+    /// Find a limit of `budget` that `growth` would exceed
+    fn check_budget(&self, budget: &Budget, growth: Growth) -> Option<BudgetExceeded> {
+        if let (Some(limit), Some(entities)) = (budget.max_entities, growth.entities) {
+            if u64::from(self.len()) + u64::from(entities) > u64::from(limit) {
+                return Some(BudgetExceeded::Entities { limit });
+            }
+        }
+        if let Some(limit) = budget.max_per_archetype {
+            let archetype = growth.archetype;
+            let len = self.archetypes.archetypes[archetype as usize].len();
+            if growth.rows != 0 && u64::from(len) + u64::from(growth.rows) > u64::from(limit) {
+                return Some(BudgetExceeded::Archetype { archetype, limit });
+            }
+        }
+        if let Some(limit) = budget.max_component_bytes {
+            let bytes = self
+                .archetypes()
+                .map(|x| {
+                    x.len() as usize
+                        * x.component_type_info()
+                            .iter()
+                            .map(|ty| ty.layout().size())
+                            .sum::<usize>()
+                })
+                .sum::<usize>();
+            if (bytes + growth.bytes_added).saturating_sub(growth.bytes_freed) > limit {
+                return Some(BudgetExceeded::ComponentBytes { limit });
+            }
+        }
+        None
+    }
+
+    /// Report reallocations of component storage to hooks as [`FrameEvent::ColumnGrown`] once a
+    /// column's allocation reaches `bytes`, or stop reporting them if `None`
     ///
-    /// # Safety
+    /// Useful for enforcing memory budgets. Not reported by default.
     ///
-    /// `id` must correspond to a currently live [`Entity`]. A despawned or never-allocated `id`
-    /// will produce undefined behavior.
-    pub unsafe fn find_entity_from_id(&self, id: u32) -> Entity {
-        self.entities.resolve_unknown_gen(id)
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// # use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    /// let mut frame = Frame::new();
+    /// let largest = Arc::new(AtomicUsize::new(0));
+    /// let sink = largest.clone();
+    /// frame.set_growth_threshold(Some(1024));
+    /// frame.add_hook(move |event| {
+    ///     if let FrameEvent::ColumnGrown { bytes, .. } = *event {
+    ///         sink.fetch_max(bytes, Ordering::Relaxed);
+    ///     }
+    /// });
+    /// frame.spawn_batch((0..1000).map(|i| (i as u64,)));
+    /// assert!(largest.load(Ordering::Relaxed) >= 8000);
+    /// ```
+    pub fn set_growth_threshold(&mut self, bytes: Option<usize>) {
+        self.growth_threshold = bytes;
     }
 
-    /// Iterate over all entities in the frame
+    /// Check every component of a type registered with [`Registration::validate`] in `registry`
     ///
-    /// Entities are yielded in arbitrary order. Prefer [`query`](Self::query) for better
-    /// performance when components will be accessed in predictable patterns.
+    /// Returns the first component found to be invalid.
+    ///
+    /// [`Registration::validate`]: crate::Registration::validate
     ///
     /// # Example
     /// ```
     /// # use moss_hecs::*;
+    /// struct Health(i32);
+    /// let mut registry = ComponentRegistry::new();
+    /// registry.register::<Health>().name("Health").validate(|h| h.0 >= 0);
+    ///
     /// let mut frame = Frame::new();
-    /// let a = frame.spawn(());
-    /// let b = frame.spawn(());
-    /// let ids = frame.iter().map(|entity_ref| entity_ref.entity()).collect::<Vec<_>>();
-    /// assert_eq!(ids.len(), 2);
-    /// assert!(ids.contains(&a));
-    /// assert!(ids.contains(&b));
+    /// frame.spawn((Health(10),));
+    /// let e = frame.spawn((Health(-5), true));
+    /// let err = frame.validate_components(&registry).unwrap_err();
+    /// assert_eq!((err.entity, err.name), (e, "Health"));
     /// ```
-    pub fn iter(&self) -> Iter<'_> {
-        Iter::new(&self.archetypes.archetypes, &self.entities)
+    ///
+    /// Panics if any validated component is uniquely borrowed.
+    pub fn validate_components(
+        &self,
+        registry: &ComponentRegistry,
+    ) -> Result<(), InvalidComponent> {
+        for archetype in self.archetypes() {
+            registry.validate_archetype(self, archetype)?;
+        }
+        Ok(())
     }
 
-    /// Add `components` to `entity`
+    /// Validate components with `registry` as they're spawned or inserted, or stop if `None`
     ///
-    /// Computational cost is proportional to the number of components `entity` has. If an entity
-    /// already has a component of a certain type, it is dropped and replaced.
+    /// Only takes effect in builds with debug assertions enabled, where an entity spawned or
+    /// inserted into with [`spawn`](Self::spawn), [`spawn_at`](Self::spawn_at) or
+    /// [`insert`](Self::insert) and its variants panics if any of its components fails validation.
+    /// Catches corrupted state close to its source; see also
+    /// [`validate_components`](Self::validate_components).
+    pub fn set_validator(&mut self, registry: Option<Arc<ComponentRegistry>>) {
+        self.validator = registry;
+    }
+
+    /// Permit only component types registered with `registry` to be spawned or inserted, or
+    /// any type if `None`
     ///
-    /// When inserting a single component, see [`insert_one`](Self::insert_one) for convenience.
+    /// Keeps e.g. plugins from introducing component types unknown to serialization or
+    /// replication layers. Once set, any operation that would store an unregistered type panics;
+    /// use [`check_registered`](Self::check_registered) to test components beforehand. Fails
+    /// without effect if the frame already holds an archetype with an unregistered type.
     ///
     /// # Example
     /// ```
     /// # use moss_hecs::*;
+    /// # use std::sync::Arc;
+    /// let mut registry = ComponentRegistry::new();
+    /// registry.register::<i32>();
     /// let mut frame = Frame::new();
-    /// let e = frame.spawn((123, "abc"));
-    /// frame.insert(e, (456, true));
-    /// assert_eq!(*frame.get::<&i32>(e).unwrap(), 456);
-    /// assert_eq!(*frame.get::<&bool>(e).unwrap(), true);
+    /// frame.set_strict_registry(Some(Arc::new(registry))).unwrap();
+    /// let e = frame.spawn((1,));
+    /// assert!(frame.check_registered(&(true,)).is_err());
     /// ```
-    pub fn insert(
+    pub fn set_strict_registry(
         &mut self,
-        entity: Entity,
-        components: impl DynamicBundle,
-    ) -> Result<(), NoSuchEntity> {
-        self.flush();
-
-        let loc = self.entities.get(entity)?;
-        self.insert_inner(entity, components, loc.archetype, loc);
+        registry: Option<Arc<ComponentRegistry>>,
+    ) -> Result<(), UnregisteredComponent> {
+        let old = mem::replace(&mut self.archetypes.strict, registry);
+        for archetype in &self.archetypes.archetypes {
+            if let Err(e) = self.archetypes.check_registered(archetype.types()) {
+                self.archetypes.strict = old;
+                return Err(e);
+            }
+        }
         Ok(())
     }
 
-    /// The implementation backing [`insert`](Self::insert) exposed so that it can also be used by [`exchange`](Self::exchange).
-    ///
-    /// Note that `graph_origin` is always equal to `loc.archetype` during insertion. Only for exchange, `graph_origin` identifies
-    /// the intermediate archetype which would be reached after removal and before insertion even though
-    /// the actual component data still resides in `loc.archetype`.
-    fn insert_inner(
+    /// Whether every type in `components` may be stored under the registry passed to
+    /// [`set_strict_registry`](Self::set_strict_registry)
+    pub fn check_registered(
+        &self,
+        components: &impl DynamicBundle,
+    ) -> Result<(), UnregisteredComponent> {
+        self.archetypes.check_registered(&components.type_info())
+    }
+
+    /// Panic if any of `entity`'s components fails validation by the registry passed to
+    /// [`set_validator`](Self::set_validator), in debug builds
+    fn debug_validate(&self, entity: Entity) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let Some(registry) = self.validator.as_deref() else {
+            return;
+        };
+        let loc = self.entities.meta[entity.id as usize].location;
+        let archetype = &self.archetypes.archetypes[loc.archetype as usize];
+        // Called with exclusive access to the frame, so nothing is borrowed
+        if let Err(e) = unsafe { registry.validate_row(entity, archetype, loc.index) } {
+            panic!("{}", e);
+        }
+    }
+
+    /// Ensure at least `additional` entities with exact components `T` can be spawned without reallocating
+    pub fn reserve<T: Bundle + 'static>(&mut self, additional: u32) {
+        self.reserve_inner::<T>(additional);
+    }
+
+    fn reserve_inner<T: Bundle + 'static>(&mut self, additional: u32) -> u32 {
+        self.flush();
+        self.entities.reserve(additional);
+
+        let archetypes = &mut self.archetypes;
+        let archetype_id = *self
+            .bundle_to_archetype
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| {
+                T::with_static_ids(|ids| {
+                    archetypes.get(ids, || T::with_static_type_info(|info| info.to_vec()))
+                })
+            });
+
+        let archetype = &mut self.archetypes.archetypes[archetype_id as usize];
+        archetype.assert_fits(additional);
+        archetype.reserve(additional);
+        self.report_growth(archetype_id);
+        archetype_id
+    }
+
+    /// Create the archetype of entities with exactly the components of `B`, if it doesn't already
+    /// exist
+    ///
+    /// Archetypes are otherwise created by the first spawn or insertion that needs them, which
+    /// also reports them to hooks and observers and causes every [`PreparedQuery`] to be prepared
+    /// again. Declaring archetypes while loading moves that work out of latency-sensitive frames.
+    ///
+    /// Returns the archetype's position in [`archetypes`](Self::archetypes).
+    ///
+    /// [`PreparedQuery`]: crate::PreparedQuery
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let archetype = frame.declare_archetype::<(i32, bool)>();
+    /// let generation = frame.archetypes_generation();
+    /// let e = frame.spawn((1, true));
+    /// assert_eq!(frame.archetype_id(e).unwrap(), archetype);
+    /// assert_eq!(frame.archetypes_generation(), generation);
+    /// ```
+    pub fn declare_archetype<B: Bundle + 'static>(&mut self) -> u32 {
+        let archetypes = &mut self.archetypes;
+        let archetype_id = *self
+            .bundle_to_archetype
+            .entry(TypeId::of::<B>())
+            .or_insert_with(|| {
+                B::with_static_ids(|ids| {
+                    archetypes.get(ids, || B::with_static_type_info(|info| info.to_vec()))
+                })
+            });
+        self.archetype_declared(archetype_id);
+        archetype_id
+    }
+
+    /// Like [`declare_archetype`](Self::declare_archetype), but for component types only known at
+    /// runtime
+    ///
+    /// Panics if `types` contains duplicates.
+    pub fn declare_archetype_dynamic(&mut self, types: &[TypeInfo]) -> u32 {
+        let mut info = types.to_vec();
+        info.sort_unstable();
+        let ids = info.iter().map(|ty| ty.id()).collect::<Box<[TypeId]>>();
+        let archetype_id = self.archetypes.get(ids, || info);
+        self.archetype_declared(archetype_id);
+        archetype_id
+    }
+
+    /// Report a newly declared archetype to hooks and observers
+    fn archetype_declared(&mut self, archetype_id: u32) {
+        self.report_growth(archetype_id);
+        if !self.observers.is_empty() {
+            self.observers.update(&self.archetypes.archetypes);
+        }
+    }
+
+    /// Iterate over the columns of `T` components in every archetype, uniquely borrowing the frame
+    ///
+    /// Like [`query_mut::<&mut T>`](Self::query_mut), visits only the archetypes holding `T`, but
+    /// yields each column as a contiguous slice suited to vectorization rather than one component
+    /// at a time. Empty columns are skipped. Use `query_mut` if entity handles are needed.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// frame.spawn((1.0f32,));
+    /// frame.spawn((2.0f32, true));
+    /// frame.spawn(("abc",));
+    /// for column in frame.columns_mut::<f32>() {
+    ///     for x in column {
+    ///         *x *= 2.0;
+    ///     }
+    /// }
+    /// let sum = frame.columns_mut::<f32>().flatten().map(|x| *x).sum::<f32>();
+    /// assert_eq!(sum, 6.0);
+    /// ```
+    pub fn columns_mut<T: Component>(&mut self) -> ColumnsMut<'_, T> {
+        ColumnsMut {
+            archetypes: &self.archetypes.archetypes,
+            ids: self.archetypes_with(TypeId::of::<T>()).iter(),
+            _marker: core::marker::PhantomData,
+        }
+    }
+
+    /// Start recording when `T` components are added and changed, for [`Changed`](crate::Changed)
+    /// and [`Added`](crate::Added) queries
+    ///
+    /// Tracking costs two words of storage per `T` component, and a store whenever one is accessed
+    /// mutably, so it's off unless requested. `T` components already present count as neither
+    /// added nor changed until they're next written. Calling this again for the same `T` has no
+    /// effect.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((1,));
+    /// frame.track_changes::<i32>();
+    /// let b = frame.spawn((2,));
+    /// let added = frame.query_mut::<Added<&i32>>().into_iter().map(|(e, _)| e).collect::<Vec<_>>();
+    /// assert_eq!(added, [b]);
+    /// ```
+    pub fn track_changes<T: Component>(&mut self) {
+        self.archetypes.track(TypeId::of::<T>());
+    }
+
+    /// Track changes to the same component types as `other`
+    pub(crate) fn copy_tracked(&mut self, other: &Frame) {
+        for &ty in &other.archetypes.tracked {
+            self.archetypes.track(ty);
+        }
+    }
+
+    /// Begin a new period of change detection for [`Changed`](crate::Changed) and
+    /// [`Added`](crate::Added) queries
+    ///
+    /// Components added or changed before this call are no longer reported. Typically called
+    /// once per frame, after every system that reacts to changes has run.
+    pub fn clear_trackers(&mut self) {
+        let tick = self.archetypes.tick.wrapping_add(1);
+        self.archetypes.tick = tick;
+        for archetype in &mut self.archetypes.archetypes {
+            archetype.set_tick(tick);
+        }
+    }
+
+    /// Despawn all entities
+    ///
+    /// Preserves allocated storage for reuse but clears metadata so that [`Entity`] values will repeat (in contrast to [`despawn`][Self::despawn]).
+    /// Operations recorded through [`defer`](Self::defer) and values saved by
+    /// [`save_previous`](Self::save_previous) are discarded.
+    pub fn clear(&mut self) {
+        self.begin_structural_change();
+        for x in &self.archetypes.archetypes {
+            x.assert_unpinned();
+        }
+        if let Some(ref allocator) = self.allocator {
+            for entity in self.iter().map(|x| x.entity()) {
+                allocator.free(entity);
+            }
+        }
+        if !self.observers.is_empty() {
+            self.observers.update(&self.archetypes.archetypes);
+            for (archetype_id, archetype) in self.archetypes.archetypes.iter().enumerate() {
+                if !self.observers.any_match(archetype_id as u32) {
+                    continue;
+                }
+                for &id in archetype.ids() {
+                    let entity = unsafe { self.entities.resolve_unknown_gen(id) };
+                    self.observers
+                        .transition(entity, Some(archetype_id as u32), None);
+                }
+            }
+        }
+        for x in &mut self.archetypes.archetypes {
+            x.clear();
+        }
+        self.entities.clear();
+        self.references.clear();
+        #[cfg(feature = "entity-history")]
+        self.history.clear();
+        #[cfg(feature = "event-log")]
+        self.event_log.clear();
+        if let Some(ref mut budget) = self.budget {
+            budget.order.clear();
+        }
+        self.deferred.get_mut().clear();
+        // Buffers still holding reservations mustn't despawn the new entities reusing their handles
+        self.abandoned = Arc::default();
+        // Entity handles will repeat, so previous values must not outlive them
+        self.save_previous();
+        self.hooks.emit(&FrameEvent::Cleared);
+    }
+
+    /// Renumber live entities so that their IDs are exactly `0..len()`, calling `f` with the old
+    /// and new handle of each entity whose ID changed
+    ///
+    /// Long-running frames whose entities have come and gone can be left with sparse IDs, bloating
+    /// side tables indexed by ID. Compaction moves the entities with the highest IDs into the
+    /// lowest free ones and releases the rest of the ID space. Handles stored in components or
+    /// elsewhere must be updated by `f`; old handles of moved entities never refer to entities
+    /// spawned later. Operations recorded through [`defer`](Self::defer) are
+    /// applied first. Components, user data and previous values move with their entity.
+    ///
+    /// Panics if the frame allocates IDs from a [`SharedAllocator`].
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let entities = (0..4).map(|i| frame.spawn((i,))).collect::<Vec<_>>();
+    /// frame.despawn(entities[0]).unwrap();
+    /// frame.despawn(entities[1]).unwrap();
+    /// let mut moved = Vec::new();
+    /// frame.compact_entities(|old, new| moved.push((old, new)));
+    /// assert_eq!(moved.len(), 2);
+    /// for (old, new) in moved {
+    ///     assert!(!frame.contains(old));
+    ///     assert!(new.id() < 2);
+    /// }
+    /// ```
+    pub fn compact_entities(&mut self, mut f: impl FnMut(Entity, Entity)) {
+        self.assert_local_allocator("compact_entities");
+        self.apply_deferred();
+        let mut budget = self.budget.take();
+        if let Some(ref mut budget) = budget {
+            budget.order.retain(|&x| self.entities.contains(x));
+        }
+        let mut remapped = HashMap::<Entity, Entity>::new();
+        for (old, new, location) in self.entities.compact() {
+            if budget.is_some() {
+                remapped.insert(old, new);
+            }
+            self.archetypes.archetypes[location.archetype as usize]
+                .set_entity_id(location.index as usize, new.id());
+            for shadow in self.shadows.values_mut() {
+                shadow.remap(old, new);
+            }
+            self.references.remap(old, new);
+            #[cfg(feature = "entity-history")]
+            self.history.remap(old, new);
+            f(old, new);
+        }
+        if let Some(ref mut budget) = budget {
+            for entity in &mut budget.order {
+                *entity = remapped.get(entity).copied().unwrap_or(*entity);
+            }
+        }
+        self.budget = budget;
+    }
+
+    /// Whether `entity` still exists
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(entity)
+    }
+
+    /// Value associated with `entity` by [`set_user_data`](Self::set_user_data), or 0 if none
+    pub fn user_data(&self, entity: Entity) -> Result<u64, NoSuchEntity> {
+        if !self.contains(entity) {
+            return Err(NoSuchEntity);
+        }
+        Ok(self.entities.user_data(entity.id()))
+    }
+
+    /// Associate `value` with `entity` until it's despawned
+    ///
+    /// Suits engine-level bookkeeping such as a network ID or scene node index. Unlike a
+    /// component, the value is stored alongside the entity's handle rather than in its archetype,
+    /// so setting it never moves the entity. Accessing it takes constant time regardless of the
+    /// entity's archetype.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((123,));
+    /// let b = frame.spawn((true,));
+    /// frame.set_user_data(a, 42).unwrap();
+    /// assert_eq!(frame.user_data(a), Ok(42));
+    /// assert_eq!(frame.user_data(b), Ok(0));
+    /// frame.despawn(a).unwrap();
+    /// assert!(frame.user_data(a).is_err());
+    /// ```
+    pub fn set_user_data(&mut self, entity: Entity, value: u64) -> Result<(), NoSuchEntity> {
+        if !self.contains(entity) {
+            return Err(NoSuchEntity);
+        }
+        self.entities.set_user_data(entity.id(), value);
+        Ok(())
+    }
+
+    /// Decode a token from [`Entity::to_token`], checking that the entity is still live
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn(());
+    /// let token = e.to_token();
+    /// assert_eq!(frame.resolve_token(&token), Ok(e));
+    /// frame.despawn(e).unwrap();
+    /// assert_eq!(frame.resolve_token(&token), Err(ResolveTokenError::Stale(e)));
+    /// ```
+    pub fn resolve_token(&self, token: &str) -> Result<Entity, ResolveTokenError> {
+        let entity = Entity::from_token(token)?;
+        if !self.contains(entity) {
+            return Err(ResolveTokenError::Stale(entity));
+        }
+        Ok(entity)
+    }
+
+    /// Efficiently iterate over all entities that have certain components, using dynamic borrow
+    /// checking
+    ///
+    /// Prefer [`query_mut`](Self::query_mut) when concurrent access to the [`Frame`] is not required.
+    ///
+    /// Calling `iter` on the returned value yields `(Entity, Q)` tuples, where `Q` is some query
+    /// type. A query type is any type for which an implementation of [`Query`] exists, e.g. `&T`,
+    /// `&mut T`, a tuple of query types, or an `Option` wrapping a query type, where `T` is any
+    /// component type. Components queried with `&mut` must only appear once. Entities which do not
+    /// have a component type referenced outside of an `Option` will be skipped. Components
+    /// referenced inside an `Option`, e.g. `Option<&mut T>`, are only borrowed from archetypes that
+    /// contain them, yielding `None` elsewhere.
+    ///
+    /// Entities are yielded in arbitrary order.
+    ///
+    /// The returned [`QueryBorrow`] can be further transformed with combinator methods; see its
+    /// documentation for details.
+    ///
+    /// Iterating a query will panic if it would violate an existing unique reference or construct
+    /// an invalid unique reference. This occurs when two simultaneously-active queries could expose
+    /// the same entity. Simultaneous queries can access the same component type if and only if the
+    /// frame contains no entities that have all components required by both queries, assuming no
+    /// other component borrows are outstanding.
+    ///
+    /// Iterating a query yields references with lifetimes bound to the [`QueryBorrow`] returned
+    /// here. To ensure those are invalidated, the return value of this method must be dropped for
+    /// its dynamic borrows from the frame to be released. Similarly, lifetime rules ensure that
+    /// references obtained from a query cannot outlive the [`QueryBorrow`].
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((123, true, "abc"));
+    /// let b = frame.spawn((456, false));
+    /// let c = frame.spawn((42, "def"));
+    /// let entities = frame.query::<(&i32, &bool)>()
+    ///     .iter()
+    ///     .map(|(e, (&i, &b))| (e, i, b)) // Copy out of the frame
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(entities.len(), 2);
+    /// assert!(entities.contains(&(a, 123, true)));
+    /// assert!(entities.contains(&(b, 456, false)));
+    /// ```
+    pub fn query<Q: Query>(&self) -> QueryBorrow<'_, Q> {
+        QueryBorrow::new(self)
+    }
+
+    /// Provide random access to any entity for a given Query.
+    pub fn view<Q: Query>(&self) -> ViewBorrow<'_, Q> {
+        ViewBorrow::new(self)
+    }
+
+    /// Provide random access to any entity for a given Query on a uniquely
+    /// borrowed frame. Like [`view`](Self::view), but faster because dynamic borrow checks can be skipped.
+    pub fn view_mut<Q: Query>(&mut self) -> View<'_, Q> {
+        assert_borrow::<Q>();
+        unsafe { View::<Q>::new(self.entities_meta(), self.archetypes_inner()) }
+    }
+
+    /// Query a uniquely borrowed frame
+    ///
+    /// Like [`query`](Self::query), but faster because dynamic borrow checks can be skipped. Note
+    /// that, unlike [`query`](Self::query), this returns an `IntoIterator` which can be passed
+    /// directly to a `for` loop.
+    pub fn query_mut<Q: Query>(&mut self) -> QueryMut<'_, Q> {
+        QueryMut::new(self)
+    }
+
+    /// Call `f` on every entity matching `Q`, then apply the structural changes it recorded
+    ///
+    /// Packages the most common shape of system: iterating over a query while spawning, despawning,
+    /// or changing the components of entities. Changes recorded through the [`DeferredOps`] passed
+    /// to `f` don't disturb the iteration, and are applied by
+    /// [`apply_deferred`](Self::apply_deferred) once it finishes, along with any operations
+    /// recorded previously through [`defer`](Self::defer).
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// struct Health(i32);
+    /// struct Corpse;
+    ///
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((Health(10),));
+    /// let b = frame.spawn((Health(1),));
+    /// frame.run::<&mut Health>(|entity, health, ops| {
+    ///     health.0 -= 5;
+    ///     if health.0 <= 0 {
+    ///         ops.despawn(entity);
+    ///         ops.spawn((Corpse,));
+    ///     }
+    /// });
+    /// assert_eq!(frame.get::<&Health>(a).unwrap().0, 5);
+    /// assert!(!frame.contains(b));
+    /// assert_eq!(frame.query_mut::<&Corpse>().into_iter().count(), 1);
+    /// ```
+    pub fn run<Q: Query>(&mut self, mut f: impl FnMut(Entity, Q::Item<'_>, &mut DeferredOps<'_>)) {
+        {
+            let mut ops = self.defer();
+            for (entity, item) in self.query::<Q>().iter() {
+                f(entity, item, &mut ops);
+            }
+        }
+        self.apply_deferred();
+    }
+
+    /// Divide the frame into shared access to components in `R` and exclusive access to
+    /// components in `W`
+    ///
+    /// `R` and `W` are tuples of component types, e.g. `(Position, Velocity)`. The two partitions
+    /// may be used concurrently, e.g. on different threads of a fork-join system, without their
+    /// queries ever conflicting. Queries through either partition which access components outside
+    /// it panic. `split` itself panics if `R` and `W` overlap; the [`split!`](crate::split!) macro
+    /// rejects overlapping component types at compile time instead, and should be preferred
+    /// wherever the types are concrete.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// struct Position(f32);
+    /// struct Velocity(f32);
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((Position(1.0), Velocity(2.0)));
+    /// let (read, write) = split!(frame, (Velocity), (Position));
+    /// std::thread::scope(|scope| {
+    ///     scope.spawn(|| {
+    ///         for (_, pos) in write.query::<&mut Position>().iter() {
+    ///             pos.0 += 1.0;
+    ///         }
+    ///     });
+    ///     scope.spawn(|| {
+    ///         assert_eq!(read.query::<&Velocity>().iter().count(), 1);
+    ///     });
+    /// });
+    /// assert_eq!(frame.get::<&Position>(e).unwrap().0, 2.0);
+    /// ```
+    pub fn split<R: Bundle + 'static, W: Bundle + 'static>(
+        &mut self,
+    ) -> (ReadPartition<'_, R>, WritePartition<'_, W>) {
+        assert_disjoint::<R, W>();
+        self.flush();
+        (ReadPartition::new(self), WritePartition::new(self))
+    }
+
+    /// Identifier unique to this frame for the life of the process
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn memo(&self) -> (u64, u32) {
+        (self.id, self.archetypes.generation())
+    }
+
+    #[inline(always)]
+    pub(crate) fn entities_meta(&self) -> &[EntityMeta] {
+        &self.entities.meta
+    }
+
+    #[inline(always)]
+    pub(crate) fn archetypes_inner(&self) -> &[Archetype] {
+        &self.archetypes.archetypes
+    }
+
+    pub(crate) fn archetypes_inner_mut(&mut self) -> &mut [Archetype] {
+        &mut self.archetypes.archetypes
+    }
+
+    /// Indices of the archetypes having components of type `ty`, in ascending order
+    pub(crate) fn archetypes_with(&self, ty: TypeId) -> &[u32] {
+        self.archetypes.by_type.get(&ty).map_or(&[][..], |x| &x[..])
+    }
+
+    /// Prepare a query against a single entity, using dynamic borrow checking
+    ///
+    /// Prefer [`query_one_mut`](Self::query_one_mut) when concurrent access to the [`Frame`] is not
+    /// required.
+    ///
+    /// Call [`get`](QueryOne::get) on the resulting [`QueryOne`] to actually execute the query. The
+    /// [`QueryOne`] value is responsible for releasing the dynamically-checked borrow made by
+    /// `get`, so it can't be dropped while references returned by `get` are live.
+    ///
+    /// Handy for accessing multiple components simultaneously.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((123, true, "abc"));
+    /// // The returned query must outlive the borrow made by `get`
+    /// let mut query = frame.query_one::<(&mut i32, &bool)>(a).unwrap();
+    /// let (number, flag) = query.get().unwrap();
+    /// if *flag { *number *= 2; }
+    /// assert_eq!(*number, 246);
+    /// ```
+    pub fn query_one<Q: Query>(&self, entity: Entity) -> Result<QueryOne<'_, Q>, NoSuchEntity> {
+        let loc = self.entities.get(entity)?;
+        Ok(unsafe {
+            QueryOne::new(
+                &self.archetypes.archetypes[loc.archetype as usize],
+                loc.index,
+            )
+        })
+    }
+
+    /// Query a single entity in a uniquely borrowed frame
+    ///
+    /// Like [`query_one`](Self::query_one), but faster because dynamic borrow checks can be
+    /// skipped. Note that, unlike [`query_one`](Self::query_one), on success this returns the
+    /// query's results directly.
+    pub fn query_one_mut<Q: Query>(
+        &mut self,
+        entity: Entity,
+    ) -> Result<Q::Item<'_>, QueryOneError> {
+        assert_borrow::<Q>();
+
+        let loc = self.entities.get(entity)?;
+        let archetype = &self.archetypes.archetypes[loc.archetype as usize];
+        let state = Q::Fetch::prepare(archetype).ok_or(QueryOneError::Unsatisfied)?;
+        let fetch = Q::Fetch::execute(archetype, state);
+        unsafe { get_filtered::<Q>(&fetch, loc.index as usize).ok_or(QueryOneError::Unsatisfied) }
+    }
+
+    /// Query a fixed number of distinct entities in a uniquely borrowed frame
+    ///
+    /// Like [`query_one_mut`](Self::query_one_mut), but for multiple entities, which would
+    /// otherwise be forbidden by the unique borrow. Panics if the same entity occurs more than
+    /// once.
+    pub fn query_many_mut<Q: Query, const N: usize>(
+        &mut self,
+        entities: [Entity; N],
+    ) -> [Result<Q::Item<'_>, QueryOneError>; N] {
+        assert_borrow::<Q>();
+        assert_distinct(&entities);
+
+        entities.map(|entity| {
+            let loc = self.entities.get(entity)?;
+            let archetype = &self.archetypes.archetypes[loc.archetype as usize];
+            let state = Q::Fetch::prepare(archetype).ok_or(QueryOneError::Unsatisfied)?;
+            let fetch = Q::Fetch::execute(archetype, state);
+            unsafe {
+                get_filtered::<Q>(&fetch, loc.index as usize).ok_or(QueryOneError::Unsatisfied)
+            }
+        })
+    }
+
+    /// Short-hand for [`entity`](Self::entity) followed by [`EntityRef::get`]
+    pub fn get<'a, T: ComponentRef<'a>>(
+        &'a self,
+        entity: Entity,
+    ) -> Result<T::Ref, ComponentError> {
+        Ok(self
+            .entity(entity)?
+            .get::<T>()
+            .ok_or_else(MissingComponent::new::<T::Component>)?)
+    }
+
+    /// Uniquely borrow a field of one of `entity`'s components by its path, e.g.
+    /// `"Transform.translation.x"`
+    ///
+    /// The first element of `path` is the [name](crate::Registration::name) of a component type in
+    /// `registry`, and each further element names a field registered with
+    /// [`Registration::field`](crate::Registration::field) on the type reached so far. Useful for
+    /// binding animation tracks or editor widgets to properties.
+    ///
+    /// Returns `None` if `entity` doesn't exist or lacks the component, if any element of the path
+    /// isn't registered, or if the field isn't an `F`.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// struct Vec3 { x: f32, y: f32, z: f32 }
+    /// struct Transform { translation: Vec3 }
+    ///
+    /// let mut registry = ComponentRegistry::new();
+    /// registry
+    ///     .register::<Transform>()
+    ///     .name("Transform")
+    ///     .field("translation", |t| &mut t.translation);
+    /// registry.register::<Vec3>().field("x", |v| &mut v.x);
+    ///
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((Transform { translation: Vec3 { x: 1.0, y: 2.0, z: 3.0 } },));
+    /// *frame.get_field_mut::<f32>(&registry, e, "Transform.translation.x").unwrap() = 4.0;
+    /// assert_eq!(frame.get::<&Transform>(e).unwrap().translation.x, 4.0);
+    /// assert!(frame.get_field_mut::<f32>(&registry, e, "Transform.translation.y").is_none());
+    /// ```
+    #[cfg(feature = "reflect")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "reflect")))]
+    pub fn get_field_mut<F: 'static>(
+        &mut self,
+        registry: &ComponentRegistry,
+        entity: Entity,
+        path: &str,
+    ) -> Option<&mut F> {
+        let (component, fields) = match path.split_once('.') {
+            Some((component, fields)) => (component, Some(fields)),
+            None => (path, None),
+        };
+        let ty = registry.find_by_name(component)?.type_info();
+        let loc = self.entities.get(entity).ok()?;
+        let archetype = &self.archetypes.archetypes[loc.archetype as usize];
+        unsafe {
+            // Unique access to `self` rules out outstanding borrows
+            let state = archetype.get_state_dynamic(ty.id())?;
+            archetype.mark_written(state);
+            archetype.mark_changed(state, loc.index);
+            let ptr = archetype.get_dynamic(ty.id(), ty.layout().size(), loc.index)?;
+            let (ty, ptr) = match fields {
+                Some(fields) => registry.resolve_path(ty.id(), ptr, fields)?,
+                None => (ty.id(), ptr),
+            };
+            (ty == TypeId::of::<F>()).then(|| &mut *ptr.as_ptr().cast::<F>())
+        }
+    }
+
+    /// Short-hand for [`entity`](Self::entity) followed by [`EntityRef::cloned`]
+    ///
+    /// Clones the components of types `T`, a tuple such as `(A, B)`, without holding any borrow
+    /// afterwards.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((42, "abc"));
+    /// let (number, name) = frame.cloned_one::<(i32, &str)>(e).unwrap();
+    /// assert_eq!((number, name), (42, "abc"));
+    /// ```
+    pub fn cloned_one<T: ClonedComponents>(&self, entity: Entity) -> Result<T, ComponentError> {
+        Ok(self.entity(entity)?.cloned::<T>()?)
+    }
+
+    /// Short-hand for [`entity`](Self::entity) followed by [`EntityRef::satisfies`]
+    pub fn satisfies<Q: Query>(&self, entity: Entity) -> Result<bool, NoSuchEntity> {
+        Ok(self.entity(entity)?.satisfies::<Q>())
+    }
+
+    /// Short-hand for [`entity`](Self::entity) followed by [`EntityRef::has_bundle`]
+    ///
+    /// Convenient for checking the outcome of spawning, inserting or removing a bundle using the
+    /// same type.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123, true));
+    /// frame.insert(e, ("abc", 1.0f32)).unwrap();
+    /// assert!(frame.has_bundle::<(&str, f32)>(e).unwrap());
+    /// frame.remove::<(&str, f32)>(e).unwrap();
+    /// assert!(!frame.has_bundle::<(&str, f32)>(e).unwrap());
+    /// assert!(frame.has_bundle::<(i32, bool)>(e).unwrap());
+    /// ```
+    pub fn has_bundle<B: Bundle + 'static>(&self, entity: Entity) -> Result<bool, NoSuchEntity> {
+        Ok(self.entity(entity)?.has_bundle::<B>())
+    }
+
+    /// Describe why `entity` doesn't satisfy `Q`, if it doesn't
+    ///
+    /// Intended for debugging queries that unexpectedly skip an entity.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123, true));
+    /// assert!(frame.explain::<&i32>(e).is_ok());
+    /// assert_eq!(
+    ///     frame.explain::<Without<(&i32, &&str), &bool>>(e),
+    ///     Err(QueryMismatch::Components {
+    ///         missing: vec!["&str"],
+    ///         excluded: vec!["bool"],
+    ///     })
+    /// );
+    /// ```
+    pub fn explain<Q: Query>(&self, entity: Entity) -> Result<(), QueryMismatch> {
+        let loc = self
+            .entities
+            .get(entity)
+            .map_err(|NoSuchEntity| QueryMismatch::NoSuchEntity)?;
+        match QueryMismatch::explain::<Q>(&self.archetypes.archetypes[loc.archetype as usize]) {
+            Some(mismatch) => Err(mismatch),
+            None => Ok(()),
+        }
+    }
+
+    /// Access an entity regardless of its component types
+    ///
+    /// Does not immediately borrow any component.
+    pub fn entity(&self, entity: Entity) -> Result<EntityRef<'_>, NoSuchEntity> {
+        let loc = self.entities.get(entity)?;
+        unsafe {
+            Ok(EntityRef::new(
+                &self.archetypes.archetypes[loc.archetype as usize],
+                entity,
+                loc.index,
+            ))
+        }
+    }
+
+    /// Given an id obtained from [`Entity::id`], reconstruct the still-live [`Entity`].
+    ///
+    /// # Safety
+    ///
+    /// `id` must correspond to a currently live [`Entity`]. A despawned or never-allocated `id`
+    /// will produce undefined behavior.
+    pub unsafe fn find_entity_from_id(&self, id: u32) -> Entity {
+        self.entities.resolve_unknown_gen(id)
+    }
+
+    /// Walk every component of a type registered in `registry`, archetype by archetype, then
+    /// column by column, then row by row
+    ///
+    /// A registry-driven traversal for debuggers and exporters: empty archetypes and components of
+    /// unregistered types are skipped, and within each archetype columns are visited in the order
+    /// of [`EntityRef::visit_sorted`], so output doesn't depend on how archetypes happened to be
+    /// constructed. Each column is borrowed only while its rows are being visited. The
+    /// [`serialize`](crate::serialize) formats don't use it, as they're driven by user-supplied
+    /// contexts rather than a registry.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// struct Dump(Vec<String>);
+    /// impl FrameVisitor for Dump {
+    ///     fn begin_column(&mut self, component: &RegisteredComponent) -> bool {
+    ///         self.0.push(component.name().into());
+    ///         true
+    ///     }
+    ///     fn visit(&mut self, _: Entity, component: DynamicComponent<'_>) {
+    ///         if let Some(x) = component.downcast_ref::<i32>() {
+    ///             self.0.push(x.to_string());
+    ///         }
+    ///     }
+    /// }
+    /// let mut registry = ComponentRegistry::new();
+    /// registry.register::<i32>().name("int");
+    /// let mut frame = Frame::new();
+    /// frame.spawn((1, "unregistered"));
+    /// let mut dump = Dump(Vec::new());
+    /// frame.visit_all(&registry, &mut dump);
+    /// assert_eq!(dump.0, ["int", "1"]);
+    /// ```
+    ///
+    /// Panics if any visited column is already uniquely borrowed.
+    pub fn visit_all(&self, registry: &ComponentRegistry, visitor: &mut dyn FrameVisitor) {
+        for archetype in &self.archetypes.archetypes {
+            if archetype.is_empty() || !visitor.begin_archetype(archetype) {
+                continue;
+            }
+            for (_, component) in registry.sorted_columns(archetype) {
+                if !visitor.begin_column(component) {
+                    continue;
+                }
+                let column = archetype
+                    .dynamic_column(component.type_info().id())
+                    .unwrap();
+                for (index, &id) in archetype.ids().iter().enumerate() {
+                    // Safety: every id stored in an archetype belongs to a live entity
+                    let entity = unsafe { self.entities.resolve_unknown_gen(id) };
+                    visitor.visit(entity, column.get_raw(index as u32).unwrap());
+                }
+                drop(column);
+                visitor.end_column(component);
+            }
+            visitor.end_archetype(archetype);
+        }
+    }
+
+    /// Iterate over all entities in the frame
+    ///
+    /// Entities are yielded in arbitrary order. Prefer [`query`](Self::query) for better
+    /// performance when components will be accessed in predictable patterns.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn(());
+    /// let b = frame.spawn(());
+    /// let ids = frame.iter().map(|entity_ref| entity_ref.entity()).collect::<Vec<_>>();
+    /// assert_eq!(ids.len(), 2);
+    /// assert!(ids.contains(&a));
+    /// assert!(ids.contains(&b));
+    /// ```
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(&self.archetypes.archetypes, &self.entities)
+    }
+
+    /// Like [`iter`](Self::iter), but returns child iterators of at most `batch_size` entities
+    ///
+    /// Useful for distributing whole-frame passes such as serialization over a threadpool, like
+    /// [`QueryBorrow::iter_batched`](crate::QueryBorrow::iter_batched).
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// frame.spawn_batch((0..5).map(|i| (i,)));
+    /// frame.spawn((true,));
+    /// let sizes = frame.iter_batched(2).map(|batch| batch.len()).collect::<Vec<_>>();
+    /// assert_eq!(sizes.iter().sum::<usize>(), 6);
+    /// assert!(sizes.iter().all(|&n| n <= 2));
+    /// ```
+    ///
+    /// Panics if `batch_size` is zero.
+    pub fn iter_batched(&self, batch_size: u32) -> BatchedEntityIter<'_> {
+        assert_ne!(batch_size, 0, "batch size must be positive");
+        BatchedEntityIter {
+            archetypes: self.archetypes.archetypes.iter(),
+            entities: &self.entities,
+            batch_size,
+            offset: 0,
+        }
+    }
+
+    /// Add `components` to `entity`
+    ///
+    /// Computational cost is proportional to the number of components `entity` has. If an entity
+    /// already has a component of a certain type, it is dropped and replaced.
+    ///
+    /// When inserting a single component, see [`insert_one`](Self::insert_one) for convenience.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123, "abc"));
+    /// frame.insert(e, (456, true));
+    /// assert_eq!(*frame.get::<&i32>(e).unwrap(), 456);
+    /// assert_eq!(*frame.get::<&bool>(e).unwrap(), true);
+    /// ```
+    ///
+    /// Panics if moving `entity` to its new archetype is rejected by the policy passed to
+    /// [`set_budget`](Self::set_budget), leaving it unchanged.
+    pub fn insert(
+        &mut self,
+        entity: Entity,
+        components: impl DynamicBundle,
+    ) -> Result<(), NoSuchEntity> {
+        self.flush();
+
+        let loc = self.entities.get(entity)?;
+        let loc = self
+            .admit_insert(entity, loc, loc.archetype, &components, false)
+            .unwrap_or_else(|e| panic!("insert rejected: {}", e));
+        self.insert_inner(entity, components, loc.archetype, loc, |ptr, ty| unsafe {
+            ty.drop(ptr)
+        });
+        Ok(())
+    }
+
+    /// Like [`insert`](Self::insert), but drops `components` rather than panicking if the insert is
+    /// rejected by the budget
+    pub(crate) fn insert_within_budget(
+        &mut self,
+        entity: Entity,
+        components: impl DynamicBundle,
+    ) -> Result<(), NoSuchEntity> {
+        self.flush();
+
+        let loc = self.entities.get(entity)?;
+        if let Ok(loc) = self.admit_insert(entity, loc, loc.archetype, &components, false) {
+            self.insert_inner(entity, components, loc.archetype, loc, |ptr, ty| unsafe {
+                ty.drop(ptr)
+            });
+        }
+        Ok(())
+    }
+
+    /// Give the reserved `entity` its `components`, checking the budget as for a spawn and
+    /// despawning `entity` if it's rejected
+    pub(crate) fn spawn_reserved(&mut self, entity: Entity, components: impl DynamicBundle) {
+        self.flush();
+
+        let Ok(loc) = self.entities.get(entity) else {
+            return;
+        };
+        match self.admit_insert(entity, loc, loc.archetype, &components, true) {
+            Ok(loc) => {
+                self.insert_inner(entity, components, loc.archetype, loc, |ptr, ty| unsafe {
+                    ty.drop(ptr)
+                });
+                self.record_spawned([entity]);
+            }
+            Err(_) => {
+                self.despawn(entity).unwrap();
+            }
+        }
+    }
+
+    /// Like [`insert`](Self::insert), but returns any components that were overwritten instead of
+    /// dropping them
+    ///
+    /// Useful for undo systems, which can restore the previous state by inserting the result.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123, "abc"));
+    /// let mut old = frame.insert_replacing(e, (456, true)).unwrap();
+    /// assert_eq!(*old.get::<&i32>().unwrap(), 123);
+    /// assert!(!old.has::<bool>());
+    /// frame.insert(e, old.build()).unwrap();
+    /// assert_eq!(*frame.get::<&i32>(e).unwrap(), 123);
+    /// ```
+    pub fn insert_replacing(
+        &mut self,
+        entity: Entity,
+        components: impl DynamicBundle,
+    ) -> Result<EntityBuilder, NoSuchEntity> {
+        self.flush();
+
+        let loc = self.entities.get(entity)?;
+        let loc = self
+            .admit_insert(entity, loc, loc.archetype, &components, false)
+            .unwrap_or_else(|e| panic!("insert rejected: {}", e));
+        let mut replaced = EntityBuilder::new();
+        self.insert_inner(entity, components, loc.archetype, loc, |ptr, ty| unsafe {
+            replaced.add_dynamic(ptr, ty)
+        });
+        Ok(replaced)
+    }
+
+    /// The implementation backing [`insert`](Self::insert) exposed so that it can also be used by [`exchange`](Self::exchange).
+    ///
+    /// Note that `graph_origin` is always equal to `loc.archetype` during insertion. Only for exchange, `graph_origin` identifies
+    /// the intermediate archetype which would be reached after removal and before insertion even though
+    /// the actual component data still resides in `loc.archetype`.
+    fn insert_inner(
         &mut self,
         entity: Entity,
         components: impl DynamicBundle,
         graph_origin: u32,
         loc: Location,
+        mut replaced: impl FnMut(*mut u8, TypeInfo),
     ) {
         let target_storage;
         let target = match components.key() {
@@ -620,14 +2511,20 @@ impl Frame {
             },
         };
 
+        let target_id = target.index;
+        if target.index != loc.archetype {
+            self.archetypes.archetypes[loc.archetype as usize].assert_unpinned();
+            self.archetypes.archetypes[target.index as usize].assert_fits(1);
+        }
+
         let source_arch = &mut self.archetypes.archetypes[loc.archetype as usize];
         unsafe {
-            // Drop the components we're overwriting
+            // Dispose of the components we're overwriting
             for &ty in &target.replaced {
                 let ptr = source_arch
                     .get_dynamic(ty.id(), ty.layout().size(), loc.index)
                     .unwrap();
-                ty.drop(ptr.as_ptr());
+                replaced(ptr.as_ptr(), ty);
             }
 
             if target.index == loc.archetype {
@@ -635,7 +2532,12 @@ impl Frame {
                 let arch = &mut self.archetypes.archetypes[loc.archetype as usize];
                 components.put(|ptr, ty| {
                     arch.put_dynamic(ptr, ty.id(), ty.layout().size(), loc.index);
+                    arch.mark_changed(arch.get_state_dynamic(ty.id()).unwrap(), loc.index);
                 });
+                if !self.references.is_empty() {
+                    self.references.update(entity, Some((arch, loc.index)));
+                }
+                self.debug_validate(entity);
                 return;
             }
 
@@ -651,133 +2553,725 @@ impl Frame {
             meta.location.archetype = target.index;
             meta.location.index = target_index;
 
-            // Move the new components
+            // Move the new components, preserving the ticks of any they replace
+            target_arch.copy_ticks(target_index, source_arch, loc.index);
             components.put(|ptr, ty| {
                 target_arch.put_dynamic(ptr, ty.id(), ty.layout().size(), target_index);
+                let state = target_arch.get_state_dynamic(ty.id()).unwrap();
+                target_arch.mark_changed(state, target_index);
+            });
+
+            // Move the components we're keeping
+            for &ty in &target.retained {
+                let src = source_arch
+                    .get_dynamic(ty.id(), ty.layout().size(), loc.index)
+                    .unwrap();
+                target_arch.put_dynamic(src.as_ptr(), ty.id(), ty.layout().size(), target_index)
+            }
+
+            // Free storage in the old archetype
+            if let Some(moved) = source_arch.remove(loc.index, false) {
+                self.entities.meta[moved as usize].location.index = loc.index;
+            }
+        }
+        self.report_growth(target_id);
+        self.notify_observers(entity, Some(loc.archetype), Some(target_id));
+        self.debug_validate(entity);
+    }
+
+    /// Add `component` to `entity`
+    ///
+    /// See [`insert`](Self::insert).
+    pub fn insert_one(
+        &mut self,
+        entity: Entity,
+        component: impl Component,
+    ) -> Result<(), NoSuchEntity> {
+        self.insert(entity, (component,))
+    }
+
+    /// Add `component` to `entity`, returning the `T` it replaced, if any
+    ///
+    /// See [`insert_replacing`](Self::insert_replacing).
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123,));
+    /// assert_eq!(frame.insert_one_replacing(e, 456).unwrap(), Some(123));
+    /// assert_eq!(frame.insert_one_replacing(e, true).unwrap(), None);
+    /// ```
+    pub fn insert_one_replacing<T: Component>(
+        &mut self,
+        entity: Entity,
+        component: T,
+    ) -> Result<Option<T>, NoSuchEntity> {
+        self.flush();
+
+        let loc = self.entities.get(entity)?;
+        let components = (component,);
+        let loc = self
+            .admit_insert(entity, loc, loc.archetype, &components, false)
+            .unwrap_or_else(|e| panic!("insert rejected: {}", e));
+        let mut replaced = None;
+        self.insert_inner(entity, components, loc.archetype, loc, |ptr, _| unsafe {
+            // The only component type that can be replaced is `T`
+            replaced = Some(ptr.cast::<T>().read());
+        });
+        Ok(replaced)
+    }
+
+    /// Overwrite the existing `T` component of each entity in `updates`
+    ///
+    /// Much faster than looking up each component individually, since the location of `T`
+    /// components is resolved only once per archetype. Entities which no longer exist or don't
+    /// have a `T` component are skipped. If an entity appears multiple times, the last value wins.
+    ///
+    /// Returns the number of components written.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((1.0f32,));
+    /// let b = frame.spawn((2.0f32, true));
+    /// let c = frame.spawn(("abc",));
+    /// assert_eq!(frame.set_many(&[(a, 10.0f32), (b, 20.0), (c, 30.0)]), 2);
+    /// assert_eq!(*frame.get::<&f32>(a).unwrap(), 10.0);
+    /// assert_eq!(*frame.get::<&f32>(b).unwrap(), 20.0);
+    /// ```
+    pub fn set_many<T: Component + Clone>(&mut self, updates: &[(Entity, T)]) -> usize {
+        // State and base address of each archetype's `T` column, resolved on first use
+        let mut columns = vec![None; self.archetypes.archetypes.len()];
+        let mut written = 0;
+        for (entity, value) in updates {
+            let Ok(loc) = self.entities.get(*entity) else {
+                continue;
+            };
+            let archetype = &self.archetypes.archetypes[loc.archetype as usize];
+            let column = columns[loc.archetype as usize].get_or_insert_with(|| {
+                archetype.get_state::<T>().map(|state| {
+                    // Unique access to `self` rules out outstanding borrows
+                    unsafe {
+                        archetype.mark_written(state);
+                    }
+                    (state, archetype.get_base::<T>(state))
+                })
             });
+            if let Some((state, base)) = *column {
+                unsafe {
+                    *base.as_ptr().add(loc.index as usize) = value.clone();
+                    archetype.mark_changed(state, loc.index);
+                }
+                written += 1;
+            }
+        }
+        written
+    }
+
+    /// Keep a copy of every `T` component's value as of the last [`save_previous`](Self::save_previous)
+    ///
+    /// Useful for e.g. interpolating positions for rendering or estimating velocities, without
+    /// maintaining a separate component for the previous value. Values are saved immediately.
+    /// Calling this again for the same `T` has no effect.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((1.0f32,));
+    /// frame.track_previous::<f32>();
+    /// *frame.get::<&mut f32>(e).unwrap() = 3.0;
+    /// for (_, current, previous) in frame.view_with_previous::<f32>().iter() {
+    ///     let interpolated = previous.map_or(*current, |p| (p + current) / 2.0);
+    ///     assert_eq!(interpolated, 2.0);
+    /// }
+    /// frame.save_previous();
+    /// assert_eq!(*frame.previous::<f32>(e).unwrap(), 3.0);
+    /// ```
+    pub fn track_previous<T: Component + Clone>(&mut self) {
+        if self.shadows.contains_key(&TypeId::of::<T>()) {
+            return;
+        }
+        let mut shadow = ShadowColumn::<T>::new();
+        shadow.save(self);
+        self.shadows.insert(TypeId::of::<T>(), Box::new(shadow));
+    }
+
+    /// Save the current value of every component type registered with
+    /// [`track_previous`](Self::track_previous), typically once per tick
+    ///
+    /// Panics if any tracked component is uniquely borrowed.
+    pub fn save_previous(&mut self) {
+        let mut shadows = mem::take(&mut self.shadows);
+        for shadow in shadows.values_mut() {
+            shadow.save(self);
+        }
+        self.shadows = shadows;
+    }
+
+    /// The value of `entity`'s `T` component as of the last [`save_previous`](Self::save_previous)
+    ///
+    /// Returns `None` if `entity` didn't have a `T` component at the time. Panics if `T` isn't
+    /// registered with [`track_previous`](Self::track_previous).
+    pub fn previous<T: Component>(&self, entity: Entity) -> Option<&T> {
+        self.shadow::<T>().get(entity)
+    }
+
+    /// Borrow every `T` component alongside its value as of the last
+    /// [`save_previous`](Self::save_previous)
+    ///
+    /// Panics if `T` isn't registered with [`track_previous`](Self::track_previous), or if any `T`
+    /// component is uniquely borrowed.
+    pub fn view_with_previous<T: Component>(&self) -> PreviousView<'_, T> {
+        PreviousView::new(self.query(), self.shadow::<T>())
+    }
+
+    fn shadow<T: Component>(&self) -> &ShadowColumn<T> {
+        self.shadows
+            .get(&TypeId::of::<T>())
+            .and_then(|x| x.as_any().downcast_ref())
+            .unwrap_or_else(|| {
+                panic!(
+                    "previous values of {} aren't tracked",
+                    core::any::type_name::<T>()
+                )
+            })
+    }
+
+    /// Remove components from `entity`
+    ///
+    /// Computational cost is proportional to the number of components `entity` has. The entity
+    /// itself is not removed, even if no components remain; use `despawn` for that. If any
+    /// component in `T` is not present in `entity`, no components are removed and an error is
+    /// returned.
+    ///
+    /// When removing a single component, see [`remove_one`](Self::remove_one) for convenience.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123, "abc", true));
+    /// assert_eq!(frame.remove::<(i32, &str)>(e), Ok((123, "abc")));
+    /// assert!(frame.get::<&i32>(e).is_err());
+    /// assert!(frame.get::<&&str>(e).is_err());
+    /// assert_eq!(*frame.get::<&bool>(e).unwrap(), true);
+    /// ```
+    pub fn remove<T: Bundle + 'static>(&mut self, entity: Entity) -> Result<T, ComponentError> {
+        self.flush();
+
+        // Gather current metadata
+        let loc = self.entities.get_mut(entity)?;
+        let old_index = loc.index;
+        let source_arch = &self.archetypes.archetypes[loc.archetype as usize];
+
+        // Move out of the source archetype, or bail out if a component is missing. Not dropped
+        // until the move is complete, in case the archetypes involved are pinned.
+        let bundle = ManuallyDrop::new(unsafe {
+            T::get(|ty| source_arch.get_dynamic(ty.id(), ty.layout().size(), old_index))?
+        });
+
+        // Find the target archetype ID
+        let target =
+            Self::remove_target::<T>(&mut self.archetypes, &mut self.remove_edges, loc.archetype);
+
+        // Store components to the target archetype and update metadata
+        let source = loc.archetype;
+        if source != target {
+            // If we actually removed any components, the entity needs to be moved into a new archetype
+            let (source_arch, target_arch) = index2(
+                &mut self.archetypes.archetypes,
+                loc.archetype as usize,
+                target as usize,
+            );
+            source_arch.assert_unpinned();
+            target_arch.assert_fits(1);
+            let target_index = unsafe { target_arch.allocate(entity.id) };
+            target_arch.copy_ticks(target_index, source_arch, old_index);
+            loc.archetype = target;
+            loc.index = target_index;
+            if let Some(moved) = unsafe {
+                source_arch.move_to(old_index, |src, ty, size| {
+                    // Only move the components present in the target archetype, i.e. the non-removed ones.
+                    if let Some(dst) = target_arch.get_dynamic(ty, size, target_index) {
+                        ptr::copy_nonoverlapping(src, dst.as_ptr(), size);
+                    }
+                })
+            } {
+                self.entities.meta[moved as usize].location.index = old_index;
+            }
+            self.report_growth(target);
+            self.notify_observers(entity, Some(source), Some(target));
+        }
+
+        Ok(ManuallyDrop::into_inner(bundle))
+    }
+
+    fn remove_target<T: Bundle + 'static>(
+        archetypes: &mut ArchetypeSet,
+        remove_edges: &mut IndexTypeIdMap<u32>,
+        old_archetype: u32,
+    ) -> u32 {
+        match remove_edges.entry((old_archetype, TypeId::of::<T>())) {
+            Entry::Occupied(entry) => *entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let info = T::with_static_type_info(|removed| {
+                    archetypes.archetypes[old_archetype as usize]
+                        .types()
+                        .iter()
+                        .filter(|x| removed.binary_search(x).is_err())
+                        .cloned()
+                        .collect::<Vec<_>>()
+                });
+                let elements = info.iter().map(|x| x.id()).collect::<Box<_>>();
+                let index = archetypes.get(&*elements, move || info);
+                *entry.insert(index)
+            }
+        }
+    }
+
+    /// Remove and drop the component of the type identified by `ty` from `entity`
+    ///
+    /// Like [`remove_one`](Self::remove_one), but for types chosen at runtime, e.g. by an editor.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// # use std::any::TypeId;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123, "abc"));
+    /// frame.remove_dynamic(e, TypeId::of::<&str>()).unwrap();
+    /// assert!(!frame.satisfies::<&&str>(e).unwrap());
+    /// assert!(frame.remove_dynamic(e, TypeId::of::<&str>()).is_err());
+    /// assert_eq!(*frame.get::<&i32>(e).unwrap(), 123);
+    /// ```
+    pub fn remove_dynamic(&mut self, entity: Entity, ty: TypeId) -> Result<(), ComponentError> {
+        self.flush();
+        let loc = self.entities.get(entity)?;
+        let source = loc.archetype;
+        let source_arch = &self.archetypes.archetypes[source as usize];
+        let removed = source_arch
+            .get_state_dynamic(ty)
+            .map(|state| source_arch.types()[state])
+            .ok_or_else(MissingComponent::dynamic)?;
+        let retained = source_arch
+            .types()
+            .iter()
+            .filter(|x| x.id() != ty)
+            .cloned()
+            .collect::<Vec<_>>();
+        let elements = retained.iter().map(|x| x.id()).collect::<Box<_>>();
+        let target = self.archetypes.get(&*elements, move || retained);
+
+        let (source_arch, target_arch) = index2(
+            &mut self.archetypes.archetypes,
+            source as usize,
+            target as usize,
+        );
+        source_arch.assert_unpinned();
+        target_arch.assert_fits(1);
+        let target_index = unsafe { target_arch.allocate(entity.id) };
+        target_arch.copy_ticks(target_index, source_arch, loc.index);
+        if let Some(moved) = unsafe {
+            source_arch.move_to(loc.index, |src, ty, size| {
+                match target_arch.get_dynamic(ty, size, target_index) {
+                    Some(dst) => ptr::copy_nonoverlapping(src, dst.as_ptr(), size),
+                    None => removed.drop(src),
+                }
+            })
+        } {
+            self.entities.meta[moved as usize].location.index = loc.index;
+        }
+        self.entities.meta[entity.id as usize].location = Location {
+            archetype: target,
+            index: target_index,
+        };
+        self.report_growth(target);
+        self.notify_observers(entity, Some(source), Some(target));
+        Ok(())
+    }
+
+    /// Drop every component of `entity`, leaving it alive with no components
+    ///
+    /// Unlike [`despawn`](Self::despawn), handles to `entity` remain valid, so pooled entities can
+    /// be stripped and reused in place.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123, "abc"));
+    /// frame.clear_entity(e).unwrap();
+    /// assert!(frame.contains(e));
+    /// assert_eq!(frame.entity(e).unwrap().len(), 0);
+    /// ```
+    pub fn clear_entity(&mut self, entity: Entity) -> Result<(), NoSuchEntity> {
+        self.flush();
+        let loc = self.entities.get_mut(entity)?;
+        let source = loc.archetype;
+        if source == 0 {
+            return Ok(());
+        }
+        let old_index = loc.index;
+        let (target_arch, source_arch) =
+            index2(&mut self.archetypes.archetypes, 0, source as usize);
+        source_arch.assert_unpinned();
+        target_arch.assert_fits(1);
+        let target_index = unsafe { target_arch.allocate(entity.id) };
+        loc.archetype = 0;
+        loc.index = target_index;
+        if let Some(moved) = unsafe { source_arch.remove(old_index, true) } {
+            self.entities.meta[moved as usize].location.index = old_index;
+        }
+        self.report_growth(0);
+        self.notify_observers(entity, Some(source), Some(0));
+        Ok(())
+    }
+
+    /// Remove the `T` component from `entity`
+    ///
+    /// See [`remove`](Self::remove).
+    pub fn remove_one<T: Component>(&mut self, entity: Entity) -> Result<T, ComponentError> {
+        self.remove::<(T,)>(entity).map(|(x,)| x)
+    }
+
+    /// Recent changes to the archetype of `entity`, oldest first
+    ///
+    /// Up to 16 transitions are retained per entity by default, including spawning and despawning,
+    /// each listing the component types gained or lost. Useful for diagnosing entities that
+    /// repeatedly move between archetypes. Entities spawned in bulk, e.g. by
+    /// [`spawn_batch`](Self::spawn_batch), have no record of being spawned.
+    ///
+    /// Requires the `entity-history` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123,));
+    /// frame.insert_one(e, true).unwrap();
+    /// frame.remove_one::<bool>(e).unwrap();
+    /// let history = frame.debug_entity_history(e);
+    /// assert_eq!(history.len(), 3);
+    /// assert_eq!(history[1].added[0].id(), std::any::TypeId::of::<bool>());
+    /// assert_eq!(history[2].removed[0].id(), std::any::TypeId::of::<bool>());
+    /// for transition in &history {
+    ///     println!("{}", transition);
+    /// }
+    /// ```
+    #[cfg(feature = "entity-history")]
+    pub fn debug_entity_history(&self, entity: Entity) -> Vec<ArchetypeTransition> {
+        self.history.get(entity, &self.archetypes.archetypes)
+    }
+
+    /// Retain up to `len` archetype transitions per entity for
+    /// [`debug_entity_history`](Self::debug_entity_history), or none if `len` is zero
+    ///
+    /// Requires the `entity-history` feature.
+    #[cfg(feature = "entity-history")]
+    pub fn set_entity_history_len(&mut self, len: usize) {
+        self.history.set_len(len);
+    }
+
+    /// Recent structural operations on this frame, oldest first
+    ///
+    /// Every spawn, insertion, removal and despawn is recorded with the component types involved
+    /// and the tick set by [`set_event_log_tick`](Self::set_event_log_tick), up to the 1024 most
+    /// recent by default. Useful as a flight recorder, e.g. dumped when an invariant check fails,
+    /// to reconstruct how an entity reached a bad state. Entities spawned in bulk are recorded as
+    /// a single [`OpKind::SpawnBatch`](crate::OpKind::SpawnBatch).
+    ///
+    /// Requires the `event-log` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// frame.set_event_log_tick(7);
+    /// let e = frame.spawn((123,));
+    /// frame.insert_one(e, true).unwrap();
+    /// let log = frame.event_log();
+    /// assert_eq!(log[1].kind, OpKind::Insert);
+    /// assert_eq!(log[1].tick, 7);
+    /// for op in &log {
+    ///     println!("{}", op);
+    /// }
+    /// ```
+    #[cfg(feature = "event-log")]
+    pub fn event_log(&self) -> Vec<LoggedOp> {
+        self.event_log.get(&self.archetypes.archetypes)
+    }
 
-            // Move the components we're keeping
-            for &ty in &target.retained {
-                let src = source_arch
-                    .get_dynamic(ty.id(), ty.layout().size(), loc.index)
-                    .unwrap();
-                target_arch.put_dynamic(src.as_ptr(), ty.id(), ty.layout().size(), target_index)
-            }
+    /// Tag operations subsequently recorded in the [`event_log`](Self::event_log) with `tick`
+    ///
+    /// Requires the `event-log` feature.
+    #[cfg(feature = "event-log")]
+    pub fn set_event_log_tick(&mut self, tick: u64) {
+        self.event_log.set_tick(tick);
+    }
 
-            // Free storage in the old archetype
-            if let Some(moved) = source_arch.remove(loc.index, false) {
-                self.entities.meta[moved as usize].location.index = loc.index;
-            }
-        }
+    /// Retain up to `capacity` operations in the [`event_log`](Self::event_log), or none if
+    /// `capacity` is zero
+    ///
+    /// Requires the `event-log` feature.
+    #[cfg(feature = "event-log")]
+    pub fn set_event_log_capacity(&mut self, capacity: usize) {
+        self.event_log.set_capacity(capacity);
     }
 
-    /// Add `component` to `entity`
+    /// Relate `child` to `parent` by giving it an `R` component referring to `parent`
     ///
-    /// See [`insert`](Self::insert).
-    pub fn insert_one(
+    /// Replaces any existing `R` of `child`, and registers `R` with
+    /// [`register_relation`](Self::register_relation) so that the `R` components referring to
+    /// `parent` are removed when it's despawned. Use [`related`](Self::related) to find the
+    /// children of a parent, and [`Related`](crate::Related) to query entities along with their
+    /// relations' targets.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// #[derive(Clone, PartialEq)]
+    /// struct ChildOf(Entity);
+    /// impl Relation for ChildOf { fn target(&self) -> Entity { self.0 } }
+    /// impl From<Entity> for ChildOf { fn from(parent: Entity) -> Self { Self(parent) } }
+    ///
+    /// let mut frame = Frame::new();
+    /// let parent = frame.spawn(());
+    /// let child = frame.spawn((1.0f32,));
+    /// frame.attach::<ChildOf>(child, parent).unwrap();
+    /// assert_eq!(frame.related::<ChildOf>(parent).collect::<Vec<_>>(), [child]);
+    /// let targets = frame.query::<Related<&f32, ChildOf>>()
+    ///     .iter()
+    ///     .map(|(_, (target, _))| target)
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(targets, [parent]);
+    /// frame.despawn(parent).unwrap();
+    /// assert!(!frame.satisfies::<&ChildOf>(child).unwrap());
+    /// ```
+    pub fn attach<R: Relation + From<Entity>>(
         &mut self,
-        entity: Entity,
-        component: impl Component,
+        child: Entity,
+        parent: Entity,
     ) -> Result<(), NoSuchEntity> {
-        self.insert(entity, (component,))
+        if !self.contains(parent) {
+            return Err(NoSuchEntity);
+        }
+        self.insert_one(child, R::from(parent))?;
+        self.register_relation::<R>();
+        Ok(())
     }
 
-    /// Remove components from `entity`
-    ///
-    /// Computational cost is proportional to the number of components `entity` has. The entity
-    /// itself is not removed, even if no components remain; use `despawn` for that. If any
-    /// component in `T` is not present in `entity`, no components are removed and an error is
-    /// returned.
+    /// Remove the `R` components referring to an entity when it's despawned or
+    /// [`take`](Self::take)n
     ///
-    /// When removing a single component, see [`remove_one`](Self::remove_one) for convenience.
+    /// Applies to every `R` however it was added, e.g. by [`attach`](Self::attach), spawning,
+    /// inserting, a batch, or deserialization. Registrations are carried over by
+    /// [`ComponentRegistry`](crate::ComponentRegistry)'s clones and merges. The cost of each
+    /// despawn is proportional to the number of entities having an `R` unless `R` was passed to
+    /// [`index_references`](Self::index_references).
     ///
     /// # Example
     /// ```
     /// # use moss_hecs::*;
+    /// #[derive(Clone, PartialEq)]
+    /// struct ChildOf(Entity);
+    /// impl Relation for ChildOf { fn target(&self) -> Entity { self.0 } }
+    ///
     /// let mut frame = Frame::new();
-    /// let e = frame.spawn((123, "abc", true));
-    /// assert_eq!(frame.remove::<(i32, &str)>(e), Ok((123, "abc")));
-    /// assert!(frame.get::<&i32>(e).is_err());
-    /// assert!(frame.get::<&&str>(e).is_err());
-    /// assert_eq!(*frame.get::<&bool>(e).unwrap(), true);
+    /// frame.register_relation::<ChildOf>();
+    /// let parent = frame.spawn(());
+    /// let child = frame.spawn((ChildOf(parent),));
+    /// frame.despawn(parent).unwrap();
+    /// assert!(!frame.satisfies::<&ChildOf>(child).unwrap());
     /// ```
-    pub fn remove<T: Bundle + 'static>(&mut self, entity: Entity) -> Result<T, ComponentError> {
-        self.flush();
-
-        // Gather current metadata
-        let loc = self.entities.get_mut(entity)?;
-        let old_index = loc.index;
-        let source_arch = &self.archetypes.archetypes[loc.archetype as usize];
+    pub fn register_relation<R: Relation>(&mut self) {
+        self.relations
+            .entry(TypeId::of::<R>())
+            .or_insert(relation::detach_from::<R>);
+    }
 
-        // Move out of the source archetype, or bail out if a component is missing
-        let bundle = unsafe {
-            T::get(|ty| source_arch.get_dynamic(ty.id(), ty.layout().size(), old_index))?
+    /// Iterate over the entities whose `R` relation refers to `target`, e.g. the children of a
+    /// parent
+    ///
+    /// Uses the index maintained for [`referencing`](Self::referencing) if `R` was passed to
+    /// [`index_references`](Self::index_references), taking time proportional to the number of
+    /// entities yielded; otherwise, every entity having an `R` is visited. Entities are yielded in
+    /// arbitrary order.
+    pub fn related<R: Relation>(
+        &mut self,
+        target: Entity,
+    ) -> impl ExactSizeIterator<Item = Entity> {
+        let holders = if self.references.indexes(TypeId::of::<R>()) {
+            self.refresh_references();
+            self.references
+                .holders(target)
+                .filter(|&(_, ty)| ty == TypeId::of::<R>())
+                .map(|(holder, _)| holder)
+                .collect::<Vec<_>>()
+        } else {
+            self.query_mut::<&R>()
+                .into_iter()
+                .filter(|(_, relation)| relation.target() == target)
+                .map(|(holder, _)| holder)
+                .collect::<Vec<_>>()
         };
+        holders.into_iter()
+    }
 
-        // Find the target archetype ID
-        let target =
-            Self::remove_target::<T>(&mut self.archetypes, &mut self.remove_edges, loc.archetype);
+    /// Adopt the relation types `source` cleans up after and indexes
+    pub(crate) fn copy_relations(&mut self, source: &Frame) {
+        self.relations
+            .extend(source.relations.iter().map(|(&ty, &detach)| (ty, detach)));
+        if self.references.register_all(&source.references) {
+            self.refresh_references();
+        }
+    }
 
-        // Store components to the target archetype and update metadata
-        if loc.archetype != target {
-            // If we actually removed any components, the entity needs to be moved into a new archetype
-            let (source_arch, target_arch) = index2(
-                &mut self.archetypes.archetypes,
-                loc.archetype as usize,
-                target as usize,
-            );
-            let target_index = unsafe { target_arch.allocate(entity.id) };
-            loc.archetype = target;
-            loc.index = target_index;
-            if let Some(moved) = unsafe {
-                source_arch.move_to(old_index, |src, ty, size| {
-                    // Only move the components present in the target archetype, i.e. the non-removed ones.
-                    if let Some(dst) = target_arch.get_dynamic(ty, size, target_index) {
-                        ptr::copy_nonoverlapping(src, dst.as_ptr(), size);
-                    }
-                })
-            } {
-                self.entities.meta[moved as usize].location.index = old_index;
-            }
+    /// Remove the `R` relation from `child`, returning it
+    ///
+    /// See [`attach`](Self::attach).
+    pub fn detach<R: Relation>(&mut self, child: Entity) -> Result<R, ComponentError> {
+        self.remove_one::<R>(child)
+    }
+
+    /// Maintain an index of the entities whose `R` component refers to each entity, for use by
+    /// [`referencing`](Self::referencing)
+    ///
+    /// The index is updated as entities are spawned and despawned and as components are inserted
+    /// and removed, at a cost independent of the number of entities. Existing `R` components are
+    /// indexed immediately. A target changed by writing to an `R` in place, e.g. through a query,
+    /// is picked up when the index is next read, by rescanning each archetype whose `R`s were
+    /// written. Indexing `R` also speeds up [`related`](Self::related), and so removing the `R`s
+    /// that refer to a despawned entity if `R` is passed to
+    /// [`register_relation`](Self::register_relation).
+    pub fn index_references<R: Relation>(&mut self) {
+        if self.references.register::<R>() {
+            self.refresh_references();
         }
+    }
 
-        Ok(bundle)
+    /// Iterate over the entities having a component of a type passed to
+    /// [`index_references`](Self::index_references) that refers to `target`
+    ///
+    /// Takes time proportional to the number of entities yielded, plus that of rescanning any
+    /// archetypes whose indexed relations were written in place. An entity referring to `target`
+    /// through several indexed relation types is yielded once for each. Entities are yielded in
+    /// arbitrary order.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// #[derive(Clone, PartialEq)]
+    /// struct Targeting(Entity);
+    /// impl Relation for Targeting { fn target(&self) -> Entity { self.0 } }
+    ///
+    /// let mut frame = Frame::new();
+    /// frame.index_references::<Targeting>();
+    /// let target = frame.spawn(());
+    /// let a = frame.spawn((Targeting(target),));
+    /// let b = frame.spawn((Targeting(target), true));
+    /// frame.despawn(a).unwrap();
+    /// assert_eq!(frame.referencing(target).collect::<Vec<_>>(), [b]);
+    /// ```
+    pub fn referencing(&mut self, target: Entity) -> impl ExactSizeIterator<Item = Entity> + '_ {
+        self.refresh_references();
+        self.references.referencing(target)
     }
 
-    fn remove_target<T: Bundle + 'static>(
-        archetypes: &mut ArchetypeSet,
-        remove_edges: &mut IndexTypeIdMap<u32>,
-        old_archetype: u32,
-    ) -> u32 {
-        match remove_edges.entry((old_archetype, TypeId::of::<T>())) {
-            Entry::Occupied(entry) => *entry.into_mut(),
-            Entry::Vacant(entry) => {
-                let info = T::with_static_type_info(|removed| {
-                    archetypes.archetypes[old_archetype as usize]
-                        .types()
-                        .iter()
-                        .filter(|x| removed.binary_search(x).is_err())
-                        .cloned()
-                        .collect::<Vec<_>>()
-                });
-                let elements = info.iter().map(|x| x.id()).collect::<Box<_>>();
-                let index = archetypes.get(&*elements, move || info);
-                *entry.insert(index)
-            }
+    /// Register `T` as a transient component, to be stripped from every entity by
+    /// [`clear_transient`](Self::clear_transient)
+    pub fn register_transient<T: Component>(&mut self) {
+        let info = TypeInfo::of::<T>();
+        if let Err(index) = self.transient.binary_search(&info) {
+            self.transient.insert(index, info);
         }
     }
 
-    /// Remove the `T` component from `entity`
+    /// Remove every component whose type was registered with
+    /// [`register_transient`](Self::register_transient), returning the number of entities affected
     ///
-    /// See [`remove`](Self::remove).
-    pub fn remove_one<T: Component>(&mut self, entity: Entity) -> Result<T, ComponentError> {
-        self.remove::<(T,)>(entity).map(|(x,)| x)
+    /// Entities are moved an archetype at a time, which is far cheaper than removing the
+    /// components individually. Suits one-frame marker or event components, e.g. `JustSpawned`,
+    /// cleared at the end of each tick.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// struct Damaged;
+    /// let mut frame = Frame::new();
+    /// frame.register_transient::<Damaged>();
+    /// let a = frame.spawn((123, Damaged));
+    /// let b = frame.spawn((456,));
+    /// assert_eq!(frame.clear_transient(), 1);
+    /// assert!(!frame.satisfies::<&Damaged>(a).unwrap());
+    /// assert_eq!(*frame.get::<&i32>(a).unwrap(), 123);
+    /// ```
+    pub fn clear_transient(&mut self) -> u32 {
+        self.flush();
+        let mut affected = 0;
+        for source in 0..self.archetypes.archetypes.len() {
+            let source_arch = &self.archetypes.archetypes[source];
+            if source_arch.is_empty()
+                || !self
+                    .transient
+                    .iter()
+                    .any(|x| source_arch.has_dynamic(x.id()))
+            {
+                continue;
+            }
+            source_arch.assert_unpinned();
+            let info = source_arch
+                .types()
+                .iter()
+                .filter(|x| self.transient.binary_search(x).is_err())
+                .cloned()
+                .collect::<Vec<_>>();
+            let elements = info.iter().map(|x| x.id()).collect::<Box<_>>();
+            let target = self.archetypes.get(&*elements, move || info);
+            let (source_arch, target_arch) =
+                index2(&mut self.archetypes.archetypes, source, target as usize);
+            target_arch.assert_fits(source_arch.len());
+            let base = unsafe { source_arch.move_all_to(target_arch) };
+            for (index, &id) in (base..).zip(&target_arch.ids()[base as usize..]) {
+                self.entities.meta[id as usize].location = Location {
+                    archetype: target,
+                    index,
+                };
+            }
+            affected += target_arch.len() - base;
+            self.report_growth(target);
+            if self.tracks_transitions() {
+                let ids =
+                    self.archetypes.archetypes[target as usize].ids()[base as usize..].to_vec();
+                for id in ids {
+                    let entity = unsafe { self.entities.resolve_unknown_gen(id) };
+                    self.notify_observers(entity, Some(source as u32), Some(target));
+                }
+            }
+        }
+        affected
     }
 
     /// Remove `S` components from `entity` and then add `components`
     ///
     /// This has the same effect as calling [`remove::<S>`](Self::remove) and then [`insert::<T>`](Self::insert),
     /// but is more efficient as the intermediate archetype after removal but before insertion is skipped.
+    ///
+    /// If any component in `S` is not present in `entity`, nothing is changed, an error is
+    /// returned, and `components` is dropped.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((123, "abc", true));
+    /// let old = frame.exchange::<(i32, &str), _>(e, (4.5f32,)).unwrap();
+    /// assert_eq!(old, (123, "abc"));
+    /// assert!(frame.satisfies::<(&f32, &bool)>(e).unwrap());
+    /// assert!(!frame.satisfies::<&i32>(e).unwrap());
+    /// ```
     pub fn exchange<S: Bundle + 'static, T: DynamicBundle>(
         &mut self,
         entity: Entity,
@@ -791,66 +3285,367 @@ impl Frame {
         // Move out of the source archetype, or bail out if a component is missing
         let source_arch = &self.archetypes.archetypes[loc.archetype as usize];
 
-        let bundle = unsafe {
+        let bundle = ManuallyDrop::new(unsafe {
             S::get(|ty| source_arch.get_dynamic(ty.id(), ty.layout().size(), loc.index))?
-        };
+        });
 
         // Find the intermediate archetype ID
         let intermediate =
             Self::remove_target::<S>(&mut self.archetypes, &mut self.remove_edges, loc.archetype);
+        // Until the components are moved, `bundle` only duplicates them, so it needn't be dropped
+        // if the insert is rejected
+        let loc = self
+            .admit_insert(entity, loc, intermediate, &components, false)
+            .unwrap_or_else(|e| panic!("insert rejected: {}", e));
+
+        self.insert_inner(entity, components, intermediate, loc, |ptr, ty| unsafe {
+            ty.drop(ptr)
+        });
+
+        Ok(ManuallyDrop::into_inner(bundle))
+    }
+
+    /// Remove the `S` component from `entity` and then add `component`
+    ///
+    /// See [`exchange`](Self::exchange).
+    pub fn exchange_one<S: Component, T: Component>(
+        &mut self,
+        entity: Entity,
+        component: T,
+    ) -> Result<S, ComponentError> {
+        self.exchange::<(S,), (T,)>(entity, (component,))
+            .map(|(x,)| x)
+    }
+
+    /// Move the `B` components of `src` to `dst`
+    ///
+    /// Equivalent to [`remove::<B>`](Self::remove) on `src` followed by [`insert`](Self::insert)
+    /// on `dst`, e.g. to transfer equipment. Components of `dst` with the same types are replaced
+    /// and dropped.
+    ///
+    /// If `dst` doesn't exist or any component in `B` is not present in `src`, nothing is changed
+    /// and an error is returned. Moving components from an entity to itself has no effect.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((123, "sword", true));
+    /// let b = frame.spawn((456,));
+    /// frame.move_bundle::<(i32, &str)>(a, b).unwrap();
+    /// assert!(!frame.satisfies::<&i32>(a).unwrap());
+    /// assert_eq!(*frame.get::<&i32>(b).unwrap(), 123);
+    /// assert_eq!(*frame.get::<&&str>(b).unwrap(), "sword");
+    /// ```
+    pub fn move_bundle<B: Bundle + 'static>(
+        &mut self,
+        src: Entity,
+        dst: Entity,
+    ) -> Result<(), ComponentError> {
+        if !self.contains(dst) {
+            return Err(ComponentError::NoSuchEntity);
+        }
+        if src == dst {
+            let loc = self.entities.get(src)?;
+            let archetype = &self.archetypes.archetypes[loc.archetype as usize];
+            // Only checks presence; the bitwise copy must not be dropped
+            core::mem::forget(unsafe {
+                B::get(|ty| archetype.get_dynamic(ty.id(), ty.layout().size(), loc.index))?
+            });
+            return Ok(());
+        }
+        let bundle = self.remove::<B>(src)?;
+        self.insert(dst, bundle)?;
+        Ok(())
+    }
+
+    /// Borrow a single component of `entity` without safety checks
+    ///
+    /// `T` must be a shared or unique reference to a component type.
+    ///
+    /// Should only be used as a building block for safe abstractions.
+    ///
+    /// # Safety
+    ///
+    /// `entity` must have been previously obtained from this [`Frame`], and no unique borrow of the
+    /// same component of `entity` may be live simultaneous to the returned reference. A unique
+    /// reference must not be obtained to a component in a column shared between frames by
+    /// [`ComponentRegistry::fork_frame`](crate::ComponentRegistry::fork_frame).
+    pub unsafe fn get_unchecked<'a, T: ComponentRef<'a>>(
+        &'a self,
+        entity: Entity,
+    ) -> Result<T, ComponentError> {
+        let loc = self.entities.get(entity)?;
+        let archetype = &self.archetypes.archetypes[loc.archetype as usize];
+        let state = archetype
+            .get_state::<T::Component>()
+            .ok_or_else(MissingComponent::new::<T::Component>)?;
+        Ok(T::from_raw(
+            archetype
+                .get_base::<T::Component>(state)
+                .as_ptr()
+                .add(loc.index as usize),
+        ))
+    }
+
+    /// Convert all reserved entities into empty entities that can be iterated and accessed
+    ///
+    /// Invoked implicitly by operations that add or remove components or entities, i.e. all
+    /// variations of `spawn`, `despawn`, `insert`, and `remove`.
+    pub fn flush(&mut self) {
+        self.begin_structural_change();
+        let observe = !self.observers.is_empty() && {
+            self.observers.update(&self.archetypes.archetypes);
+            self.observers.any_match(0)
+        };
+        #[cfg(feature = "event-log")]
+        if self.entities.needs_flush() {
+            self.event_log.spawn_batch(0);
+        }
+        let arch = &mut self.archetypes.archetypes[0];
+        let mut flushed = Vec::new();
+        self.entities.flush(|id, location| {
+            location.index = unsafe { arch.allocate(id) };
+            if observe {
+                flushed.push(id);
+            }
+        });
+        for id in flushed {
+            let entity = unsafe { self.entities.resolve_unknown_gen(id) };
+            self.observers.transition(entity, None, Some(0));
+        }
+        let abandoned = mem::take(&mut *self.abandoned.lock());
+        for entity in abandoned {
+            let _ = self.despawn(entity);
+        }
+    }
+
+    /// Where a [`CommandBuffer`] returns entities it reserved if it's discarded without being run
+    pub(crate) fn abandoned_reservations(&self) -> Arc<Mutex<Vec<Entity>>> {
+        self.abandoned.clone()
+    }
+
+    /// Apply operations recorded through [`defer`](Self::defer), after [`flush`](Self::flush)ing
+    /// reserved entities
+    ///
+    /// Unlike reserved entities, deferred operations are never applied implicitly.
+    pub fn apply_deferred(&mut self) {
+        self.flush();
+        let mut cmd = mem::take(self.deferred.get_mut());
+        cmd.run_on(self);
+        // Keep the buffer's allocation for reuse
+        *self.deferred.get_mut() = cmd;
+    }
+
+    /// Record structural changes to apply on the next [`apply_deferred`](Self::apply_deferred)
+    ///
+    /// Unlike a [`CommandBuffer`], the returned [`DeferredOps`] only requires shared access to the
+    /// frame, so it can be used while queries are borrowed. Operations recorded through any number
+    /// of `DeferredOps` are applied in the order they were recorded.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// frame.spawn((1, true));
+    /// frame.spawn((2, false));
+    /// let ops = frame.defer();
+    /// let mut spawned = Vec::new();
+    /// for (e, (&x, &flag)) in frame.query::<(&i32, &bool)>().iter() {
+    ///     if flag {
+    ///         spawned.push(ops.spawn((x * 10,)));
+    ///     } else {
+    ///         ops.despawn(e);
+    ///     }
+    /// }
+    /// frame.apply_deferred();
+    /// assert_eq!(frame.len(), 2);
+    /// assert_eq!(*frame.get::<&i32>(spawned[0]).unwrap(), 10);
+    /// ```
+    pub fn defer(&self) -> DeferredOps<'_> {
+        DeferredOps::new(self)
+    }
+
+    pub(crate) fn deferred_commands(&self) -> &Mutex<CommandBuffer> {
+        &self.deferred
+    }
+
+    /// Run two versions of a system, keeping the writes of `old` and reporting every `T` component
+    /// on which `new` disagrees
+    ///
+    /// Validates a rewrite of a system's queries or logic before switching over to it. `new` runs
+    /// first; its results are captured into a scratch buffer, and every `T` component is restored
+    /// to its prior value before `old` runs. Both systems must only modify components in place,
+    /// and the two must write no component types other than `T`, since only `T` is restored. The
+    /// ticks `new` stamps on `T` are restored too, so [`Changed`](crate::Changed) reflects only the
+    /// writes of `old`.
+    ///
+    /// Neither requirement is fully enforced. Structural changes are caught only in debug builds,
+    /// as if by a [`ReadTransaction`]; in release builds they go unnoticed, and entities `new`
+    /// despawns or strips of `T` are left out of the result. Writes `new` makes to components other
+    /// than `T` are never detected and persist in every build.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((1,));
+    /// let b = frame.spawn((-2,));
+    /// let mismatches = frame.compare_systems::<i32>(
+    ///     |frame| frame.query_mut::<&mut i32>().into_iter().for_each(|(_, x)| *x = x.abs()),
+    ///     |frame| frame.query_mut::<&mut i32>().into_iter().for_each(|(_, x)| *x *= x.signum()),
+    /// );
+    /// assert!(mismatches.is_empty());
+    /// let mismatches = frame.compare_systems::<i32>(
+    ///     |frame| frame.query_mut::<&mut i32>().into_iter().for_each(|(_, x)| *x += 1),
+    ///     |frame| frame.query_mut::<&mut i32>().into_iter().for_each(|(_, x)| *x *= 2),
+    /// );
+    /// assert_eq!(mismatches, [WriteMismatch { entity: b, old: 3, new: 4 }]);
+    /// assert_eq!(*frame.get::<&i32>(a).unwrap(), 2);
+    /// ```
+    pub fn compare_systems<T: Component + Clone + PartialEq>(
+        &mut self,
+        old: impl FnOnce(&mut Frame),
+        new: impl FnOnce(&mut Frame),
+    ) -> Vec<WriteMismatch<T>> {
+        let transaction = self.read_transaction();
+        // Keyed by entity, so results still pair up if a system reorders entities
+        let mut before = self
+            .query_mut::<&T>()
+            .into_iter()
+            .map(|(entity, x)| (entity, x.clone()))
+            .collect::<HashMap<_, _>>();
+        let mut ticks = HashMap::with_capacity(before.len());
+        for &entity in before.keys() {
+            let loc = self.entities.get(entity).unwrap();
+            let archetype = &self.archetypes.archetypes[loc.archetype as usize];
+            let state = archetype.get_state::<T>().unwrap();
+            ticks.insert(entity, archetype.component_ticks(state, loc.index));
+        }
+        new(self);
+        // Restore through the columns rather than a `&mut T` query, so that the ticks `new` stamped
+        // are rolled back along with its writes rather than restamped
+        let mut scratch = HashMap::with_capacity(before.len());
+        for (entity, prior) in before.drain() {
+            let Ok(loc) = self.entities.get(entity) else {
+                continue;
+            };
+            let archetype = &self.archetypes.archetypes[loc.archetype as usize];
+            let Some(state) = archetype.get_state::<T>() else {
+                continue;
+            };
+            unsafe {
+                archetype.mark_written(state);
+                let x = &mut *archetype
+                    .get_base::<T>(state)
+                    .as_ptr()
+                    .add(loc.index as usize);
+                scratch.insert(entity, mem::replace(x, prior));
+                if let Some(ticks) = ticks[&entity] {
+                    archetype.set_component_ticks(state, loc.index, ticks);
+                }
+            }
+        }
+        old(self);
+        drop(transaction);
+        self.query_mut::<&T>()
+            .into_iter()
+            .filter_map(|(entity, old)| {
+                let new = scratch.remove(&entity)?;
+                (*old != new).then(|| WriteMismatch {
+                    entity,
+                    old: old.clone(),
+                    new,
+                })
+            })
+            .collect()
+    }
+
+    /// Begin a region in which no structural changes may be made to the frame
+    ///
+    /// See [`ReadTransaction`].
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((1,));
+    /// let transaction = frame.read_transaction();
+    /// for (_, x) in frame.query_mut::<&mut i32>() {
+    ///     *x += 1;
+    /// }
+    /// // `frame.despawn(e)` would panic in debug builds
+    /// drop(transaction);
+    /// frame.write_transaction().despawn(e).unwrap();
+    /// ```
+    pub fn read_transaction(&self) -> ReadTransaction {
+        ReadTransaction::new(&self.readers)
+    }
 
-        self.insert_inner(entity, components, intermediate, loc);
+    /// Access the frame for structural changes, checking that no [`ReadTransaction`] is live
+    ///
+    /// Panics if a read transaction is live, regardless of whether debug assertions are enabled.
+    pub fn write_transaction(&mut self) -> WriteTransaction<'_> {
+        assert!(!self.in_read_transaction(), "read transaction in progress");
+        WriteTransaction::new(self)
+    }
 
-        Ok(bundle)
+    /// Whether any [`ReadTransaction`] is live
+    pub fn in_read_transaction(&self) -> bool {
+        self.readers.load(Ordering::Acquire) != 0
     }
 
-    /// Remove the `S` component from `entity` and then add `component`
-    ///
-    /// See [`exchange`](Self::exchange).
-    pub fn exchange_one<S: Component, T: Component>(
-        &mut self,
-        entity: Entity,
-        component: T,
-    ) -> Result<S, ComponentError> {
-        self.exchange::<(S,), (T,)>(entity, (component,))
-            .map(|(x,)| x)
+    /// Called before any structural change
+    fn begin_structural_change(&self) {
+        debug_assert!(
+            !self.in_read_transaction(),
+            "structural change during a read transaction"
+        );
     }
 
-    /// Borrow a single component of `entity` without safety checks
+    /// Write the components described by `layout` of each entity matching `Q` into consecutive
+    /// elements of `out`, returning the number of entities written
     ///
-    /// `T` must be a shared or unique reference to a component type.
+    /// Entities lacking any component named by `layout` are skipped. Entities are written in the
+    /// order they're visited by [`query`](Self::query), one archetype at a time, with bounds checked
+    /// once up front. Useful for filling GPU vertex or instance buffers. Bytes past the written
+    /// elements are left untouched, as are those reserved by [`InterleavedLayout::padding`].
     ///
-    /// Should only be used as a building block for safe abstractions.
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// #[derive(Copy, Clone)]
+    /// struct Position([f32; 2]);
+    /// #[derive(Copy, Clone)]
+    /// struct Scale(f32);
+    /// struct Hidden;
     ///
-    /// # Safety
+    /// let mut frame = Frame::new();
+    /// frame.spawn((Position([1.0, 2.0]), Scale(3.0)));
+    /// frame.spawn((Position([4.0, 5.0]), Scale(6.0), Hidden));
+    /// frame.spawn((Position([7.0, 8.0]),));
     ///
-    /// `entity` must have been previously obtained from this [`Frame`], and no unique borrow of the
-    /// same component of `entity` may be live simultaneous to the returned reference.
-    pub unsafe fn get_unchecked<'a, T: ComponentRef<'a>>(
-        &'a self,
-        entity: Entity,
-    ) -> Result<T, ComponentError> {
-        let loc = self.entities.get(entity)?;
-        let archetype = &self.archetypes.archetypes[loc.archetype as usize];
-        let state = archetype
-            .get_state::<T::Component>()
-            .ok_or_else(MissingComponent::new::<T::Component>)?;
-        Ok(T::from_raw(
-            archetype
-                .get_base::<T::Component>(state)
-                .as_ptr()
-                .add(loc.index as usize),
-        ))
-    }
-
-    /// Convert all reserved entities into empty entities that can be iterated and accessed
+    /// let mut layout = InterleavedLayout::new();
+    /// // Safety: neither type contains padding
+    /// unsafe { layout.field::<Position>().field::<Scale>() };
+    /// let mut buffer = vec![0; 2 * layout.stride()];
+    /// let n = frame.gather_interleaved::<Without<(), &Hidden>>(&layout, &mut buffer).unwrap();
+    /// assert_eq!(n, 1);
+    /// let floats = buffer[..layout.stride()]
+    ///     .chunks(4)
+    ///     .map(|x| f32::from_ne_bytes(x.try_into().unwrap()))
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(floats, [1.0, 2.0, 3.0]);
+    /// ```
     ///
-    /// Invoked implicitly by operations that add or remove components or entities, i.e. all
-    /// variations of `spawn`, `despawn`, `insert`, and `remove`.
-    pub fn flush(&mut self) {
-        let arch = &mut self.archetypes.archetypes[0];
-        self.entities
-            .flush(|id, location| location.index = unsafe { arch.allocate(id) });
+    /// Returns an error without writing anything if `out` is too small to hold every element, and
+    /// panics if any component to be copied is uniquely borrowed.
+    pub fn gather_interleaved<Q: Query>(
+        &self,
+        layout: &InterleavedLayout,
+        out: &mut [u8],
+    ) -> Result<usize, BufferTooSmall> {
+        layout.gather::<Q>(self.archetypes_inner(), out)
     }
 
     /// Inspect the archetypes that entities are organized into
@@ -862,12 +3657,191 @@ impl Frame {
         self.archetypes_inner().iter()
     }
 
+    /// Position in [`archetypes`](Self::archetypes) of the archetype holding `entity`
+    ///
+    /// Archetypes are never removed, so an archetype keeps its position for the frame's lifetime.
+    /// Combined with [`ComponentRegistry::archetype_masks`], this allows external systems to test
+    /// an entity's components without further calls into the frame.
+    pub fn archetype_id(&self, entity: Entity) -> Result<u32, NoSuchEntity> {
+        Ok(self.entities.get(entity)?.archetype)
+    }
+
+    /// Access metadata of type `T` attached to the `archetype`th element of
+    /// [`archetypes`](Self::archetypes), creating it with [`Default`] if absent
+    ///
+    /// Lets per-archetype caches, such as a GPU instance buffer or a broad-phase bucket, live
+    /// alongside the archetype rather than in a parallel map. Metadata can be read back during
+    /// iteration with [`Archetype::metadata`], and is dropped along with the archetype. Any
+    /// number of types may be attached to each archetype.
+    ///
+    /// Panics if `archetype` is out of bounds.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// #[derive(Default)]
+    /// struct InstanceBuffer(Option<u64>);
+    ///
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((1, true));
+    /// let archetype = frame.archetype_id(e).unwrap();
+    /// frame.archetype_metadata_mut::<InstanceBuffer>(archetype).0 = Some(7);
+    /// for archetype in frame.archetypes() {
+    ///     if let Some(buffer) = archetype.metadata::<InstanceBuffer>() {
+    ///         assert_eq!(buffer.0, Some(7));
+    ///     }
+    /// }
+    /// ```
+    pub fn archetype_metadata_mut<T: Default + Send + Sync + 'static>(
+        &mut self,
+        archetype: u32,
+    ) -> &mut T {
+        self.archetypes.archetypes[archetype as usize].metadata_mut()
+    }
+
+    /// Replace every `T` component in the `archetype`th element of [`archetypes`](Self::archetypes)
+    /// with `values`, in the order of [`Archetype::ids`], returning the previous values
+    ///
+    /// Suits systems that compute a whole column's new values out of place, e.g. on another
+    /// thread. If `values.capacity()` equals [`Archetype::capacity`], its storage is adopted in
+    /// constant time rather than copied.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((1, true));
+    /// let b = frame.spawn((2, false));
+    /// let index = frame.archetypes().position(|x| x.has::<i32>()).unwrap();
+    /// let archetype = frame.archetypes().nth(index).unwrap();
+    /// let mut values = Vec::with_capacity(archetype.capacity() as usize);
+    /// values.extend(archetype.get::<&i32>().unwrap().iter().map(|x| x * 10));
+    /// let old = frame.replace_column(index, values).unwrap();
+    /// assert_eq!(old, [1, 2]);
+    /// assert_eq!(*frame.get::<&i32>(a).unwrap(), 10);
+    /// assert_eq!(*frame.get::<&i32>(b).unwrap(), 20);
+    /// ```
+    ///
+    /// Panics if `archetype` is out of bounds, or if the archetype is pinned and `values` would be
+    /// adopted.
+    pub fn replace_column<T: Component>(
+        &mut self,
+        archetype: usize,
+        mut values: Vec<T>,
+    ) -> Result<Vec<T>, ReplaceColumnError> {
+        let archetype = &mut self.archetypes.archetypes[archetype];
+        if !archetype.has::<T>() {
+            return Err(MissingComponent::new::<T>().into());
+        }
+        if values.len() != archetype.len() as usize {
+            return Err(ReplaceColumnError::WrongLength {
+                expected: archetype.len(),
+                actual: values.len(),
+            });
+        }
+        if values.capacity() == archetype.capacity() as usize {
+            archetype.assert_unpinned();
+        }
+        unsafe {
+            archetype.swap_column(&mut values);
+        }
+        Ok(values)
+    }
+
+    /// Keep entities with a `K` component ordered by its value within each archetype when
+    /// [`regroup`](Self::regroup) is called
+    ///
+    /// Grouping entities by e.g. the region they belong to lets queries which only process some
+    /// groups touch contiguous memory. Replaces any previous group key.
+    pub fn set_group_key<K: Component + Ord>(&mut self) {
+        fn sort_order<K: Component + Ord>(archetype: &Archetype) -> Option<Vec<u32>> {
+            let keys = archetype.get::<&K>()?;
+            if keys.windows(2).all(|x| x[0] <= x[1]) {
+                return None;
+            }
+            let mut order = (0..archetype.len()).collect::<Vec<u32>>();
+            order.sort_by(|&a, &b| keys[a as usize].cmp(&keys[b as usize]));
+            Some(order)
+        }
+        self.group_key = Some(sort_order::<K>);
+    }
+
+    /// Stop ordering entities by the key set with [`set_group_key`](Self::set_group_key)
+    pub fn clear_group_key(&mut self) {
+        self.group_key = None;
+    }
+
+    /// Reorder entities within each archetype by the key set with
+    /// [`set_group_key`](Self::set_group_key), returning the number of archetypes reordered
+    ///
+    /// Entities spawned or moved into an archetype are appended to it, so this should be called
+    /// again after changes to restore the grouping. Archetypes which are already in order are
+    /// skipped cheaply. Iteration order and [`Archetype::ids`] reflect the grouping; entity
+    /// handles are unaffected.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// #[derive(PartialEq, Eq, PartialOrd, Ord)]
+    /// struct Region(u32);
+    ///
+    /// let mut frame = Frame::new();
+    /// frame.set_group_key::<Region>();
+    /// let a = frame.spawn((Region(2), "a"));
+    /// let b = frame.spawn((Region(1), "b"));
+    /// let c = frame.spawn((Region(2), "c"));
+    /// assert_eq!(frame.regroup(), 1);
+    /// let order = frame.query::<&&str>().iter().map(|(_, &s)| s).collect::<Vec<_>>();
+    /// assert_eq!(order, ["b", "a", "c"]);
+    /// assert_eq!(*frame.get::<&&str>(c).unwrap(), "c");
+    /// ```
+    ///
+    /// Panics if an archetype to be reordered is pinned.
+    pub fn regroup(&mut self) -> usize {
+        let Some(sort_order) = self.group_key else {
+            return 0;
+        };
+        let mut count = 0;
+        for archetype in &mut self.archetypes.archetypes {
+            let Some(order) = sort_order(archetype) else {
+                continue;
+            };
+            archetype.assert_unpinned();
+            unsafe {
+                archetype.permute(&order);
+            }
+            for (index, &id) in archetype.ids().iter().enumerate() {
+                self.entities.meta[id as usize].location.index = index as u32;
+            }
+            count += 1;
+        }
+        count
+    }
+
     /// Despawn `entity`, yielding a [`DynamicBundle`] of its components
     ///
-    /// Useful for moving entities between frames.
+    /// Useful for moving entities between frames. Relations referring to `entity` are removed as
+    /// if it were despawned; see [`register_relation`](Self::register_relation).
     pub fn take(&mut self, entity: Entity) -> Result<TakenEntity<'_>, NoSuchEntity> {
+        let allocator = self.allocator.clone();
+        let taken = self.take_inner(entity)?;
+        if let Some(allocator) = allocator {
+            allocator.free(entity);
+        }
+        Ok(taken)
+    }
+
+    /// Like `take`, but doesn't release `entity`'s ID to a [`SharedAllocator`]
+    fn take_inner(&mut self, entity: Entity) -> Result<TakenEntity<'_>, NoSuchEntity> {
         self.flush();
+        if !self.contains(entity) {
+            return Err(NoSuchEntity);
+        }
+        // Relations `entity` holds to itself are taken along with it
+        self.detach_relations(entity);
         let loc = self.entities.get(entity)?;
+        self.archetypes.archetypes[loc.archetype as usize].assert_unpinned();
+        self.notify_observers(entity, Some(loc.archetype), None);
         let archetype = &mut self.archetypes.archetypes[loc.archetype as usize];
         unsafe {
             Ok(TakenEntity::new(
@@ -879,6 +3853,42 @@ impl Frame {
         }
     }
 
+    /// Move `entity` and all its components into `dest`, preserving its [`Entity`] handle
+    ///
+    /// Fails without modifying either frame if `entity` doesn't exist in `self`, if its ID is
+    /// already in use by a live entity in `dest`, or unless both frames allocate IDs from the same
+    /// [`SharedAllocator`] or neither uses one. An ID moved from a frame with a shared allocator
+    /// stays allocated, so it can't move to one without, and vice versa.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut a = Frame::new();
+    /// let mut b = Frame::new();
+    /// let e = a.spawn((123, "abc"));
+    /// a.transfer(e, &mut b).unwrap();
+    /// assert!(!a.contains(e));
+    /// assert_eq!(*b.get::<&i32>(e).unwrap(), 123);
+    /// ```
+    pub fn transfer(&mut self, entity: Entity, dest: &mut Frame) -> Result<(), TransferError> {
+        match (&self.allocator, &dest.allocator) {
+            (None, None) => {}
+            (Some(a), Some(b)) if a.ptr_eq(b) => {}
+            _ => return Err(TransferError::Allocator),
+        }
+        self.flush();
+        dest.flush();
+        if !self.entities.contains(entity) {
+            return Err(TransferError::NoSuchEntity);
+        }
+        if !dest.entities.is_free(entity.id()) {
+            return Err(TransferError::Occupied);
+        }
+        let components = self.take_inner(entity).unwrap();
+        dest.spawn_at(entity, components);
+        Ok(())
+    }
+
     /// Returns a distinct value after `archetypes` is changed
     ///
     /// Store the current value after deriving information from [`archetypes`](Self::archetypes),
@@ -973,6 +3983,107 @@ impl From<MissingComponent> for ComponentError {
     }
 }
 
+/// Errors that arise when replacing a column with [`Frame::replace_column`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ReplaceColumnError {
+    /// The archetype did not have the component type being replaced
+    MissingComponent(MissingComponent),
+    /// The number of values differed from the number of entities in the archetype
+    WrongLength {
+        /// Number of entities in the archetype
+        expected: u32,
+        /// Number of values supplied
+        actual: usize,
+    },
+}
+
+#[cfg(feature = "std")]
+impl Error for ReplaceColumnError {}
+
+impl fmt::Display for ReplaceColumnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ReplaceColumnError::*;
+        match *self {
+            MissingComponent(ref x) => x.fmt(f),
+            WrongLength { expected, actual } => write!(
+                f,
+                "expected {} values for the column, but got {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl From<MissingComponent> for ReplaceColumnError {
+    fn from(x: MissingComponent) -> Self {
+        ReplaceColumnError::MissingComponent(x)
+    }
+}
+
+/// A component on which two systems compared by [`Frame::compare_systems`] disagree
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WriteMismatch<T> {
+    /// Entity having the component
+    pub entity: Entity,
+    /// Value written by the old system, which the frame keeps
+    pub old: T,
+    /// Value written by the new system
+    pub new: T,
+}
+
+/// Errors that arise when transferring an entity between frames
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum TransferError {
+    /// The entity was already despawned
+    NoSuchEntity,
+    /// The destination frame has a live entity with the same ID
+    Occupied,
+    /// The frames don't allocate IDs from the same [`SharedAllocator`]
+    Allocator,
+}
+
+#[cfg(feature = "std")]
+impl Error for TransferError {}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use TransferError::*;
+        match *self {
+            NoSuchEntity => f.write_str("no such entity"),
+            Occupied => f.write_str("entity ID in use by destination"),
+            Allocator => f.write_str("frames don't share an allocator"),
+        }
+    }
+}
+
+/// Errors that arise when resolving a token from [`Entity::to_token`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ResolveTokenError {
+    /// The string isn't a valid token
+    Invalid(ParseEntityTokenError),
+    /// The token is valid, but the entity it refers to has been despawned
+    Stale(Entity),
+}
+
+#[cfg(feature = "std")]
+impl Error for ResolveTokenError {}
+
+impl fmt::Display for ResolveTokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ResolveTokenError::*;
+        match *self {
+            Invalid(ref x) => x.fmt(f),
+            Stale(entity) => write!(f, "entity {:?} no longer exists", entity),
+        }
+    }
+}
+
+impl From<ParseEntityTokenError> for ResolveTokenError {
+    fn from(x: ParseEntityTokenError) -> Self {
+        ResolveTokenError::Invalid(x)
+    }
+}
+
 /// Errors that arise when querying a single entity
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum QueryOneError {
@@ -1074,6 +4185,85 @@ impl ExactSizeIterator for Iter<'_> {
     }
 }
 
+/// Batched version of [`Iter`], returned by [`Frame::iter_batched`]
+pub struct BatchedEntityIter<'a> {
+    archetypes: core::slice::Iter<'a, Archetype>,
+    entities: &'a Entities,
+    batch_size: u32,
+    /// Index of the first entity of the next batch in the current archetype
+    offset: u32,
+}
+
+unsafe impl Send for BatchedEntityIter<'_> {}
+unsafe impl Sync for BatchedEntityIter<'_> {}
+
+impl<'a> Iterator for BatchedEntityIter<'a> {
+    type Item = EntityBatch<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let archetype = self.archetypes.as_slice().first()?;
+            if self.offset >= archetype.len() {
+                self.archetypes.next();
+                self.offset = 0;
+                continue;
+            }
+            let start = self.offset;
+            self.offset += self.batch_size.min(archetype.len() - start);
+            return Some(EntityBatch {
+                archetype,
+                entities: self.entities,
+                index: start,
+                end: self.offset,
+            });
+        }
+    }
+}
+
+/// A sequence of entities yielded by [`BatchedEntityIter`]
+pub struct EntityBatch<'a> {
+    archetype: &'a Archetype,
+    entities: &'a Entities,
+    index: u32,
+    end: u32,
+}
+
+unsafe impl Send for EntityBatch<'_> {}
+unsafe impl Sync for EntityBatch<'_> {}
+
+impl<'a> Iterator for EntityBatch<'a> {
+    type Item = EntityRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index == self.end {
+            return None;
+        }
+        let index = self.index;
+        self.index += 1;
+        let id = self.archetype.entity_id(index);
+        Some(unsafe {
+            EntityRef::new(
+                self.archetype,
+                Entity {
+                    id,
+                    generation: self.entities.meta[id as usize].generation,
+                },
+                index,
+            )
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+impl ExactSizeIterator for EntityBatch<'_> {
+    fn len(&self) -> usize {
+        (self.end - self.index) as usize
+    }
+}
+
 impl<A: DynamicBundle> Extend<A> for Frame {
     fn extend<T>(&mut self, iter: T)
     where
@@ -1093,6 +4283,38 @@ impl<A: DynamicBundle> core::iter::FromIterator<A> for Frame {
     }
 }
 
+/// Iterator over the columns of a component type, returned by [`Frame::columns_mut`]
+pub struct ColumnsMut<'a, T> {
+    archetypes: &'a [Archetype],
+    ids: core::slice::Iter<'a, u32>,
+    _marker: core::marker::PhantomData<&'a mut T>,
+}
+
+impl<'a, T: Component> Iterator for ColumnsMut<'a, T> {
+    type Item = &'a mut [T];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let archetype = &self.archetypes[*self.ids.next()? as usize];
+            if archetype.is_empty() {
+                continue;
+            }
+            let state = archetype.get_state::<T>().unwrap();
+            // The frame is uniquely borrowed, and each archetype is visited at most once
+            return Some(unsafe {
+                archetype.mark_written(state);
+                let base = archetype.get_base::<T>(state);
+                archetype.mark_all_changed(state);
+                core::slice::from_raw_parts_mut(base.as_ptr(), archetype.len() as usize)
+            });
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.ids.len()))
+    }
+}
+
 /// Determines freshness of information derived from [`Frame::archetypes`]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct ArchetypesGeneration(u32);
@@ -1105,6 +4327,10 @@ where
 {
     inner: I,
     entities: &'a mut Entities,
+    observers: &'a mut Observers,
+    references: &'a mut relation::References,
+    /// Spawn order recorded for the budget, if one is enforced
+    budgeted: Option<&'a mut VecDeque<Entity>>,
     archetype_id: u32,
     archetype: &'a mut Archetype,
 }
@@ -1128,6 +4354,7 @@ where
 
     fn next(&mut self) -> Option<Entity> {
         let components = self.inner.next()?;
+        self.archetype.assert_fits(1);
         let entity = self.entities.alloc();
         let index = unsafe { self.archetype.allocate(entity.id) };
         unsafe {
@@ -1140,6 +4367,18 @@ where
             archetype: self.archetype_id,
             index,
         };
+        self.observers
+            .transition(entity, None, Some(self.archetype_id));
+        if !self.references.is_empty() {
+            // `entity` was just spawned into `archetype`, which is uniquely borrowed
+            unsafe {
+                self.references
+                    .update(entity, Some((self.archetype, index)));
+            }
+        }
+        if let Some(ref mut order) = self.budgeted {
+            order.push_back(entity);
+        }
         Some(entity)
     }
 
@@ -1195,6 +4434,14 @@ struct ArchetypeSet {
     /// Maps sorted component type sets to archetypes
     index: HashMap<Box<[TypeId]>, u32>,
     archetypes: Vec<Archetype>,
+    /// Registry of the only component types permitted in new archetypes, if any
+    strict: Option<Arc<ComponentRegistry>>,
+    /// Archetypes having each component type, in ascending order
+    by_type: TypeIdMap<Vec<u32>>,
+    /// Tick of every archetype, advanced by `Frame::clear_trackers`
+    tick: u32,
+    /// Component types whose changes are tracked, set by `Frame::track_changes`
+    tracked: Vec<TypeId>,
 }
 
 impl ArchetypeSet {
@@ -1203,6 +4450,28 @@ impl ArchetypeSet {
         Self {
             index: Some((Box::default(), 0)).into_iter().collect(),
             archetypes: vec![Archetype::new(Vec::new())],
+            strict: None,
+            by_type: HashMap::default(),
+            tick: 0,
+            tracked: Vec::new(),
+        }
+    }
+
+    /// Find a type in `types` that isn't registered with the strict registry, if any
+    fn check_registered(&self, types: &[TypeInfo]) -> Result<(), UnregisteredComponent> {
+        let Some(ref registry) = self.strict else {
+            return Ok(());
+        };
+        match types.iter().find(|ty| !registry.contains(ty.id())) {
+            Some(&ty) => Err(UnregisteredComponent { ty }),
+            None => Ok(()),
+        }
+    }
+
+    /// Panic if `types` includes a type that isn't registered with the strict registry
+    fn assert_registered(&self, types: &[TypeInfo]) {
+        if let Err(e) = self.check_registered(types) {
+            panic!("{}", e);
         }
     }
 
@@ -1219,21 +4488,56 @@ impl ArchetypeSet {
     }
 
     fn insert(&mut self, components: Box<[TypeId]>, info: Vec<TypeInfo>) -> u32 {
+        self.assert_registered(&info);
         let x = self.archetypes.len() as u32;
-        self.archetypes.push(Archetype::new(info));
+        for &id in components.iter() {
+            self.by_type.entry(id).or_default().push(x);
+        }
+        let mut archetype = Archetype::new(info);
+        archetype.set_tick(self.tick);
+        archetype.track_all(&self.tracked);
+        self.archetypes.push(archetype);
         let old = self.index.insert(components, x);
         debug_assert!(old.is_none(), "inserted duplicate archetype");
         x
     }
 
+    /// Start tracking changes to components of type `ty`
+    fn track(&mut self, ty: TypeId) {
+        if self.tracked.contains(&ty) {
+            return;
+        }
+        self.tracked.push(ty);
+        for &x in self.by_type.get(&ty).map_or(&[][..], |x| &x[..]) {
+            let archetype = &mut self.archetypes[x as usize];
+            let state = archetype.get_state_dynamic(ty).unwrap();
+            archetype.track(state);
+        }
+    }
+
+    /// Check that `archetype` could be passed to `insert_batch`
+    fn assert_batch_fits(&self, archetype: &Archetype) {
+        let ids = archetype
+            .types()
+            .iter()
+            .map(|info| info.id())
+            .collect::<Box<_>>();
+        if let Some(&x) = self.index.get(&ids) {
+            self.archetypes[x as usize].assert_fits(archetype.len());
+        }
+    }
+
     /// Returns archetype ID and starting location index
-    fn insert_batch(&mut self, archetype: Archetype) -> (u32, u32) {
+    fn insert_batch(&mut self, mut archetype: Archetype) -> (u32, u32) {
         let ids = archetype
             .types()
             .iter()
             .map(|info| info.id())
             .collect::<Box<_>>();
 
+        if !self.index.contains_key(&ids) {
+            self.assert_registered(archetype.types());
+        }
         match self.index.entry(ids) {
             Entry::Occupied(x) => {
                 // Duplicate of existing archetype
@@ -1247,6 +4551,11 @@ impl ArchetypeSet {
             Entry::Vacant(x) => {
                 // Brand new archetype
                 let id = self.archetypes.len() as u32;
+                for &ty in archetype.type_ids() {
+                    self.by_type.entry(ty).or_default().push(id);
+                }
+                archetype.track_all(&self.tracked);
+                archetype.adopt(self.tick);
                 self.archetypes.push(archetype);
                 x.insert(id);
                 (id, 0)
@@ -1254,6 +4563,17 @@ impl ArchetypeSet {
         }
     }
 
+    /// Like `insert_batch`, but leaves `archetype` empty rather than consuming it
+    fn insert_batch_from(&mut self, archetype: &mut Archetype) -> (u32, u32) {
+        let id = self.get(archetype.type_ids(), || archetype.types().to_vec());
+        let existing = &mut self.archetypes[id as usize];
+        let base = existing.len();
+        unsafe {
+            existing.merge_from(archetype);
+        }
+        (id, base)
+    }
+
     fn generation(&self) -> u32 {
         self.archetypes.len() as u32
     }
@@ -1306,6 +4626,21 @@ struct InsertTarget {
     index: u32,
 }
 
+/// A change to the contents of a frame, checked against its budget before it's made
+#[derive(Copy, Clone)]
+struct Growth {
+    /// Archetype entities are spawned or moved into
+    archetype: u32,
+    /// Number of entities added to `archetype`
+    rows: u32,
+    /// Number of entities added to the frame, or `None` if the change isn't a spawn
+    entities: Option<u32>,
+    /// Size of the components added
+    bytes_added: usize,
+    /// Size of the components dropped or moved out
+    bytes_freed: usize,
+}
+
 type IndexTypeIdMap<V> = HashMap<(u32, TypeId), V, BuildHasherDefault<IndexTypeIdHasher>>;
 
 #[derive(Default)]
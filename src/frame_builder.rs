@@ -0,0 +1,78 @@
+use crate::{Frame, SharedAllocator};
+
+/// Configures a [`Frame`] before it's created
+///
+/// Obtained from [`Frame::builder`]. Every setting defaults to that of [`Frame::new`].
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// let frame = Frame::builder()
+///     .entity_capacity(10_000)
+///     .archetype_capacity(64)
+///     .growth_threshold(Some(1 << 20))
+///     .handle_quarantine(0)
+///     .build();
+/// assert_eq!(frame.handle_quarantine(), 0);
+/// ```
+#[derive(Clone, Default)]
+pub struct FrameBuilder {
+    entities: u32,
+    archetypes: usize,
+    allocator: Option<SharedAllocator>,
+    growth_threshold: Option<usize>,
+    handle_quarantine: Option<u32>,
+}
+
+impl FrameBuilder {
+    /// Create a builder with default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of entities that can be allocated without reallocating
+    pub fn entity_capacity(mut self, entities: u32) -> Self {
+        self.entities = entities;
+        self
+    }
+
+    /// Number of archetypes that can be created without reallocating
+    pub fn archetype_capacity(mut self, archetypes: usize) -> Self {
+        self.archetypes = archetypes;
+        self
+    }
+
+    /// Allocate entity IDs from `allocator`
+    ///
+    /// See [`Frame::with_allocator`].
+    pub fn allocator(mut self, allocator: SharedAllocator) -> Self {
+        self.allocator = Some(allocator);
+        self
+    }
+
+    /// See [`Frame::set_growth_threshold`]
+    pub fn growth_threshold(mut self, bytes: Option<usize>) -> Self {
+        self.growth_threshold = bytes;
+        self
+    }
+
+    /// See [`Frame::set_handle_quarantine`]
+    pub fn handle_quarantine(mut self, spawns: u32) -> Self {
+        self.handle_quarantine = Some(spawns);
+        self
+    }
+
+    /// Create the configured frame
+    pub fn build(self) -> Frame {
+        let mut frame = match self.allocator {
+            Some(allocator) => Frame::with_allocator(allocator),
+            None => Frame::new(),
+        };
+        frame.reserve_capacity(self.entities, self.archetypes);
+        frame.set_growth_threshold(self.growth_threshold);
+        if let Some(spawns) = self.handle_quarantine {
+            frame.set_handle_quarantine(spawns);
+        }
+        frame
+    }
+}
@@ -0,0 +1,133 @@
+use alloc::vec::Vec;
+use core::any::TypeId;
+use core::fmt;
+use core::mem;
+use core::ptr;
+
+use crate::{Archetype, Component, Fetch, Query};
+
+/// Arrangement of components within each element of an interleaved buffer
+///
+/// Describes e.g. per-instance vertex data laid out as `[position, color, scale]`, for use with
+/// [`Frame::gather_interleaved`](crate::Frame::gather_interleaved). Fields are packed in the order
+/// they're added, without implicit padding.
+#[derive(Debug, Clone, Default)]
+pub struct InterleavedLayout {
+    fields: Vec<Field>,
+    stride: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Field {
+    ty: TypeId,
+    offset: usize,
+    size: usize,
+}
+
+impl InterleavedLayout {
+    /// Create a layout with no fields
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a field holding a copy of each entity's `T` component
+    ///
+    /// # Safety
+    ///
+    /// `T` must not contain padding or other uninitialized bytes, as its bytes are copied verbatim
+    /// into the output buffer. `#[repr(C)]` types made up of such types without gaps between
+    /// fields, such as `[f32; 3]`, qualify.
+    pub unsafe fn field<T: Component + Copy>(&mut self) -> &mut Self {
+        self.fields.push(Field {
+            ty: TypeId::of::<T>(),
+            offset: self.stride,
+            size: mem::size_of::<T>(),
+        });
+        self.stride += mem::size_of::<T>();
+        self
+    }
+
+    /// Append `bytes` bytes which are left untouched in the output buffer
+    pub fn padding(&mut self, bytes: usize) -> &mut Self {
+        self.stride += bytes;
+        self
+    }
+
+    /// Size of each element, in bytes
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Copy the fields of every entity in `archetypes` that matches `Q` and has each field's
+    /// component into consecutive elements of `out`
+    pub(crate) fn gather<Q: Query>(
+        &self,
+        archetypes: &[Archetype],
+        out: &mut [u8],
+    ) -> Result<usize, BufferTooSmall> {
+        let matches = |archetype: &Archetype| {
+            !archetype.is_empty()
+                && Q::Fetch::access(archetype).is_some()
+                && self
+                    .fields
+                    .iter()
+                    .all(|field| archetype.has_dynamic(field.ty))
+        };
+        let count = archetypes
+            .iter()
+            .filter(|archetype| matches(archetype))
+            .map(|archetype| archetype.len() as usize)
+            .sum::<usize>();
+        let required = count * self.stride;
+        if out.len() < required {
+            return Err(BufferTooSmall { required });
+        }
+
+        let mut columns = Vec::with_capacity(self.fields.len());
+        let mut element = out.as_mut_ptr();
+        for archetype in archetypes.iter().filter(|archetype| matches(archetype)) {
+            columns.clear();
+            for field in &self.fields {
+                let state = archetype.get_state_dynamic(field.ty).unwrap();
+                let base = unsafe {
+                    archetype.borrow_raw(state);
+                    archetype.get_dynamic(field.ty, field.size, 0).unwrap()
+                };
+                columns.push((state, base.as_ptr() as *const u8, field));
+            }
+            // Bounds were checked up front, and every column holds `archetype.len()` components
+            unsafe {
+                for index in 0..archetype.len() as usize {
+                    for &(_, base, field) in &columns {
+                        ptr::copy_nonoverlapping(
+                            base.add(index * field.size),
+                            element.add(field.offset),
+                            field.size,
+                        );
+                    }
+                    element = element.add(self.stride);
+                }
+                for &(state, _, _) in &columns {
+                    archetype.release_raw(state);
+                }
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Error indicating that a buffer couldn't hold every element to be written to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferTooSmall {
+    /// Size in bytes the buffer must have
+    pub required: usize,
+}
+
+impl fmt::Display for BufferTooSmall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "buffer too small; {} bytes required", self.required)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BufferTooSmall {}
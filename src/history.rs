@@ -0,0 +1,134 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Archetype, Entity, TypeInfo};
+
+/// A change in the archetype of an entity, recorded when the `entity-history` feature is enabled
+///
+/// Obtained from [`Frame::debug_entity_history`](crate::Frame::debug_entity_history).
+#[derive(Debug, Clone)]
+pub struct ArchetypeTransition {
+    /// Archetype the entity left, or `None` if it was spawned
+    pub from: Option<u32>,
+    /// Archetype the entity entered, or `None` if it was despawned
+    pub to: Option<u32>,
+    /// Component types the entity gained
+    pub added: Vec<TypeInfo>,
+    /// Component types the entity lost
+    pub removed: Vec<TypeInfo>,
+}
+
+impl fmt::Display for ArchetypeTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.from, self.to) {
+            (None, _) => f.write_str("spawned")?,
+            (_, None) => f.write_str("despawned")?,
+            (Some(from), Some(to)) => write!(f, "moved from archetype {} to {}", from, to)?,
+        }
+        for (sign, types) in [('+', &self.added), ('-', &self.removed)] {
+            for ty in types.iter() {
+                match ty.type_name() {
+                    Some(name) => write!(f, " {}{}", sign, name)?,
+                    None => write!(f, " {}{:?}", sign, ty.id())?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Recent archetype transitions of each entity
+pub(crate) struct History {
+    /// Maximum number of transitions retained per entity
+    len: usize,
+    /// Generation of the entity each log describes and the log itself, indexed by entity ID
+    logs: Vec<(u32, Log)>,
+}
+
+/// Source and destination archetypes of an entity's transitions, oldest first
+type Log = VecDeque<(Option<u32>, Option<u32>)>;
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            len: 16,
+            logs: Vec::new(),
+        }
+    }
+}
+
+impl History {
+    pub fn is_enabled(&self) -> bool {
+        self.len != 0
+    }
+
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len;
+        for (_, log) in &mut self.logs {
+            while log.len() > len {
+                log.pop_front();
+            }
+        }
+    }
+
+    pub fn record(&mut self, entity: Entity, from: Option<u32>, to: Option<u32>) {
+        if self.len == 0 {
+            return;
+        }
+        let id = entity.id() as usize;
+        if self.logs.len() <= id {
+            self.logs.resize_with(id + 1, Default::default);
+        }
+        let (generation, log) = &mut self.logs[id];
+        if *generation != entity.generation.get() {
+            *generation = entity.generation.get();
+            log.clear();
+        }
+        if log.len() == self.len {
+            log.pop_front();
+        }
+        log.push_back((from, to));
+    }
+
+    pub fn get(&self, entity: Entity, archetypes: &[Archetype]) -> Vec<ArchetypeTransition> {
+        let Some((generation, log)) = self.logs.get(entity.id() as usize) else {
+            return Vec::new();
+        };
+        if *generation != entity.generation.get() {
+            return Vec::new();
+        }
+        let types = |id: Option<u32>| id.map_or(&[][..], |id| archetypes[id as usize].types());
+        log.iter()
+            .map(|&(from, to)| {
+                let (old, new) = (types(from), types(to));
+                ArchetypeTransition {
+                    from,
+                    to,
+                    added: new.iter().filter(|x| !old.contains(x)).copied().collect(),
+                    removed: old.iter().filter(|x| !new.contains(x)).copied().collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Account for `old` being renumbered to `new`
+    pub fn remap(&mut self, old: Entity, new: Entity) {
+        let Some((generation, log)) = self.logs.get_mut(old.id() as usize) else {
+            return;
+        };
+        if *generation != old.generation.get() {
+            return;
+        }
+        let log = core::mem::take(log);
+        if self.logs.len() <= new.id() as usize {
+            self.logs
+                .resize_with(new.id() as usize + 1, Default::default);
+        }
+        self.logs[new.id() as usize] = (new.generation.get(), log);
+    }
+
+    pub fn clear(&mut self) {
+        self.logs.clear();
+    }
+}
@@ -0,0 +1,68 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{Entity, TypeInfo};
+
+/// A structural change to a [`Frame`](crate::Frame), reported to hooks registered with
+/// [`Frame::add_hook`](crate::Frame::add_hook)
+///
+/// Events describe wholesale changes so that external mirrors of a frame (physics worlds, render
+/// caches) can invalidate in bulk rather than tracking individual entities.
+#[derive(Debug, Copy, Clone)]
+#[non_exhaustive]
+pub enum FrameEvent<'a> {
+    /// Every entity was despawned by [`Frame::clear`](crate::Frame::clear)
+    Cleared,
+    /// The listed entities were despawned by [`Frame::despawn_batch`](crate::Frame::despawn_batch)
+    DespawnedBatch(&'a [Entity]),
+    /// The listed entities were spawned as copies of another frame's by
+    /// [`ComponentRegistry::merge_frame`](crate::ComponentRegistry::merge_frame)
+    Merged(&'a [Entity]),
+    /// An archetype storing entities with exactly the listed component types was created
+    ArchetypeCreated(&'a [TypeInfo]),
+    /// Storage for a column of components was reallocated to at least the threshold set by
+    /// [`Frame::set_growth_threshold`](crate::Frame::set_growth_threshold)
+    ColumnGrown {
+        /// Type of the column's components
+        ty: TypeInfo,
+        /// Size of the column's new allocation, in bytes
+        bytes: usize,
+    },
+}
+
+/// Handle to a hook registered with [`Frame::add_hook`](crate::Frame::add_hook)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct HookId(u32);
+
+type Hook = Box<dyn FnMut(&FrameEvent<'_>) + Send + Sync>;
+
+/// Callbacks invoked on structural changes to a frame
+#[derive(Default)]
+pub(crate) struct Hooks {
+    next_id: u32,
+    hooks: Vec<(HookId, Hook)>,
+}
+
+impl Hooks {
+    pub fn add(&mut self, hook: Hook) -> HookId {
+        let id = HookId(self.next_id);
+        self.next_id = self.next_id.checked_add(1).unwrap();
+        self.hooks.push((id, hook));
+        id
+    }
+
+    pub fn remove(&mut self, id: HookId) -> bool {
+        let len = self.hooks.len();
+        self.hooks.retain(|&(x, _)| x != id);
+        self.hooks.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    pub fn emit(&mut self, event: &FrameEvent<'_>) {
+        for (_, hook) in &mut self.hooks {
+            hook(event);
+        }
+    }
+}
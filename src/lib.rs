@@ -68,53 +68,116 @@ macro_rules! smaller_tuples_too {
     };
 }
 
+mod allocator;
 mod archetype;
 mod batch;
 mod borrow;
+mod budget;
 mod bundle;
+mod cached_matches;
 mod change_tracker;
 mod command_buffer;
+mod deadline;
+mod dynamic_column;
+mod dynamic_query;
 mod entities;
 mod entity_builder;
 mod entity_ref;
+mod ephemeral;
+#[cfg(feature = "event-log")]
+mod event_log;
+mod extension;
 mod frame;
+mod frame_builder;
+mod gather;
+#[cfg(feature = "entity-history")]
+mod history;
+mod hooks;
+mod map_entities;
+mod observer;
+mod previous;
 mod query;
 mod query_one;
+mod registry;
+mod relation;
 #[cfg(any(feature = "row-serialize", feature = "column-serialize"))]
 pub mod serialize;
+mod split;
+mod system;
 mod take;
+mod transaction;
+mod visit;
+mod weak_entity;
 
-pub use archetype::{Archetype, ArchetypeColumn, ArchetypeColumnMut, TypeIdMap, TypeInfo};
-pub use batch::{BatchIncomplete, BatchWriter, ColumnBatch, ColumnBatchBuilder, ColumnBatchType};
+pub use allocator::SharedAllocator;
+pub use archetype::{
+    Archetype, ArchetypeColumn, ArchetypeColumnMut, ArchetypePin, TypeIdMap, TypeInfo,
+};
+pub use batch::{
+    BatchFull, BatchIncomplete, BatchWriter, ColumnBatch, ColumnBatchBuilder, ColumnBatchType,
+};
+pub use budget::{Budget, BudgetAction, BudgetExceeded};
 pub use bundle::{
-    bundle_satisfies_query, dynamic_bundle_satisfies_query, Bundle, DynamicBundle,
-    DynamicBundleClone, MissingComponent,
+    bundle_query_mismatch, bundle_satisfies_query, dynamic_bundle_satisfies_query, Bundle,
+    DynamicBundle, DynamicBundleClone, MissingComponent,
 };
+pub use cached_matches::CachedMatches;
 pub use change_tracker::{ChangeTracker, Changes};
-pub use command_buffer::CommandBuffer;
-pub use entities::{Entity, NoSuchEntity};
-pub use entity_builder::{BuiltEntity, BuiltEntityClone, EntityBuilder, EntityBuilderClone};
-pub use entity_ref::{ComponentRef, ComponentRefShared, EntityRef, Ref, RefMut};
+pub use command_buffer::{
+    CommandBuffer, CommandFailure, CommandKind, DeferredOps, RecordedCommand,
+};
+pub use deadline::{set_borrow_deadline, set_long_borrow_handler, LongBorrow};
+pub use dynamic_column::{ComponentColumn, ComponentColumnMut};
+pub use dynamic_query::{DynamicQuery, DynamicRow};
+pub use entities::{Entity, NoSuchEntity, ParseEntityTokenError};
+pub use entity_builder::{
+    BuiltEntity, BuiltEntityClone, EntityBuilder, EntityBuilderClone, EntityBuilderPool,
+    MappedEntityClone,
+};
+pub use entity_ref::{ClonedComponents, ComponentRef, ComponentRefShared, EntityRef, Ref, RefMut};
+pub use ephemeral::EphemeralFrame;
+#[cfg(feature = "event-log")]
+pub use event_log::{LoggedOp, OpKind};
+pub use extension::{set_type_extension, TypeExtension};
 pub use frame::{
-    ArchetypesGeneration, Component, ComponentError, Frame, Iter, QueryOneError, SpawnBatchIter,
-    SpawnColumnBatchIter,
+    ArchetypesGeneration, BatchedEntityIter, ColumnsMut, Component, ComponentError, EntityBatch,
+    Frame, Iter, QueryOneError, ReplaceColumnError, ResolveTokenError, SpawnBatchIter,
+    SpawnColumnBatchIter, TransferError, WriteMismatch,
 };
+pub use frame_builder::FrameBuilder;
+pub use gather::{BufferTooSmall, InterleavedLayout};
+#[cfg(feature = "entity-history")]
+pub use history::ArchetypeTransition;
+pub use hooks::{FrameEvent, HookId};
+pub use map_entities::{EntityMap, MapEntities};
+pub use observer::{MatchEvent, ObserverId};
+pub use previous::PreviousView;
 pub use query::{
-    Access, Batch, BatchedIter, Or, PreparedQuery, PreparedQueryBorrow, PreparedQueryIter,
-    PreparedView, Query, QueryBorrow, QueryIter, QueryMut, QueryShared, Satisfies, View,
-    ViewBorrow, With, Without,
+    Access, Added, Batch, BatchedIter, Changed, Or, OwnedItem, PreparedQuery, PreparedQueryBorrow,
+    PreparedQueryIter, PreparedView, Query, QueryBorrow, QueryIter, QueryMismatch, QueryMut,
+    QueryShared, Satisfies, StridedIter, View, ViewBorrow, With, Without,
+};
+pub use query_one::{QueryOne, RefMany};
+pub use registry::{
+    ComponentKey, ComponentMask, ComponentRegistry, DefaultedComponent, InvalidComponent,
+    RegisteredComponent, Registration, Schema, UnregisteredComponent,
 };
-pub use query_one::QueryOne;
+pub use relation::{Related, Relation};
+pub use split::{ReadPartition, WritePartition};
+pub use system::{PreparedSystems, QuerySet};
 pub use take::TakenEntity;
+pub use transaction::{ReadTransaction, WriteTransaction};
+pub use visit::{ComponentVisitor, DynamicComponent, FrameVisitor};
+pub use weak_entity::WeakEntity;
 
 // Unstable implementation details needed by the macros
 #[doc(hidden)]
 pub use bundle::DynamicClone;
 #[doc(hidden)]
-pub use query::Fetch;
+pub use query::{Explanation, Fetch};
 
 #[cfg(feature = "macros")]
-pub use moss_hecs_macros::{Bundle, DynamicBundleClone, Query};
+pub use moss_hecs_macros::{Bundle, DynamicBundleClone, MapEntities, Query};
 
 fn align(x: usize, alignment: usize) -> usize {
     debug_assert!(alignment.is_power_of_two());
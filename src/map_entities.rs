@@ -0,0 +1,120 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
+
+use crate::Entity;
+
+/// Table of replacement handles for entities copied from one [`Frame`](crate::Frame) to another
+///
+/// Returned by [`ComponentRegistry::merge_frame`](crate::ComponentRegistry::merge_frame), which
+/// applies it to the components of the copied entities through [`MapEntities`].
+#[derive(Debug, Default, Clone)]
+pub struct EntityMap {
+    map: HashMap<Entity, Entity>,
+}
+
+impl EntityMap {
+    /// Create an empty map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `old` is now `new`, returning the previous replacement for `old`, if any
+    pub fn insert(&mut self, old: Entity, new: Entity) -> Option<Entity> {
+        self.map.insert(old, new)
+    }
+
+    /// The replacement for `old`, if any
+    pub fn get(&self, old: Entity) -> Option<Entity> {
+        self.map.get(&old).copied()
+    }
+
+    /// The replacement for `entity`, or `entity` itself if it has none
+    ///
+    /// Handles to entities that weren't copied, such as those already in the destination frame,
+    /// are left unchanged.
+    pub fn map(&self, entity: Entity) -> Entity {
+        self.get(entity).unwrap_or(entity)
+    }
+
+    /// Number of replaced entities
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Whether no entities are replaced
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Iterate over `(old, new)` pairs in arbitrary order
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (Entity, Entity)> + '_ {
+        self.map.iter().map(|(&old, &new)| (old, new))
+    }
+}
+
+/// Types containing [`Entity`] handles that must be rewritten when entities are copied between
+/// frames
+///
+/// Registered with [`Registration::map_entities`](crate::Registration::map_entities) so that
+/// [`ComponentRegistry::merge_frame`](crate::ComponentRegistry::merge_frame) keeps references
+/// between copied entities, e.g. from a prefab or a loaded save, pointing at the copies. Can be
+/// derived with the `macros` feature, in which case fields annotated with `#[map_entities]` are
+/// mapped and all others are left alone.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// struct Target(Entity);
+///
+/// impl MapEntities for Target {
+///     fn map_entities(&mut self, map: &EntityMap) {
+///         self.0.map_entities(map);
+///     }
+/// }
+/// ```
+pub trait MapEntities {
+    /// Replace every [`Entity`] in `self` according to `map`
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+impl MapEntities for Entity {
+    fn map_entities(&mut self, map: &EntityMap) {
+        *self = map.map(*self);
+    }
+}
+
+impl<T: MapEntities> MapEntities for Option<T> {
+    fn map_entities(&mut self, map: &EntityMap) {
+        if let Some(x) = self {
+            x.map_entities(map);
+        }
+    }
+}
+
+impl<T: MapEntities + ?Sized> MapEntities for Box<T> {
+    fn map_entities(&mut self, map: &EntityMap) {
+        (**self).map_entities(map);
+    }
+}
+
+impl<T: MapEntities> MapEntities for [T] {
+    fn map_entities(&mut self, map: &EntityMap) {
+        for x in self {
+            x.map_entities(map);
+        }
+    }
+}
+
+impl<T: MapEntities, const N: usize> MapEntities for [T; N] {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self[..].map_entities(map);
+    }
+}
+
+impl<T: MapEntities> MapEntities for Vec<T> {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self[..].map_entities(map);
+    }
+}
@@ -0,0 +1,100 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{Archetype, Entity, Fetch, Query};
+
+/// Change in whether an entity matches an observed query, reported to observers registered with
+/// [`Frame::observe`](crate::Frame::observe)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum MatchEvent {
+    /// The entity began to match, having been spawned or gained or lost components
+    Entered,
+    /// The entity ceased to match, having been despawned or gained or lost components
+    Exited,
+}
+
+/// Handle to an observer registered with [`Frame::observe`](crate::Frame::observe)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ObserverId(u32);
+
+type Callback = Box<dyn FnMut(Entity, MatchEvent) + Send + Sync>;
+
+struct Observer {
+    id: ObserverId,
+    matches: fn(&Archetype) -> bool,
+    /// Whether each archetype matches, indexed by archetype ID
+    cache: Vec<bool>,
+    callback: Callback,
+}
+
+/// Callbacks invoked when entities begin or cease to match queries
+#[derive(Default)]
+pub(crate) struct Observers {
+    next_id: u32,
+    observers: Vec<Observer>,
+}
+
+impl Observers {
+    pub fn add<Q: Query>(&mut self, archetypes: &[Archetype], callback: Callback) -> ObserverId {
+        let id = ObserverId(self.next_id);
+        self.next_id = self.next_id.checked_add(1).unwrap();
+        let mut observer = Observer {
+            id,
+            matches: |archetype| Q::Fetch::access(archetype).is_some(),
+            cache: Vec::new(),
+            callback,
+        };
+        observer.update(archetypes);
+        self.observers.push(observer);
+        id
+    }
+
+    pub fn remove(&mut self, id: ObserverId) -> bool {
+        let len = self.observers.len();
+        self.observers.retain(|x| x.id != id);
+        self.observers.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    /// Determine whether archetypes created since the last call match each query
+    pub fn update(&mut self, archetypes: &[Archetype]) {
+        for observer in &mut self.observers {
+            observer.update(archetypes);
+        }
+    }
+
+    /// Whether any observer is interested in entities of archetype `id`
+    ///
+    /// Archetypes created since the last `update` are assumed not to be.
+    pub fn any_match(&self, id: u32) -> bool {
+        self.observers
+            .iter()
+            .any(|x| x.cache.get(id as usize).copied().unwrap_or(false))
+    }
+
+    /// Notify observers of `entity` moving from archetype `from` to archetype `to`, with `None`
+    /// standing for nonexistence
+    ///
+    /// Archetypes must have been accounted for by `update`.
+    pub fn transition(&mut self, entity: Entity, from: Option<u32>, to: Option<u32>) {
+        for observer in &mut self.observers {
+            let was = from.map_or(false, |id| observer.cache[id as usize]);
+            let is = to.map_or(false, |id| observer.cache[id as usize]);
+            match (was, is) {
+                (false, true) => (observer.callback)(entity, MatchEvent::Entered),
+                (true, false) => (observer.callback)(entity, MatchEvent::Exited),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Observer {
+    fn update(&mut self, archetypes: &[Archetype]) {
+        let known = self.cache.len();
+        self.cache
+            .extend(archetypes[known..].iter().map(|x| (self.matches)(x)));
+    }
+}
@@ -0,0 +1,102 @@
+use alloc::vec::Vec;
+use core::any::Any;
+
+use crate::{Component, Entity, Frame, QueryBorrow};
+
+/// Snapshot of a component type's values, maintained by [`Frame::save_previous`]
+pub(crate) trait Shadow: Send + Sync {
+    fn save(&mut self, frame: &Frame);
+    /// Account for `old` being renumbered to `new`
+    fn remap(&mut self, old: Entity, new: Entity);
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Copies of every `T` component as of the last snapshot
+pub(crate) struct ShadowColumn<T> {
+    values: Vec<T>,
+    /// Index in `values` of each entity's `T`, indexed by entity ID
+    slots: Vec<Option<(Entity, u32)>>,
+}
+
+impl<T> ShadowColumn<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            slots: Vec::new(),
+        }
+    }
+
+    pub(crate) fn get(&self, entity: Entity) -> Option<&T> {
+        let (slot_entity, index) = (*self.slots.get(entity.id() as usize)?)?;
+        if slot_entity != entity {
+            return None;
+        }
+        Some(&self.values[index as usize])
+    }
+}
+
+impl<T: Component + Clone> Shadow for ShadowColumn<T> {
+    fn save(&mut self, frame: &Frame) {
+        self.values.clear();
+        for slot in &mut self.slots {
+            *slot = None;
+        }
+        for archetype in frame.archetypes() {
+            let Some(column) = archetype.get::<&T>() else {
+                continue;
+            };
+            for (&id, value) in archetype.ids().iter().zip(column.iter()) {
+                if self.slots.len() <= id as usize {
+                    self.slots.resize(id as usize + 1, None);
+                }
+                // Every ID in an archetype belongs to a live entity
+                let entity = unsafe { frame.find_entity_from_id(id) };
+                self.slots[id as usize] = Some((entity, self.values.len() as u32));
+                self.values.push(value.clone());
+            }
+        }
+    }
+
+    fn remap(&mut self, old: Entity, new: Entity) {
+        let Some(&Some((entity, index))) = self.slots.get(old.id() as usize) else {
+            return;
+        };
+        if entity != old {
+            return;
+        }
+        self.slots[old.id() as usize] = None;
+        if self.slots.len() <= new.id() as usize {
+            self.slots.resize(new.id() as usize + 1, None);
+        }
+        self.slots[new.id() as usize] = Some((new, index));
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Borrow of every `T` component in a [`Frame`] alongside its value as of the last
+/// [`Frame::save_previous`]
+///
+/// Obtained from [`Frame::view_with_previous`].
+pub struct PreviousView<'a, T: Component> {
+    query: QueryBorrow<'a, &'static T>,
+    shadow: &'a ShadowColumn<T>,
+}
+
+impl<'a, T: Component> PreviousView<'a, T> {
+    pub(crate) fn new(query: QueryBorrow<'a, &'static T>, shadow: &'a ShadowColumn<T>) -> Self {
+        Self { query, shadow }
+    }
+
+    /// Iterate over entities with a `T` component, yielding its current and previous values
+    ///
+    /// The previous value is `None` for entities which didn't have a `T` when it was last saved.
+    pub fn iter(&mut self) -> impl Iterator<Item = (Entity, &T, Option<&T>)> + '_ {
+        let shadow = self.shadow;
+        self.query
+            .iter()
+            .map(move |(entity, current)| (entity, current, shadow.get(entity)))
+    }
+}
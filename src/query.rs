@@ -5,13 +5,14 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use core::any::TypeId;
+use core::any::{type_name, TypeId};
+use core::fmt;
 use core::marker::PhantomData;
 use core::ptr::NonNull;
 use core::slice::Iter as SliceIter;
 
 use crate::alloc::{boxed::Box, vec::Vec};
-use crate::archetype::Archetype;
+use crate::archetype::{Archetype, ComponentTicks};
 use crate::entities::EntityMeta;
 use crate::{Component, Entity, Frame};
 
@@ -36,6 +37,22 @@ pub trait Query {
     /// - Bounds-checking must be performed externally
     /// - Any resulting borrows must be legal (e.g. no &mut to something another iterator might access)
     unsafe fn get<'a>(fetch: &Self::Fetch, n: usize) -> Self::Item<'a>;
+
+    #[doc(hidden)]
+    /// Whether [`filter`](Self::filter) may reject some entities of a matching archetype
+    const FILTERED: bool = false;
+
+    #[doc(hidden)]
+    /// Whether the `n`th item in this archetype should be yielded, for queries like [`Changed`]
+    /// which select individual entities rather than whole archetypes
+    ///
+    /// # Safety
+    /// Same as [`get`](Self::get)
+    #[inline(always)]
+    unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+        let _ = (fetch, n);
+        true
+    }
 }
 
 /// Marker trait indicating whether a given [`Query`] will not produce unique references
@@ -66,6 +83,153 @@ pub unsafe trait Fetch: Clone + Sized {
 
     /// Invoke `f` for every component type that may be borrowed and whether the borrow is unique
     fn for_each_borrow(f: impl FnMut(TypeId, bool));
+
+    /// A component type present in every archetype that [`prepare`](Self::prepare) accepts, if any
+    ///
+    /// Lets iteration visit only the archetypes holding that type instead of examining each one.
+    fn required() -> Option<TypeId> {
+        None
+    }
+
+    /// Record in `out` the component types which decide whether `archetype` satisfies this query
+    fn explain(archetype: &Archetype, out: &mut Explanation) {
+        out.condition(type_name::<Self>(), Self::access(archetype).is_some());
+    }
+}
+
+/// Accumulates the reasons an archetype fails a query, for [`QueryMismatch`]
+#[doc(hidden)]
+#[derive(Default)]
+pub struct Explanation {
+    missing: Vec<&'static str>,
+    excluded: Vec<&'static str>,
+    /// Whether the query being explained is a `Without` filter
+    inverted: bool,
+}
+
+impl Explanation {
+    /// Note whether a component type required by the current query is present
+    pub fn component<T: Component>(&mut self, present: bool) {
+        self.condition(type_name::<T>(), present);
+    }
+
+    /// Note whether a condition named `name` required by the current query is met
+    pub fn condition(&mut self, name: &'static str, satisfied: bool) {
+        match (satisfied, self.inverted) {
+            (false, false) => self.missing.push(name),
+            (true, true) => self.excluded.push(name),
+            _ => {}
+        }
+    }
+
+    /// Explain `F`, whose success excludes an archetype from the current query
+    fn excluding<F: Fetch>(&mut self, archetype: &Archetype) {
+        self.inverted = !self.inverted;
+        F::explain(archetype, self);
+        self.inverted = !self.inverted;
+    }
+}
+
+/// Why an entity doesn't satisfy a query, as reported by [`Frame::explain`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryMismatch {
+    /// The entity was despawned, or never existed
+    NoSuchEntity,
+    /// The entity's components don't match the query
+    Components {
+        /// Names of required component types the entity lacks
+        missing: Vec<&'static str>,
+        /// Names of component types the entity has which a [`Without`] filter rules out
+        excluded: Vec<&'static str>,
+    },
+}
+
+impl QueryMismatch {
+    pub(crate) fn explain<Q: Query>(archetype: &Archetype) -> Option<Self> {
+        if Q::Fetch::access(archetype).is_some() {
+            return None;
+        }
+        let mut out = Explanation::default();
+        Q::Fetch::explain(archetype, &mut out);
+        Some(Self::Components {
+            missing: out.missing,
+            excluded: out.excluded,
+        })
+    }
+}
+
+impl fmt::Display for QueryMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoSuchEntity => f.write_str("no such entity"),
+            Self::Components { missing, excluded } => {
+                f.write_str("query not satisfied")?;
+                if !missing.is_empty() {
+                    write!(f, "; missing {}", missing.join(", "))?;
+                }
+                if !excluded.is_empty() {
+                    write!(f, "; excluded by {}", excluded.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for QueryMismatch {}
+
+/// A query item that can be cloned into a value independent of the [`Frame`]
+///
+/// Implemented for references to [`Clone`] components, and for `Option`s, [`Or`]s and tuples of
+/// such items. Used by [`QueryBorrow::collect_into`].
+pub trait OwnedItem {
+    /// Owned form of the item, e.g. `T` for `&T`
+    type Owned;
+
+    /// Clone the item's data
+    fn to_owned_item(self) -> Self::Owned;
+}
+
+impl<T: Clone> OwnedItem for &T {
+    type Owned = T;
+
+    fn to_owned_item(self) -> T {
+        self.clone()
+    }
+}
+
+impl<T: Clone> OwnedItem for &mut T {
+    type Owned = T;
+
+    fn to_owned_item(self) -> T {
+        self.clone()
+    }
+}
+
+impl<T: OwnedItem> OwnedItem for Option<T> {
+    type Owned = Option<T::Owned>;
+
+    fn to_owned_item(self) -> Self::Owned {
+        self.map(T::to_owned_item)
+    }
+}
+
+impl<L: OwnedItem, R: OwnedItem> OwnedItem for Or<L, R> {
+    type Owned = Or<L::Owned, R::Owned>;
+
+    fn to_owned_item(self) -> Self::Owned {
+        self.map(L::to_owned_item, R::to_owned_item)
+    }
+}
+
+/// Result of [`Satisfies`]
+impl OwnedItem for bool {
+    type Owned = bool;
+
+    fn to_owned_item(self) -> bool {
+        self
+    }
 }
 
 /// Type of access a [`Query`] may have to an [`Archetype`]
@@ -79,7 +243,7 @@ pub enum Access {
     Write,
 }
 
-impl<'a, T: Component> Query for &'a T {
+impl<T: Component> Query for &T {
     type Item<'q> = &'q T;
 
     type Fetch = FetchRead<T>;
@@ -89,7 +253,7 @@ impl<'a, T: Component> Query for &'a T {
     }
 }
 
-unsafe impl<'a, T> QueryShared for &'a T {}
+unsafe impl<T> QueryShared for &T {}
 
 #[doc(hidden)]
 pub struct FetchRead<T>(NonNull<T>);
@@ -125,6 +289,14 @@ unsafe impl<T: Component> Fetch for FetchRead<T> {
     fn for_each_borrow(mut f: impl FnMut(TypeId, bool)) {
         f(TypeId::of::<T>(), false);
     }
+
+    fn required() -> Option<TypeId> {
+        Some(TypeId::of::<T>())
+    }
+
+    fn explain(archetype: &Archetype, out: &mut Explanation) {
+        out.component::<T>(archetype.has::<T>());
+    }
 }
 
 impl<T> Clone for FetchRead<T> {
@@ -134,24 +306,36 @@ impl<T> Clone for FetchRead<T> {
     }
 }
 
-impl<'a, T: Component> Query for &'a mut T {
+impl<T: Component> Query for &mut T {
     type Item<'q> = &'q mut T;
 
     type Fetch = FetchWrite<T>;
 
     unsafe fn get<'q>(fetch: &FetchWrite<T>, n: usize) -> &'q mut T {
-        &mut *fetch.0.as_ptr().add(n)
+        if let Some(ticks) = fetch.ticks {
+            (*ticks.as_ptr().add(n)).changed = fetch.tick;
+        }
+        &mut *fetch.base.as_ptr().add(n)
     }
 }
 
 #[doc(hidden)]
-pub struct FetchWrite<T>(NonNull<T>);
+pub struct FetchWrite<T> {
+    base: NonNull<T>,
+    /// `None` unless changes to `T` are tracked
+    ticks: Option<NonNull<ComponentTicks>>,
+    tick: u32,
+}
 
 unsafe impl<T: Component> Fetch for FetchWrite<T> {
     type State = usize;
 
     fn dangling() -> Self {
-        Self(NonNull::dangling())
+        Self {
+            base: NonNull::dangling(),
+            ticks: None,
+            tick: 0,
+        }
     }
 
     fn access(archetype: &Archetype) -> Option<Access> {
@@ -170,7 +354,15 @@ unsafe impl<T: Component> Fetch for FetchWrite<T> {
         Some(archetype.get_state::<T>()?)
     }
     fn execute(archetype: &Archetype, state: Self::State) -> Self {
-        Self(archetype.get_base::<T>(state))
+        // Executed only once the column is borrowed uniquely, or the frame is
+        unsafe {
+            archetype.mark_written(state);
+        }
+        Self {
+            base: archetype.get_base::<T>(state),
+            ticks: archetype.get_ticks(state),
+            tick: archetype.tick(),
+        }
     }
     fn release(archetype: &Archetype, state: Self::State) {
         archetype.release_mut::<T>(state);
@@ -179,12 +371,20 @@ unsafe impl<T: Component> Fetch for FetchWrite<T> {
     fn for_each_borrow(mut f: impl FnMut(TypeId, bool)) {
         f(TypeId::of::<T>(), true);
     }
+
+    fn required() -> Option<TypeId> {
+        Some(TypeId::of::<T>())
+    }
+
+    fn explain(archetype: &Archetype, out: &mut Explanation) {
+        out.component::<T>(archetype.has::<T>());
+    }
 }
 
 impl<T> Clone for FetchWrite<T> {
     #[inline]
     fn clone(&self) -> Self {
-        Self(self.0)
+        Self { ..*self }
     }
 }
 
@@ -194,7 +394,12 @@ impl<T: Query> Query for Option<T> {
     type Fetch = TryFetch<T::Fetch>;
 
     unsafe fn get<'q>(fetch: &TryFetch<T::Fetch>, n: usize) -> Option<T::Item<'q>> {
-        Some(T::get(fetch.0.as_ref()?, n))
+        let fetch = fetch.0.as_ref()?;
+        // Entities `T` doesn't select yield `None` rather than being skipped
+        if T::FILTERED && !T::filter(fetch, n) {
+            return None;
+        }
+        Some(T::get(fetch, n))
     }
 }
 
@@ -235,6 +440,8 @@ unsafe impl<T: Fetch> Fetch for TryFetch<T> {
     fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
         T::for_each_borrow(f);
     }
+
+    fn explain(_archetype: &Archetype, _out: &mut Explanation) {}
 }
 
 /// Holds an `L`, or an `R`, or both
@@ -286,6 +493,60 @@ impl<L, R> Or<L, R> {
         }
     }
 
+    /// Extract `L` and `R` if both are present
+    pub fn both(self) -> Option<(L, R)> {
+        match self {
+            Or::Both(l, r) => Some((l, r)),
+            _ => None,
+        }
+    }
+
+    /// Extract `L` if present, otherwise convert `R` with `f`
+    ///
+    /// Convenient for preferring one component while falling back to another.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// struct Speed(f32);
+    /// struct DefaultSpeed(f32);
+    ///
+    /// let mut frame = Frame::new();
+    /// frame.spawn((Speed(2.0), DefaultSpeed(1.0)));
+    /// frame.spawn((DefaultSpeed(1.0),));
+    /// let mut speeds = frame
+    ///     .query_mut::<Or<&Speed, &DefaultSpeed>>()
+    ///     .into_iter()
+    ///     .map(|(_, x)| x.map_left(|s| s.0).left_or_else(|d| d.0))
+    ///     .collect::<Vec<f32>>();
+    /// speeds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    /// assert_eq!(speeds, [1.0, 2.0]);
+    /// ```
+    pub fn left_or_else(self, f: impl FnOnce(R) -> L) -> L {
+        match self {
+            Or::Left(l) | Or::Both(l, _) => l,
+            Or::Right(r) => f(r),
+        }
+    }
+
+    /// Extract `R` if present, otherwise convert `L` with `f`
+    pub fn right_or_else(self, f: impl FnOnce(L) -> R) -> R {
+        match self {
+            Or::Right(r) | Or::Both(_, r) => r,
+            Or::Left(l) => f(l),
+        }
+    }
+
+    /// Transform `L` with `f`, leaving `R` unchanged
+    pub fn map_left<L1>(self, f: impl FnOnce(L) -> L1) -> Or<L1, R> {
+        self.map(f, |r| r)
+    }
+
+    /// Transform `R` with `f`, leaving `L` unchanged
+    pub fn map_right<R1>(self, f: impl FnOnce(R) -> R1) -> Or<L, R1> {
+        self.map(|l| l, f)
+    }
+
     /// Transform `L` with `f` and `R` with `g`
     pub fn map<L1, R1, F, G>(self, f: F, g: G) -> Or<L1, R1>
     where
@@ -335,7 +596,25 @@ impl<L: Query, R: Query> Query for Or<L, R> {
     type Fetch = FetchOr<L::Fetch, R::Fetch>;
 
     unsafe fn get<'q>(fetch: &Self::Fetch, n: usize) -> Self::Item<'q> {
-        fetch.0.as_ref().map(|l| L::get(l, n), |r| R::get(r, n))
+        match fetch.0 {
+            // Report only the side whose filter selected the entity
+            Or::Both(ref l, ref r) if Self::FILTERED => match (L::filter(l, n), R::filter(r, n)) {
+                (true, false) => Or::Left(L::get(l, n)),
+                (false, true) => Or::Right(R::get(r, n)),
+                _ => Or::Both(L::get(l, n), R::get(r, n)),
+            },
+            ref fetch => fetch.as_ref().map(|l| L::get(l, n), |r| R::get(r, n)),
+        }
+    }
+
+    const FILTERED: bool = L::FILTERED || R::FILTERED;
+
+    unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+        match fetch.0 {
+            Or::Left(ref l) => L::filter(l, n),
+            Or::Right(ref r) => R::filter(r, n),
+            Or::Both(ref l, ref r) => L::filter(l, n) || R::filter(r, n),
+        }
     }
 }
 
@@ -376,6 +655,22 @@ unsafe impl<L: Fetch, R: Fetch> Fetch for FetchOr<L, R> {
         L::for_each_borrow(&mut f);
         R::for_each_borrow(&mut f);
     }
+
+    fn explain(archetype: &Archetype, out: &mut Explanation) {
+        // Report every alternative when neither is satisfied, or, when excluding, only those
+        // that are
+        if out.inverted {
+            if L::access(archetype).is_some() {
+                L::explain(archetype, out);
+            }
+            if R::access(archetype).is_some() {
+                R::explain(archetype, out);
+            }
+        } else if Self::access(archetype).is_none() {
+            L::explain(archetype, out);
+            R::explain(archetype, out);
+        }
+    }
 }
 
 /// Transforms query `Q` by skipping entities satisfying query `R`
@@ -405,6 +700,12 @@ impl<Q: Query, R: Query> Query for Without<Q, R> {
     unsafe fn get<'q>(fetch: &Self::Fetch, n: usize) -> Self::Item<'q> {
         Q::get(&fetch.0, n)
     }
+
+    const FILTERED: bool = Q::FILTERED;
+
+    unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+        Q::filter(&fetch.0, n)
+    }
 }
 
 unsafe impl<Q: QueryShared, R> QueryShared for Without<Q, R> {}
@@ -446,6 +747,17 @@ unsafe impl<F: Fetch, G: Fetch> Fetch for FetchWithout<F, G> {
     fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
         F::for_each_borrow(f);
     }
+
+    fn required() -> Option<TypeId> {
+        F::required()
+    }
+
+    fn explain(archetype: &Archetype, out: &mut Explanation) {
+        F::explain(archetype, out);
+        if G::access(archetype).is_some() {
+            out.excluding::<G>(archetype);
+        }
+    }
 }
 
 impl<F: Clone, G> Clone for FetchWithout<F, G> {
@@ -484,6 +796,12 @@ impl<Q: Query, R: Query> Query for With<Q, R> {
     unsafe fn get<'q>(fetch: &Self::Fetch, n: usize) -> Self::Item<'q> {
         Q::get(&fetch.0, n)
     }
+
+    const FILTERED: bool = Q::FILTERED;
+
+    unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+        Q::filter(&fetch.0, n)
+    }
 }
 
 unsafe impl<Q: QueryShared, R> QueryShared for With<Q, R> {}
@@ -523,6 +841,15 @@ unsafe impl<F: Fetch, G: Fetch> Fetch for FetchWith<F, G> {
     fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
         F::for_each_borrow(f);
     }
+
+    fn required() -> Option<TypeId> {
+        F::required().or_else(G::required)
+    }
+
+    fn explain(archetype: &Archetype, out: &mut Explanation) {
+        F::explain(archetype, out);
+        G::explain(archetype, out);
+    }
 }
 
 impl<F: Clone, G> Clone for FetchWith<F, G> {
@@ -590,6 +917,8 @@ unsafe impl<F: Fetch> Fetch for FetchSatisfies<F> {
     fn release(_archetype: &Archetype, _state: Self::State) {}
 
     fn for_each_borrow(_: impl FnMut(TypeId, bool)) {}
+
+    fn explain(_archetype: &Archetype, _out: &mut Explanation) {}
 }
 
 impl<T> Clone for FetchSatisfies<T> {
@@ -599,20 +928,238 @@ impl<T> Clone for FetchSatisfies<T> {
     }
 }
 
+/// Query yielding only the entities whose `T` component changed since the last
+/// [`Frame::clear_trackers`](crate::Frame::clear_trackers)
+///
+/// `Q` is `&T` or `&mut T`, and determines how the component is accessed. Changes to `T` must be
+/// tracked with [`Frame::track_changes`](crate::Frame::track_changes), or executing the query
+/// panics. A component counts as changed when it's added or replaced, or accessed mutably, e.g.
+/// through a `&mut T` query, [`Frame::get`](crate::Frame::get), or a column. Writes through
+/// unchecked accessors such as [`Frame::get_unchecked`](crate::Frame::get_unchecked) aren't
+/// tracked.
+///
+/// Unlike other queries, which select whole archetypes, iterating over a `Changed` query visits
+/// each matching entity to check its ticks, so the `len` of its iterators is linear in the number
+/// of entities left to visit. Wrap it in an `Option` to yield `None` for unchanged entities
+/// rather than skipping them.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// let mut frame = Frame::new();
+/// frame.track_changes::<i32>();
+/// let a = frame.spawn((1,));
+/// let b = frame.spawn((2,));
+/// frame.clear_trackers();
+/// *frame.get::<&mut i32>(b).unwrap() += 1;
+/// let changed = frame.query_mut::<Changed<&i32>>()
+///     .into_iter()
+///     .map(|(e, &x)| (e, x))
+///     .collect::<Vec<_>>();
+/// assert_eq!(changed, [(b, 3)]);
+/// ```
+pub struct Changed<Q>(PhantomData<fn() -> Q>);
+
+impl<T: Component> Query for Changed<&T> {
+    type Item<'q> = &'q T;
+
+    type Fetch = FetchTicks<T, FetchRead<T>, false>;
+
+    unsafe fn get<'q>(fetch: &Self::Fetch, n: usize) -> Self::Item<'q> {
+        <&T>::get(&fetch.inner, n)
+    }
+
+    const FILTERED: bool = true;
+
+    unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+        fetch.selects(n)
+    }
+}
+
+impl<T: Component> Query for Changed<&mut T> {
+    type Item<'q> = &'q mut T;
+
+    type Fetch = FetchTicks<T, FetchWrite<T>, false>;
+
+    unsafe fn get<'q>(fetch: &Self::Fetch, n: usize) -> Self::Item<'q> {
+        <&mut T>::get(&fetch.inner, n)
+    }
+
+    const FILTERED: bool = true;
+
+    unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+        fetch.selects(n)
+    }
+}
+
+unsafe impl<T> QueryShared for Changed<&T> {}
+
+/// Query yielding only the entities whose `T` component was added since the last
+/// [`Frame::clear_trackers`](crate::Frame::clear_trackers)
+///
+/// Includes components of newly spawned entities. Moving an entity between archetypes by adding
+/// or removing other components doesn't count as adding its `T` component; replacing it with
+/// [`Frame::insert`](crate::Frame::insert) counts as a change instead. Otherwise behaves like
+/// [`Changed`].
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// let mut frame = Frame::new();
+/// frame.track_changes::<i32>();
+/// let a = frame.spawn((1,));
+/// frame.clear_trackers();
+/// let b = frame.spawn((2,));
+/// frame.insert_one(a, true).unwrap();
+/// let added = frame.query_mut::<Added<&i32>>()
+///     .into_iter()
+///     .map(|(e, _)| e)
+///     .collect::<Vec<_>>();
+/// assert_eq!(added, [b]);
+/// ```
+pub struct Added<Q>(PhantomData<fn() -> Q>);
+
+impl<T: Component> Query for Added<&T> {
+    type Item<'q> = &'q T;
+
+    type Fetch = FetchTicks<T, FetchRead<T>, true>;
+
+    unsafe fn get<'q>(fetch: &Self::Fetch, n: usize) -> Self::Item<'q> {
+        <&T>::get(&fetch.inner, n)
+    }
+
+    const FILTERED: bool = true;
+
+    unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+        fetch.selects(n)
+    }
+}
+
+impl<T: Component> Query for Added<&mut T> {
+    type Item<'q> = &'q mut T;
+
+    type Fetch = FetchTicks<T, FetchWrite<T>, true>;
+
+    unsafe fn get<'q>(fetch: &Self::Fetch, n: usize) -> Self::Item<'q> {
+        <&mut T>::get(&fetch.inner, n)
+    }
+
+    const FILTERED: bool = true;
+
+    unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+        fetch.selects(n)
+    }
+}
+
+unsafe impl<T> QueryShared for Added<&T> {}
+
+#[doc(hidden)]
+pub struct FetchTicks<T, F, const ADDED: bool> {
+    inner: F,
+    ticks: NonNull<ComponentTicks>,
+    tick: u32,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T, F, const ADDED: bool> FetchTicks<T, F, ADDED> {
+    /// Whether the `n`th component was added, or changed, during the current tick
+    #[inline]
+    unsafe fn selects(&self, n: usize) -> bool {
+        let ticks = *self.ticks.as_ptr().add(n);
+        if ADDED {
+            ticks.added == self.tick
+        } else {
+            ticks.changed == self.tick
+        }
+    }
+}
+
+unsafe impl<T: Component, F: Fetch<State = usize>, const ADDED: bool> Fetch
+    for FetchTicks<T, F, ADDED>
+{
+    type State = usize;
+
+    fn dangling() -> Self {
+        Self {
+            inner: F::dangling(),
+            ticks: NonNull::dangling(),
+            tick: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        F::access(archetype)
+    }
+
+    fn borrow(archetype: &Archetype, state: Self::State) {
+        F::borrow(archetype, state);
+    }
+    fn prepare(archetype: &Archetype) -> Option<Self::State> {
+        F::prepare(archetype)
+    }
+    fn execute(archetype: &Archetype, state: Self::State) -> Self {
+        let ticks = archetype.get_ticks(state).unwrap_or_else(|| {
+            panic!(
+                "changes to {} aren't tracked; see `Frame::track_changes`",
+                type_name::<T>()
+            )
+        });
+        Self {
+            inner: F::execute(archetype, state),
+            ticks,
+            tick: archetype.tick(),
+            _marker: PhantomData,
+        }
+    }
+    fn release(archetype: &Archetype, state: Self::State) {
+        F::release(archetype, state);
+    }
+
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        F::for_each_borrow(f);
+    }
+
+    fn required() -> Option<TypeId> {
+        F::required()
+    }
+
+    fn explain(archetype: &Archetype, out: &mut Explanation) {
+        F::explain(archetype, out);
+    }
+}
+
+impl<T, F: Clone, const ADDED: bool> Clone for FetchTicks<T, F, ADDED> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ticks: self.ticks,
+            tick: self.tick,
+            _marker: PhantomData,
+        }
+    }
+}
+
 /// A borrow of a [`Frame`](crate::Frame) sufficient to execute the query `Q`
 ///
 /// Note that borrows are not released until this object is dropped.
 pub struct QueryBorrow<'w, Q: Query> {
     frame: &'w Frame,
     borrowed: bool,
+    filter: Option<Box<ArchetypeFilter<'w>>>,
     _marker: PhantomData<Q>,
 }
 
+/// Predicate selecting the archetypes a query visits
+type ArchetypeFilter<'a> = dyn Fn(&Archetype) -> bool + Send + Sync + 'a;
+
 impl<'w, Q: Query> QueryBorrow<'w, Q> {
     pub(crate) fn new(frame: &'w Frame) -> Self {
         Self {
-            frame: frame,
+            frame,
             borrowed: false,
+            filter: None,
             _marker: PhantomData,
         }
     }
@@ -621,7 +1168,35 @@ impl<'w, Q: Query> QueryBorrow<'w, Q> {
     // The lifetime narrowing here is required for soundness.
     pub fn iter(&mut self) -> QueryIter<'_, Q> {
         self.borrow();
-        unsafe { QueryIter::new(self.frame) }
+        unsafe { QueryIter::new(self.frame, self.filter.as_deref()) }
+    }
+
+    /// Restrict iteration to entities in archetypes for which `predicate` returns true
+    ///
+    /// Allows selecting archetypes by criteria that can't be expressed as components, e.g.
+    /// archetypes recorded earlier. Applies to [`iter`](Self::iter) and
+    /// [`iter_batched`](Self::iter_batched), but not [`view`](Self::view). Replaces any previous
+    /// predicate.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((123, true));
+    /// let b = frame.spawn((456,));
+    /// let entities = frame.query::<&i32>()
+    ///     .filter_archetypes(|archetype| archetype.len() > 0 && !archetype.has::<bool>())
+    ///     .iter()
+    ///     .map(|(e, &i)| (e, i))
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(entities, [(b, 456)]);
+    /// ```
+    pub fn filter_archetypes(
+        mut self,
+        predicate: impl Fn(&Archetype) -> bool + Send + Sync + 'w,
+    ) -> Self {
+        self.filter = Some(Box::new(predicate));
+        self
     }
 
     /// Provide random access to the query results
@@ -630,6 +1205,53 @@ impl<'w, Q: Query> QueryBorrow<'w, Q> {
         unsafe { View::new(self.frame.entities_meta(), self.frame.archetypes_inner()) }
     }
 
+    /// Replace the contents of `out` with owned copies of the query results
+    ///
+    /// Reuses `out`'s allocation, avoiding a fresh `Vec` every time results need to outlive the
+    /// borrow.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((123, true));
+    /// let mut results = Vec::new();
+    /// frame.query::<(&i32, Option<&bool>)>().collect_into(&mut results);
+    /// assert_eq!(results, [(a, (123, Some(true)))]);
+    /// ```
+    pub fn collect_into<O>(&mut self, out: &mut Vec<(Entity, O)>)
+    where
+        for<'a> Q::Item<'a>: OwnedItem<Owned = O>,
+    {
+        out.clear();
+        out.extend(self.iter().map(|(e, x)| (e, x.to_owned_item())));
+    }
+
+    /// Like `iter`, but distributes the results over rayon's thread pool
+    ///
+    /// Work is split per archetype and, within large archetypes, per batch of entities.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// use rayon::prelude::*;
+    /// let mut frame = Frame::new();
+    /// frame.spawn_batch((0..1000).map(|i| (i, 1u64)));
+    /// frame.query::<(&mut i32, &u64)>().par_iter().for_each(|(_, (x, y))| *x += *y as i32);
+    /// let sum: i32 = frame.query::<&i32>().par_iter().map(|(_, x)| *x).sum();
+    /// assert_eq!(sum, (1..=1000).sum());
+    /// ```
+    #[cfg(feature = "parallel")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "parallel")))]
+    pub fn par_iter(
+        &mut self,
+    ) -> impl rayon::iter::ParallelIterator<Item = (Entity, Q::Item<'_>)> + '_
+    where
+        for<'a> Q::Item<'a>: Send,
+    {
+        par_batches(self.iter_batched(PAR_BATCH_SIZE))
+    }
+
     /// Like `iter`, but returns child iterators of at most `batch_size` elements
     ///
     /// Useful for distributing work over a threadpool.
@@ -641,6 +1263,41 @@ impl<'w, Q: Query> QueryBorrow<'w, Q> {
                 self.frame.entities_meta(),
                 self.frame.archetypes_inner().iter(),
                 batch_size,
+                self.filter.as_deref(),
+            )
+        }
+    }
+
+    /// Like `iter`, but visits only every `stride`th entity of each archetype, starting at `offset`
+    ///
+    /// Entities are selected by their position within their archetype, so passing an `offset`
+    /// that increases by one each frame visits every entity once per `stride` frames, provided
+    /// they don't move between archetypes in the meantime. Useful for amortizing expensive
+    /// per-entity work, such as AI or audio updates, across several frames.
+    ///
+    /// Panics if `stride` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let entities = (0..10).map(|i| frame.spawn((i,))).collect::<Vec<_>>();
+    /// let mut visited = Vec::new();
+    /// for tick in 0..3 {
+    ///     let mut query = frame.query::<&i32>();
+    ///     visited.extend(query.iter_strided(tick, 3).map(|(e, _)| e));
+    /// }
+    /// visited.sort();
+    /// assert_eq!(visited, entities);
+    /// ```
+    // The lifetime narrowing here is required for soundness.
+    pub fn iter_strided(&mut self, offset: u32, stride: u32) -> StridedIter<'_, Q> {
+        self.borrow();
+        unsafe {
+            StridedIter::new(
+                QueryIter::new(self.frame, self.filter.as_deref()),
+                offset,
+                stride,
             )
         }
     }
@@ -706,6 +1363,7 @@ impl<'w, Q: Query> QueryBorrow<'w, Q> {
         let x = QueryBorrow {
             frame: self.frame,
             borrowed: self.borrowed,
+            filter: self.filter.take(),
             _marker: PhantomData,
         };
         // Ensure `Drop` won't fire redundantly
@@ -737,8 +1395,9 @@ impl<'q, 'w, Q: Query> IntoIterator for &'q mut QueryBorrow<'w, Q> {
 /// Iterator over the set of entities with the components in `Q`
 pub struct QueryIter<'q, Q: Query> {
     frame: &'q Frame,
-    archetypes: core::ops::Range<usize>,
+    archetypes: Candidates<'q>,
     iter: ChunkIter<Q>,
+    filter: Option<&'q ArchetypeFilter<'q>>,
 }
 
 impl<'q, Q: Query> QueryIter<'q, Q> {
@@ -746,21 +1405,44 @@ impl<'q, Q: Query> QueryIter<'q, Q> {
     ///
     /// `'q` must be sufficient to guarantee that `Q` cannot violate borrow safety, either with
     /// dynamic borrow checks or by representing exclusive access to the `Frame`.
-    unsafe fn new(frame: &'q Frame) -> Self {
-        let n = frame.archetypes().len();
+    unsafe fn new(frame: &'q Frame, filter: Option<&'q ArchetypeFilter<'q>>) -> Self {
         Self {
             frame,
-            archetypes: 0..n,
+            archetypes: Candidates::new::<Q>(frame),
             iter: ChunkIter::empty(),
+            filter,
+        }
+    }
+
+    /// Look up the entity with index `id`
+    #[inline(always)]
+    fn entity(&self, id: u32) -> Entity {
+        Entity {
+            id,
+            generation: unsafe {
+                self.frame
+                    .entities_meta()
+                    .get_unchecked(id as usize)
+                    .generation
+            },
         }
     }
 
+    /// Whether the query should visit `archetype`
+    fn accepts(&self, archetype: &Archetype) -> bool {
+        self.filter.map_or(true, |f| f(archetype))
+    }
+
     /// Advance query to the next archetype
     ///
     /// Outlined from `Iterator::next` for improved iteration performance.
     fn next_archetype(&mut self) -> Option<()> {
         let archetype = self.archetypes.next()?;
         let archetype = unsafe { self.frame.archetypes_inner().get_unchecked(archetype) };
+        if !self.accepts(archetype) {
+            self.iter = ChunkIter::empty();
+            return Some(());
+        }
         let state = Q::Fetch::prepare(archetype);
         let fetch = state.map(|state| Q::Fetch::execute(archetype, state));
         self.iter = fetch.map_or(ChunkIter::empty(), |fetch| ChunkIter::new(archetype, fetch));
@@ -782,20 +1464,7 @@ impl<'q, Q: Query> Iterator for QueryIter<'q, Q> {
                     self.next_archetype()?;
                     continue;
                 }
-                Some((id, components)) => {
-                    return Some((
-                        Entity {
-                            id,
-                            generation: unsafe {
-                                self.frame
-                                    .entities_meta()
-                                    .get_unchecked(id as usize)
-                                    .generation
-                            },
-                        },
-                        components,
-                    ));
-                }
+                Some((id, components)) => return Some((self.entity(id), components)),
             }
         }
     }
@@ -811,13 +1480,48 @@ impl<'q, Q: Query> ExactSizeIterator for QueryIter<'q, Q> {
         self.archetypes
             .clone()
             .map(|x| unsafe { self.frame.archetypes_inner().get_unchecked(x) })
-            .filter(|&x| Q::Fetch::access(x).is_some())
-            .map(|x| x.len() as usize)
+            .filter(|&x| self.accepts(x))
+            .filter_map(|x| Some(ChunkIter::<Q>::count(x, Q::Fetch::prepare(x)?)))
             .sum::<usize>()
             + self.iter.remaining()
     }
 }
 
+/// Indices of the archetypes a [`QueryIter`] might match
+///
+/// Most queries require some component type, e.g. `T` for `&mut T`. Rather than testing every
+/// archetype, such queries walk the list of archetypes holding that type that the frame maintains
+/// as archetypes are created, so a single-component query over a frame with many unrelated
+/// archetypes costs no more than one over a frame holding only `T`. Each column visited is then
+/// read front to back, a pattern hardware prefetchers already stream ahead of, so no explicit
+/// prefetching is done.
+#[derive(Clone)]
+enum Candidates<'q> {
+    All(core::ops::Range<usize>),
+    Having(SliceIter<'q, u32>),
+}
+
+impl<'q> Candidates<'q> {
+    fn new<Q: Query>(frame: &'q Frame) -> Self {
+        match Q::Fetch::required() {
+            Some(ty) => Candidates::Having(frame.archetypes_with(ty).iter()),
+            None => Candidates::All(0..frame.archetypes().len()),
+        }
+    }
+}
+
+impl Iterator for Candidates<'_> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        match *self {
+            Candidates::All(ref mut range) => range.next(),
+            Candidates::Having(ref mut ids) => ids.next().map(|&x| x as usize),
+        }
+    }
+}
+
 /// A query builder that's convertible directly into an iterator
 pub struct QueryMut<'q, Q: Query> {
     iter: QueryIter<'q, Q>,
@@ -828,7 +1532,7 @@ impl<'q, Q: Query> QueryMut<'q, Q> {
         assert_borrow::<Q>();
 
         Self {
-            iter: unsafe { QueryIter::new(frame) },
+            iter: unsafe { QueryIter::new(frame, None) },
         }
     }
 
@@ -842,6 +1546,39 @@ impl<'q, Q: Query> QueryMut<'q, Q> {
         }
     }
 
+    /// Provide read-only random access to a subset of the components borrowed by `Q`, for entities
+    /// matching `Q`
+    ///
+    /// Allows helpers that only need shared access to be called mid-system without giving up the
+    /// query. Panics if `R` borrows a component that `Q` doesn't.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// fn total(view: &View<&i32>, entities: &[Entity]) -> i32 {
+    ///     entities.iter().filter_map(|&e| view.get(e)).sum()
+    /// }
+    ///
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((1, true));
+    /// let b = frame.spawn((2, true));
+    /// let mut query = frame.query_mut::<(&mut i32, &bool)>();
+    /// let sum = total(&query.as_shared::<&i32>(), &[a, b]);
+    /// for (_, (x, _)) in query {
+    ///     *x += sum;
+    /// }
+    /// assert_eq!(*frame.get::<&i32>(a).unwrap(), 4);
+    /// ```
+    pub fn as_shared<R: Query + QueryShared>(&self) -> View<'_, R> {
+        assert_subset::<Q, R>();
+        let archetypes = self.iter.frame.archetypes_inner();
+        unsafe {
+            View::new_filtered(self.iter.frame.entities_meta(), archetypes, |i| {
+                Q::Fetch::access(&archetypes[i]).is_some()
+            })
+        }
+    }
+
     /// Transform the query into one that requires another query be satisfied
     ///
     /// See `QueryBorrow::with`
@@ -859,10 +1596,42 @@ impl<'q, Q: Query> QueryMut<'q, Q> {
     /// Helper to change the type of the query
     fn transform<R: Query>(self) -> QueryMut<'q, R> {
         QueryMut {
-            iter: unsafe { QueryIter::new(self.iter.frame) },
+            iter: unsafe { QueryIter::new(self.iter.frame, None) },
         }
     }
 
+    /// Replace the contents of `out` with owned copies of the query results
+    ///
+    /// See `QueryBorrow::collect_into`
+    pub fn collect_into<O>(self, out: &mut Vec<(Entity, O)>)
+    where
+        for<'a> Q::Item<'a>: OwnedItem<Owned = O>,
+    {
+        out.clear();
+        out.extend(self.iter.map(|(e, x)| (e, x.to_owned_item())));
+    }
+
+    /// Like `into_iter`, but distributes the results over rayon's thread pool
+    ///
+    /// See `QueryBorrow::par_iter`.
+    #[cfg(feature = "parallel")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "parallel")))]
+    pub fn par_iter_mut(
+        &mut self,
+    ) -> impl rayon::iter::ParallelIterator<Item = (Entity, Q::Item<'_>)> + '_
+    where
+        for<'a> Q::Item<'a>: Send,
+    {
+        par_batches(unsafe {
+            BatchedIter::<'_, Q>::new(
+                self.iter.frame.entities_meta(),
+                self.iter.frame.archetypes_inner().iter(),
+                PAR_BATCH_SIZE,
+                None,
+            )
+        })
+    }
+
     /// Like `into_iter`, but returns child iterators of at most `batch_size` elements
     ///
     /// Useful for distributing work over a threadpool.
@@ -872,9 +1641,17 @@ impl<'q, Q: Query> QueryMut<'q, Q> {
                 self.iter.frame.entities_meta(),
                 self.iter.frame.archetypes_inner().iter(),
                 batch_size,
+                None,
             )
         }
     }
+
+    /// Like `into_iter`, but visits only every `stride`th entity of each archetype
+    ///
+    /// See `QueryBorrow::iter_strided`
+    pub fn into_iter_strided(self, offset: u32, stride: u32) -> StridedIter<'q, Q> {
+        StridedIter::new(self.iter, offset, stride)
+    }
 }
 
 impl<'q, Q: Query> IntoIterator for QueryMut<'q, Q> {
@@ -887,6 +1664,18 @@ impl<'q, Q: Query> IntoIterator for QueryMut<'q, Q> {
     }
 }
 
+/// Check that every component borrowed by `R` is also borrowed by `Q`
+fn assert_subset<Q: Query, R: Query>() {
+    R::Fetch::for_each_borrow(|a, _| {
+        let mut found = false;
+        Q::Fetch::for_each_borrow(|b, _| found |= a == b);
+        core::assert!(
+            found,
+            "shared query borrows a component not borrowed by the original"
+        );
+    });
+}
+
 /// Check that Q doesn't alias a `&mut T` on its own. Currently over-conservative for `Or` queries.
 pub(crate) fn assert_borrow<Q: Query>() {
     // This looks like an ugly O(n^2) loop, but everything's constant after inlining, so in
@@ -906,6 +1695,19 @@ pub(crate) fn assert_borrow<Q: Query>() {
     });
 }
 
+/// Look up the `n`th item in `fetch`'s archetype, unless `Q` filters it out
+///
+/// # Safety
+///
+/// As for [`Query::get`]
+#[inline]
+pub(crate) unsafe fn get_filtered<'a, Q: Query>(fetch: &Q::Fetch, n: usize) -> Option<Q::Item<'a>> {
+    if Q::FILTERED && !Q::filter(fetch, n) {
+        return None;
+    }
+    Some(Q::get(fetch, n))
+}
+
 struct ChunkIter<Q: Query> {
     entities: NonNull<u32>,
     fetch: Q::Fetch,
@@ -934,20 +1736,101 @@ impl<Q: Query> ChunkIter<Q> {
 
     #[inline]
     unsafe fn next<'a>(&mut self) -> Option<(u32, Q::Item<'a>)> {
-        if self.position == self.len {
-            return None;
+        loop {
+            if self.position == self.len {
+                return None;
+            }
+            let position = self.position;
+            self.position += 1;
+            if Q::FILTERED && !Q::filter(&self.fetch, position) {
+                continue;
+            }
+            let entity = self.entities.as_ptr().add(position);
+            let item = Q::get(&self.fetch, position);
+            return Some((*entity, item));
         }
-        let entity = self.entities.as_ptr().add(self.position);
-        let item = Q::get(&self.fetch, self.position);
-        self.position += 1;
-        Some((*entity, item))
     }
 
     fn remaining(&self) -> usize {
-        self.len - self.position
+        if !Q::FILTERED {
+            return self.len - self.position;
+        }
+        (self.position..self.len)
+            .filter(|&n| unsafe { Q::filter(&self.fetch, n) })
+            .count()
+    }
+
+    /// Number of entities in `archetype` that `Q` would yield
+    fn count(archetype: &Archetype, state: <Q::Fetch as Fetch>::State) -> usize {
+        if !Q::FILTERED {
+            return archetype.len() as usize;
+        }
+        Self::new(archetype, Q::Fetch::execute(archetype, state)).remaining()
+    }
+}
+
+/// Strided version of [`QueryIter`], returned by
+/// [`QueryBorrow::iter_strided`](crate::QueryBorrow::iter_strided)
+pub struct StridedIter<'q, Q: Query> {
+    iter: QueryIter<'q, Q>,
+    offset: usize,
+    stride: usize,
+}
+
+impl<'q, Q: Query> StridedIter<'q, Q> {
+    fn new(iter: QueryIter<'q, Q>, offset: u32, stride: u32) -> Self {
+        assert!(stride != 0, "stride must be nonzero");
+        Self {
+            iter,
+            offset: (offset % stride) as usize,
+            stride: stride as usize,
+        }
+    }
+}
+
+unsafe impl<'q, Q: Query> Send for StridedIter<'q, Q> where for<'a> Q::Item<'a>: Send {}
+unsafe impl<'q, Q: Query> Sync for StridedIter<'q, Q> where for<'a> Q::Item<'a>: Send {}
+
+impl<'q, Q: Query> Iterator for StridedIter<'q, Q> {
+    type Item = (Entity, Q::Item<'q>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Skip ahead to the next position congruent to `offset`
+            let chunk = &mut self.iter.iter;
+            let skip = (self.offset + self.stride - chunk.position % self.stride) % self.stride;
+            chunk.position = (chunk.position + skip).min(chunk.len);
+            match unsafe { chunk.next() } {
+                None => self.iter.next_archetype()?,
+                Some((id, components)) => return Some((self.iter.entity(id), components)),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.iter.len()))
     }
 }
 
+/// Number of entities per unit of work handed to rayon by `par_iter`
+#[cfg(feature = "parallel")]
+const PAR_BATCH_SIZE: u32 = 1024;
+
+/// Flatten `batches` into a parallel iterator over their elements
+#[cfg(feature = "parallel")]
+fn par_batches<'q, Q: Query>(
+    batches: BatchedIter<'q, Q>,
+) -> impl rayon::iter::ParallelIterator<Item = (Entity, Q::Item<'q>)> + 'q
+where
+    for<'a> Q::Item<'a>: Send,
+{
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+    batches
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .flat_map_iter(|batch| batch)
+}
+
 /// Batched version of [`QueryIter`]
 pub struct BatchedIter<'q, Q: Query> {
     _marker: PhantomData<&'q Q>,
@@ -955,6 +1838,7 @@ pub struct BatchedIter<'q, Q: Query> {
     archetypes: SliceIter<'q, Archetype>,
     batch_size: u32,
     batch: u32,
+    filter: Option<&'q ArchetypeFilter<'q>>,
 }
 
 impl<'q, Q: Query> BatchedIter<'q, Q> {
@@ -966,6 +1850,7 @@ impl<'q, Q: Query> BatchedIter<'q, Q> {
         meta: &'q [EntityMeta],
         archetypes: SliceIter<'q, Archetype>,
         batch_size: u32,
+        filter: Option<&'q ArchetypeFilter<'q>>,
     ) -> Self {
         Self {
             _marker: PhantomData,
@@ -973,6 +1858,7 @@ impl<'q, Q: Query> BatchedIter<'q, Q> {
             archetypes,
             batch_size,
             batch: 0,
+            filter,
         }
     }
 }
@@ -988,7 +1874,7 @@ impl<'q, Q: Query> Iterator for BatchedIter<'q, Q> {
             let mut archetypes = self.archetypes.clone();
             let archetype = archetypes.next()?;
             let offset = self.batch_size * self.batch;
-            if offset >= archetype.len() {
+            if offset >= archetype.len() || !self.filter.map_or(true, |f| f(archetype)) {
                 self.archetypes = archetypes;
                 self.batch = 0;
                 continue;
@@ -1085,6 +1971,15 @@ macro_rules! tuple_impl {
             fn for_each_borrow(mut f: impl FnMut(TypeId, bool)) {
                 $($name::for_each_borrow(&mut f);)*
             }
+
+            fn required() -> Option<TypeId> {
+                None $(.or_else($name::required))*
+            }
+
+            #[allow(unused_variables)]
+            fn explain(archetype: &Archetype, out: &mut Explanation) {
+                $($name::explain(archetype, out);)*
+            }
         }
 
         impl<$($name: Query),*> Query for ($($name,)*) {
@@ -1098,9 +1993,28 @@ macro_rules! tuple_impl {
                 let ($(ref $name,)*) = *fetch;
                 ($($name::get($name, n),)*)
             }
+
+            const FILTERED: bool = false $(|| $name::FILTERED)*;
+
+            #[allow(unused_variables)]
+            unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+                #[allow(non_snake_case)]
+                let ($(ref $name,)*) = *fetch;
+                true $(&& $name::filter($name, n))*
+            }
         }
 
         unsafe impl<$($name: QueryShared),*> QueryShared for ($($name,)*) {}
+
+        impl<$($name: OwnedItem),*> OwnedItem for ($($name,)*) {
+            type Owned = ($($name::Owned,)*);
+
+            #[allow(non_snake_case, clippy::unused_unit)]
+            fn to_owned_item(self) -> Self::Owned {
+                let ($($name,)*) = self;
+                ($($name.to_owned_item(),)*)
+            }
+        }
     };
 }
 
@@ -1112,6 +2026,10 @@ pub struct PreparedQuery<Q: Query> {
     memo: (u64, u32),
     state: Box<[(usize, <Q::Fetch as Fetch>::State)]>,
     fetch: Box<[Option<Q::Fetch>]>,
+    /// Whether the matched archetypes changed since the last `matches_changed`
+    changed: bool,
+    /// Called whenever the matched archetypes change
+    on_change: Option<Box<dyn FnMut() + Send + Sync>>,
 }
 
 impl<Q: Query> Default for PreparedQuery<Q> {
@@ -1128,22 +2046,104 @@ impl<Q: Query> PreparedQuery<Q> {
             memo: (0, 0),
             state: Default::default(),
             fetch: Default::default(),
+            changed: false,
+            on_change: None,
+        }
+    }
+
+    /// Prepare the query again if `frame` isn't the one it was last prepared for, or has gained
+    /// archetypes since
+    #[inline]
+    fn update(&mut self, frame: &Frame) -> bool {
+        let stale = self.memo != frame.memo();
+        if stale {
+            self.prepare(frame);
         }
+        stale
     }
 
     #[cold]
-    fn prepare(frame: &Frame) -> Self {
+    fn prepare(&mut self, frame: &Frame) {
         let memo = frame.memo();
 
         let state = frame
             .archetypes()
             .enumerate()
             .filter_map(|(idx, x)| Q::Fetch::prepare(x).map(|state| (idx, state)))
-            .collect();
+            .collect::<Box<[_]>>();
+
+        let changed = memo.0 != self.memo.0
+            || state.len() != self.state.len()
+            || state.iter().zip(&*self.state).any(|(x, y)| x.0 != y.0);
+        self.memo = memo;
+        self.state = state;
+        self.fetch = frame.archetypes().map(|_| None).collect();
+        if changed {
+            self.changed = true;
+            if let Some(ref mut f) = self.on_change {
+                f();
+            }
+        }
+    }
+
+    /// Whether the set of archetypes matching the query in `frame` changed since the last call
+    ///
+    /// Caches derived from the query's matches, such as a sorted index or a GPU buffer per
+    /// archetype, need only be rebuilt when this returns `true`. Archetypes matching the query
+    /// are never removed, so a change means that a new archetype matches, or that the query was
+    /// last used with a different frame. Always `true` on the first call with a frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let mut query = PreparedQuery::<&i32>::new();
+    /// frame.spawn((1,));
+    /// assert!(query.matches_changed(&frame));
+    /// assert!(!query.matches_changed(&frame));
+    /// frame.spawn((true,));
+    /// assert!(!query.matches_changed(&frame));
+    /// frame.spawn((2, true));
+    /// assert!(query.matches_changed(&frame));
+    /// ```
+    pub fn matches_changed(&mut self, frame: &Frame) -> bool {
+        self.update(frame);
+        core::mem::take(&mut self.changed)
+    }
+
+    /// Call `f` whenever the set of archetypes matching the query changes
+    ///
+    /// Changes are discovered when the query is next used, or by
+    /// [`matches_changed`](Self::matches_changed) or [`warm`](Self::warm). Replaces any previous
+    /// hook.
+    pub fn set_change_hook(&mut self, f: impl FnMut() + Send + Sync + 'static) {
+        self.on_change = Some(Box::new(f));
+    }
 
-        let fetch = frame.archetypes().map(|_| None).collect();
+    /// Stop calling the function passed to [`set_change_hook`](Self::set_change_hook)
+    pub fn clear_change_hook(&mut self) {
+        self.on_change = None;
+    }
 
-        Self { memo, state, fetch }
+    /// Find the archetypes of `frame` matching the query ahead of its first use, without
+    /// borrowing any components
+    ///
+    /// Returns whether the cached matches were out of date. Calling this e.g. after loading a
+    /// level keeps the cost of finding matches out of the next query. The cache is invalidated
+    /// whenever `frame` gains an archetype.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// frame.spawn((1, true));
+    /// let mut query = PreparedQuery::<&i32>::new();
+    /// assert!(query.warm(&frame));
+    /// assert!(!query.warm(&frame));
+    /// assert_eq!(query.query(&frame).iter().count(), 1);
+    /// ```
+    pub fn warm(&mut self, frame: &Frame) -> bool {
+        self.update(frame)
     }
 
     /// Query `frame`, using dynamic borrow checking
@@ -1151,9 +2151,7 @@ impl<Q: Query> PreparedQuery<Q> {
     /// This will panic if it would violate an existing unique reference
     /// or construct an invalid unique reference.
     pub fn query<'q>(&'q mut self, frame: &'q Frame) -> PreparedQueryBorrow<'q, Q> {
-        if self.memo != frame.memo() {
-            *self = Self::prepare(frame);
-        }
+        self.update(frame);
 
         let meta = frame.entities_meta();
         let archetypes = frame.archetypes_inner();
@@ -1167,9 +2165,7 @@ impl<Q: Query> PreparedQuery<Q> {
     pub fn query_mut<'q>(&'q mut self, frame: &'q mut Frame) -> PreparedQueryIter<'q, Q> {
         assert_borrow::<Q>();
 
-        if self.memo != frame.memo() {
-            *self = Self::prepare(frame);
-        }
+        self.update(frame);
 
         let meta = frame.entities_meta();
         let archetypes = frame.archetypes_inner();
@@ -1181,9 +2177,7 @@ impl<Q: Query> PreparedQuery<Q> {
     pub fn view_mut<'q>(&'q mut self, frame: &'q mut Frame) -> PreparedView<'q, Q> {
         assert_borrow::<Q>();
 
-        if self.memo != frame.memo() {
-            *self = Self::prepare(frame);
-        }
+        self.update(frame);
 
         let meta = frame.entities_meta();
         let archetypes = frame.archetypes_inner();
@@ -1311,7 +2305,7 @@ impl<Q: Query> ExactSizeIterator for PreparedQueryIter<'_, Q> {
     fn len(&self) -> usize {
         self.state
             .clone()
-            .map(|(idx, _)| self.archetypes[*idx].len() as usize)
+            .map(|(idx, state)| ChunkIter::<Q>::count(&self.archetypes[*idx], *state))
             .sum::<usize>()
             + self.iter.remaining()
     }
@@ -1347,6 +2341,34 @@ impl<'q, Q: Query> View<'q, Q> {
         }
     }
 
+    /// Like `new`, but only for archetypes whose index satisfies `matches`
+    ///
+    /// # Safety
+    ///
+    /// As for `new`, restricted to the matching archetypes.
+    unsafe fn new_filtered(
+        meta: &'q [EntityMeta],
+        archetypes: &'q [Archetype],
+        matches: impl Fn(usize) -> bool,
+    ) -> Self {
+        let fetch = archetypes
+            .iter()
+            .enumerate()
+            .map(|(i, archetype)| {
+                if !matches(i) {
+                    return None;
+                }
+                Q::Fetch::prepare(archetype).map(|state| Q::Fetch::execute(archetype, state))
+            })
+            .collect();
+
+        Self {
+            meta,
+            archetypes,
+            fetch,
+        }
+    }
+
     /// Retrieve the query results corresponding to `entity`
     ///
     /// Will yield `None` if the entity does not exist or does not match the query.
@@ -1363,7 +2385,7 @@ impl<'q, Q: Query> View<'q, Q> {
 
         self.fetch[meta.location.archetype as usize]
             .as_ref()
-            .map(|fetch| unsafe { Q::get(fetch, meta.location.index as usize) })
+            .and_then(|fetch| unsafe { get_filtered::<Q>(fetch, meta.location.index as usize) })
     }
 
     /// Retrieve the query results corresponding to `entity`
@@ -1381,7 +2403,33 @@ impl<'q, Q: Query> View<'q, Q> {
         if meta.generation != entity.generation {
             return false;
         }
-        self.fetch[meta.location.archetype as usize].is_some()
+        self.fetch[meta.location.archetype as usize]
+            .as_ref()
+            .map_or(false, |fetch| unsafe {
+                !Q::FILTERED || Q::filter(fetch, meta.location.index as usize)
+            })
+    }
+
+    /// Temporarily reborrow a subset of the components borrowed by `Q` as read-only
+    ///
+    /// The resulting view yields the same entities as this one. Panics if `R` borrows a component
+    /// that `Q` doesn't.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((1, 2.0f32));
+    /// let mut query = frame.query_mut::<(&mut i32, &f32)>();
+    /// let mut view = query.view();
+    /// let x = *view.as_shared::<&i32>().get(a).unwrap();
+    /// *view.get_mut(a).unwrap().0 += x;
+    /// assert_eq!(*view.get_mut(a).unwrap().0, 2);
+    /// ```
+    pub fn as_shared<R: Query + QueryShared>(&mut self) -> View<'_, R> {
+        assert_subset::<Q, R>();
+        let fetch = &self.fetch;
+        unsafe { View::new_filtered(self.meta, self.archetypes, |i| fetch[i].is_some()) }
     }
 
     /// Like `get_mut`, but allows simultaneous access to multiple entities
@@ -1397,7 +2445,45 @@ impl<'q, Q: Query> View<'q, Q> {
 
         self.fetch[meta.location.archetype as usize]
             .as_ref()
-            .map(|fetch| Q::get(fetch, meta.location.index as usize))
+            .and_then(|fetch| get_filtered::<Q>(fetch, meta.location.index as usize))
+    }
+
+    /// Like [`get_unchecked`](Self::get_unchecked), but skips checking that `entity` is live
+    ///
+    /// For hot random-access loops over entities whose liveness the caller has just established,
+    /// e.g. handles obtained from the same frame since it was last modified. Yields `None` only if
+    /// the entity does not match the query.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((1, true));
+    /// let b = frame.spawn((true,));
+    /// let view = frame.view_mut::<&mut i32>();
+    /// // Safety: `a` and `b` were just spawned, and no component is borrowed
+    /// unsafe {
+    ///     assert_eq!(*view.get_live_unchecked(a).unwrap(), 1);
+    ///     assert!(view.get_live_unchecked(b).is_none());
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// `entity` must be live in the frame this view borrows, and no unique borrow of the fetched
+    /// components of `entity` may be live.
+    pub unsafe fn get_live_unchecked(&self, entity: Entity) -> Option<Q::Item<'_>> {
+        debug_assert!(
+            self.meta
+                .get(entity.id as usize)
+                .map_or(false, |x| x.generation == entity.generation),
+            "entity is not live"
+        );
+        let meta = self.meta.get_unchecked(entity.id as usize);
+        self.fetch
+            .get_unchecked(meta.location.archetype as usize)
+            .as_ref()
+            .and_then(|fetch| get_filtered::<Q>(fetch, meta.location.index as usize))
     }
 
     /// Like `get_mut`, but allows checked simultaneous access to multiple entities
@@ -1555,7 +2641,7 @@ impl<'q, Q: Query> PreparedView<'q, Q> {
 
         self.fetch[meta.location.archetype as usize]
             .as_ref()
-            .map(|fetch| unsafe { Q::get(fetch, meta.location.index as usize) })
+            .and_then(|fetch| unsafe { get_filtered::<Q>(fetch, meta.location.index as usize) })
     }
 
     /// Retrieve the query results corresponding to `entity`
@@ -1573,7 +2659,11 @@ impl<'q, Q: Query> PreparedView<'q, Q> {
         if meta.generation != entity.generation {
             return false;
         }
-        self.fetch[meta.location.archetype as usize].is_some()
+        self.fetch[meta.location.archetype as usize]
+            .as_ref()
+            .map_or(false, |fetch| unsafe {
+                !Q::FILTERED || Q::filter(fetch, meta.location.index as usize)
+            })
     }
 
     /// Like `get_mut`, but allows simultaneous access to multiple entities
@@ -1589,7 +2679,29 @@ impl<'q, Q: Query> PreparedView<'q, Q> {
 
         self.fetch[meta.location.archetype as usize]
             .as_ref()
-            .map(|fetch| Q::get(fetch, meta.location.index as usize))
+            .and_then(|fetch| get_filtered::<Q>(fetch, meta.location.index as usize))
+    }
+
+    /// Like [`get_unchecked`](Self::get_unchecked), but skips checking that `entity` is live
+    ///
+    /// See [`View::get_live_unchecked`].
+    ///
+    /// # Safety
+    ///
+    /// `entity` must be live in the frame this view borrows, and no unique borrow of the fetched
+    /// components of `entity` may be live.
+    pub unsafe fn get_live_unchecked(&self, entity: Entity) -> Option<Q::Item<'_>> {
+        debug_assert!(
+            self.meta
+                .get(entity.id as usize)
+                .map_or(false, |x| x.generation == entity.generation),
+            "entity is not live"
+        );
+        let meta = self.meta.get_unchecked(entity.id as usize);
+        self.fetch
+            .get_unchecked(meta.location.archetype as usize)
+            .as_ref()
+            .and_then(|fetch| get_filtered::<Q>(fetch, meta.location.index as usize))
     }
 
     /// Like `get_mut`, but allows checked simultaneous access to multiple entities
@@ -1699,6 +2811,18 @@ impl<'w, Q: Query> ViewBorrow<'w, Q> {
         self.view.get_unchecked(entity)
     }
 
+    /// Like `get_unchecked`, but skips checking that `entity` is live
+    ///
+    /// See [`View::get_live_unchecked`].
+    ///
+    /// # Safety
+    ///
+    /// `entity` must be live in the frame this view borrows, and no unique borrow of the fetched
+    /// components of `entity` may be live.
+    pub unsafe fn get_live_unchecked(&self, entity: Entity) -> Option<Q::Item<'_>> {
+        self.view.get_live_unchecked(entity)
+    }
+
     /// Like `get_mut`, but allows checked simultaneous access to multiple entities
     ///
     /// For N > 3, the check for distinct entities will clone the array and take O(N log N) time.
@@ -1,5 +1,3 @@
-use core::marker::PhantomData;
-
 use crate::query::{assert_borrow, Fetch, With, Without};
 use crate::{Archetype, Query};
 
@@ -7,8 +5,8 @@ use crate::{Archetype, Query};
 pub struct QueryOne<'a, Q: Query> {
     archetype: &'a Archetype,
     index: u32,
-    borrowed: bool,
-    _marker: PhantomData<Q>,
+    /// State and fetch of the borrow taken by `get`, released on drop
+    borrowed: Option<(<Q::Fetch as Fetch>::State, Q::Fetch)>,
 }
 
 impl<'a, Q: Query> QueryOne<'a, Q> {
@@ -23,8 +21,7 @@ impl<'a, Q: Query> QueryOne<'a, Q> {
         Self {
             archetype,
             index,
-            borrowed: false,
-            _marker: PhantomData,
+            borrowed: None,
         }
     }
 
@@ -36,14 +33,35 @@ impl<'a, Q: Query> QueryOne<'a, Q> {
     /// pre-existing borrow.
     // Note that this uses self's lifetime, not 'a, for soundness.
     pub fn get(&mut self) -> Option<Q::Item<'_>> {
-        if self.borrowed {
+        if self.borrowed.is_some() {
             panic!("called QueryOnce::get twice; construct a new query instead");
         }
-        let state = Q::Fetch::prepare(self.archetype)?;
+        if !self.borrow() {
+            return None;
+        }
+        unsafe { Some(self.item()) }
+    }
+
+    /// Borrow the components accessed by `Q`, returning `false` if the entity doesn't satisfy it
+    fn borrow(&mut self) -> bool {
+        let Some(state) = Q::Fetch::prepare(self.archetype) else {
+            return false;
+        };
         Q::Fetch::borrow(self.archetype, state);
         let fetch = Q::Fetch::execute(self.archetype, state);
-        self.borrowed = true;
-        unsafe { Some(Q::get(&fetch, self.index as usize)) }
+        let selected = !Q::FILTERED || unsafe { Q::filter(&fetch, self.index as usize) };
+        self.borrowed = Some((state, fetch));
+        selected
+    }
+
+    /// The query result, borrowed by a previous successful call to `borrow`
+    ///
+    /// # Safety
+    ///
+    /// `borrow` must have returned `true`
+    unsafe fn item(&mut self) -> Q::Item<'_> {
+        let (_, ref fetch) = *self.borrowed.as_ref().unwrap_unchecked();
+        Q::get(fetch, self.index as usize)
     }
 
     /// Transform the query into one that requires another query be satisfied
@@ -60,24 +78,15 @@ impl<'a, Q: Query> QueryOne<'a, Q> {
         self.transform()
     }
 
-    /// Helper to change the type of the query
-    fn transform<R: Query>(mut self) -> QueryOne<'a, R> {
-        let x = QueryOne {
-            archetype: self.archetype,
-            index: self.index,
-            borrowed: self.borrowed,
-            _marker: PhantomData,
-        };
-        // Ensure `Drop` won't fire redundantly
-        self.borrowed = false;
-        x
+    /// Helper to change the type of the query, releasing any borrow
+    fn transform<R: Query>(self) -> QueryOne<'a, R> {
+        unsafe { QueryOne::new(self.archetype, self.index) }
     }
 }
 
 impl<Q: Query> Drop for QueryOne<'_, Q> {
     fn drop(&mut self) {
-        if self.borrowed {
-            let state = Q::Fetch::prepare(self.archetype).unwrap();
+        if let Some((state, _)) = self.borrowed {
             Q::Fetch::release(self.archetype, state);
         }
     }
@@ -85,3 +94,30 @@ impl<Q: Query> Drop for QueryOne<'_, Q> {
 
 unsafe impl<Q: Query> Send for QueryOne<'_, Q> {}
 unsafe impl<Q: Query> Sync for QueryOne<'_, Q> {}
+
+/// Several components of a single entity, borrowed together
+///
+/// Obtained from [`EntityRef::get_many`](crate::EntityRef::get_many). The entity is resolved and
+/// every component borrowed once, up front; the borrows are released when the guard is dropped.
+pub struct RefMany<'a, Q: Query> {
+    query: QueryOne<'a, Q>,
+}
+
+impl<'a, Q: Query> RefMany<'a, Q> {
+    /// Borrow the components of the entity in `archetype` at `index`, or `None` if it doesn't
+    /// satisfy `Q`
+    ///
+    /// # Safety
+    ///
+    /// `index` must be in-bounds for `archetype`
+    pub(crate) unsafe fn new(archetype: &'a Archetype, index: u32) -> Option<Self> {
+        let mut query = QueryOne::new(archetype, index);
+        query.borrow().then_some(Self { query })
+    }
+
+    /// Access the borrowed components
+    // Note that this uses self's lifetime, not 'a, for soundness.
+    pub fn get(&mut self) -> Q::Item<'_> {
+        unsafe { self.query.item() }
+    }
+}
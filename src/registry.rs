@@ -0,0 +1,1155 @@
+use core::any::{type_name, TypeId};
+use core::fmt;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
+
+use crate::archetype::{TypeIdMap, TypeInfo};
+use crate::bundle::DynamicClone;
+use crate::{
+    Archetype, BuiltEntityClone, ColumnBatchBuilder, ColumnBatchType, Component, DynamicComponent,
+    Entity, EntityBuilder, EntityBuilderClone, EntityMap, EntityRef, Frame, FrameEvent,
+    MapEntities,
+};
+
+/// Runtime information about component types
+///
+/// Tools such as inspectors and serializers often handle components whose types aren't known
+/// statically. Registering a type here records functions for operating on type-erased values of
+/// that type, which can then be applied to e.g. the [`DynamicComponent`]s passed to a
+/// [`ComponentVisitor`](crate::ComponentVisitor).
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// let mut registry = ComponentRegistry::new();
+/// registry.register::<i32>().debug();
+/// registry.register::<bool>().name("flag").debug();
+///
+/// let mut frame = Frame::new();
+/// let e = frame.spawn((42, true));
+/// let mut out = Vec::new();
+/// frame.entity(e).unwrap().visit(&mut |component: DynamicComponent<'_>| {
+///     let entry = registry.get(component.type_info().id()).unwrap();
+///     out.push(format!("{}: {:?}", entry.name(), entry.debug(component).unwrap()));
+/// });
+/// out.sort();
+/// assert_eq!(out, ["flag: true", "i32: 42"]);
+/// ```
+#[derive(Default)]
+pub struct ComponentRegistry {
+    components: TypeIdMap<RegisteredComponent>,
+    /// Type assigned each [`ComponentKey`]
+    keys: HashMap<ComponentKey, TypeId>,
+}
+
+impl ComponentRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `T`, or access its existing registration to record more information about it
+    ///
+    /// Types are initially named by [`core::any::type_name`].
+    pub fn register<T: Component>(&mut self) -> Registration<'_, T> {
+        let index = self.components.len() as u32;
+        let entry =
+            self.components
+                .entry(TypeId::of::<T>())
+                .or_insert_with(|| RegisteredComponent {
+                    info: TypeInfo::of::<T>(),
+                    index,
+                    name: type_name::<T>(),
+                    key: None,
+                    debug: None,
+                    clone: None,
+                    clone_one: None,
+                    map_entities: None,
+                    validate: None,
+                    default: None,
+                    #[cfg(feature = "reflect")]
+                    fields: Vec::new(),
+                });
+        Registration {
+            entry,
+            keys: &mut self.keys,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Look up the registration of the component type identified by `id`
+    pub fn get(&self, id: TypeId) -> Option<&RegisteredComponent> {
+        self.components.get(&id)
+    }
+
+    /// Whether the component type identified by `id` has been registered
+    pub fn contains(&self, id: TypeId) -> bool {
+        self.components.contains_key(&id)
+    }
+
+    /// Iterate over all registered component types, in arbitrary order
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &RegisteredComponent> + '_ {
+        self.components.values()
+    }
+
+    /// Number of registered component types
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    /// Whether no component types have been registered
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// Bitmask of the registered component types present in `archetype`
+    ///
+    /// Bit [`RegisteredComponent::index`] is set for each registered type. Unregistered types are
+    /// ignored.
+    pub fn archetype_mask(&self, archetype: &Archetype) -> ComponentMask {
+        let mut words = vec![0; (self.components.len() + 63) / 64];
+        for &id in archetype.type_ids() {
+            if let Some(component) = self.components.get(&id) {
+                words[component.index as usize / 64] |= 1 << (component.index % 64);
+            }
+        }
+        ComponentMask(words)
+    }
+
+    /// Registrations of the component types stored in `archetype`, with the index of each type's
+    /// column, ordered by [`name`](RegisteredComponent::name) and then by registration order
+    ///
+    /// Independent of the order of `archetype`'s columns, even among types sharing a name.
+    pub(crate) fn sorted_columns<'a>(
+        &'a self,
+        archetype: &Archetype,
+    ) -> Vec<(usize, &'a RegisteredComponent)> {
+        let mut columns = archetype
+            .types()
+            .iter()
+            .enumerate()
+            .filter_map(|(state, ty)| Some((state, self.get(ty.id())?)))
+            .collect::<Vec<_>>();
+        columns.sort_unstable_by_key(|&(_, component)| (component.name(), component.index()));
+        columns
+    }
+
+    /// [`archetype_mask`](Self::archetype_mask) of each archetype in `frame`, indexed by
+    /// [`Frame::archetype_id`]
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut registry = ComponentRegistry::new();
+    /// let flag = registry.register::<bool>().index();
+    /// let number = registry.register::<i32>().index();
+    /// let mut frame = Frame::new();
+    /// let entities = [frame.spawn((true,)), frame.spawn((1, false)), frame.spawn(("abc",))];
+    /// let masks = registry.archetype_masks(&frame);
+    /// let has_number = entities
+    ///     .iter()
+    ///     .map(|&e| masks[frame.archetype_id(e).unwrap() as usize].contains(number))
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(has_number, [false, true, false]);
+    /// assert!(masks[frame.archetype_id(entities[0]).unwrap() as usize].contains(flag));
+    /// ```
+    pub fn archetype_masks(&self, frame: &Frame) -> Vec<ComponentMask> {
+        frame.archetypes().map(|x| self.archetype_mask(x)).collect()
+    }
+
+    /// Construct a new [`Frame`] holding copies of `frame`'s entities, with the same handles
+    ///
+    /// Only components whose types were registered with [`Registration::cloneable`] or
+    /// [`Registration::copyable`] are copied; entities are otherwise spawned without them.
+    /// Components registered as `copyable` are copied a whole column at a time rather than
+    /// cloned individually. Relation types registered with
+    /// [`Frame::register_relation`](crate::Frame::register_relation) or
+    /// [`Frame::index_references`](crate::Frame::index_references) are registered with the clone,
+    /// and changes are tracked for the same types, as by
+    /// [`Frame::track_changes`](crate::Frame::track_changes).
+    ///
+    /// Newly allocated entity handles may differ between `frame` and the clone.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut registry = ComponentRegistry::new();
+    /// registry.register::<i32>().copyable();
+    /// registry.register::<String>().cloneable();
+    ///
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((42, "abc".to_string(), true));
+    /// let cloned = registry.clone_frame(&frame);
+    /// let a = cloned.entity(a).unwrap();
+    /// assert_eq!(*a.get::<&i32>().unwrap(), 42);
+    /// assert_eq!(*a.get::<&String>().unwrap(), "abc");
+    /// assert!(!a.has::<bool>());
+    /// ```
+    ///
+    /// Panics if any registered component in `frame` is uniquely borrowed.
+    pub fn clone_frame(&self, frame: &Frame) -> Frame {
+        let mut cloned = Frame::new();
+        cloned.copy_relations(frame);
+        cloned.copy_tracked(frame);
+        for archetype in frame.archetypes() {
+            let cloners = self.cloners(archetype);
+            let mut batch = Self::clone_batch(&cloners, archetype.len());
+            for &(info, cloner) in &cloners {
+                match cloner {
+                    CloneColumn::Copy => unsafe { copy_column(info, archetype, &mut batch) },
+                    CloneColumn::Clone(f) => f(archetype, &mut batch),
+                }
+            }
+            let batch = batch.build().expect("every column is filled");
+            let handles = archetype
+                .ids()
+                .iter()
+                .map(|&id| unsafe { frame.find_entity_from_id(id) })
+                .collect::<Vec<Entity>>();
+            cloned.spawn_column_batch_at(&handles, batch);
+        }
+        cloned
+    }
+
+    /// Like [`clone_frame`](Self::clone_frame), but columns of components registered with
+    /// [`Registration::copyable`] are shared between `frame` and the result rather than copied
+    ///
+    /// Each shared column is copied only once either frame first writes to it or adds or removes
+    /// entities in its archetype, making it cheap to fork many short-lived copies of a frame for
+    /// e.g. speculative simulation. A column written through a pointer retained from an
+    /// [`Archetype::pin`] is not copied, so don't fork frames whose columns are modified that way.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut registry = ComponentRegistry::new();
+    /// registry.register::<i32>().copyable();
+    ///
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((42,));
+    /// let mut forked = registry.fork_frame(&mut frame);
+    /// *forked.get::<&mut i32>(a).unwrap() = 7;
+    /// assert_eq!(*frame.get::<&i32>(a).unwrap(), 42);
+    /// assert_eq!(*forked.get::<&i32>(a).unwrap(), 7);
+    /// ```
+    pub fn fork_frame(&self, frame: &mut Frame) -> Frame {
+        let mut forked = Frame::new();
+        forked.copy_relations(frame);
+        forked.copy_tracked(frame);
+        for index in 0..frame.archetypes_inner().len() {
+            let archetype = &mut frame.archetypes_inner_mut()[index];
+            let cloners = self.cloners(archetype);
+            let mut batch = Self::clone_batch(&cloners, archetype.len());
+            for &(info, cloner) in &cloners {
+                match cloner {
+                    // `copyable` requires `Copy`, so values may be shared bitwise and never dropped
+                    CloneColumn::Copy => unsafe {
+                        if !batch.share_raw(info, archetype) {
+                            copy_column(info, archetype, &mut batch);
+                        }
+                    },
+                    CloneColumn::Clone(f) => f(archetype, &mut batch),
+                }
+            }
+            let batch = batch.build().expect("every column is filled");
+            let ids = archetype.ids().to_vec();
+            let handles = ids
+                .into_iter()
+                .map(|id| unsafe { frame.find_entity_from_id(id) })
+                .collect::<Vec<Entity>>();
+            forked.spawn_column_batch_at(&handles, batch);
+        }
+        forked
+    }
+
+    /// Clone every entity in `source` into `dest` under a new handle, returning the handles
+    /// assigned
+    ///
+    /// Like [`clone_frame`](Self::clone_frame), only components registered with
+    /// [`Registration::cloneable`] or [`Registration::copyable`] are cloned. Entity handles inside
+    /// cloned components registered with [`Registration::map_entities`] are then rewritten to
+    /// refer to the new entities, so groups of entities that refer to one another, such as a
+    /// prefab or a save loaded with [`serialize`](crate::serialize), can be instantiated any
+    /// number of times. Handles to entities outside `source` are left unchanged. Relation types
+    /// registered with `source` are registered with `dest`, as by
+    /// [`clone_frame`](Self::clone_frame). Hooks registered on `dest` are notified once with
+    /// [`FrameEvent::Merged`](crate::FrameEvent::Merged) if any entities were spawned.
+    ///
+    /// Panics if `dest` allocates IDs from a [`SharedAllocator`](crate::SharedAllocator) or if
+    /// any registered component in `source` is uniquely borrowed.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// #[derive(Clone)]
+    /// struct Parent(Entity);
+    /// impl MapEntities for Parent {
+    ///     fn map_entities(&mut self, map: &EntityMap) {
+    ///         self.0.map_entities(map);
+    ///     }
+    /// }
+    ///
+    /// let mut registry = ComponentRegistry::new();
+    /// registry.register::<i32>().copyable();
+    /// registry.register::<Parent>().cloneable().map_entities();
+    ///
+    /// let mut prefab = Frame::new();
+    /// let root = prefab.spawn((0,));
+    /// let child = prefab.spawn((1, Parent(root)));
+    ///
+    /// let mut frame = Frame::new();
+    /// let map = registry.merge_frame(&prefab, &mut frame);
+    /// let (root, child) = (map.get(root).unwrap(), map.get(child).unwrap());
+    /// assert_eq!(frame.get::<&Parent>(child).unwrap().0, root);
+    /// ```
+    pub fn merge_frame(&self, source: &Frame, dest: &mut Frame) -> EntityMap {
+        let mut map = EntityMap::new();
+        let mut spawned = Vec::new();
+        dest.copy_relations(source);
+        // New entities are appended to their archetypes, after these rows
+        let existing = dest
+            .archetypes()
+            .map(|archetype| archetype.len())
+            .collect::<Vec<_>>();
+        for archetype in source.archetypes() {
+            if archetype.is_empty() {
+                continue;
+            }
+            let cloners = self.cloners(archetype);
+            let mut batch = Self::clone_batch(&cloners, archetype.len());
+            for &(info, cloner) in &cloners {
+                match cloner {
+                    CloneColumn::Copy => unsafe { copy_column(info, archetype, &mut batch) },
+                    CloneColumn::Clone(f) => f(archetype, &mut batch),
+                }
+            }
+            let batch = batch.build().expect("every column is filled");
+            for (&id, new) in archetype.ids().iter().zip(dest.spawn_column_batch(batch)) {
+                map.insert(unsafe { source.find_entity_from_id(id) }, new);
+                spawned.push(new);
+            }
+        }
+        for (index, archetype) in dest.archetypes_inner_mut().iter_mut().enumerate() {
+            let start = existing.get(index).copied().unwrap_or(0);
+            self.map_rows(archetype, start..archetype.len(), &map);
+        }
+        // Remapping rewrote the targets of relations indexed when the rows were spawned
+        dest.refresh_references();
+        if !spawned.is_empty() {
+            dest.emit_event(&FrameEvent::Merged(&spawned));
+        }
+        map
+    }
+
+    /// Apply `map` to the components registered with [`Registration::map_entities`] in `rows`
+    fn map_rows(&self, archetype: &mut Archetype, rows: core::ops::Range<u32>, map: &EntityMap) {
+        if rows.is_empty() {
+            return;
+        }
+        for (state, ty) in archetype.types().iter().enumerate() {
+            let Some(f) = self
+                .components
+                .get(&ty.id())
+                .and_then(|entry| entry.map_entities)
+            else {
+                continue;
+            };
+            // `archetype` is uniquely accessible, and `f` was registered for the column's type
+            unsafe {
+                archetype.mark_written(state);
+                for index in rows.clone() {
+                    let ptr = archetype
+                        .get_dynamic(ty.id(), ty.layout().size(), index)
+                        .unwrap();
+                    f(ptr, map);
+                }
+            }
+        }
+    }
+
+    /// The registered types in `archetype` that can be cloned, and how
+    fn cloners(&self, archetype: &Archetype) -> Vec<(TypeInfo, CloneColumn)> {
+        archetype
+            .component_types()
+            .filter_map(|id| {
+                let entry = self.components.get(&id)?;
+                Some((entry.info, entry.clone?))
+            })
+            .collect()
+    }
+
+    /// Prepare a batch to hold `len` entities' worth of the components cloned by `cloners`
+    fn clone_batch(cloners: &[(TypeInfo, CloneColumn)], len: u32) -> ColumnBatchBuilder {
+        let mut batch_type = ColumnBatchType::new();
+        for &(info, _) in cloners {
+            batch_type.add_dynamic(info);
+        }
+        batch_type.into_batch(len)
+    }
+
+    /// Clone `entity`'s components into a bundle, e.g. to duplicate it or extract a prefab
+    ///
+    /// Only components whose types were registered with [`Registration::cloneable`] or
+    /// [`Registration::copyable`] are cloned; others are left out.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// let mut registry = ComponentRegistry::new();
+    /// registry.register::<i32>().copyable();
+    /// registry.register::<String>().cloneable();
+    ///
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((42, "abc".to_string(), true));
+    /// let prefab = registry.clone_entity_bundle(frame.entity(a).unwrap());
+    /// let b = frame.spawn(&prefab);
+    /// let b = frame.entity(b).unwrap();
+    /// assert_eq!(*b.get::<&i32>().unwrap(), 42);
+    /// assert_eq!(*b.get::<&String>().unwrap(), "abc");
+    /// assert!(!b.has::<bool>());
+    /// ```
+    ///
+    /// Panics if any component of `entity` is uniquely borrowed.
+    pub fn clone_entity_bundle(&self, entity: EntityRef<'_>) -> BuiltEntityClone {
+        let mut builder = EntityBuilderClone::new();
+        entity.visit(&mut |component: DynamicComponent<'_>| {
+            let Some(clone) = self
+                .components
+                .get(&component.type_info().id())
+                .and_then(|entry| entry.clone_one)
+            else {
+                return;
+            };
+            // `clone` was registered for the component's type
+            unsafe {
+                builder.add_clone_of(component.as_ptr().as_ptr(), clone);
+            }
+        });
+        builder.build()
+    }
+
+    /// Look up the registration of the component type named `name`
+    ///
+    /// See [`Registration::name`].
+    pub fn find_by_name(&self, name: &str) -> Option<&RegisteredComponent> {
+        self.components.values().find(|entry| entry.name == name)
+    }
+
+    /// Look up the registration of the component type assigned `key`
+    ///
+    /// See [`Registration::key`].
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// struct Health(u32);
+    ///
+    /// let mut registry = ComponentRegistry::new();
+    /// registry.register::<Health>().key("game::Health");
+    ///
+    /// let mut frame = Frame::new();
+    /// let e = frame.spawn((Health(10),));
+    /// // e.g. a key received from a plugin or read from a save file
+    /// let key = ComponentKey::new("game::Health");
+    /// let ty = registry.find_by_key(key).unwrap().type_info().id();
+    /// let mut query = DynamicQuery::new();
+    /// query.read(ty);
+    /// assert!(frame.entity(e).unwrap().query_dynamic(&query).is_some());
+    /// ```
+    pub fn find_by_key(&self, key: ComponentKey) -> Option<&RegisteredComponent> {
+        self.components.get(self.keys.get(&key)?)
+    }
+
+    /// Follow `path`, a `.`-separated sequence of field names registered with
+    /// [`Registration::field`], from a value of type `ty` at `ptr`
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must address a value of type `ty`
+    #[cfg(feature = "reflect")]
+    pub(crate) unsafe fn resolve_path(
+        &self,
+        mut ty: TypeId,
+        mut ptr: NonNull<u8>,
+        path: &str,
+    ) -> Option<(TypeId, NonNull<u8>)> {
+        for name in path.split('.') {
+            let entry = self.components.get(&ty)?;
+            let field = entry.fields.iter().find(|x| x.name == name)?;
+            ptr = (field.get)(ptr);
+            ty = field.ty;
+        }
+        Some((ty, ptr))
+    }
+
+    /// Give each entity in `frame` covered by `schema` the components it requires but lacks, using
+    /// their registered defaults
+    ///
+    /// Useful after loading entities saved before a component was added to the schema, though the
+    /// `deserialize_with_defaults` functions of the [`serialize`](crate::serialize) modules can
+    /// fill them in while loading instead. Returns a report of each component filled in.
+    ///
+    /// # Example
+    /// ```
+    /// # use moss_hecs::*;
+    /// struct Player;
+    ///
+    /// let mut registry = ComponentRegistry::new();
+    /// registry.register::<u32>().name("health").default_with(|| 100);
+    /// let schema = Schema::new().scope::<Player>().require::<u32>();
+    ///
+    /// let mut frame = Frame::new();
+    /// let a = frame.spawn((Player, "old save"));
+    /// let b = frame.spawn((Player, 42u32));
+    /// let c = frame.spawn(("not a player",));
+    /// let report = registry.fill_defaults(&mut frame, &schema);
+    /// assert_eq!(report, [DefaultedComponent { entity: a, name: "health" }]);
+    /// assert_eq!(*frame.get::<&u32>(a).unwrap(), 100);
+    /// assert_eq!(*frame.get::<&u32>(b).unwrap(), 42);
+    /// assert!(!frame.entity(c).unwrap().has::<u32>());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a type required by `schema` has no registered default.
+    pub fn fill_defaults(&self, frame: &mut Frame, schema: &Schema) -> Vec<DefaultedComponent> {
+        self.check_schema(schema);
+        let mut targets = Vec::new();
+        for archetype in frame.archetypes().filter(|x| !x.is_empty()) {
+            let missing = self.missing_defaults(schema, |ty| archetype.has_dynamic(ty));
+            if missing.is_empty() {
+                continue;
+            }
+            let entities = archetype
+                .ids()
+                .iter()
+                .map(|&id| unsafe { frame.find_entity_from_id(id) })
+                .collect::<Vec<Entity>>();
+            targets.push((missing, entities));
+        }
+
+        let mut report = Vec::new();
+        let mut builder = EntityBuilder::new();
+        for (missing, entities) in targets {
+            for entity in entities {
+                for entry in &missing {
+                    entry.add_default(DefaultTarget::Entity(&mut builder));
+                    report.push(DefaultedComponent {
+                        entity,
+                        name: entry.name,
+                    });
+                }
+                frame.insert(entity, builder.build()).unwrap();
+            }
+        }
+        report
+    }
+
+    /// Panic unless every type required by `schema` has a registered default
+    pub(crate) fn check_schema(&self, schema: &Schema) {
+        for &ty in &schema.required {
+            self.default_entry(ty);
+        }
+    }
+
+    /// Registration of `ty`, which must have a default
+    fn default_entry(&self, ty: TypeId) -> &RegisteredComponent {
+        match self.components.get(&ty) {
+            Some(entry) if entry.default.is_some() => entry,
+            Some(entry) => panic!(
+                "{} is required by the schema but has no registered default",
+                entry.name
+            ),
+            None => panic!("a component type required by the schema isn't registered"),
+        }
+    }
+
+    /// Registrations of the types `schema` requires of an entity whose component types are
+    /// identified by `has`, but which it lacks
+    pub(crate) fn missing_defaults(
+        &self,
+        schema: &Schema,
+        has: impl Fn(TypeId) -> bool,
+    ) -> Vec<&RegisteredComponent> {
+        if !schema.scope.iter().all(|&ty| has(ty)) {
+            return Vec::new();
+        }
+        schema
+            .required
+            .iter()
+            .filter(|&&ty| !has(ty))
+            .map(|&ty| self.default_entry(ty))
+            .collect()
+    }
+
+    /// Add the components `schema` requires of `entity` but that `builder` lacks, noting each in
+    /// `report`
+    #[cfg(feature = "row-serialize")]
+    pub(crate) fn fill_entity_defaults(
+        &self,
+        schema: &Schema,
+        entity: Entity,
+        builder: &mut EntityBuilder,
+        report: &mut Vec<DefaultedComponent>,
+    ) {
+        let missing =
+            self.missing_defaults(schema, |ty| builder.component_types().any(|x| x == ty));
+        for entry in missing {
+            entry.add_default(DefaultTarget::Entity(builder));
+            report.push(DefaultedComponent {
+                entity,
+                name: entry.name,
+            });
+        }
+    }
+
+    /// Check the components of the entity at `index` in `archetype` against their validators
+    ///
+    /// # Safety
+    ///
+    /// `index` must be in bounds, and the entity's components must not be uniquely borrowed
+    pub(crate) unsafe fn validate_row(
+        &self,
+        entity: Entity,
+        archetype: &Archetype,
+        index: u32,
+    ) -> Result<(), InvalidComponent> {
+        for ty in archetype.types() {
+            let Some(entry) = self.components.get(&ty.id()) else {
+                continue;
+            };
+            let Some(validate) = entry.validate.as_ref() else {
+                continue;
+            };
+            let ptr = archetype
+                .get_dynamic(ty.id(), ty.layout().size(), index)
+                .unwrap();
+            if !validate(ptr) {
+                return Err(InvalidComponent {
+                    entity,
+                    name: entry.name,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check every component of `archetype` with a registered validator
+    pub(crate) fn validate_archetype(
+        &self,
+        frame: &Frame,
+        archetype: &Archetype,
+    ) -> Result<(), InvalidComponent> {
+        for ty in archetype.types() {
+            let Some(entry) = self.components.get(&ty.id()) else {
+                continue;
+            };
+            let Some(validate) = entry.validate.as_ref() else {
+                continue;
+            };
+            unsafe {
+                let state = archetype.get_state_dynamic(ty.id()).unwrap();
+                archetype.borrow_raw(state);
+                let base = archetype.get_dynamic(ty.id(), ty.layout().size(), 0);
+                let invalid = (0..archetype.len()).find(|&index| {
+                    let ptr = base
+                        .unwrap()
+                        .as_ptr()
+                        .add(index as usize * ty.layout().size());
+                    !validate(NonNull::new_unchecked(ptr))
+                });
+                archetype.release_raw(state);
+                if let Some(index) = invalid {
+                    return Err(InvalidComponent {
+                        entity: frame.find_entity_from_id(archetype.ids()[index as usize]),
+                        name: entry.name,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error indicating that a component failed its [`Registration::validate`] check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidComponent {
+    /// Entity holding the invalid component
+    pub entity: Entity,
+    /// Registered name of the component's type
+    pub name: &'static str,
+}
+
+impl fmt::Display for InvalidComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "component {} of entity {:?} failed validation",
+            self.name, self.entity
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidComponent {}
+
+/// Error indicating that a component type isn't registered with the registry passed to
+/// [`Frame::set_strict_registry`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnregisteredComponent {
+    /// The unregistered type
+    pub ty: TypeInfo,
+}
+
+impl fmt::Display for UnregisteredComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ty.type_name() {
+            Some(name) => write!(f, "component type {} is not registered", name),
+            None => write!(f, "component type {:?} is not registered", self.ty.id()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UnregisteredComponent {}
+
+/// Component types that entities must have, e.g. those added to a save format since older saves
+/// were written
+///
+/// Entities lacking a required type are given its default, registered with
+/// [`Registration::default_with`] or [`Registration::defaultable`], by
+/// [`ComponentRegistry::fill_defaults`] and the `deserialize_with_defaults` functions of the
+/// [`serialize`](crate::serialize) modules. A schema covers every entity unless narrowed with
+/// [`scope`](Self::scope).
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    /// Types an entity must have to be covered
+    scope: Vec<TypeId>,
+    required: Vec<TypeId>,
+}
+
+impl Schema {
+    /// Create a schema requiring nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require covered entities to have a `T`
+    pub fn require<T: Component>(mut self) -> Self {
+        let ty = TypeId::of::<T>();
+        if !self.required.contains(&ty) {
+            self.required.push(ty);
+        }
+        self
+    }
+
+    /// Cover only entities having a `T`, e.g. a marker identifying a kind of entity
+    pub fn scope<T: Component>(mut self) -> Self {
+        let ty = TypeId::of::<T>();
+        if !self.scope.contains(&ty) {
+            self.scope.push(ty);
+        }
+        self
+    }
+}
+
+/// A component given its default value because a [`Schema`] required it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultedComponent {
+    /// Entity that lacked the component
+    pub entity: Entity,
+    /// Registered name of the component's type
+    pub name: &'static str,
+}
+
+/// # Safety
+///
+/// Values of type `info` must be safe to duplicate bitwise
+unsafe fn copy_column(info: TypeInfo, archetype: &Archetype, batch: &mut ColumnBatchBuilder) {
+    let state = archetype.get_state_dynamic(info.id()).unwrap();
+    archetype.borrow_raw(state);
+    let src = archetype
+        .get_dynamic(info.id(), info.layout().size(), 0)
+        .unwrap();
+    let copied = batch.extend_raw(info, src, archetype.len());
+    archetype.release_raw(state);
+    debug_assert!(copied);
+}
+
+/// Set of registered component types, produced by [`ComponentRegistry::archetype_mask`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ComponentMask(Vec<u64>);
+
+impl ComponentMask {
+    /// Whether the type with [`RegisteredComponent::index`] `index` is in the set
+    pub fn contains(&self, index: u32) -> bool {
+        self.0
+            .get(index as usize / 64)
+            .map_or(false, |word| word & (1 << (index % 64)) != 0)
+    }
+
+    /// Whether every type in `other` is in the set
+    pub fn contains_all(&self, other: &ComponentMask) -> bool {
+        other
+            .0
+            .iter()
+            .enumerate()
+            .all(|(i, &word)| self.0.get(i).copied().unwrap_or(0) & word == word)
+    }
+
+    /// The set as little-endian words of 64 bits, e.g. for upload to a GPU
+    ///
+    /// Bit `i % 64` of word `i / 64` is set if the type with index `i` is in the set.
+    pub fn words(&self) -> &[u64] {
+        &self.0
+    }
+}
+
+/// Identifier of a component type that, unlike [`TypeId`], is stable across builds and binaries
+///
+/// Derived from a name chosen by the application by a fixed hash function, so a plugin or a later
+/// build of the same program computes the same key for the same name. Assigned to types by
+/// [`Registration::key`] and resolved by [`ComponentRegistry::find_by_key`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ComponentKey(u64);
+
+impl ComponentKey {
+    /// Compute the key for `name`
+    pub const fn new(name: &str) -> Self {
+        // 64-bit FNV-1a
+        let bytes = name.as_bytes();
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let mut i = 0;
+        while i < bytes.len() {
+            hash ^= bytes[i] as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+            i += 1;
+        }
+        Self(hash)
+    }
+
+    /// Convert to a form convenient for passing outside of Rust
+    pub const fn to_bits(self) -> u64 {
+        self.0
+    }
+
+    /// Reconstruct a key previously produced by [`to_bits`](Self::to_bits)
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ComponentKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ComponentKey {
+    fn deserialize<D>(deserializer: D) -> Result<ComponentKey, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        u64::deserialize(deserializer).map(ComponentKey)
+    }
+}
+
+/// Runtime information about a component type recorded in a [`ComponentRegistry`]
+pub struct RegisteredComponent {
+    info: TypeInfo,
+    /// Position in registration order
+    index: u32,
+    name: &'static str,
+    key: Option<ComponentKey>,
+    debug: Option<unsafe fn(NonNull<u8>, &mut fmt::Formatter<'_>) -> fmt::Result>,
+    clone: Option<CloneColumn>,
+    /// Clones a single value, set along with `clone`
+    clone_one: Option<DynamicClone>,
+    map_entities: Option<unsafe fn(NonNull<u8>, &EntityMap)>,
+    validate: Option<Box<dyn Fn(NonNull<u8>) -> bool + Send + Sync>>,
+    default: Option<AddDefault>,
+    #[cfg(feature = "reflect")]
+    fields: Vec<Field>,
+}
+
+/// Accessor for a field of a component, recorded by [`Registration::field`]
+#[cfg(feature = "reflect")]
+struct Field {
+    name: &'static str,
+    ty: TypeId,
+    get: Box<dyn Fn(NonNull<u8>) -> NonNull<u8> + Send + Sync>,
+}
+
+/// Adds default values of a component type to a builder
+type AddDefault = Box<dyn Fn(DefaultTarget<'_>) + Send + Sync>;
+
+/// Destination of default values produced by an [`AddDefault`]
+pub(crate) enum DefaultTarget<'a> {
+    /// Add one value to an entity
+    Entity(&'a mut EntityBuilder),
+    /// Push values into the batch's column until it's full
+    #[cfg(feature = "column-serialize")]
+    Column(&'a mut ColumnBatchBuilder),
+}
+
+/// How [`ComponentRegistry::clone_frame`] duplicates a column
+#[derive(Copy, Clone)]
+enum CloneColumn {
+    /// Bitwise copy of the whole column
+    Copy,
+    /// Clone each component into the batch
+    Clone(fn(&Archetype, &mut ColumnBatchBuilder)),
+}
+
+impl RegisteredComponent {
+    /// Layout and drop information for the component type
+    pub fn type_info(&self) -> TypeInfo {
+        self.info
+    }
+
+    /// Position of the type in registration order, identifying it in [`ComponentMask`]s
+    ///
+    /// Stable for the lifetime of the registry.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Human-readable name of the component type
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Stable identifier of the component type, if one was assigned by [`Registration::key`]
+    pub fn key(&self) -> Option<ComponentKey> {
+        self.key
+    }
+
+    /// Format `component` using the type's [`Debug`](fmt::Debug) implementation
+    ///
+    /// Returns `None` if no `Debug` implementation was registered, or if `component` is of a
+    /// different type.
+    pub fn debug<'a>(&self, component: DynamicComponent<'a>) -> Option<impl fmt::Debug + 'a> {
+        let debug = self.debug?;
+        if component.type_info().id() != self.info.id() {
+            return None;
+        }
+        Some(DebugComponent { component, debug })
+    }
+
+    /// Whether values of the component type can be duplicated by
+    /// [`ComponentRegistry::clone_frame`] and [`ComponentRegistry::clone_entity_bundle`]
+    pub fn is_cloneable(&self) -> bool {
+        self.clone.is_some()
+    }
+
+    /// Whether the component type was registered with [`Registration::copyable`]
+    pub fn is_copyable(&self) -> bool {
+        matches!(self.clone, Some(CloneColumn::Copy))
+    }
+
+    /// Whether the component type was registered with [`Registration::map_entities`]
+    pub fn maps_entities(&self) -> bool {
+        self.map_entities.is_some()
+    }
+
+    /// Whether the component type has a default value, given to entities lacking it when a
+    /// [`Schema`] requires it
+    pub fn has_default(&self) -> bool {
+        self.default.is_some()
+    }
+
+    /// Add default values to `target`
+    ///
+    /// Panics if the type has no default.
+    pub(crate) fn add_default(&self, target: DefaultTarget<'_>) {
+        (self.default.as_ref().unwrap())(target);
+    }
+
+    /// Check `component` with the function passed to [`Registration::validate`]
+    ///
+    /// Returns `None` if no validator was registered, or if `component` is of a different type.
+    pub fn validate(&self, component: DynamicComponent<'_>) -> Option<bool> {
+        let validate = self.validate.as_ref()?;
+        if component.type_info().id() != self.info.id() {
+            return None;
+        }
+        Some(validate(component.as_ptr()))
+    }
+}
+
+/// Builder-style access to the registration of `T` in a [`ComponentRegistry`]
+pub struct Registration<'a, T> {
+    entry: &'a mut RegisteredComponent,
+    keys: &'a mut HashMap<ComponentKey, TypeId>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: Component> Registration<'a, T> {
+    /// See [`RegisteredComponent::index`]
+    pub fn index(&self) -> u32 {
+        self.entry.index
+    }
+
+    /// Set the name reported by [`RegisteredComponent::name`]
+    pub fn name(self, name: &'static str) -> Self {
+        self.entry.name = name;
+        self
+    }
+
+    /// Assign `T` the stable identifier [`ComponentKey::new(name)`](ComponentKey::new)
+    ///
+    /// Unlike [`TypeId`], the key is the same in every build of every binary, so it can identify
+    /// `T` in serialized data or across dynamic library boundaries. Replaces any key previously
+    /// assigned to `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is already assigned to a different type, e.g. because `name` is reused or,
+    /// far less likely, because two names hash to the same key.
+    pub fn key(self, name: &str) -> Self {
+        let key = ComponentKey::new(name);
+        let id = self.entry.info.id();
+        let existing = *self.keys.entry(key).or_insert(id);
+        assert!(
+            existing == id,
+            "component key {:?} of {} is already assigned to another type",
+            name,
+            type_name::<T>()
+        );
+        if let Some(old) = self.entry.key.replace(key) {
+            if old != key {
+                self.keys.remove(&old);
+            }
+        }
+        self
+    }
+
+    /// Record `T`'s [`Debug`](fmt::Debug) implementation
+    pub fn debug(self) -> Self
+    where
+        T: fmt::Debug,
+    {
+        self.entry.debug = Some(|ptr, f| unsafe { fmt::Debug::fmt(ptr.cast::<T>().as_ref(), f) });
+        self
+    }
+
+    /// Record `T`'s [`Clone`] implementation, allowing it to be duplicated by
+    /// [`ComponentRegistry::clone_frame`]
+    pub fn cloneable(self) -> Self
+    where
+        T: Clone,
+    {
+        self.entry.clone_one = Some(DynamicClone::new::<T>());
+        if self.entry.clone.is_none() {
+            self.entry.clone = Some(CloneColumn::Clone(|src, dest| {
+                let mut column = dest.writer::<T>().unwrap();
+                for component in &*src.get::<&T>().unwrap() {
+                    _ = column.push(component.clone());
+                }
+            }));
+        }
+        self
+    }
+
+    /// Record `T`'s [`MapEntities`] implementation, allowing [`ComponentRegistry::merge_frame`] to
+    /// rewrite the entity handles it contains
+    pub fn map_entities(self) -> Self
+    where
+        T: MapEntities,
+    {
+        self.entry.map_entities =
+            Some(|ptr, map| unsafe { ptr.cast::<T>().as_mut() }.map_entities(map));
+        self
+    }
+
+    /// Record that `T` is [`Copy`], allowing [`ComponentRegistry::clone_frame`] to duplicate
+    /// entire columns of it with a single memory copy
+    pub fn copyable(self) -> Self
+    where
+        T: Copy,
+    {
+        self.entry.clone = Some(CloneColumn::Copy);
+        self.entry.clone_one = Some(DynamicClone::new::<T>());
+        self
+    }
+
+    /// Record a check that every valid `T` satisfies, such as a value being in range
+    ///
+    /// Applied by [`Frame::validate_components`], and in debug builds whenever a component is
+    /// spawned or inserted into a frame given this registry with [`Frame::set_validator`].
+    pub fn validate(self, check: impl Fn(&T) -> bool + Send + Sync + 'static) -> Self {
+        self.entry.validate = Some(Box::new(move |ptr| {
+            check(unsafe { ptr.cast::<T>().as_ref() })
+        }));
+        self
+    }
+
+    /// Record a constructor for the value given to entities lacking `T` when a [`Schema`] requires
+    /// it, e.g. by [`ComponentRegistry::fill_defaults`]
+    pub fn default_with(self, construct: impl Fn() -> T + Send + Sync + 'static) -> Self {
+        self.entry.default = Some(Box::new(move |target| match target {
+            DefaultTarget::Entity(builder) => {
+                builder.add(construct());
+            }
+            #[cfg(feature = "column-serialize")]
+            DefaultTarget::Column(batch) => {
+                let mut writer = batch.writer::<T>().unwrap();
+                while writer.push(construct()).is_ok() {}
+            }
+        }));
+        self
+    }
+
+    /// Record an accessor for the field `name` of `T`, of type `F`, for use by
+    /// [`Frame::get_field_mut`]
+    ///
+    /// Nested fields are reached by also registering `F` and its fields.
+    #[cfg(feature = "reflect")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "reflect")))]
+    pub fn field<F: 'static>(self, name: &'static str, get: fn(&mut T) -> &mut F) -> Self {
+        let field = Field {
+            name,
+            ty: TypeId::of::<F>(),
+            get: Box::new(move |ptr| unsafe {
+                NonNull::from(get(ptr.cast::<T>().as_mut())).cast()
+            }),
+        };
+        match self.entry.fields.iter_mut().find(|x| x.name == name) {
+            Some(existing) => *existing = field,
+            None => self.entry.fields.push(field),
+        }
+        self
+    }
+
+    /// Record `T`'s [`Default`] implementation as its default, like
+    /// [`default_with`](Self::default_with)
+    pub fn defaultable(self) -> Self
+    where
+        T: Default,
+    {
+        self.default_with(T::default)
+    }
+}
+
+struct DebugComponent<'a> {
+    component: DynamicComponent<'a>,
+    debug: unsafe fn(NonNull<u8>, &mut fmt::Formatter<'_>) -> fmt::Result,
+}
+
+impl fmt::Debug for DebugComponent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Type was checked on construction
+        unsafe { (self.debug)(self.component.as_ptr(), f) }
+    }
+}
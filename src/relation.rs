@@ -0,0 +1,233 @@
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
+
+use core::any::TypeId;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+use crate::query::FetchRead;
+use crate::{Archetype, Component, Entity, Frame, Query, QueryShared, TypeInfo};
+
+/// A component which refers to another entity, e.g. a parent or a target
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// #[derive(Clone, PartialEq)]
+/// struct ChildOf(Entity);
+///
+/// impl Relation for ChildOf {
+///     fn target(&self) -> Entity {
+///         self.0
+///     }
+/// }
+/// ```
+pub trait Relation: Component + Clone + PartialEq {
+    /// The entity referred to
+    fn target(&self) -> Entity;
+}
+
+/// Index from each entity to the entities whose relations refer to it
+///
+/// Maintained by [`Frame`] as entities are spawned, despawned, and have components inserted or
+/// removed, so that [`Frame::referencing`] costs time proportional to its output rather than the
+/// number of holders. Relations written in place are picked up by [`refresh`](Self::refresh).
+#[derive(Default)]
+pub(crate) struct References {
+    /// Reads the target of each indexed relation type
+    kinds: Vec<(TypeInfo, ReadTarget)>,
+    /// Current target of each holder's relation of each indexed type
+    targets: HashMap<(Entity, TypeId), Entity>,
+    /// Holders of relations of each indexed type, keyed by target
+    holders: HashMap<Entity, Vec<(Entity, TypeId)>>,
+    /// Column version of each indexed relation type in each archetype when it was last scanned
+    synced: HashMap<(u32, TypeId), u32>,
+}
+
+/// Reads the target of a type-erased relation
+type ReadTarget = unsafe fn(NonNull<u8>) -> Entity;
+
+impl References {
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    /// Start indexing `R`, returning `false` if it already was
+    pub fn register<R: Relation>(&mut self) -> bool {
+        let ty = TypeInfo::of::<R>();
+        if self.kinds.iter().any(|&(x, _)| x == ty) {
+            return false;
+        }
+        self.kinds
+            .push((ty, |ptr| unsafe { ptr.cast::<R>().as_ref() }.target()));
+        true
+    }
+
+    /// Start indexing every type indexed by `other`, returning whether any wasn't already
+    pub fn register_all(&mut self, other: &References) -> bool {
+        let mut registered = false;
+        for &(ty, target) in &other.kinds {
+            if !self.indexes(ty.id()) {
+                self.kinds.push((ty, target));
+                registered = true;
+            }
+        }
+        registered
+    }
+
+    /// Account for `holder`'s components now being stored at `row`, or nowhere if despawned
+    ///
+    /// # Safety
+    ///
+    /// `row` must address a live entity, and no indexed column of its archetype may be uniquely
+    /// borrowed
+    pub unsafe fn update(&mut self, holder: Entity, row: Option<(&Archetype, u32)>) {
+        for &(ty, target) in &self.kinds {
+            let new = row.and_then(|(archetype, index)| {
+                let ptr = archetype.get_dynamic(ty.id(), ty.layout().size(), index)?;
+                Some(target(ptr))
+            });
+            let key = (holder, ty.id());
+            let old = self.targets.get(&key).copied();
+            if old == new {
+                continue;
+            }
+            if let Some(old) = old {
+                self.targets.remove(&key);
+                Self::unlink(&mut self.holders, old, key);
+            }
+            if let Some(new) = new {
+                self.targets.insert(key, new);
+                self.holders.entry(new).or_default().push(key);
+            }
+        }
+    }
+
+    /// Rescan the archetypes whose indexed relation columns were written since they were last
+    /// scanned, e.g. by a query for `&mut R`, resolving stored ids to entities with `resolve`
+    ///
+    /// # Safety
+    ///
+    /// Every id stored in `archetypes` must belong to a live entity, and no indexed column may be
+    /// uniquely borrowed
+    pub unsafe fn refresh(&mut self, archetypes: &[Archetype], resolve: impl Fn(u32) -> Entity) {
+        for (id, archetype) in archetypes.iter().enumerate() {
+            let mut stale = false;
+            for &(ty, _) in &self.kinds {
+                let Some(version) = archetype.column_version_dynamic(ty.id()) else {
+                    continue;
+                };
+                stale |= self.synced.insert((id as u32, ty.id()), version) != Some(version);
+            }
+            if !stale {
+                continue;
+            }
+            for (index, &holder) in archetype.ids().iter().enumerate() {
+                self.update(resolve(holder), Some((archetype, index as u32)));
+            }
+        }
+    }
+
+    fn unlink(
+        holders: &mut HashMap<Entity, Vec<(Entity, TypeId)>>,
+        target: Entity,
+        key: (Entity, TypeId),
+    ) {
+        if let Some(list) = holders.get_mut(&target) {
+            if let Some(i) = list.iter().position(|&x| x == key) {
+                list.swap_remove(i);
+            }
+            if list.is_empty() {
+                holders.remove(&target);
+            }
+        }
+    }
+
+    /// Account for every entity being despawned
+    pub fn clear(&mut self) {
+        self.targets.clear();
+        self.holders.clear();
+    }
+
+    /// Account for the entity `old` being renumbered to `new`
+    pub fn remap(&mut self, old: Entity, new: Entity) {
+        if self.is_empty() {
+            return;
+        }
+        // As a holder
+        for &(ty, _) in &self.kinds {
+            let Some(target) = self.targets.remove(&(old, ty.id())) else {
+                continue;
+            };
+            self.targets.insert((new, ty.id()), target);
+            if let Some(list) = self.holders.get_mut(&target) {
+                for key in list.iter_mut().filter(|key| **key == (old, ty.id())) {
+                    key.0 = new;
+                }
+            }
+        }
+        // As a target
+        if let Some(list) = self.holders.remove(&old) {
+            for &key in &list {
+                self.targets.insert(key, new);
+            }
+            self.holders.insert(new, list);
+        }
+    }
+
+    /// Whether relations of type `ty` are indexed
+    pub fn indexes(&self, ty: TypeId) -> bool {
+        self.kinds.iter().any(|(x, _)| x.id() == ty)
+    }
+
+    /// Entities holding an indexed relation that refers to `target`, and the relation's type
+    pub fn holders(&self, target: Entity) -> impl ExactSizeIterator<Item = (Entity, TypeId)> + '_ {
+        self.holders
+            .get(&target)
+            .map_or(&[][..], |x| &x[..])
+            .iter()
+            .copied()
+    }
+
+    /// Entities holding an indexed relation that refers to `target`
+    pub fn referencing(&self, target: Entity) -> impl ExactSizeIterator<Item = Entity> + '_ {
+        self.holders(target).map(|(holder, _)| holder)
+    }
+}
+
+/// Query yielding the target of each entity's `R` relation along with the result of `Q`
+///
+/// Only entities having an `R` component are matched. To visit only the holders of relations
+/// referring to one entity, e.g. the children of a parent, find them with [`Frame::related`],
+/// which uses the reverse index when `R` is indexed. See [`Frame::attach`] for an example.
+pub struct Related<Q, R>(PhantomData<(Q, fn() -> R)>);
+
+impl<Q: Query, R: Relation> Query for Related<Q, R> {
+    type Item<'q> = (Entity, Q::Item<'q>);
+
+    type Fetch = (FetchRead<R>, Q::Fetch);
+
+    unsafe fn get<'q>(fetch: &Self::Fetch, n: usize) -> Self::Item<'q> {
+        (<&R>::get(&fetch.0, n).target(), Q::get(&fetch.1, n))
+    }
+
+    const FILTERED: bool = Q::FILTERED;
+
+    unsafe fn filter(fetch: &Self::Fetch, n: usize) -> bool {
+        Q::filter(&fetch.1, n)
+    }
+}
+
+unsafe impl<Q: QueryShared, R> QueryShared for Related<Q, R> {}
+
+/// Remove the `R` components of every entity other than `target` whose relation targets `target`
+pub(crate) fn detach_from<R: Relation>(frame: &mut Frame, target: Entity) {
+    let holders = frame
+        .related::<R>(target)
+        .filter(|&holder| holder != target)
+        .collect::<Vec<_>>();
+    for holder in holders {
+        let _ = frame.remove_one::<R>(holder);
+    }
+}
@@ -14,16 +14,23 @@
 //! identified by the corresponding component ID.
 
 use crate::alloc::vec::Vec;
-use core::{any::type_name, cell::RefCell, fmt, marker::PhantomData};
+use core::{
+    any::{type_name, TypeId},
+    cell::RefCell,
+    fmt,
+    marker::PhantomData,
+};
 
 use serde::{
-    de::{self, DeserializeSeed, SeqAccess, Unexpected, Visitor},
-    ser::{SerializeSeq, SerializeTuple},
+    de::{self, DeserializeOwned, DeserializeSeed, SeqAccess, Unexpected, Visitor},
+    ser::{self, SerializeSeq, SerializeTuple},
     Deserialize, Deserializer, Serialize, Serializer,
 };
 
+use crate::registry::DefaultTarget;
 use crate::{
-    Archetype, ColumnBatch, ColumnBatchBuilder, ColumnBatchType, Component, Entity, Frame, Query,
+    Archetype, ColumnBatch, ColumnBatchBuilder, ColumnBatchType, Component, ComponentKey,
+    ComponentRegistry, DefaultedComponent, Entity, Frame, Query, Schema,
 };
 
 /// Implements serialization of archetypes
@@ -122,6 +129,50 @@ where
     Ok(())
 }
 
+/// If `archetype` has `T` components, serialize the [`ComponentKey`] assigned to `T` in `registry`
+/// into `out`
+///
+/// Unlike an application-defined ID passed to [`try_serialize_id`], keys also identify types
+/// registered by plugins. Resolve them when deserializing with [`add_keyed`].
+///
+/// Returns `Err` if `T` has no key in `registry`.
+pub fn try_serialize_key<T, S>(
+    registry: &ComponentRegistry,
+    archetype: &Archetype,
+    out: &mut S,
+) -> Result<(), S::Error>
+where
+    T: Component,
+    S: SerializeTuple,
+{
+    if !archetype.has::<T>() {
+        return Ok(());
+    }
+    let key = registry
+        .get(TypeId::of::<T>())
+        .and_then(|entry| entry.key())
+        .ok_or_else(|| {
+            <S::Error as ser::Error>::custom(format_args!("{} has no key", type_name::<T>()))
+        })?;
+    out.serialize_element(&key)
+}
+
+/// Add the component type assigned `key` in `registry` to `batch`, returning the type's ID
+///
+/// Useful for implementing [`DeserializeContext::deserialize_component_ids()`] for IDs written by
+/// [`try_serialize_key`]. Returns `Err` if no type is assigned `key`.
+pub fn add_keyed<E: de::Error>(
+    registry: &ComponentRegistry,
+    key: ComponentKey,
+    batch: &mut ColumnBatchType,
+) -> Result<TypeId, E> {
+    let entry = registry
+        .find_by_key(key)
+        .ok_or_else(|| E::custom(format_args!("unknown component key {:?}", key)))?;
+    batch.add_dynamic(entry.type_info());
+    Ok(entry.type_info().id())
+}
+
 /// If `archetype` has `T` components, serialize them into `out`
 ///
 /// Useful for implementing [`SerializeContext::serialize_components()`].
@@ -136,6 +187,67 @@ where
     Ok(())
 }
 
+/// Whole-column transformation of `T` components for serialization, e.g. quantization or delta
+/// encoding
+///
+/// Operating on entire columns at once allows codecs to exploit similarity between neighboring
+/// components and to run at memory bandwidth. Apply codecs with [`try_serialize_encoded()`] and
+/// [`deserialize_column_encoded()`], typically selected by a [`SerializeContext`] and
+/// [`DeserializeContext`] according to component type.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::serialize::column::*;
+/// struct Position([f32; 3]);
+///
+/// /// Stores positions to the nearest centimeter
+/// struct Centimeters;
+///
+/// impl ColumnCodec<Position> for Centimeters {
+///     type Encoded = Vec<[i32; 3]>;
+///
+///     fn encode(&self, column: &[Position]) -> Self::Encoded {
+///         column.iter().map(|p| p.0.map(|x| (x * 100.0).round() as i32)).collect()
+///     }
+///
+///     fn decode(&self, encoded: Self::Encoded) -> Vec<Position> {
+///         encoded.into_iter().map(|p| Position(p.map(|x| x as f32 / 100.0))).collect()
+///     }
+/// }
+/// ```
+pub trait ColumnCodec<T> {
+    /// Serialized representation of a column
+    type Encoded: Serialize + DeserializeOwned;
+
+    /// Encode the components of a single archetype
+    fn encode(&self, column: &[T]) -> Self::Encoded;
+
+    /// Reconstruct components from the output of [`encode`](Self::encode)
+    ///
+    /// Deserialization fails if the number of components returned differs from the number encoded.
+    fn decode(&self, encoded: Self::Encoded) -> Vec<T>;
+}
+
+/// If `archetype` has `T` components, encode them with `codec` and serialize the result into `out`
+///
+/// Useful for implementing [`SerializeContext::serialize_components()`]. Must be paired with
+/// [`deserialize_column_encoded()`] using an equivalent codec.
+pub fn try_serialize_encoded<T, C, S>(
+    archetype: &Archetype,
+    codec: &C,
+    out: &mut S,
+) -> Result<(), S::Error>
+where
+    T: Component,
+    C: ColumnCodec<T> + ?Sized,
+    S: SerializeTuple,
+{
+    if let Some(xs) = archetype.get::<&T>() {
+        out.serialize_element(&codec.encode(&xs))?;
+    }
+    Ok(())
+}
+
 /// Serialize components from `collection` into a single element of `out`
 fn serialize_collection<I, S>(collection: I, out: &mut S) -> Result<(), S::Error>
 where
@@ -412,6 +524,53 @@ where
         })
 }
 
+/// Deserialize a column of `entity_count` `T`s encoded by `codec` from `seq` into `out`
+///
+/// See [`try_serialize_encoded()`].
+pub fn deserialize_column_encoded<'de, T, C, A>(
+    codec: &C,
+    entity_count: u32,
+    seq: &mut A,
+    out: &mut ColumnBatchBuilder,
+) -> Result<(), A::Error>
+where
+    T: Component,
+    C: ColumnCodec<T> + ?Sized,
+    A: SeqAccess<'de>,
+{
+    let encoded = seq.next_element::<C::Encoded>()?.ok_or_else(|| {
+        de::Error::invalid_value(
+            Unexpected::Other("end of components"),
+            &"a column of components",
+        )
+    })?;
+    let components = codec.decode(encoded);
+    if components.len() != entity_count as usize {
+        return Err(de::Error::invalid_length(
+            components.len(),
+            &ExpectedComponents::<T>(entity_count, PhantomData),
+        ));
+    }
+    let mut out = out.writer::<T>().expect("unexpected component type");
+    for component in components {
+        if out.push(component).is_err() {
+            return Err(de::Error::invalid_value(
+                Unexpected::Other("extra component"),
+                &ExpectedComponents::<T>(entity_count, PhantomData),
+            ));
+        }
+    }
+    Ok(())
+}
+
+struct ExpectedComponents<T>(u32, PhantomData<fn() -> T>);
+
+impl<T> de::Expected for ExpectedComponents<T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a set of {} {} values", self.0, type_name::<T>())
+    }
+}
+
 /// Deserializer for a single component type, for use in [`DeserializeContext::deserialize_components()`]
 struct DeserializeColumn<'a, T> {
     entity_count: u32,
@@ -496,15 +655,54 @@ where
 }
 
 /// Deserialize a [`Frame`] with a [`DeserializeContext`] and a [`Deserializer`]
+///
+/// Entities keep the handles they were serialized with. To load them into an existing frame
+/// instead, e.g. to instantiate a saved prefab, pass the result to
+/// [`ComponentRegistry::merge_frame`](crate::ComponentRegistry::merge_frame), which assigns new
+/// handles and rewrites references between the loaded entities through
+/// [`MapEntities`](crate::MapEntities).
 pub fn deserialize<'de, C, D>(context: &mut C, deserializer: D) -> Result<Frame, D::Error>
 where
     C: DeserializeContext,
     D: Deserializer<'de>,
 {
-    deserializer.deserialize_seq(FrameVisitor(context))
+    deserializer.deserialize_seq(FrameVisitor(context, None))
 }
 
-struct FrameVisitor<'a, C>(&'a mut C);
+/// Like [`deserialize`], but gives each archetype the columns `schema` requires that it was saved
+/// without, filled with their defaults registered in `registry`
+///
+/// Lets saves written before a component was added to the schema load rather than leaving entities
+/// incomplete. Returns the frame along with a report of each component filled in.
+///
+/// # Panics
+///
+/// Panics if a type required by `schema` has no default registered in `registry`.
+pub fn deserialize_with_defaults<'de, C, D>(
+    context: &mut C,
+    registry: &ComponentRegistry,
+    schema: &Schema,
+    deserializer: D,
+) -> Result<(Frame, Vec<DefaultedComponent>), D::Error>
+where
+    C: DeserializeContext,
+    D: Deserializer<'de>,
+{
+    registry.check_schema(schema);
+    let mut report = Vec::new();
+    let frame = deserializer
+        .deserialize_seq(FrameVisitor(context, Some((registry, schema, &mut report))))?;
+    Ok((frame, report))
+}
+
+/// Registry and schema to fill in missing columns from, and the report of components filled in
+type Defaults<'a> = (
+    &'a ComponentRegistry,
+    &'a Schema,
+    &'a mut Vec<DefaultedComponent>,
+);
+
+struct FrameVisitor<'a, C>(&'a mut C, Option<Defaults<'a>>);
 
 impl<'de, 'a, C> Visitor<'de> for FrameVisitor<'a, C>
 where
@@ -522,9 +720,12 @@ where
     {
         let mut frame = Frame::new();
         let mut entities = Vec::new();
-        while let Some(bundle) =
-            seq.next_element_seed(DeserializeArchetype(self.0, &mut entities))?
-        {
+        let mut defaults = self.1;
+        while let Some(bundle) = seq.next_element_seed(DeserializeArchetype(
+            self.0,
+            &mut entities,
+            defaults.as_mut(),
+        ))? {
             frame.spawn_column_batch_at(&entities, bundle);
             entities.clear();
         }
@@ -532,9 +733,13 @@ where
     }
 }
 
-struct DeserializeArchetype<'a, C>(&'a mut C, &'a mut Vec<Entity>);
+struct DeserializeArchetype<'a, 'b, C>(
+    &'a mut C,
+    &'a mut Vec<Entity>,
+    Option<&'a mut Defaults<'b>>,
+);
 
-impl<'de, 'a, C> DeserializeSeed<'de> for DeserializeArchetype<'a, C>
+impl<'de, 'a, 'b, C> DeserializeSeed<'de> for DeserializeArchetype<'a, 'b, C>
 where
     C: DeserializeContext,
 {
@@ -544,13 +749,13 @@ where
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_tuple(4, ArchetypeVisitor(self.0, self.1))
+        deserializer.deserialize_tuple(4, ArchetypeVisitor(self.0, self.1, self.2))
     }
 }
 
-struct ArchetypeVisitor<'a, C>(&'a mut C, &'a mut Vec<Entity>);
+struct ArchetypeVisitor<'a, 'b, C>(&'a mut C, &'a mut Vec<Entity>, Option<&'a mut Defaults<'b>>);
 
-impl<'de, 'a, C> Visitor<'de> for ArchetypeVisitor<'a, C>
+impl<'de, 'a, 'b, C> Visitor<'de> for ArchetypeVisitor<'a, 'b, C>
 where
     C: DeserializeContext,
 {
@@ -571,9 +776,16 @@ where
             .next_element::<u32>()?
             .ok_or_else(|| de::Error::invalid_length(1, &self))?;
         self.1.reserve(entity_count as usize);
-        let ty = seq
+        let mut ty = seq
             .next_element_seed(DeserializeComponentIds(self.0, component_count))?
             .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let missing = match self.2 {
+            Some((registry, schema, _)) => registry.missing_defaults(schema, |x| ty.has_dynamic(x)),
+            None => Vec::new(),
+        };
+        for entry in &missing {
+            ty.add_dynamic(entry.type_info());
+        }
         let mut batch = ty.into_batch(entity_count);
         seq.next_element_seed(DeserializeComponents {
             ctx: self.0,
@@ -583,6 +795,15 @@ where
             out: &mut batch,
         })?
         .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+        if let Some((_, _, report)) = self.2 {
+            for entry in missing {
+                entry.add_default(DefaultTarget::Column(&mut batch));
+                report.extend(self.1.iter().map(|&entity| DefaultedComponent {
+                    entity,
+                    name: entry.name(),
+                }));
+            }
+        }
         batch.build().map_err(|_| {
             de::Error::invalid_value(
                 Unexpected::Other("incomplete archetype"),
@@ -773,7 +994,7 @@ mod tests {
     impl<Q> PartialEq for SerFrame<Q> {
         fn eq(&self, other: &Self) -> bool {
             fn same_components<T: Component + PartialEq>(x: &EntityRef, y: &EntityRef) -> bool {
-                x.get::<&T>().as_ref().map(|x| &**x) == y.get::<&T>().as_ref().map(|x| &**x)
+                x.get::<&T>().as_deref() == y.get::<&T>().as_deref()
             }
 
             for (x, y) in self.0.iter().zip(other.0.iter()) {
@@ -808,7 +1029,7 @@ mod tests {
 
     impl<'a, Q: Query> Serialize for SerFrameInner<'a, Q> {
         fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
-            helpers::serialize::<Q, S>(&self.0, s)
+            helpers::serialize::<Q, S>(self.0, s)
         }
     }
 
@@ -1051,4 +1272,423 @@ mod tests {
             Token::TupleStructEnd,
         ])
     }
+
+    /// Stores positions to the nearest centimeter
+    struct Centimeters;
+
+    impl ColumnCodec<Position> for Centimeters {
+        type Encoded = Vec<[i32; 3]>;
+
+        fn encode(&self, column: &[Position]) -> Self::Encoded {
+            column
+                .iter()
+                .map(|p| p.0.map(|x| (x * 100.0) as i32))
+                .collect()
+        }
+
+        fn decode(&self, encoded: Self::Encoded) -> Vec<Position> {
+            encoded
+                .into_iter()
+                .map(|p| Position(p.map(|x| x as f32 / 100.0)))
+                .collect()
+        }
+    }
+
+    struct QuantizedContext;
+
+    impl SerializeContext for QuantizedContext {
+        fn component_count(&self, archetype: &Archetype) -> usize {
+            archetype.has::<Position>() as usize
+        }
+
+        fn serialize_component_ids<S: SerializeTuple>(
+            &mut self,
+            archetype: &Archetype,
+            mut out: S,
+        ) -> Result<S::Ok, S::Error> {
+            try_serialize_id::<Position, _, _>(archetype, &ComponentId::Position, &mut out)?;
+            out.end()
+        }
+
+        fn serialize_components<S: SerializeTuple>(
+            &mut self,
+            archetype: &Archetype,
+            mut out: S,
+        ) -> Result<S::Ok, S::Error> {
+            try_serialize_encoded::<Position, _, _>(archetype, &Centimeters, &mut out)?;
+            out.end()
+        }
+    }
+
+    impl DeserializeContext for QuantizedContext {
+        fn deserialize_component_ids<'de, A>(
+            &mut self,
+            mut seq: A,
+        ) -> Result<ColumnBatchType, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut batch = ColumnBatchType::new();
+            while seq.next_element::<ComponentId>()?.is_some() {
+                batch.add::<Position>();
+            }
+            Ok(batch)
+        }
+
+        fn deserialize_components<'de, A>(
+            &mut self,
+            entity_count: u32,
+            mut seq: A,
+            batch: &mut ColumnBatchBuilder,
+        ) -> Result<(), A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            if batch.writer::<Position>().is_some() {
+                deserialize_column_encoded::<Position, _, _>(
+                    &Centimeters,
+                    entity_count,
+                    &mut seq,
+                    batch,
+                )?;
+            }
+            Ok(())
+        }
+    }
+
+    struct QuantizedFrame(Frame);
+
+    impl Serialize for QuantizedFrame {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            serialize(&self.0, &mut QuantizedContext, s)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for QuantizedFrame {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            deserialize(&mut QuantizedContext, d).map(QuantizedFrame)
+        }
+    }
+
+    impl PartialEq for QuantizedFrame {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.len() == other.0.len()
+                && self.0.iter().zip(other.0.iter()).all(|(x, y)| {
+                    x.entity() == y.entity()
+                        && x.get::<&Position>().as_deref() == y.get::<&Position>().as_deref()
+                })
+        }
+    }
+
+    impl fmt::Debug for QuantizedFrame {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_map()
+                .entries(
+                    self.0
+                        .iter()
+                        .map(|e| (e.entity(), e.get::<&Position>().map(|x| *x))),
+                )
+                .finish()
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn encoded_roundtrip() {
+        use serde_test::{Token, assert_tokens};
+
+        let mut frame = Frame::new();
+        let e0 = frame.spawn((Position([0.5, 1.0, -2.0]),));
+
+        assert_tokens(&QuantizedFrame(frame), &[
+            Token::Seq { len: Some(1) },
+
+            Token::Tuple { len: 4 },
+            Token::U32(1),
+            Token::U32(1),
+            Token::Tuple { len: 1 },
+            Token::UnitVariant { name: "ComponentId", variant: "Position" },
+            Token::TupleEnd,
+            Token::Tuple { len: 2 },
+            Token::Tuple { len: 1 },
+            Token::U64(e0.to_bits().into()),
+            Token::TupleEnd,
+            Token::Seq { len: Some(1) },
+            Token::Tuple { len: 3 },
+            Token::I32(50),
+            Token::I32(100),
+            Token::I32(-200),
+            Token::TupleEnd,
+            Token::SeqEnd,
+            Token::TupleEnd,
+            Token::TupleEnd,
+
+            Token::SeqEnd,
+        ])
+    }
+
+    /// Identifies components by the keys assigned in `registry`
+    struct KeyedContext {
+        registry: ComponentRegistry,
+        components: Vec<TypeId>,
+    }
+
+    impl KeyedContext {
+        fn new() -> Self {
+            let mut registry = ComponentRegistry::new();
+            registry.register::<Position>().key("Position");
+            registry.register::<Velocity>().key("Velocity");
+            Self {
+                registry,
+                components: Vec::new(),
+            }
+        }
+    }
+
+    impl SerializeContext for KeyedContext {
+        fn component_count(&self, archetype: &Archetype) -> usize {
+            archetype.has::<Position>() as usize + archetype.has::<Velocity>() as usize
+        }
+
+        fn serialize_component_ids<S: SerializeTuple>(
+            &mut self,
+            archetype: &Archetype,
+            mut out: S,
+        ) -> Result<S::Ok, S::Error> {
+            try_serialize_key::<Position, _>(&self.registry, archetype, &mut out)?;
+            try_serialize_key::<Velocity, _>(&self.registry, archetype, &mut out)?;
+            out.end()
+        }
+
+        fn serialize_components<S: SerializeTuple>(
+            &mut self,
+            archetype: &Archetype,
+            mut out: S,
+        ) -> Result<S::Ok, S::Error> {
+            try_serialize::<Position, _>(archetype, &mut out)?;
+            try_serialize::<Velocity, _>(archetype, &mut out)?;
+            out.end()
+        }
+    }
+
+    impl DeserializeContext for KeyedContext {
+        fn deserialize_component_ids<'de, A>(
+            &mut self,
+            mut seq: A,
+        ) -> Result<ColumnBatchType, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            self.components.clear();
+            let mut batch = ColumnBatchType::new();
+            while let Some(key) = seq.next_element()? {
+                let id = add_keyed(&self.registry, key, &mut batch)?;
+                self.components.push(id);
+            }
+            Ok(batch)
+        }
+
+        fn deserialize_components<'de, A>(
+            &mut self,
+            entity_count: u32,
+            mut seq: A,
+            batch: &mut ColumnBatchBuilder,
+        ) -> Result<(), A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            for &id in &self.components {
+                if id == TypeId::of::<Position>() {
+                    deserialize_column::<Position, _>(entity_count, &mut seq, batch)?;
+                } else {
+                    deserialize_column::<Velocity, _>(entity_count, &mut seq, batch)?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    struct KeyedFrame(Frame);
+
+    impl Serialize for KeyedFrame {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            serialize(&self.0, &mut KeyedContext::new(), s)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for KeyedFrame {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            deserialize(&mut KeyedContext::new(), d).map(KeyedFrame)
+        }
+    }
+
+    impl PartialEq for KeyedFrame {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.len() == other.0.len()
+                && self.0.iter().zip(other.0.iter()).all(|(x, y)| {
+                    x.entity() == y.entity()
+                        && x.get::<&Position>().as_deref() == y.get::<&Position>().as_deref()
+                        && x.get::<&Velocity>().as_deref() == y.get::<&Velocity>().as_deref()
+                })
+        }
+    }
+
+    impl fmt::Debug for KeyedFrame {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_map()
+                .entries(self.0.iter().map(|e| {
+                    (
+                        e.entity(),
+                        (
+                            e.get::<&Position>().map(|x| *x),
+                            e.get::<&Velocity>().map(|x| *x),
+                        ),
+                    )
+                }))
+                .finish()
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn keyed_roundtrip() {
+        use serde_test::{Token, assert_tokens, assert_de_tokens_error};
+
+        let mut frame = Frame::new();
+        let e0 = frame.spawn((Velocity([1.0, 2.0, 3.0]),));
+
+        assert_tokens(&KeyedFrame(frame), &[
+            Token::Seq { len: Some(1) },
+
+            Token::Tuple { len: 4 },
+            Token::U32(1),
+            Token::U32(1),
+            Token::Tuple { len: 1 },
+            Token::U64(ComponentKey::new("Velocity").to_bits()),
+            Token::TupleEnd,
+            Token::Tuple { len: 2 },
+            Token::Tuple { len: 1 },
+            Token::U64(e0.to_bits().into()),
+            Token::TupleEnd,
+            Token::Tuple { len: 1 },
+            Token::NewtypeStruct { name: "Velocity" },
+            Token::Tuple { len: 3 },
+            Token::F32(1.0),
+            Token::F32(2.0),
+            Token::F32(3.0),
+            Token::TupleEnd,
+            Token::TupleEnd,
+            Token::TupleEnd,
+            Token::TupleEnd,
+
+            Token::SeqEnd,
+        ]);
+
+        let unknown = ComponentKey::new("Mana");
+        assert_de_tokens_error::<KeyedFrame>(&[
+            Token::Seq { len: Some(1) },
+            Token::Tuple { len: 4 },
+            Token::U32(1),
+            Token::U32(1),
+            Token::Tuple { len: 1 },
+            Token::U64(unknown.to_bits()),
+        ], &alloc::format!("unknown component key {:?}", unknown));
+    }
+
+    /// Frame deserialized with default velocities, and the report of those filled in
+    struct DefaultedFrame(Frame, Vec<DefaultedComponent>);
+
+    impl<'de> Deserialize<'de> for DefaultedFrame {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let mut registry = ComponentRegistry::new();
+            registry
+                .register::<Velocity>()
+                .default_with(|| Velocity([0.0; 3]));
+            let schema = Schema::new().scope::<Position>().require::<Velocity>();
+            let (frame, report) =
+                deserialize_with_defaults(&mut Context::default(), &registry, &schema, d)?;
+            Ok(DefaultedFrame(frame, report))
+        }
+    }
+
+    impl PartialEq for DefaultedFrame {
+        fn eq(&self, other: &Self) -> bool {
+            self.1 == other.1
+                && self.0.len() == other.0.len()
+                && self.0.iter().zip(other.0.iter()).all(|(x, y)| {
+                    x.entity() == y.entity()
+                        && x.get::<&Position>().as_deref() == y.get::<&Position>().as_deref()
+                        && x.get::<&Velocity>().as_deref() == y.get::<&Velocity>().as_deref()
+                })
+        }
+    }
+
+    impl fmt::Debug for DefaultedFrame {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let entities = self.0.iter().map(|e| {
+                (
+                    e.entity(),
+                    (
+                        e.get::<&Position>().map(|x| *x),
+                        e.get::<&Velocity>().map(|x| *x),
+                    ),
+                )
+            });
+            f.debug_tuple("DefaultedFrame")
+                .field(&entities.collect::<Vec<_>>())
+                .field(&self.1)
+                .finish()
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn fill_defaults() {
+        use serde_test::{Token, assert_de_tokens};
+
+        let mut expected = Frame::new();
+        let p0 = Position([1.0, 2.0, 3.0]);
+        let p1 = Position([4.0, 5.0, 6.0]);
+        let e0 = expected.spawn((p0, Velocity([0.0; 3])));
+        let e1 = expected.spawn((p1, Velocity([0.0; 3])));
+        let velocity = core::any::type_name::<Velocity>();
+        let report = alloc::vec![
+            DefaultedComponent { entity: e0, name: velocity },
+            DefaultedComponent { entity: e1, name: velocity },
+        ];
+
+        assert_de_tokens(&DefaultedFrame(expected, report), &[
+            Token::Seq { len: Some(1) },
+
+            Token::Tuple { len: 4 },
+            Token::U32(2),
+            Token::U32(1),
+            Token::Tuple { len: 1 },
+            Token::UnitVariant { name: "ComponentId", variant: "Position" },
+            Token::TupleEnd,
+            Token::Tuple { len: 2 },
+            Token::Tuple { len: 2 },
+            Token::U64(e0.to_bits().into()),
+            Token::U64(e1.to_bits().into()),
+            Token::TupleEnd,
+            Token::Tuple { len: 2 },
+            Token::NewtypeStruct { name: "Position" },
+            Token::Tuple { len: 3 },
+            Token::F32(1.0),
+            Token::F32(2.0),
+            Token::F32(3.0),
+            Token::TupleEnd,
+            Token::NewtypeStruct { name: "Position" },
+            Token::Tuple { len: 3 },
+            Token::F32(4.0),
+            Token::F32(5.0),
+            Token::F32(6.0),
+            Token::TupleEnd,
+            Token::TupleEnd,
+            Token::TupleEnd,
+            Token::TupleEnd,
+
+            Token::SeqEnd,
+        ]);
+    }
 }
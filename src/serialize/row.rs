@@ -9,15 +9,23 @@
 //! In terms of the serde data model, we treat a [`Frame`] as a map of entity IDs to user-controlled
 //! maps of component IDs to data.
 
-use core::{cell::RefCell, fmt};
+use core::{
+    any::{type_name, TypeId},
+    cell::RefCell,
+    fmt,
+};
 
 use serde::{
     de::{DeserializeSeed, MapAccess, Visitor},
-    ser::SerializeMap,
+    ser::{self, SerializeMap},
     Deserializer, Serialize, Serializer,
 };
 
-use crate::{Component, EntityBuilder, EntityRef, Frame, Query};
+use crate::alloc::vec::Vec;
+use crate::{
+    Component, ComponentRegistry, DefaultedComponent, EntityBuilder, EntityRef, Frame, Query,
+    Schema,
+};
 
 /// Implements serialization of individual entities
 ///
@@ -89,6 +97,33 @@ pub fn try_serialize<T: Component + Serialize, K: Serialize + ?Sized, S: Seriali
     Ok(())
 }
 
+/// If `entity` has component `T`, serialize it in `map` under the [`ComponentKey`] assigned to `T`
+/// in `registry`
+///
+/// Keys identify types consistently across builds and binaries. When deserializing, read the key as
+/// a [`ComponentKey`] and resolve it with [`ComponentRegistry::find_by_key`].
+///
+/// Returns `Err` if `T` has no key in `registry`.
+///
+/// [`ComponentKey`]: crate::ComponentKey
+pub fn try_serialize_keyed<T: Component + Serialize, S: SerializeMap>(
+    entity: &EntityRef<'_>,
+    registry: &ComponentRegistry,
+    map: &mut S,
+) -> Result<(), S::Error> {
+    let Some(x) = entity.get::<&T>() else {
+        return Ok(());
+    };
+    let key = registry
+        .get(TypeId::of::<T>())
+        .and_then(|entry| entry.key())
+        .ok_or_else(|| {
+            <S::Error as ser::Error>::custom(format_args!("{} has no key", type_name::<T>()))
+        })?;
+    map.serialize_key(&key)?;
+    map.serialize_value(&*x)
+}
+
 /// Serialize a [`Frame`] through a [`SerializeContext`] to a [`Serializer`]
 // Note: deliberately not implemented in terms of `serialize_satisying::<(), _, _>` to avoid an
 // extra loop over the archetypes
@@ -145,12 +180,44 @@ impl<'a, C: SerializeContext> Serialize for SerializeComponents<'a, C> {
 }
 
 /// Deserialize a [`Frame`] with a [`DeserializeContext`] and a [`Deserializer`]
+///
+/// Entities keep the handles they were serialized with. To load them into an existing frame
+/// instead, e.g. to instantiate a saved prefab, pass the result to
+/// [`ComponentRegistry::merge_frame`](crate::ComponentRegistry::merge_frame), which assigns new
+/// handles and rewrites references between the loaded entities through
+/// [`MapEntities`](crate::MapEntities).
 pub fn deserialize<'de, C, D>(context: &mut C, deserializer: D) -> Result<Frame, D::Error>
 where
     C: DeserializeContext,
     D: Deserializer<'de>,
 {
-    deserializer.deserialize_map(FrameVisitor(context))
+    deserializer.deserialize_map(FrameVisitor(context, None))
+}
+
+/// Like [`deserialize`], but gives each entity the components `schema` requires that it was saved
+/// without, using their defaults registered in `registry`
+///
+/// Lets saves written before a component was added to the schema load rather than leaving entities
+/// incomplete. Returns the frame along with a report of each component filled in.
+///
+/// # Panics
+///
+/// Panics if a type required by `schema` has no default registered in `registry`.
+pub fn deserialize_with_defaults<'de, C, D>(
+    context: &mut C,
+    registry: &ComponentRegistry,
+    schema: &Schema,
+    deserializer: D,
+) -> Result<(Frame, Vec<DefaultedComponent>), D::Error>
+where
+    C: DeserializeContext,
+    D: Deserializer<'de>,
+{
+    registry.check_schema(schema);
+    let mut report = Vec::new();
+    let frame = deserializer
+        .deserialize_map(FrameVisitor(context, Some((registry, schema, &mut report))))?;
+    Ok((frame, report))
 }
 
 /// Implements deserialization of entities from a serde [`MapAccess`] into an [`EntityBuilder`]
@@ -207,7 +274,14 @@ pub trait DeserializeContext {
         M: MapAccess<'de>;
 }
 
-struct FrameVisitor<'a, C>(&'a mut C);
+/// Registry and schema to fill in missing components from, and the report of those filled in
+type Defaults<'a> = (
+    &'a ComponentRegistry,
+    &'a Schema,
+    &'a mut Vec<DefaultedComponent>,
+);
+
+struct FrameVisitor<'a, C>(&'a mut C, Option<Defaults<'a>>);
 
 impl<'de, 'a, C> Visitor<'de> for FrameVisitor<'a, C>
 where
@@ -225,8 +299,12 @@ where
     {
         let mut frame = Frame::new();
         let mut builder = EntityBuilder::new();
+        let mut defaults = self.1;
         while let Some(id) = map.next_key()? {
             map.next_value_seed(DeserializeComponents(self.0, &mut builder))?;
+            if let Some((registry, schema, ref mut report)) = defaults {
+                registry.fill_entity_defaults(schema, id, &mut builder, report);
+            }
             frame.spawn_at(id, builder.build());
         }
         Ok(frame)
@@ -298,7 +376,7 @@ mod tests {
     impl PartialEq for SerFrame {
         fn eq(&self, other: &Self) -> bool {
             fn same_components<T: Component + PartialEq>(x: &EntityRef, y: &EntityRef) -> bool {
-                x.get::<&T>().as_ref().map(|x| &**x) == y.get::<&T>().as_ref().map(|x| &**x)
+                x.get::<&T>().as_deref() == y.get::<&T>().as_deref()
             }
 
             for (x, y) in self.0.iter().zip(other.0.iter()) {
@@ -441,7 +519,7 @@ mod tests {
     impl<Q> PartialEq for SerSatisfyingFrame<Q> {
         fn eq(&self, other: &Self) -> bool {
             fn same_components<T: Component + PartialEq>(x: &EntityRef, y: &EntityRef) -> bool {
-                x.get::<&T>().as_ref().map(|x| &**x) == y.get::<&T>().as_ref().map(|x| &**x)
+                x.get::<&T>().as_deref() == y.get::<&T>().as_deref()
             }
 
             for (x, y) in self.0.iter().zip(other.0.iter()) {
@@ -476,7 +554,7 @@ mod tests {
 
     impl<'a, Q: Query> Serialize for SerSatisfyingFrameInner<'a, Q> {
         fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
-            crate::serialize::row::serialize_satisfying::<Q, Context, S>(&self.0, &mut Context, s)
+            crate::serialize::row::serialize_satisfying::<Q, Context, S>(self.0, &mut Context, s)
         }
     }
 
@@ -579,4 +657,86 @@ mod tests {
             Token::TupleStructEnd,
         ])
     }
+
+    /// Frame deserialized with default velocities, and the report of those filled in
+    struct DefaultedFrame(Frame, Vec<DefaultedComponent>);
+
+    impl<'de> Deserialize<'de> for DefaultedFrame {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let mut registry = ComponentRegistry::new();
+            registry
+                .register::<Velocity>()
+                .default_with(|| Velocity([0.0; 3]));
+            let schema = Schema::new().scope::<Position>().require::<Velocity>();
+            let (frame, report) = deserialize_with_defaults(&mut Context, &registry, &schema, d)?;
+            Ok(DefaultedFrame(frame, report))
+        }
+    }
+
+    impl PartialEq for DefaultedFrame {
+        fn eq(&self, other: &Self) -> bool {
+            self.1 == other.1
+                && self.0.len() == other.0.len()
+                && self.0.iter().zip(other.0.iter()).all(|(x, y)| {
+                    x.entity() == y.entity()
+                        && x.get::<&Position>().as_deref() == y.get::<&Position>().as_deref()
+                        && x.get::<&Velocity>().as_deref() == y.get::<&Velocity>().as_deref()
+                })
+        }
+    }
+
+    impl fmt::Debug for DefaultedFrame {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let entities = self.0.iter().map(|e| {
+                (
+                    e.entity(),
+                    (
+                        e.get::<&Position>().map(|x| *x),
+                        e.get::<&Velocity>().map(|x| *x),
+                    ),
+                )
+            });
+            f.debug_tuple("DefaultedFrame")
+                .field(&entities.collect::<Vec<_>>())
+                .field(&self.1)
+                .finish()
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn fill_defaults() {
+        use serde_test::{Token, assert_de_tokens};
+
+        let mut expected = Frame::new();
+        let p0 = Position([1.0, 2.0, 3.0]);
+        let e0 = expected.spawn((p0, Velocity([0.0; 3])));
+        let e1 = expected.spawn(());
+
+        let report = alloc::vec![DefaultedComponent {
+            entity: e0,
+            name: core::any::type_name::<Velocity>(),
+        }];
+
+        assert_de_tokens(&DefaultedFrame(expected, report), &[
+            Token::Map { len: Some(2) },
+
+            Token::U64(e0.to_bits().into()),
+            Token::Map { len: None },
+            Token::UnitVariant { name: "ComponentId", variant: "Position" },
+            Token::NewtypeStruct { name: "Position" },
+            Token::Tuple { len: 3 },
+            Token::F32(1.0),
+            Token::F32(2.0),
+            Token::F32(3.0),
+            Token::TupleEnd,
+            Token::MapEnd,
+
+            Token::U64(e1.to_bits().into()),
+            Token::Map { len: None },
+            Token::MapEnd,
+
+            Token::MapEnd,
+        ]);
+    }
 }
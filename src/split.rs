@@ -0,0 +1,158 @@
+use core::marker::PhantomData;
+
+use crate::{
+    Bundle, Component, ComponentError, Entity, Fetch, Frame, Query, QueryBorrow, Ref, RefMut,
+};
+
+/// Shared access to the components in `R`, obtained from [`Frame::split`]
+///
+/// Queries may only read components in `R`; any other access panics.
+pub struct ReadPartition<'a, R> {
+    frame: &'a Frame,
+    _marker: PhantomData<fn() -> R>,
+}
+
+impl<'a, R: Bundle + 'static> ReadPartition<'a, R> {
+    pub(crate) fn new(frame: &'a Frame) -> Self {
+        Self {
+            frame,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Query the partition
+    ///
+    /// Panics if `Q` borrows a component outside `R`, or borrows any component uniquely.
+    pub fn query<Q: Query>(&self) -> QueryBorrow<'a, Q> {
+        assert_access::<Q, R>(false);
+        self.frame.query()
+    }
+
+    /// Borrow the `T` component of `entity`
+    ///
+    /// Panics if `T` isn't in `R`.
+    pub fn get<T: Component>(&self, entity: Entity) -> Result<Ref<'a, T>, ComponentError> {
+        assert_access::<&T, R>(false);
+        self.frame.get::<&T>(entity)
+    }
+
+    /// Whether `entity` exists
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.frame.contains(entity)
+    }
+}
+
+/// Exclusive access to the components in `W`, obtained from [`Frame::split`]
+///
+/// Queries may read and write components in `W`; any other access panics.
+pub struct WritePartition<'a, W> {
+    frame: &'a Frame,
+    _marker: PhantomData<fn() -> W>,
+}
+
+impl<'a, W: Bundle + 'static> WritePartition<'a, W> {
+    pub(crate) fn new(frame: &'a Frame) -> Self {
+        Self {
+            frame,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Query the partition
+    ///
+    /// Panics if `Q` borrows a component outside `W`.
+    pub fn query<Q: Query>(&self) -> QueryBorrow<'a, Q> {
+        assert_access::<Q, W>(true);
+        self.frame.query()
+    }
+
+    /// Borrow the `T` component of `entity`
+    ///
+    /// Panics if `T` isn't in `W`.
+    pub fn get<T: Component>(&self, entity: Entity) -> Result<Ref<'a, T>, ComponentError> {
+        assert_access::<&T, W>(true);
+        self.frame.get::<&T>(entity)
+    }
+
+    /// Uniquely borrow the `T` component of `entity`
+    ///
+    /// Panics if `T` isn't in `W`.
+    pub fn get_mut<T: Component>(&self, entity: Entity) -> Result<RefMut<'a, T>, ComponentError> {
+        assert_access::<&mut T, W>(true);
+        self.frame.get::<&mut T>(entity)
+    }
+
+    /// Whether `entity` exists
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.frame.contains(entity)
+    }
+}
+
+/// Check that `Q` only borrows components in `S`, and only uniquely if `unique` is set
+fn assert_access<Q: Query, S: Bundle + 'static>(unique: bool) {
+    S::with_static_ids(|ids| {
+        Q::Fetch::for_each_borrow(|id, is_unique| {
+            assert!(
+                ids.contains(&id),
+                "query accesses a component outside the partition"
+            );
+            assert!(
+                unique || !is_unique,
+                "query writes to a read-only partition"
+            );
+        })
+    })
+}
+
+/// Check that `R` and `W` share no component types
+pub(crate) fn assert_disjoint<R: Bundle + 'static, W: Bundle + 'static>() {
+    R::with_static_ids(|read| {
+        W::with_static_ids(|write| {
+            assert!(
+                !read.iter().any(|x| write.contains(x)),
+                "read and write partitions overlap"
+            );
+        })
+    })
+}
+
+/// Divide a [`Frame`] into read-only and exclusive partitions, like [`Frame::split`], rejecting
+/// component types named by both at compile time
+///
+/// Takes the frame and parenthesized lists of the component types to read and to write. Component
+/// types can't be compared during constant evaluation, so each list is checked by implementing a
+/// local marker trait for every type, which fails to compile if a type appears twice. The types
+/// must therefore be concrete, rather than generic parameters; generic code can fall back to
+/// `Frame::split`, which checks for overlap when it's called.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// struct Position(f32);
+/// struct Velocity(f32);
+/// let mut frame = Frame::new();
+/// frame.spawn((Position(1.0), Velocity(2.0)));
+/// let (read, write) = split!(frame, (Velocity), (Position));
+/// for (_, pos) in write.query::<&mut Position>().iter() {
+///     pos.0 += read.query::<&Velocity>().iter().map(|(_, v)| v.0).sum::<f32>();
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use moss_hecs::*;
+/// struct Position(f32);
+/// struct Velocity(f32);
+/// let mut frame = Frame::new();
+/// let (read, write) = split!(frame, (Position, Velocity), (Position));
+/// ```
+#[macro_export]
+macro_rules! split {
+    ($frame:expr, ($($read:ty),* $(,)?), ($($write:ty),* $(,)?)) => {{
+        const _: () = {
+            trait ReadAndWritePartitionsOverlap {}
+            $(impl ReadAndWritePartitionsOverlap for $read {})*
+            $(impl ReadAndWritePartitionsOverlap for $write {})*
+        };
+        $frame.split::<($($read,)*), ($($write,)*)>()
+    }};
+}
@@ -0,0 +1,109 @@
+use crate::{CommandBuffer, Frame, PreparedQuery, PreparedQueryBorrow, Query};
+
+/// A set of queries run together by [`PreparedSystems`]
+///
+/// Implemented for tuples of up to 15 query types.
+pub trait QuerySet {
+    /// Prepared state of every query
+    type Prepared;
+    /// Borrows of every query from a frame
+    type Borrow<'q>
+    where
+        Self: 'q;
+
+    /// State for queries that will be prepared on first use
+    fn unprepared() -> Self::Prepared;
+
+    /// Borrow every query from `frame`, preparing them again if the frame has changed
+    fn borrow<'q>(prepared: &'q mut Self::Prepared, frame: &'q Frame) -> Self::Borrow<'q>
+    where
+        Self: 'q;
+}
+
+macro_rules! tuple_impl {
+    ($($name: ident),*) => {
+        impl<$($name: Query),*> QuerySet for ($($name,)*) {
+            type Prepared = ($(PreparedQuery<$name>,)*);
+            type Borrow<'q> = ($(PreparedQueryBorrow<'q, $name>,)*) where Self: 'q;
+
+            #[allow(clippy::unused_unit)]
+            fn unprepared() -> Self::Prepared {
+                ($(PreparedQuery::<$name>::new(),)*)
+            }
+
+            #[allow(unused_variables, clippy::unused_unit)]
+            fn borrow<'q>(prepared: &'q mut Self::Prepared, frame: &'q Frame) -> Self::Borrow<'q>
+            where
+                Self: 'q,
+            {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = prepared;
+                ($($name.query(frame),)*)
+            }
+        }
+    };
+}
+
+smaller_tuples_too!(tuple_impl, O, N, M, L, K, J, I, H, G, F, E, D, C, B, A);
+
+/// Several [`PreparedQuery`]s and a [`CommandBuffer`] stored together for reuse by a system
+///
+/// Each [`run`](Self::run) borrows every query from a frame, hands them to a function alongside
+/// the command buffer, then applies the commands it recorded once the queries are released.
+/// Queries are borrowed with dynamic borrow checking, so they may overlap as long as no
+/// component is borrowed uniquely by one while borrowed by another.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// struct Health(i32);
+/// struct Poison(i32);
+///
+/// let mut frame = Frame::new();
+/// let a = frame.spawn((Health(10), Poison(3)));
+/// let b = frame.spawn((Health(2), Poison(5)));
+/// let mut system = PreparedSystems::<(&mut Health, &Poison)>::new();
+/// system.run(&mut frame, |(mut health, mut poison), cmd| {
+///     let poison = poison.view();
+///     for (entity, health) in health.iter() {
+///         health.0 -= poison.get(entity).map_or(0, |x| x.0);
+///         if health.0 <= 0 {
+///             cmd.despawn(entity);
+///         }
+///     }
+/// });
+/// assert_eq!(frame.get::<&Health>(a).unwrap().0, 7);
+/// assert!(!frame.contains(b));
+/// ```
+pub struct PreparedSystems<S: QuerySet> {
+    queries: S::Prepared,
+    cmd: CommandBuffer,
+}
+
+impl<S: QuerySet> PreparedSystems<S> {
+    /// Create a system whose queries will be prepared on first use
+    pub fn new() -> Self {
+        Self {
+            queries: S::unprepared(),
+            cmd: CommandBuffer::new(),
+        }
+    }
+
+    /// Borrow every query from `frame` and pass them to `f` along with a command buffer, then
+    /// apply the recorded commands to `frame`
+    pub fn run<R>(
+        &mut self,
+        frame: &mut Frame,
+        f: impl FnOnce(S::Borrow<'_>, &mut CommandBuffer) -> R,
+    ) -> R {
+        let result = f(S::borrow(&mut self.queries, frame), &mut self.cmd);
+        self.cmd.run_on(frame);
+        result
+    }
+}
+
+impl<S: QuerySet> Default for PreparedSystems<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,59 @@
+use alloc::sync::Arc;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::Frame;
+
+/// Guard asserting that no structural changes are made to a [`Frame`] while it's live
+///
+/// Obtained from [`Frame::read_transaction`]. Unlike a borrow of the frame, the guard may be held
+/// while the frame is mutably accessed, e.g. by a scheduler while it runs systems that are only
+/// meant to modify components in place. When debug assertions are enabled, spawning, despawning,
+/// inserting or removing components, or otherwise changing the set of entities or archetypes
+/// panics while any read transaction is live.
+pub struct ReadTransaction {
+    readers: Arc<AtomicUsize>,
+}
+
+impl ReadTransaction {
+    pub(crate) fn new(readers: &Arc<AtomicUsize>) -> Self {
+        readers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            readers: readers.clone(),
+        }
+    }
+}
+
+impl Drop for ReadTransaction {
+    fn drop(&mut self) {
+        self.readers.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// Exclusive access to a [`Frame`] for making structural changes
+///
+/// Obtained from [`Frame::write_transaction`], which checks that no [`ReadTransaction`] is live.
+/// Dereferences to the frame.
+pub struct WriteTransaction<'a> {
+    frame: &'a mut Frame,
+}
+
+impl<'a> WriteTransaction<'a> {
+    pub(crate) fn new(frame: &'a mut Frame) -> Self {
+        Self { frame }
+    }
+}
+
+impl Deref for WriteTransaction<'_> {
+    type Target = Frame;
+
+    fn deref(&self) -> &Frame {
+        self.frame
+    }
+}
+
+impl DerefMut for WriteTransaction<'_> {
+    fn deref_mut(&mut self) -> &mut Frame {
+        self.frame
+    }
+}
@@ -0,0 +1,98 @@
+use core::any::TypeId;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+use crate::archetype::TypeInfo;
+use crate::{Archetype, Component, Entity, RegisteredComponent};
+
+/// A borrowed component of statically unknown type
+///
+/// Typically obtained through a [`ComponentVisitor`]. Operations on values of the underlying type
+/// can be looked up in a [`ComponentRegistry`](crate::ComponentRegistry).
+#[derive(Copy, Clone)]
+pub struct DynamicComponent<'a> {
+    ty: TypeInfo,
+    ptr: NonNull<u8>,
+    _marker: PhantomData<&'a ()>,
+}
+
+impl<'a> DynamicComponent<'a> {
+    /// # Safety
+    ///
+    /// `ptr` must address a value of the type described by `ty` which is borrowed for `'a`
+    pub(crate) unsafe fn new(ty: TypeInfo, ptr: NonNull<u8>) -> Self {
+        Self {
+            ty,
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Type of the component
+    pub fn type_info(&self) -> TypeInfo {
+        self.ty
+    }
+
+    /// Address of the component
+    ///
+    /// The component may be read through this pointer while the `DynamicComponent` is live.
+    pub fn as_ptr(&self) -> NonNull<u8> {
+        self.ptr
+    }
+
+    /// Access the component as a `T`, if that's its type
+    pub fn downcast_ref<T: Component>(&self) -> Option<&'a T> {
+        if self.ty.id() != TypeId::of::<T>() {
+            return None;
+        }
+        Some(unsafe { self.ptr.cast::<T>().as_ref() })
+    }
+}
+
+/// Callback invoked on each of an entity's components
+///
+/// See [`EntityRef::visit`](crate::EntityRef::visit).
+pub trait ComponentVisitor {
+    /// Inspect a single component
+    fn visit(&mut self, component: DynamicComponent<'_>);
+}
+
+impl<F: FnMut(DynamicComponent<'_>)> ComponentVisitor for F {
+    fn visit(&mut self, component: DynamicComponent<'_>) {
+        self(component)
+    }
+}
+
+/// Callbacks invoked while walking an entire [`Frame`](crate::Frame)
+///
+/// See [`Frame::visit_all`](crate::Frame::visit_all). Archetypes are entered in turn, then each
+/// registered column within them, then each row of that column. Only
+/// [`visit`](Self::visit) is required; the remaining methods let exporters emit nested structure
+/// or skip parts of the frame.
+pub trait FrameVisitor {
+    /// Called before an archetype's columns are visited; return `false` to skip it
+    fn begin_archetype(&mut self, archetype: &Archetype) -> bool {
+        let _ = archetype;
+        true
+    }
+
+    /// Called before a column's rows are visited; return `false` to skip it
+    fn begin_column(&mut self, component: &RegisteredComponent) -> bool {
+        let _ = component;
+        true
+    }
+
+    /// Inspect `entity`'s component in the current column
+    fn visit(&mut self, entity: Entity, component: DynamicComponent<'_>);
+
+    /// Called after every row of a column entered by [`begin_column`](Self::begin_column)
+    fn end_column(&mut self, component: &RegisteredComponent) {
+        let _ = component;
+    }
+
+    /// Called after every column of an archetype entered by
+    /// [`begin_archetype`](Self::begin_archetype)
+    fn end_archetype(&mut self, archetype: &Archetype) {
+        let _ = archetype;
+    }
+}
@@ -0,0 +1,49 @@
+use crate::{Entity, Frame};
+
+/// A long-lived reference to an entity in a particular [`Frame`]
+///
+/// Components referring to other entities, such as a target or a parent, may outlive what they
+/// refer to. A `WeakEntity` records which frame its entity belongs to as well as its handle, so a
+/// single [`resolve`](Self::resolve) call rejects both despawned entities and lookups in the wrong
+/// frame, e.g. one whose IDs happen to overlap after a level reload.
+///
+/// Frames constructed by cloning or deserialization are distinct from their source, so references
+/// into the source don't resolve in them.
+///
+/// # Example
+/// ```
+/// # use moss_hecs::*;
+/// let mut frame = Frame::new();
+/// let target = frame.spawn(());
+/// let weak = WeakEntity::new(&frame, target);
+/// assert_eq!(weak.resolve(&frame), Some(target));
+/// assert_eq!(weak.resolve(&Frame::new()), None);
+/// frame.despawn(target).unwrap();
+/// assert_eq!(weak.resolve(&frame), None);
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct WeakEntity {
+    entity: Entity,
+    frame: u64,
+}
+
+impl WeakEntity {
+    /// Refer to `entity` in `frame`
+    pub fn new(frame: &Frame, entity: Entity) -> Self {
+        Self {
+            entity,
+            frame: frame.id(),
+        }
+    }
+
+    /// The referenced entity, if it's still live in `frame` and `frame` is the one it was
+    /// referred to in
+    pub fn resolve(&self, frame: &Frame) -> Option<Entity> {
+        (frame.id() == self.frame && frame.contains(self.entity)).then_some(self.entity)
+    }
+
+    /// The referenced entity's handle, without checking whether it's live
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+}
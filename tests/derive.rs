@@ -1,12 +1,15 @@
-//!
-
 #[cfg(feature = "macros")]
 #[cfg_attr(miri, ignore)]
 #[test]
 fn derive() {
     const TEST_DIR: &str = "tests/derive";
     let t = trybuild::TestCases::new();
-    let failures = &["enum.rs", "union.rs", "wrong_lifetime.rs"];
+    let failures = &[
+        "enum.rs",
+        "union.rs",
+        "wrong_lifetime.rs",
+        "duplicate_fields.rs",
+    ];
     let successes = &[
         "unit_structs.rs",
         "tuple_structs.rs",
@@ -15,6 +18,7 @@ fn derive() {
         "generics.rs",
         "nested_query.rs",
         "export.rs",
+        "map_entities.rs",
     ];
     for &passing_test in successes {
         t.pass(format!("{}/{}", TEST_DIR, passing_test));
@@ -0,0 +1,14 @@
+use moss_hecs::Bundle;
+
+#[derive(Bundle)]
+struct Foo {
+    x: i32,
+    y: i32,
+    z: bool,
+    w: i32,
+}
+
+#[derive(Bundle)]
+struct Bar(u8, Option<u8>, Option<u8>);
+
+fn main() {}
@@ -0,0 +1,25 @@
+use moss_hecs::{Entity, MapEntities};
+
+#[derive(MapEntities)]
+struct Generic<T> {
+    #[map_entities]
+    inner: T,
+}
+
+#[derive(MapEntities)]
+struct Tuple(#[map_entities] Entity, u32);
+
+#[derive(MapEntities)]
+struct Unit;
+
+#[derive(MapEntities)]
+enum Empty {}
+
+fn main() {
+    fn assert_impl<T: MapEntities>() {}
+    assert_impl::<Generic<Entity>>();
+    assert_impl::<Generic<Vec<Entity>>>();
+    assert_impl::<Tuple>();
+    assert_impl::<Unit>();
+    assert_impl::<Empty>();
+}
@@ -79,6 +79,113 @@ fn derived_query() {
     );
 }
 
+#[test]
+#[cfg(feature = "macros")]
+fn derived_query_filters() {
+    #[derive(Query, Debug, PartialEq)]
+    struct Unarmed<'a> {
+        #[query(without = &'a bool)]
+        x: &'a i32,
+        #[query(with = &'a u8)]
+        #[query(without = &'a u16)]
+        y: &'a mut f32,
+    }
+
+    #[derive(Query, Debug, PartialEq)]
+    enum Target<'a> {
+        Armed(#[query(with = &'a bool)] &'a i32),
+        Unarmed(&'a i32),
+    }
+
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, 2.0f32, 3u8));
+    let b = frame.spawn((1, 2.0f32, 3u8, true));
+    frame.spawn((1, 2.0f32));
+    frame.spawn((1, 2.0f32, 3u8, 4u16));
+    let mut query = PreparedQuery::<Unarmed>::new();
+    let results = query
+        .query_mut(&mut frame)
+        .map(|(e, x)| (e, *x.x, *x.y))
+        .collect::<Vec<_>>();
+    assert_eq!(results, [(a, 1, 2.0)]);
+    assert_eq!(
+        frame.query_one_mut::<Target>(a).unwrap(),
+        Target::Unarmed(&1)
+    );
+    assert_eq!(frame.query_one_mut::<Target>(b).unwrap(), Target::Armed(&1));
+}
+
+#[test]
+#[cfg(feature = "macros")]
+fn derived_enum_query() {
+    #[derive(Query, Debug, PartialEq)]
+    enum State<'a> {
+        Attacking {
+            target: &'a Entity,
+            damage: &'a mut u32,
+        },
+        Fleeing(&'a f32),
+        Idle,
+    }
+
+    #[derive(Query, Debug, PartialEq)]
+    enum Alive<'a> {
+        Healthy(&'a i32),
+    }
+
+    let mut frame = Frame::new();
+    let idle = frame.spawn((true,));
+    let fleeing = frame.spawn((1.5f32,));
+    // Earlier variants take precedence
+    let attacking = frame.spawn((idle, 3u32, 2.5f32));
+
+    match frame.query_one_mut::<State>(attacking).unwrap() {
+        State::Attacking { target, damage } => {
+            assert_eq!(*target, idle);
+            *damage += 1;
+        }
+        x => panic!("unexpected {:?}", x),
+    }
+    assert_eq!(*frame.get::<&u32>(attacking).unwrap(), 4);
+    assert_eq!(
+        frame.query_one_mut::<State>(fleeing).unwrap(),
+        State::Fleeing(&1.5)
+    );
+    assert_eq!(frame.query_one_mut::<State>(idle).unwrap(), State::Idle);
+    assert_eq!(frame.query::<State>().iter().count(), 3);
+
+    assert_eq!(frame.query::<Alive>().iter().count(), 0);
+    assert_eq!(
+        frame.explain::<Alive>(idle),
+        Err(QueryMismatch::Components {
+            missing: vec!["i32"],
+            excluded: vec![],
+        })
+    );
+    assert_eq!(
+        frame.explain::<Without<&bool, State>>(attacking),
+        Err(QueryMismatch::Components {
+            missing: vec!["bool"],
+            excluded: vec!["moss_hecs::entities::Entity", "u32"],
+        })
+    );
+}
+
+#[test]
+#[cfg(feature = "macros")]
+#[should_panic(expected = "query violates a unique borrow")]
+fn derived_enum_query_aliasing() {
+    #[derive(Query)]
+    #[allow(dead_code)]
+    enum Aliasing<'a> {
+        Unique(&'a mut u32),
+        Both(&'a u32, &'a mut u32),
+    }
+
+    let mut frame = Frame::new();
+    frame.query_mut::<Aliasing>();
+}
+
 #[test]
 #[cfg(feature = "macros")]
 fn derived_bundle_clone() {
@@ -481,6 +588,27 @@ fn access_builder_components() {
     assert_eq!(*frame.get::<&i32>(g).unwrap(), 456);
 }
 
+#[test]
+fn builder_satisfies() {
+    let mut builder = EntityBuilder::new();
+    assert!(builder.satisfies::<()>());
+    builder.add(1u8).add("abc");
+    assert!(builder.satisfies::<(&u8, &mut &str)>());
+    assert!(!builder.satisfies::<(&u8, &i32)>());
+    assert!(builder.satisfies::<Or<&i32, &u8>>());
+    assert!(!builder.satisfies::<With<&u8, &i32>>());
+
+    let mut frame = Frame::new();
+    let e = frame.spawn(builder.build());
+    assert!(frame.satisfies::<(&u8, &&str)>(e).unwrap());
+    assert!(builder.satisfies::<()>() && !builder.satisfies::<&u8>());
+
+    let mut builder = EntityBuilderClone::new();
+    builder.add(1u8);
+    assert!(builder.satisfies::<Without<&u8, &bool>>());
+    assert!(!builder.satisfies::<&bool>());
+}
+
 #[test]
 fn build_entity_bundle() {
     let mut frame = Frame::new();
@@ -700,10 +828,11 @@ fn derived_bundle() {
     )
 )]
 fn bad_bundle_derive() {
+    // Fields spelled with the same type are rejected at compile time, but generic ones can't be
     #[derive(Bundle)]
-    struct Foo {
-        x: i32,
-        y: i32,
+    struct Foo<A, B> {
+        x: A,
+        y: B,
     }
 
     let mut frame = Frame::new();
@@ -752,6 +881,59 @@ fn exchange_components() {
     assert!(frame.get::<&bool>(entity).is_ok());
 }
 
+#[test]
+fn exchange_bundles() {
+    let mut frame = Frame::new();
+
+    let entity = frame.spawn(("abc".to_owned(), 123, 'x'));
+    let other = frame.spawn(("def".to_owned(), 456));
+
+    assert!(frame.exchange::<(String, bool), _>(entity, (1.5,)).is_err());
+    assert!(frame.satisfies::<(&String, &i32, &char)>(entity).unwrap());
+
+    let (s, i) = frame
+        .exchange::<(String, i32), _>(entity, (true, 2.5f32))
+        .unwrap();
+    assert_eq!(s, "abc");
+    assert_eq!(i, 123);
+    assert!(frame.satisfies::<(&char, &bool, &f32)>(entity).unwrap());
+    assert!(!frame.satisfies::<&String>(entity).unwrap());
+
+    // Exchanging a component for a new value of the same type replaces it in place
+    let (c,) = frame.exchange::<(char,), _>(entity, ('y',)).unwrap();
+    assert_eq!(c, 'x');
+    assert_eq!(*frame.get::<&char>(entity).unwrap(), 'y');
+
+    assert_eq!(*frame.get::<&String>(other).unwrap(), "def");
+}
+
+#[test]
+fn reserve_contiguous() {
+    let mut frame = Frame::new();
+    let a = frame.spawn(());
+    frame.reserve_entity();
+    let reserved = frame.reserve_entities_contiguous(4).unwrap();
+    let ids = reserved.ids().unwrap();
+    assert_eq!(ids, 2..6);
+    let reserved = reserved.collect::<Vec<_>>();
+    assert!(reserved.iter().map(|e| e.id()).eq(ids));
+    assert_eq!(frame.reserve_entity().id(), 6);
+
+    frame.flush();
+    assert!(reserved.iter().all(|&e| frame.contains(e)));
+
+    // Freed IDs must be recycled first
+    frame.despawn(a).unwrap();
+    assert!(frame.reserve_entities_contiguous(2).is_none());
+    assert!(frame.reserve_entities(1).ids().is_none());
+    assert_eq!(frame.reserve_entities(2).ids(), Some(7..9));
+    frame.flush();
+    assert_eq!(
+        frame.reserve_entities_contiguous(2).unwrap().ids(),
+        Some(9..11)
+    );
+}
+
 #[test]
 fn reserve() {
     let mut frame = Frame::new();
@@ -882,6 +1064,14 @@ fn duplicate_components_panic() {
     frame.reserve::<(f32, i64, f32)>(1);
 }
 
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "duplicate u64, u8 components; each type must occur at most once!")]
+fn duplicate_components_listed() {
+    let mut frame = Frame::new();
+    frame.spawn((1u8, 2u64, 3u8, true, 4u64, 5u8));
+}
+
 #[test]
 fn spawn_column_batch() {
     let mut frame = Frame::new();
@@ -977,89 +1167,3599 @@ fn query_or() {
 }
 
 #[test]
-fn len() {
+fn or_accessors() {
+    let left = Or::<i32, bool>::Left(1);
+    let right = Or::<i32, bool>::Right(true);
+    let both = Or::Both(2, false);
+
+    assert_eq!(both.both(), Some((2, false)));
+    assert_eq!(left.both(), None);
+    assert_eq!(left.left_or_else(|b| b as i32), 1);
+    assert_eq!(right.left_or_else(|b| b as i32 * 10), 10);
+    assert_eq!(both.left_or_else(|_| unreachable!()), 2);
+    assert!(left.right_or_else(|x| x > 0));
+    assert!(!both.right_or_else(|_| unreachable!()));
+    assert_eq!(both.map_left(|x| x * 3), Or::Both(6, false));
+    assert_eq!(right.map_left(|x| x * 3), Or::Right(true));
+    assert_eq!(left.map_right(|b| !b), Or::Left(1));
+    assert_eq!(both.map_right(|b| !b), Or::Both(2, true));
+
+    let mut value = both;
+    if let Or::Both(l, _) = value.as_mut() {
+        *l += 1;
+    }
+    assert_eq!(value.as_ref().left(), Some(&3));
+}
+
+#[test]
+fn explain_query() {
     let mut frame = Frame::new();
-    let ent = frame.spawn(());
-    frame.spawn(());
-    frame.spawn(());
-    assert_eq!(frame.len(), 3);
-    frame.despawn(ent).unwrap();
-    assert_eq!(frame.len(), 2);
-    frame.clear();
-    assert_eq!(frame.len(), 0);
+    let e = frame.spawn((42, true));
+
+    assert_eq!(frame.explain::<(&i32, &mut bool)>(e), Ok(()));
+    assert_eq!(frame.explain::<Option<&&str>>(e), Ok(()));
+    assert_eq!(
+        frame.explain::<(&i32, &&str, &f32)>(e),
+        Err(QueryMismatch::Components {
+            missing: vec!["&str", "f32"],
+            excluded: vec![],
+        })
+    );
+    assert_eq!(
+        frame.explain::<Without<&i32, (&bool, Option<&f32>)>>(e),
+        Err(QueryMismatch::Components {
+            missing: vec![],
+            excluded: vec!["bool"],
+        })
+    );
+    assert_eq!(
+        frame.explain::<Or<&f32, &u8>>(e),
+        Err(QueryMismatch::Components {
+            missing: vec!["f32", "u8"],
+            excluded: vec![],
+        })
+    );
+    assert_eq!(frame.explain::<With<&i32, Or<&f32, &bool>>>(e), Ok(()));
+    assert_eq!(
+        frame
+            .explain::<With<&i32, &f32>>(e)
+            .unwrap_err()
+            .to_string(),
+        "query not satisfied; missing f32"
+    );
+
+    frame.despawn(e).unwrap();
+    assert_eq!(frame.explain::<&i32>(e), Err(QueryMismatch::NoSuchEntity));
 }
 
 #[test]
-fn take() {
-    let mut frame_a = Frame::new();
-    let e = frame_a.spawn(("abc".to_string(), 42));
-    let f = frame_a.spawn(("def".to_string(), 17));
-    let mut frame_b = Frame::new();
-    let e2 = frame_b.spawn(frame_a.take(e).unwrap());
-    assert!(!frame_a.contains(e));
-    assert_eq!(*frame_b.get::<&String>(e2).unwrap(), "abc");
-    assert_eq!(*frame_b.get::<&i32>(e2).unwrap(), 42);
-    assert_eq!(*frame_a.get::<&String>(f).unwrap(), "def");
-    assert_eq!(*frame_a.get::<&i32>(f).unwrap(), 17);
-    frame_b.take(e2).unwrap();
-    assert!(!frame_b.contains(e2));
+#[cfg(feature = "macros")]
+fn explain_derived_query() {
+    #[derive(Query)]
+    #[allow(dead_code)]
+    struct Foo<'a> {
+        x: &'a i32,
+        y: Option<&'a bool>,
+        z: &'a u8,
+    }
+
+    let mut frame = Frame::new();
+    let e = frame.spawn((true,));
+    assert_eq!(
+        frame.explain::<Foo>(e),
+        Err(QueryMismatch::Components {
+            missing: vec!["i32", "u8"],
+            excluded: vec![],
+        })
+    );
+    assert_eq!(
+        frame.explain::<Without<Foo, &bool>>(e),
+        Err(QueryMismatch::Components {
+            missing: vec!["i32", "u8"],
+            excluded: vec!["bool"],
+        })
+    );
 }
 
 #[test]
-fn empty_archetype_conflict() {
+fn entity_builder_pool() {
     let mut frame = Frame::new();
-    let _ = frame.spawn((42, true));
-    let _ = frame.spawn((17, "abc"));
-    let e = frame.spawn((12, false, "def"));
+    let mut pool = EntityBuilderPool::new();
+    assert!(pool.is_empty());
+
+    let mut a = pool.acquire();
+    let mut b = pool.acquire();
+    a.add(1).add(true);
+    b.add(2).add("b");
+    let e = frame.spawn(a.build());
+    let f = frame.spawn(b.build());
+    // Leftover components are dropped on release
+    b.add(String::from("leftover"));
+    pool.release(a);
+    pool.release(b);
+    assert_eq!(pool.len(), 2);
+
+    let c = pool.acquire();
+    assert_eq!(c.component_types().count(), 0);
+    assert_eq!(pool.len(), 1);
+    assert!(frame.satisfies::<(&i32, &bool)>(e).unwrap());
+    assert_eq!(*frame.get::<&&str>(f).unwrap(), "b");
+}
+
+#[test]
+fn bundle_satisfies_assertion() {
+    runtime_assert_bundle_satisfies!((i32, bool), &i32, (&mut i32, Option<&f32>));
+    assert_eq!(bundle_query_mismatch::<(i32, bool), &bool>(), None);
+    assert_eq!(
+        bundle_query_mismatch::<(i32, bool), Without<&f32, &bool>>(),
+        Some(QueryMismatch::Components {
+            missing: vec!["f32"],
+            excluded: vec!["bool"],
+        })
+    );
+}
+
+#[test]
+#[should_panic(
+    expected = "bundle `(i32, bool)` doesn't satisfy query `(&i32, &u8)`: query not satisfied; missing u8"
+)]
+fn bundle_satisfies_assertion_fails() {
+    runtime_assert_bundle_satisfies!((i32, bool), (&i32, &u8));
+}
+
+#[test]
+fn cloned_one() {
+    let mut frame = Frame::new();
+    let e = frame.spawn((42, String::from("abc"), true));
+
+    let (name, number) = frame.cloned_one::<(String, i32)>(e).unwrap();
+    assert_eq!(name, "abc");
+    assert_eq!(number, 42);
+    assert_eq!(frame.cloned_one::<()>(e), Ok(()));
+    assert_eq!(
+        frame.cloned_one::<(i32, f32)>(e),
+        Err(ComponentError::MissingComponent(
+            MissingComponent::new::<f32>()
+        ))
+    );
+
+    // No borrows outlive the call
+    *frame.get::<&mut i32>(e).unwrap() = 7;
+    assert_eq!(frame.cloned_one::<(i32,)>(e), Ok((7,)));
+
     frame.despawn(e).unwrap();
-    for _ in frame.query::<(&mut i32, &&str)>().iter() {
-        for _ in frame.query::<(&mut i32, &bool)>().iter() {}
-    }
+    assert_eq!(
+        frame.cloned_one::<(i32,)>(e),
+        Err(ComponentError::NoSuchEntity)
+    );
 }
 
 #[test]
-fn component_ref_map() {
-    struct TestComponent {
-        id: i32,
+fn entity_tokens() {
+    let mut frame = Frame::new();
+    let a = frame.spawn(());
+    let b = frame.spawn(());
+    let token = b.to_token();
+    assert_eq!(Entity::from_token(&token), Ok(b));
+    assert_ne!(a.to_token(), token);
+
+    for malformed in [
+        "", "1v1", "v1-00", "1v0-00", "1v-00", "+1v1-00", "1v1-0", "1v1-zz", "1x1-00",
+    ] {
+        assert_eq!(
+            Entity::from_token(malformed),
+            Err(ParseEntityTokenError::Malformed),
+            "{malformed:?}"
+        );
     }
+    let (body, checksum) = token.split_once('-').unwrap();
+    let wrong = (u8::from_str_radix(checksum, 16).unwrap() ^ 1) as u32;
+    assert_eq!(
+        Entity::from_token(&format!("{body}-{wrong:02x}")),
+        Err(ParseEntityTokenError::ChecksumMismatch)
+    );
+    assert_eq!(
+        frame.resolve_token("1v1-zz"),
+        Err(ResolveTokenError::Invalid(ParseEntityTokenError::Malformed))
+    );
+
+    frame.despawn(b).unwrap();
+    assert_eq!(
+        frame.resolve_token(&token),
+        Err(ResolveTokenError::Stale(b))
+    );
+    let c = frame.spawn(());
+    assert_eq!(c.id(), b.id());
+    assert_eq!(
+        frame.resolve_token(&token),
+        Err(ResolveTokenError::Stale(b))
+    );
+    assert_eq!(frame.resolve_token(&c.to_token()), Ok(c));
+}
 
+#[test]
+fn query_collect_into() {
     let mut frame = Frame::new();
-    let e = frame.spawn((TestComponent { id: 21 },));
+    let a = frame.spawn((1, String::from("a")));
+    let b = frame.spawn((2, String::from("b"), true));
+    frame.spawn((3.0f32,));
 
-    let e_ref = frame.entity(e).unwrap();
-    {
-        let comp = e_ref.get::<&'_ TestComponent>().unwrap();
-        // Test that no unbalanced releases occur when cloning refs.
-        let _comp2 = comp.clone();
-        let id = Ref::map(comp, |c| &c.id);
-        assert_eq!(*id, 21);
-    }
+    let mut out = Vec::new();
+    frame
+        .query::<(&i32, &String, Option<&bool>)>()
+        .collect_into(&mut out);
+    out.sort_by_key(|&(e, _)| e);
+    assert_eq!(
+        out,
+        [
+            (a, (1, String::from("a"), None)),
+            (b, (2, String::from("b"), Some(true))),
+        ]
+    );
 
-    {
-        let comp = e_ref.get::<&'_ mut TestComponent>().unwrap();
-        let mut id = RefMut::map(comp, |c| &mut c.id);
-        *id = 31;
+    let capacity = out.capacity();
+    frame.despawn(a).unwrap();
+    frame
+        .query_mut::<(&mut i32, &String, Option<&bool>)>()
+        .collect_into(&mut out);
+    assert_eq!(out, [(b, (2, String::from("b"), Some(true)))]);
+    assert_eq!(out.capacity(), capacity);
+
+    let mut flags = Vec::new();
+    frame.query::<Satisfies<&bool>>().collect_into(&mut flags);
+    assert_eq!(flags.len(), 2);
+}
+
+#[test]
+fn component_validation() {
+    #[derive(Debug, Clone, Copy)]
+    struct Health(i32);
+
+    let mut registry = ComponentRegistry::new();
+    registry
+        .register::<Health>()
+        .name("Health")
+        .validate(|h| h.0 >= 0);
+    registry.register::<f32>().validate(|x| x.is_finite());
+    registry.register::<bool>();
+    let registry = std::sync::Arc::new(registry);
+
+    let mut frame = Frame::new();
+    frame.set_validator(Some(registry.clone()));
+    let a = frame.spawn((Health(3), 1.0f32));
+    let b = frame.spawn((Health(0), true));
+    frame.insert_one(b, 2.0f32).unwrap();
+    frame.get::<&mut Health>(a).unwrap().0 = -1;
+
+    let mut results = Vec::new();
+    frame
+        .entity(a)
+        .unwrap()
+        .visit(&mut |component: DynamicComponent<'_>| {
+            let entry = registry.get(component.type_info().id()).unwrap();
+            results.push((entry.name(), entry.validate(component)));
+        });
+    results.sort();
+    assert_eq!(results, [("Health", Some(false)), ("f32", Some(true))]);
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        frame.insert_one(b, f32::NAN).unwrap();
+    }));
+    assert!(result.is_err());
+
+    frame.set_validator(None);
+    let mut registry = ComponentRegistry::new();
+    registry.register::<Health>().validate(|h| h.0 >= 0);
+    assert_eq!(
+        frame.validate_components(&registry),
+        Err(InvalidComponent {
+            entity: a,
+            name: std::any::type_name::<Health>(),
+        })
+    );
+    frame.get::<&mut Health>(a).unwrap().0 = 1;
+    assert_eq!(frame.validate_components(&registry), Ok(()));
+}
+
+#[test]
+fn invalidate_handles() {
+    let mut frame = Frame::new();
+    frame.set_handle_quarantine(3);
+    assert_eq!(frame.handle_quarantine(), 3);
+    let a = frame.spawn((1,));
+    let b = frame.spawn((2,));
+    let c = frame.spawn((3,));
+    assert_eq!(frame.invalidate_handles(&[a, b, a]), 2);
+    assert!(!frame.contains(a) && !frame.contains(b));
+    assert_eq!(frame.len(), 1);
+
+    // Ordinary despawns are recycled first
+    frame.despawn(c).unwrap();
+    let c2 = frame.spawn(());
+    assert_eq!(c2.id(), c.id());
+    let batch = frame.spawn_batch((0..2).map(|i| (i,))).collect::<Vec<_>>();
+    assert!(batch.iter().all(|e| e.id() != a.id() && e.id() != b.id()));
+    let reused = [frame.spawn(()), frame.spawn(())];
+    let mut ids = reused.map(|e| e.id());
+    ids.sort_unstable();
+    assert_eq!(ids, [a.id(), b.id()]);
+    assert!(!frame.contains(a) && !frame.contains(b));
+}
+
+#[test]
+fn gather_interleaved() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1u16, [2u8; 2]));
+    let b = frame.spawn((3u16, [4u8; 2], true));
+    frame.spawn((5u16,));
+    let c = frame.spawn((6u16, [7u8; 2]));
+
+    let mut layout = InterleavedLayout::new();
+    unsafe {
+        layout.field::<[u8; 2]>().padding(1).field::<u16>();
     }
+    assert_eq!(layout.stride(), 5);
 
-    {
-        let comp = e_ref.get::<&'_ TestComponent>().unwrap();
-        let id = Ref::map(comp, |c| &c.id);
-        assert_eq!(*id, 31);
+    assert_eq!(
+        frame.gather_interleaved::<()>(&layout, &mut [0; 14]),
+        Err(BufferTooSmall { required: 15 })
+    );
+
+    let mut buffer = [0xff; 16];
+    assert_eq!(frame.gather_interleaved::<()>(&layout, &mut buffer), Ok(3));
+    let order = frame
+        .query::<(&u16, &[u8; 2])>()
+        .iter()
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    let mut expected = Vec::new();
+    for e in order {
+        let x = frame.get::<&u16>(e).unwrap().to_ne_bytes();
+        let y = *frame.get::<&[u8; 2]>(e).unwrap();
+        expected.extend_from_slice(&[y[0], y[1], 0xff, x[0], x[1]]);
     }
+    expected.push(0xff);
+    assert_eq!(buffer[..], expected[..]);
+
+    let mut buffer = [0; 5];
+    assert_eq!(
+        frame.gather_interleaved::<Without<(), &bool>>(&layout, &mut buffer[..]),
+        Err(BufferTooSmall { required: 10 })
+    );
+    frame.despawn(a).unwrap();
+    frame.despawn(c).unwrap();
+    assert_eq!(
+        frame.gather_interleaved::<With<(), &bool>>(&layout, &mut buffer),
+        Ok(1)
+    );
+    assert_eq!(buffer[..3], [4, 4, 0]);
+    assert_eq!(buffer[3..], 3u16.to_ne_bytes());
+    assert!(frame.contains(b));
 }
 
 #[test]
-fn query_many() {
+fn observers() {
+    use std::sync::{Arc, Mutex};
+
     let mut frame = Frame::new();
-    let a = frame.spawn((42, true));
-    let b = frame.spawn((17,));
-    assert_eq!(frame.query_many_mut::<&i32, 2>([a, b]), [Ok(&42), Ok(&17)]);
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let sink = log.clone();
+    let observer = frame.observe::<Without<&i32, &bool>>(move |entity, event| {
+        sink.lock().unwrap().push((entity, event))
+    });
+    let everything = Arc::new(Mutex::new(Vec::new()));
+    let sink = everything.clone();
+    frame.observe::<()>(move |entity, event| sink.lock().unwrap().push((entity, event)));
+    let take =
+        |log: &Arc<Mutex<Vec<(Entity, MatchEvent)>>>| std::mem::take(&mut *log.lock().unwrap());
+
+    let a = frame.spawn((1,));
+    let b = frame.spawn(("abc",));
+    assert_eq!(take(&log), [(a, MatchEvent::Entered)]);
+    frame.insert_one(b, 2).unwrap();
+    frame.insert_one(a, true).unwrap();
+    assert_eq!(
+        take(&log),
+        [(b, MatchEvent::Entered), (a, MatchEvent::Exited)]
+    );
+    frame.remove_one::<bool>(a).unwrap();
+    frame.despawn(b).unwrap();
+    assert_eq!(
+        take(&log),
+        [(a, MatchEvent::Entered), (b, MatchEvent::Exited)]
+    );
+
+    let batch = frame.spawn_batch((0..2).map(|i| (i,))).collect::<Vec<_>>();
+    let mut ty = ColumnBatchType::new();
+    ty.add::<i32>();
+    let mut builder = ty.into_batch(1);
+    builder.writer::<i32>().unwrap().push(3).unwrap();
+    let columns = frame
+        .spawn_column_batch(builder.build().unwrap())
+        .collect::<Vec<_>>();
+    let expected = batch
+        .iter()
+        .chain(&columns)
+        .map(|&e| (e, MatchEvent::Entered))
+        .collect::<Vec<_>>();
+    assert_eq!(take(&log), expected);
+
+    // Replacing an entity with `spawn_at` reports the old one leaving
+    frame.spawn_at(columns[0], (false,));
+    assert_eq!(take(&log), [(columns[0], MatchEvent::Exited)]);
+
+    let reserved = frame.reserve_entity();
+    take(&everything);
+    frame.flush();
+    assert_eq!(take(&everything), [(reserved, MatchEvent::Entered)]);
+
+    assert!(frame.remove_observer(observer));
+    assert!(!frame.remove_observer(observer));
+    frame.despawn(a).unwrap();
+    assert!(take(&log).is_empty());
+
+    let remaining = frame.iter().map(|e| e.entity()).collect::<Vec<_>>();
+    take(&everything);
+    frame.clear();
+    let mut cleared = take(&everything);
+    cleared.sort_by_key(|&(e, _)| e);
+    let mut expected = remaining
+        .into_iter()
+        .map(|e| (e, MatchEvent::Exited))
+        .collect::<Vec<_>>();
+    expected.sort_by_key(|&(e, _)| e);
+    assert_eq!(cleared, expected);
 }
 
 #[test]
-#[should_panic]
-fn query_many_duplicate() {
+fn spawn_batch_at() {
     let mut frame = Frame::new();
-    let e = frame.spawn(());
-    _ = frame.query_many_mut::<(), 2>([e, e]);
+    let a = frame.spawn((1, true));
+    let b = frame.spawn((2, "abc"));
+    // Moved within the target archetype when `b` is replaced
+    let e = frame.spawn((6, "mno"));
+    frame.despawn(a).unwrap();
+    let c = Entity::from_bits(10 << 32 | 7).unwrap();
+
+    frame.spawn_batch_at([(a, (3, "def")), (b, (4, "ghi")), (c, (5, "jkl"))]);
+    assert_eq!(frame.len(), 4);
+    assert_eq!(frame.archetypes().filter(|x| !x.is_empty()).count(), 1);
+    for (entity, expected) in [(a, 3), (b, 4), (c, 5), (e, 6)] {
+        assert_eq!(*frame.get::<&i32>(entity).unwrap(), expected);
+    }
+    assert_eq!(*frame.get::<&&str>(b).unwrap(), "ghi");
+
+    let d = frame.spawn(());
+    assert!(d != a && d != b && d != c && d != e);
+
+    let duplicated = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        frame.spawn_batch_at([(d, (7,)), (d, (8,))]);
+    }));
+    assert!(duplicated.is_err());
+    assert_eq!(frame.len(), 5);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn borrow_deadline() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    struct Slow;
+    static OVERRUNS: AtomicUsize = AtomicUsize::new(0);
+
+    set_long_borrow_handler(|borrow| {
+        if borrow.type_name.ends_with("Slow") {
+            assert!(borrow.unique);
+            OVERRUNS.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+    set_borrow_deadline(Some(Duration::from_millis(20)));
+
+    let mut frame = Frame::new();
+    let e = frame.spawn((Slow,));
+    drop(frame.get::<&mut Slow>(e).unwrap());
+    assert_eq!(OVERRUNS.load(Ordering::Relaxed), 0);
+    let guard = frame.get::<&mut Slow>(e).unwrap();
+    std::thread::sleep(Duration::from_millis(40));
+    drop(guard);
+    assert_eq!(OVERRUNS.load(Ordering::Relaxed), 1);
+
+    set_borrow_deadline(None);
+}
+
+#[test]
+fn fill_defaults() {
+    #[derive(Default, Debug, PartialEq)]
+    struct Velocity(f32);
+    struct Body;
+
+    let mut registry = ComponentRegistry::new();
+    registry.register::<Velocity>().defaultable();
+    registry.register::<u8>().default_with(|| 7);
+    registry.register::<bool>();
+    assert!(registry
+        .get(std::any::TypeId::of::<u8>())
+        .unwrap()
+        .has_default());
+    assert!(!registry
+        .get(std::any::TypeId::of::<bool>())
+        .unwrap()
+        .has_default());
+
+    let mut frame = Frame::new();
+    let a = frame.spawn((true,));
+    let b = frame.spawn((Velocity(1.0), 3u8));
+    let c = frame.spawn((5u8,));
+    let d = frame.spawn((Body, 5u8));
+    let schema = Schema::new().require::<Velocity>().require::<u8>();
+    let mut report = registry.fill_defaults(&mut frame, &schema);
+    report.sort_by_key(|x| (x.entity, x.name));
+    let velocity = std::any::type_name::<Velocity>();
+    let mut expected = [
+        DefaultedComponent {
+            entity: a,
+            name: velocity,
+        },
+        DefaultedComponent {
+            entity: a,
+            name: "u8",
+        },
+        DefaultedComponent {
+            entity: c,
+            name: velocity,
+        },
+        DefaultedComponent {
+            entity: d,
+            name: velocity,
+        },
+    ];
+    expected.sort_by_key(|x| (x.entity, x.name));
+    assert_eq!(report, expected);
+
+    assert_eq!(*frame.get::<&Velocity>(a).unwrap(), Velocity(0.0));
+    assert_eq!(*frame.get::<&u8>(a).unwrap(), 7);
+    assert!(*frame.get::<&bool>(a).unwrap());
+    assert_eq!(*frame.get::<&Velocity>(b).unwrap(), Velocity(1.0));
+    assert_eq!(*frame.get::<&u8>(b).unwrap(), 3);
+    assert_eq!(*frame.get::<&u8>(c).unwrap(), 5);
+    assert!(registry.fill_defaults(&mut frame, &schema).is_empty());
+
+    // Scoped schemas only cover entities having the scope's types
+    let e = frame.spawn((Body,));
+    let f = frame.spawn((false,));
+    let schema = Schema::new().scope::<Body>().require::<u8>();
+    assert_eq!(
+        registry.fill_defaults(&mut frame, &schema),
+        [DefaultedComponent {
+            entity: e,
+            name: "u8"
+        }]
+    );
+    assert_eq!(*frame.get::<&u8>(e).unwrap(), 7);
+    assert!(!frame.entity(f).unwrap().has::<u8>());
+}
+
+#[test]
+#[should_panic(expected = "has no registered default")]
+fn fill_defaults_requires_default() {
+    let mut registry = ComponentRegistry::new();
+    registry.register::<bool>();
+    let schema = Schema::new().scope::<i32>().require::<bool>();
+    // Checked even when no entity is covered
+    registry.fill_defaults(&mut Frame::new(), &schema);
+}
+
+#[test]
+fn query_dynamic() {
+    use std::any::TypeId;
+
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true, "abc"));
+    let b = frame.spawn((2, "def"));
+
+    let mut query = DynamicQuery::new();
+    query
+        .read(TypeId::of::<i32>())
+        .write(TypeId::of::<&str>())
+        .read(TypeId::of::<&str>())
+        .without(TypeId::of::<bool>());
+    assert!(frame.entity(a).unwrap().query_dynamic(&query).is_none());
+
+    let entity = frame.entity(b).unwrap();
+    let mut row = entity.query_dynamic(&query).unwrap();
+    let number = row.get(TypeId::of::<i32>()).unwrap();
+    assert_eq!(number.downcast_ref::<i32>(), Some(&2));
+    assert!(row.get(TypeId::of::<bool>()).is_none());
+    assert!(row.get_mut::<i32>().is_none());
+    *row.get_mut::<&str>().unwrap() = "ghi";
+    assert!(entity.get::<&i32>().is_some());
+    drop(row);
+    assert_eq!(*entity.get::<&&str>().unwrap(), "ghi");
+
+    let mut query = DynamicQuery::new();
+    query.with(TypeId::of::<bool>());
+    let row = frame.entity(a).unwrap().query_dynamic(&query).unwrap();
+    assert!(row.get(TypeId::of::<bool>()).is_none());
+    assert!(frame.entity(b).unwrap().query_dynamic(&query).is_none());
+}
+
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn query_dynamic_borrow_conflict() {
+    use std::any::TypeId;
+
+    let mut frame = Frame::new();
+    let e = frame.spawn((1,));
+    let entity = frame.entity(e).unwrap();
+    let _guard = entity.get::<&i32>().unwrap();
+    let mut query = DynamicQuery::new();
+    query.write(TypeId::of::<i32>());
+    entity.query_dynamic(&query);
+}
+
+#[test]
+fn weak_entity() {
+    struct Target(WeakEntity);
+
+    let mut frame = Frame::new();
+    let a = frame.spawn(());
+    let b = frame.spawn((Target(WeakEntity::new(&frame, a)),));
+    let target = frame.get::<&Target>(b).unwrap().0;
+    assert_eq!(target.entity(), a);
+    assert_eq!(target.resolve(&frame), Some(a));
+
+    let mut other = Frame::new();
+    other.spawn_at(a, ());
+    assert_eq!(target.resolve(&other), None);
+
+    frame.despawn(a).unwrap();
+    let c = frame.spawn(());
+    assert_eq!(c.id(), a.id());
+    assert_eq!(target.resolve(&frame), None);
+}
+
+#[test]
+fn replace_column() {
+    let mut frame = Frame::new();
+    let entities = (0..3)
+        .map(|i| frame.spawn((i.to_string(), i)))
+        .collect::<Vec<_>>();
+    let index = frame.archetypes().position(|x| x.has::<String>()).unwrap();
+    let capacity = frame.archetypes().nth(index).unwrap().capacity() as usize;
+
+    // Copied into place
+    let old = frame
+        .replace_column(index, vec!["a".to_string(), "b".into(), "c".into()])
+        .unwrap();
+    assert_eq!(old, ["0", "1", "2"]);
+    // Adopted
+    let mut values = Vec::with_capacity(capacity);
+    values.extend(["d".to_string(), "e".into(), "f".into()]);
+    let old = frame.replace_column(index, values).unwrap();
+    assert_eq!(old, ["a", "b", "c"]);
+    assert_eq!(old.capacity(), capacity);
+    for (&entity, expected) in entities.iter().zip(["d", "e", "f"]) {
+        assert_eq!(*frame.get::<&String>(entity).unwrap(), expected);
+    }
+
+    assert_eq!(
+        frame.replace_column(index, vec![String::new()]),
+        Err(ReplaceColumnError::WrongLength {
+            expected: 3,
+            actual: 1
+        })
+    );
+    assert_eq!(
+        frame.replace_column(index, vec![true; 3]),
+        Err(ReplaceColumnError::MissingComponent(
+            MissingComponent::new::<bool>()
+        ))
+    );
+
+    frame.despawn(entities[0]).unwrap();
+    let e = frame.spawn(("g".to_string(), 3));
+    assert_eq!(*frame.get::<&String>(e).unwrap(), "g");
+    assert_eq!(*frame.get::<&String>(entities[2]).unwrap(), "f");
+}
+
+#[test]
+fn iter_batched() {
+    let mut frame = Frame::new();
+    let mut expected = frame.spawn_batch((0..5).map(|i| (i,))).collect::<Vec<_>>();
+    expected.push(frame.spawn((true,)));
+    expected.extend(frame.spawn_batch((0..2).map(|i| (i, "abc"))));
+    let empty = frame.spawn((1.0f32,));
+    frame.despawn(empty).unwrap();
+
+    let batches = frame
+        .iter_batched(2)
+        .map(|batch| batch.map(|x| x.entity()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut lens = batches.iter().map(|x| x.len()).collect::<Vec<_>>();
+    lens.sort_unstable();
+    assert_eq!(lens, [1, 1, 2, 2, 2]);
+    let mut seen = batches.concat();
+    seen.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(seen, expected);
+}
+
+#[test]
+fn prepared_query_warm() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let mut query = PreparedQuery::<&mut i32>::new();
+    assert!(query.warm(&frame));
+    assert!(!query.warm(&frame));
+
+    // Warming doesn't borrow
+    let guard = frame.get::<&mut i32>(a).unwrap();
+    assert!(!query.warm(&frame));
+    drop(guard);
+
+    frame.spawn((2, "abc"));
+    assert!(query.warm(&frame));
+    for (_, x) in query.query_mut(&mut frame) {
+        *x += 1;
+    }
+    assert!(!query.warm(&frame));
+    assert_eq!(*frame.get::<&i32>(a).unwrap(), 2);
+    assert!(query.warm(&Frame::new()));
+}
+
+#[test]
+fn clone_entity_bundle() {
+    let mut registry = ComponentRegistry::new();
+    registry.register::<i32>().copyable();
+    registry.register::<String>().cloneable();
+    registry.register::<bool>().debug();
+
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, "abc".to_string(), true, 'x'));
+    let bundle = registry.clone_entity_bundle(frame.entity(a).unwrap());
+    let mut builder = bundle.to_builder();
+    builder.add(2);
+    let b = frame.spawn(&bundle);
+    let c = frame.spawn(&builder.build());
+    for (entity, number) in [(b, 1), (c, 2)] {
+        let entity = frame.entity(entity).unwrap();
+        assert_eq!(*entity.get::<&i32>().unwrap(), number);
+        assert_eq!(*entity.get::<&String>().unwrap(), "abc");
+        assert!(!entity.has::<bool>());
+        assert!(!entity.has::<char>());
+    }
+    assert_eq!(*frame.get::<&String>(a).unwrap(), "abc");
+
+    let empty = frame.spawn(());
+    let bundle = registry.clone_entity_bundle(frame.entity(empty).unwrap());
+    let d = frame.spawn(&bundle);
+    assert_eq!(frame.entity(d).unwrap().len(), 0);
+}
+
+#[test]
+fn filter_archetypes() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    frame.spawn((2,));
+    let c = frame.spawn((3, "abc"));
+    let chosen = frame
+        .archetypes()
+        .filter(|x| x.has::<bool>() || x.has::<&str>())
+        .map(|x| x.ids().to_vec())
+        .collect::<Vec<_>>();
+
+    let mut query = frame
+        .query::<&i32>()
+        .filter_archetypes(|x| chosen.iter().any(|ids| ids == x.ids()));
+    let iter = query.iter();
+    assert_eq!(iter.len(), 2);
+    let mut found = iter.map(|(e, &x)| (e, x)).collect::<Vec<_>>();
+    found.sort_unstable();
+    assert_eq!(found, [(a, 1), (c, 3)]);
+    let batched = query.iter_batched(1).flatten().count();
+    assert_eq!(batched, 2);
+
+    let found = frame
+        .query::<&i32>()
+        .filter_archetypes(|_| false)
+        .without::<&bool>()
+        .iter()
+        .count();
+    assert_eq!(found, 0);
+}
+
+#[test]
+fn regroup() {
+    let mut frame = Frame::new();
+    assert_eq!(frame.regroup(), 0);
+    frame.set_group_key::<u8>();
+
+    let mut spawned = Vec::new();
+    for i in 0..50u32 {
+        let key = (i * 7 % 5) as u8;
+        spawned.push((frame.spawn((key, i, i.to_string())), key, i));
+    }
+    spawned.push((frame.spawn((3u8,)), 3, 0));
+    frame.despawn(spawned.remove(10).0).unwrap();
+    assert_eq!(frame.regroup(), 1);
+    assert_eq!(frame.regroup(), 0);
+
+    let keys = frame
+        .query::<(&u8, &u32)>()
+        .iter()
+        .map(|(_, (&k, _))| k)
+        .collect::<Vec<_>>();
+    assert!(keys.windows(2).all(|x| x[0] <= x[1]));
+    for &(entity, key, i) in &spawned {
+        assert_eq!(*frame.get::<&u8>(entity).unwrap(), key);
+        if let Ok(x) = frame.get::<&u32>(entity) {
+            assert_eq!(*x, i);
+            assert_eq!(*frame.get::<&String>(entity).unwrap(), i.to_string());
+        }
+    }
+    frame.despawn(spawned[0].0).unwrap();
+    assert!(!frame.contains(spawned[0].0));
+    assert_eq!(*frame.get::<&u32>(spawned[1].0).unwrap(), 1);
+
+    frame.clear_group_key();
+    frame.spawn((0u8, 0u32, String::new()));
+    assert_eq!(frame.regroup(), 0);
+}
+
+#[test]
+fn move_bundle() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, "abc".to_string(), true));
+    let b = frame.spawn((2, 'x'));
+    frame.move_bundle::<(i32, String)>(a, b).unwrap();
+    assert_eq!(frame.entity(a).unwrap().len(), 1);
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 1);
+    assert_eq!(*frame.get::<&String>(b).unwrap(), "abc");
+    assert_eq!(*frame.get::<&char>(b).unwrap(), 'x');
+
+    assert_eq!(
+        frame.move_bundle::<(i32,)>(a, b),
+        Err(ComponentError::MissingComponent(
+            MissingComponent::new::<i32>()
+        ))
+    );
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 1);
+    frame.move_bundle::<(String,)>(b, b).unwrap();
+    assert_eq!(*frame.get::<&String>(b).unwrap(), "abc");
+    assert!(frame.move_bundle::<(bool,)>(a, a).is_ok());
+    assert!(frame.move_bundle::<(i32,)>(a, a).is_err());
+
+    let c = frame.spawn(());
+    frame.despawn(c).unwrap();
+    assert_eq!(
+        frame.move_bundle::<(String,)>(b, c),
+        Err(ComponentError::NoSuchEntity)
+    );
+    assert_eq!(*frame.get::<&String>(b).unwrap(), "abc");
+}
+
+#[test]
+#[cfg(feature = "reflect")]
+fn get_field_mut() {
+    #[derive(Debug, PartialEq)]
+    struct Vec2 {
+        x: f32,
+        y: f32,
+    }
+    struct Body {
+        position: Vec2,
+        mass: f32,
+    }
+
+    let mut registry = ComponentRegistry::new();
+    registry
+        .register::<Body>()
+        .name("Body")
+        .field("position", |b| &mut b.position)
+        .field("mass", |b| &mut b.mass);
+    registry
+        .register::<Vec2>()
+        .field("x", |v| &mut v.x)
+        .field("y", |v| &mut v.y);
+    registry.register::<u32>().name("Id");
+
+    let mut frame = Frame::new();
+    let e = frame.spawn((
+        Body {
+            position: Vec2 { x: 1.0, y: 2.0 },
+            mass: 3.0,
+        },
+        7u32,
+    ));
+    *frame
+        .get_field_mut::<f32>(&registry, e, "Body.position.y")
+        .unwrap() = 5.0;
+    *frame
+        .get_field_mut::<f32>(&registry, e, "Body.mass")
+        .unwrap() = 6.0;
+    frame
+        .get_field_mut::<Vec2>(&registry, e, "Body.position")
+        .unwrap()
+        .x = 8.0;
+    *frame.get_field_mut::<u32>(&registry, e, "Id").unwrap() += 1;
+    {
+        let body = frame.get::<&Body>(e).unwrap();
+        assert_eq!(body.position, Vec2 { x: 8.0, y: 5.0 });
+        assert_eq!(body.mass, 6.0);
+    }
+    assert_eq!(*frame.get::<&u32>(e).unwrap(), 8);
+
+    assert!(frame
+        .get_field_mut::<f64>(&registry, e, "Body.mass")
+        .is_none());
+    assert!(frame
+        .get_field_mut::<f32>(&registry, e, "Body.velocity")
+        .is_none());
+    assert!(frame
+        .get_field_mut::<f32>(&registry, e, "Body.mass.x")
+        .is_none());
+    assert!(frame
+        .get_field_mut::<f32>(&registry, e, "Shape.mass")
+        .is_none());
+    let f = frame.spawn((1u32,));
+    assert!(frame
+        .get_field_mut::<f32>(&registry, f, "Body.mass")
+        .is_none());
+    frame.despawn(e).unwrap();
+    assert!(frame.get_field_mut::<u32>(&registry, e, "Id").is_none());
+}
+
+#[test]
+fn from_raw_parts() {
+    let mut source = Frame::new();
+    let a = source.spawn((1, true));
+    let b = source.spawn((2,));
+    let c = source.spawn((3, false));
+    let d = source.spawn((4, true));
+    source.despawn(b).unwrap();
+
+    let mut ty = ColumnBatchType::new();
+    ty.add::<i32>().add::<bool>();
+    let bake = |handles: &[Entity]| {
+        let mut batch = ty.clone().into_batch(handles.len() as u32);
+        let mut ints = batch.writer::<i32>().unwrap();
+        for &handle in handles {
+            ints.push(*source.get::<&i32>(handle).unwrap()).unwrap();
+        }
+        let mut bools = batch.writer::<bool>().unwrap();
+        for &handle in handles {
+            bools.push(*source.get::<&bool>(handle).unwrap()).unwrap();
+        }
+        batch.build().unwrap()
+    };
+    // Separate batches of the same archetype are merged
+    let batches = [(bake(&[a, c]), &[a, c][..]), (bake(&[d]), &[d][..])];
+
+    let mut frame = unsafe { Frame::from_raw_parts(batches) };
+    assert_eq!(frame.len(), 3);
+    assert!(!frame.contains(b));
+    for handle in [a, c, d] {
+        assert_eq!(
+            frame.query_one_mut::<(&i32, &bool)>(handle).unwrap(),
+            source.query_one_mut::<(&i32, &bool)>(handle).unwrap()
+        );
+    }
+    assert_eq!(frame.archetypes().filter(|x| !x.is_empty()).count(), 1);
+
+    let e = frame.spawn((5,));
+    assert_eq!(e.id(), b.id());
+    frame.despawn(a).unwrap();
+    assert_eq!(*frame.get::<&i32>(d).unwrap(), 4);
+}
+
+#[test]
+fn component_masks() {
+    let mut registry = ComponentRegistry::new();
+    let number = registry.register::<i32>().index();
+    let flag = registry.register::<bool>().index();
+    assert_eq!(registry.register::<i32>().index(), number);
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true, "unregistered"));
+    let b = frame.spawn((2,));
+    let c = frame.spawn(());
+
+    let masks = registry.archetype_masks(&frame);
+    assert_eq!(masks.len(), frame.archetypes().len());
+    let mask = |e| &masks[frame.archetype_id(e).unwrap() as usize];
+    assert!(mask(a).contains(number) && mask(a).contains(flag));
+    assert!(mask(b).contains(number) && !mask(b).contains(flag));
+    assert_eq!(mask(c).words(), [0]);
+    assert!(mask(a).contains_all(mask(b)));
+    assert!(!mask(b).contains_all(mask(a)));
+    assert!(!mask(a).contains(64));
+
+    frame.despawn(c).unwrap();
+    assert!(frame.archetype_id(c).is_err());
+}
+
+#[test]
+fn run_with_deferred_ops() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let b = frame.spawn((2, false));
+    let c = frame.spawn((3,));
+    let mut visited = 0;
+    frame.run::<(&mut i32, Option<&bool>)>(|entity, (n, flag), ops| {
+        visited += 1;
+        *n *= 10;
+        match flag {
+            Some(true) => ops.remove_one::<bool>(entity),
+            Some(false) => ops.despawn(entity),
+            None => {
+                ops.insert_one(entity, "c");
+                ops.spawn((*n + 1,));
+            }
+        }
+    });
+    assert_eq!(visited, 3);
+    assert_eq!(frame.len(), 3);
+    assert_eq!(frame.query_one_mut::<&i32>(a).unwrap(), &10);
+    assert!(!frame.satisfies::<&bool>(a).unwrap());
+    assert!(!frame.contains(b));
+    assert_eq!(
+        frame.query_one_mut::<(&i32, &&str)>(c).unwrap(),
+        (&30, &"c")
+    );
+    assert!(frame.query_mut::<&i32>().into_iter().any(|(_, &n)| n == 31));
+}
+
+#[test]
+fn archetype_versions() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let index = frame.archetype_id(a).unwrap() as usize;
+    let versions = |frame: &Frame| {
+        let archetype = frame.archetypes().nth(index).unwrap();
+        (
+            archetype.version(),
+            archetype.column_version::<i32>().unwrap(),
+            archetype.column_version::<bool>().unwrap(),
+        )
+    };
+    let (structure, ints, bools) = versions(&frame);
+
+    // Reads change nothing
+    for _ in frame.query::<&i32>().iter() {}
+    assert!(*frame.get::<&bool>(a).unwrap());
+    assert_eq!(versions(&frame), (structure, ints, bools));
+
+    // Unique access changes only the affected column
+    *frame.get::<&mut i32>(a).unwrap() += 1;
+    let (_, new_ints, new_bools) = versions(&frame);
+    assert_ne!(new_ints, ints);
+    assert_eq!(new_bools, bools);
+    for (_, x) in frame.query_mut::<&mut bool>() {
+        *x = false;
+    }
+    assert_ne!(versions(&frame).2, bools);
+
+    // Structural changes
+    let (structure, ints, _) = versions(&frame);
+    let b = frame.spawn((2, true));
+    assert_ne!(versions(&frame).0, structure);
+    let (structure, _, _) = versions(&frame);
+    frame.despawn(b).unwrap();
+    assert_ne!(versions(&frame).0, structure);
+    let (structure, _, _) = versions(&frame);
+    frame.insert_one(a, 5).unwrap();
+    assert_eq!(versions(&frame).0, structure);
+    assert_ne!(versions(&frame).1, ints);
+}
+
+#[test]
+fn user_data() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1,));
+    let b = frame.spawn((2,));
+    frame.set_user_data(a, 7).unwrap();
+    frame.set_user_data(b, u64::MAX).unwrap();
+
+    // Unaffected by archetype changes
+    frame.insert_one(a, true).unwrap();
+    frame.remove_one::<i32>(b).unwrap();
+    assert_eq!(frame.user_data(a), Ok(7));
+    assert_eq!(frame.user_data(b), Ok(u64::MAX));
+
+    // Reset when the ID is reused
+    frame.despawn(b).unwrap();
+    assert_eq!(frame.set_user_data(b, 1), Err(NoSuchEntity));
+    let c = frame.spawn(());
+    assert_eq!(c.id(), b.id());
+    assert_eq!(frame.user_data(c), Ok(0));
+
+    // Reset when a handle is respawned in place
+    frame.spawn_at(a, (2,));
+    assert_eq!(frame.user_data(a), Ok(0));
+
+    frame.set_user_data(c, 3).unwrap();
+    frame.clear();
+    let d = frame.spawn(());
+    assert_eq!(frame.user_data(d), Ok(0));
+}
+
+#[test]
+fn iter_strided() {
+    let mut frame = Frame::new();
+    let a = (0..5).map(|i| frame.spawn((i,))).collect::<Vec<_>>();
+    let b = (0..4).map(|i| frame.spawn((i, true))).collect::<Vec<_>>();
+    let mut query = frame.query::<&i32>();
+    let even = query.iter_strided(0, 2).map(|(e, _)| e).collect::<Vec<_>>();
+    assert_eq!(even, [a[0], a[2], a[4], b[0], b[2]]);
+    let odd = query.iter_strided(3, 2).map(|(e, _)| e).collect::<Vec<_>>();
+    assert_eq!(odd, [a[1], a[3], b[1], b[3]]);
+    drop(query);
+
+    for (_, x) in frame.query_mut::<&mut i32>().into_iter_strided(1, 4) {
+        *x = -1;
+    }
+    assert_eq!(*frame.get::<&i32>(a[1]).unwrap(), -1);
+    assert_eq!(*frame.get::<&i32>(b[1]).unwrap(), -1);
+    assert_eq!(*frame.get::<&i32>(a[2]).unwrap(), 2);
+    assert_eq!(
+        frame
+            .query::<&i32>()
+            .without::<&bool>()
+            .iter_strided(0, 100)
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn clear_transient() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct JustSpawned;
+    #[allow(dead_code)]
+    struct Damaged(Arc<()>);
+    let damage = Arc::new(());
+    let mut frame = Frame::new();
+    frame.register_transient::<JustSpawned>();
+    frame.register_transient::<Damaged>();
+    frame.register_transient::<Damaged>();
+    let a = frame.spawn((1, JustSpawned));
+    let b = frame.spawn((2, JustSpawned, Damaged(damage.clone())));
+    let c = frame.spawn((JustSpawned,));
+    let d = frame.spawn((4, true));
+    let e = frame.spawn((5, Damaged(damage.clone())));
+    let entered = Arc::new(AtomicUsize::new(0));
+    let counter = entered.clone();
+    frame.observe::<Without<&i32, &JustSpawned>>(move |_, event| {
+        if event == MatchEvent::Entered {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+    assert_eq!(frame.clear_transient(), 4);
+    assert_eq!(entered.load(Ordering::Relaxed), 2);
+    assert_eq!(frame.query::<&JustSpawned>().iter().count(), 0);
+    assert_eq!(frame.query::<&Damaged>().iter().count(), 0);
+    assert_eq!(Arc::strong_count(&damage), 1);
+    for (entity, value) in [(a, 1), (b, 2), (d, 4), (e, 5)] {
+        assert_eq!(*frame.get::<&i32>(entity).unwrap(), value);
+    }
+    assert!(frame.contains(c));
+    assert!(frame.satisfies::<&bool>(d).unwrap());
+    assert_eq!(frame.clear_transient(), 0);
+
+    let f = frame.spawn((6, JustSpawned));
+    assert_eq!(frame.clear_transient(), 1);
+    assert_eq!(*frame.get::<&i32>(f).unwrap(), 6);
+    frame.despawn(a).unwrap();
+    assert_eq!(*frame.get::<&i32>(f).unwrap(), 6);
+}
+
+#[test]
+fn split() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, 2.0f32, true));
+    let b = frame.spawn((3, 4.0f32));
+    let (read, write) = split!(frame, (f32, bool), (i32));
+    for (_, x) in write.query::<&mut i32>().iter() {
+        *x += *read.get::<f32>(a).unwrap() as i32;
+    }
+    *write.get_mut::<i32>(b).unwrap() += 1;
+    assert_eq!(*write.get::<i32>(a).unwrap(), 3);
+    assert_eq!(*read.get::<f32>(b).unwrap(), 4.0);
+    assert_eq!(read.query::<&bool>().with::<&i32>().iter().count(), 1);
+    assert!(catch_unwind(AssertUnwindSafe(|| read.query::<&mut f32>().iter().count())).is_err());
+    assert!(catch_unwind(AssertUnwindSafe(|| read.get::<i32>(a).is_ok())).is_err());
+    assert!(catch_unwind(AssertUnwindSafe(|| write.query::<&bool>().iter().count())).is_err());
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 6);
+}
+
+#[test]
+#[should_panic(expected = "read and write partitions overlap")]
+fn split_overlapping() {
+    let mut frame = Frame::new();
+    frame.split::<(i32, bool), (f32, i32)>();
+}
+
+#[test]
+#[cfg(feature = "entity-history")]
+fn entity_history() {
+    use std::any::TypeId;
+
+    let mut frame = Frame::new();
+    frame.set_entity_history_len(3);
+    let a = frame.spawn((1,));
+    for _ in 0..4 {
+        frame.insert_one(a, true).unwrap();
+        frame.remove_one::<bool>(a).unwrap();
+    }
+    let history = frame.debug_entity_history(a);
+    assert_eq!(history.len(), 3);
+    assert!(history[0].added.is_empty());
+    assert_eq!(history[0].removed[0].id(), TypeId::of::<bool>());
+    assert_eq!(history[1].added[0].id(), TypeId::of::<bool>());
+    assert_eq!(history[1].from, history[2].to);
+
+    frame.despawn(a).unwrap();
+    let history = frame.debug_entity_history(a);
+    assert_eq!(history.last().unwrap().to, None);
+    assert_eq!(history.last().unwrap().removed.len(), 1);
+    assert!(history.last().unwrap().to_string().starts_with("despawned"));
+
+    let b = frame.spawn((2, 3.0f32));
+    assert_eq!(b.id(), a.id());
+    assert!(frame.debug_entity_history(a).is_empty());
+    let history = frame.debug_entity_history(b);
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].from, None);
+    assert_eq!(history[0].added.len(), 2);
+
+    struct Marker;
+    frame.register_transient::<Marker>();
+    frame.insert_one(b, Marker).unwrap();
+    frame.clear_transient();
+    assert_eq!(frame.debug_entity_history(b).len(), 3);
+}
+
+#[test]
+fn ephemeral_frame() {
+    let mut frame = EphemeralFrame::new();
+    let a = frame.spawn((1, true));
+    let ids = frame
+        .spawn_batch((0..10).map(|i| (i, 2.0f32)))
+        .collect::<Vec<_>>();
+    assert_eq!(frame.len(), 11);
+    assert_eq!(*frame.get::<&i32>(ids[3]).unwrap(), 3);
+    assert_eq!(frame.query::<&i32>().iter().count(), 11);
+    for (_, x) in frame.query_mut::<&mut f32>() {
+        *x *= 2.0;
+    }
+    assert!(frame.contains(a));
+    let capacity = frame
+        .frame()
+        .archetypes()
+        .map(|x| x.capacity())
+        .collect::<Vec<_>>();
+
+    frame.reset();
+    assert!(frame.is_empty());
+    assert!(!frame.contains(ids[0]));
+    let b = frame.spawn((5, true));
+    assert_eq!(b, a);
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 5);
+    assert_eq!(
+        frame
+            .frame()
+            .archetypes()
+            .map(|x| x.capacity())
+            .collect::<Vec<_>>(),
+        capacity
+    );
+
+    // Components with drop glue are still dropped by a reset
+    let shared = std::sync::Arc::new(());
+    frame.spawn((shared.clone(), 1));
+    frame.spawn_batch((0..3).map(|_| (shared.clone(),)));
+    assert_eq!(std::sync::Arc::strong_count(&shared), 5);
+    frame.reset();
+    assert_eq!(std::sync::Arc::strong_count(&shared), 1);
+}
+
+#[test]
+fn optional_mut() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, false));
+    let b = frame.spawn((2,));
+
+    for (_, (&x, flag)) in frame.query::<(&i32, Option<&mut bool>)>().iter() {
+        if let Some(flag) = flag {
+            *flag = x == 1;
+        }
+    }
+    assert!(*frame.get::<&bool>(a).unwrap());
+
+    let mut seen = frame
+        .query_mut::<(&i32, Option<&mut bool>)>()
+        .into_iter()
+        .map(|(_, (&x, flag))| (x, flag.map(|x| core::mem::replace(x, false))))
+        .collect::<Vec<_>>();
+    seen.sort();
+    assert_eq!(seen, [(1, Some(true)), (2, None)]);
+
+    let mut query = PreparedQuery::<Option<&mut bool>>::default();
+    assert_eq!(
+        query
+            .query(&frame)
+            .iter()
+            .filter(|(_, x)| x.is_some())
+            .count(),
+        1
+    );
+    assert_eq!(query.query_mut(&mut frame).count(), 2);
+
+    assert_eq!(frame.query_one_mut::<Option<&mut bool>>(b).unwrap(), None);
+    let mut view = frame.view_mut::<Option<&mut bool>>();
+    let [x, y] = view.get_many_mut([a, b]);
+    assert_eq!(x, Some(Some(&mut false)));
+    assert_eq!(y, Some(None));
+}
+
+#[test]
+#[cfg(feature = "macros")]
+fn derived_query_optional_mut() {
+    #[derive(Query, Debug, PartialEq)]
+    struct Foo<'a> {
+        x: &'a i32,
+        y: Option<&'a mut bool>,
+    }
+
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, false));
+    let b = frame.spawn((2,));
+    for (_, foo) in frame.query_mut::<Foo>() {
+        if let Some(y) = foo.y {
+            *y = true;
+        }
+    }
+    assert!(*frame.get::<&bool>(a).unwrap());
+    assert_eq!(
+        frame.query_one_mut::<Foo>(b).unwrap(),
+        Foo { x: &2, y: None }
+    );
+    let mut query = PreparedQuery::<Foo>::default();
+    assert_eq!(query.query(&frame).iter().count(), 2);
+}
+
+#[test]
+#[cfg(feature = "macros")]
+fn has_bundle() {
+    #[derive(Bundle)]
+    struct Unit {
+        health: i32,
+        alive: bool,
+    }
+
+    let mut frame = Frame::new();
+    let e = frame.spawn(Unit {
+        health: 10,
+        alive: true,
+    });
+    assert!(frame.has_bundle::<Unit>(e).unwrap());
+    assert!(frame.has_bundle::<()>(e).unwrap());
+    assert!(!frame.has_bundle::<(i32, f32)>(e).unwrap());
+    frame.insert_one(e, 1.0f32).unwrap();
+    assert!(frame.has_bundle::<(i32, f32)>(e).unwrap());
+    frame.remove::<Unit>(e).unwrap();
+    assert!(!frame.has_bundle::<Unit>(e).unwrap());
+    assert!(frame.entity(e).unwrap().has_bundle::<(f32,)>());
+    frame.despawn(e).unwrap();
+    assert!(frame.has_bundle::<Unit>(e).is_err());
+}
+
+#[test]
+fn compact_entities() {
+    struct Target(Entity);
+
+    let mut frame = Frame::new();
+    frame.track_previous::<i32>();
+    let entities = (0..10).map(|i| frame.spawn((i, true))).collect::<Vec<_>>();
+    let last = entities[9];
+    frame.insert_one(entities[0], Target(last)).unwrap();
+    frame.set_user_data(last, 99).unwrap();
+    frame.save_previous();
+    for &e in &entities[1..5] {
+        frame.despawn(e).unwrap();
+    }
+    frame.invalidate_handles(&[entities[5]]);
+    let deferred = frame.spawn((10, false));
+    frame.defer().despawn(deferred);
+
+    let mut remap = std::collections::HashMap::new();
+    frame.compact_entities(|old, new| {
+        remap.insert(old, new);
+    });
+    assert_eq!(frame.len(), 5);
+    assert_eq!(remap.len(), 4);
+    let ids = frame
+        .iter()
+        .map(|e| e.entity().id())
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(ids, (0..5).collect());
+    for (i, &e) in entities.iter().enumerate().skip(6) {
+        let new = remap[&e];
+        assert!(!frame.contains(e));
+        assert_eq!(*frame.get::<&i32>(new).unwrap(), i as i32);
+        assert_eq!(*frame.previous::<i32>(new).unwrap(), i as i32);
+    }
+    assert_eq!(*frame.get::<&i32>(entities[0]).unwrap(), 0);
+    assert_eq!(frame.user_data(remap[&last]).unwrap(), 99);
+    for (_, target) in frame.query_mut::<&mut Target>() {
+        target.0 = remap[&target.0];
+    }
+    assert_eq!(frame.get::<&Target>(entities[0]).unwrap().0, remap[&last]);
+
+    // IDs released by compaction are reused without reviving the old handles
+    let mut respawned = (0..6).map(|_| frame.spawn(())).collect::<Vec<_>>();
+    respawned.push(frame.reserve_entity());
+    assert_eq!(respawned[0].id(), 5);
+    assert!(respawned.iter().any(|e| e.id() == last.id()));
+    for &e in &entities[5..] {
+        assert!(!frame.contains(e));
+        assert!(frame.entity(e).is_err());
+    }
+    frame.flush();
+    for &e in &entities[5..] {
+        assert!(!frame.contains(e));
+    }
+    assert!(respawned.iter().all(|&e| frame.contains(e)));
+    for &e in &respawned {
+        frame.despawn(e).unwrap();
+    }
+    frame.compact_entities(|_, _| panic!("already compact"));
+}
+
+#[test]
+fn prepared_systems() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let mut system = PreparedSystems::<(&mut i32, &bool, ())>::new();
+    for tick in 0..3 {
+        let spawned = system.run(&mut frame, |(mut numbers, mut flags, mut all), cmd| {
+            for (_, x) in numbers.iter() {
+                *x += 1;
+            }
+            assert_eq!(flags.iter().count(), tick + 1);
+            cmd.spawn((0, false));
+            all.iter().count()
+        });
+        assert_eq!(spawned, tick + 1);
+    }
+    assert_eq!(frame.len(), 4);
+    assert_eq!(*frame.get::<&i32>(a).unwrap(), 4);
+
+    frame.spawn(("abc",));
+    let total = PreparedSystems::<()>::new().run(&mut frame, |(), cmd| {
+        cmd.despawn(a);
+        7
+    });
+    assert_eq!(total, 7);
+    assert!(!frame.contains(a));
+}
+
+#[test]
+fn type_extensions() {
+    use std::any::TypeId;
+
+    struct Tagged;
+    struct Untagged;
+    struct Late;
+    #[derive(Debug, PartialEq)]
+    struct Binding(u32);
+
+    set_type_extension::<Tagged>(&Binding(1));
+    let mut frame = Frame::new();
+    frame.spawn((Tagged, Untagged, Late));
+    set_type_extension::<Late>(&Binding(2));
+    let binding = |ty: &TypeInfo| ty.extension().and_then(|x| x.downcast_ref::<Binding>());
+
+    let archetype = frame.archetypes().find(|x| x.has::<Tagged>()).unwrap();
+    let types = archetype.component_type_info();
+    let find = |id| types.iter().find(|x| x.id() == id).unwrap();
+    assert_eq!(binding(find(TypeId::of::<Tagged>())), Some(&Binding(1)));
+    assert_eq!(binding(find(TypeId::of::<Untagged>())), None);
+    assert_eq!(binding(find(TypeId::of::<Late>())), Some(&Binding(2)));
+
+    let mut builder = EntityBuilder::new();
+    builder.add(Tagged);
+    let built = builder.build();
+    assert_eq!(binding(&built.type_info()[0]), Some(&Binding(1)));
+
+    let mut cmd = CommandBuffer::new();
+    cmd.spawn((Late,));
+    let types = cmd.iter().next().unwrap().types().collect::<Vec<_>>();
+    assert_eq!(binding(&types[0]), Some(&Binding(2)));
+    cmd.clear();
+
+    let result = std::panic::catch_unwind(|| set_type_extension::<Tagged>(&Binding(3)));
+    assert!(result.is_err());
+}
+
+#[test]
+#[cfg(feature = "event-log")]
+fn event_log() {
+    use std::any::TypeId;
+
+    let mut frame = Frame::new();
+    frame.set_event_log_tick(1);
+    let a = frame.spawn((1,));
+    frame.insert_one(a, true).unwrap();
+    frame.set_event_log_tick(2);
+    frame.exchange_one::<bool, _>(a, 2.0f32).unwrap();
+    frame.spawn_batch((0..3).map(|i| (i, 'x'))).count();
+    frame.despawn(a).unwrap();
+
+    let log = frame.event_log();
+    let kinds = log.iter().map(|x| x.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            OpKind::Spawn,
+            OpKind::Insert,
+            OpKind::Remove,
+            OpKind::Insert,
+            OpKind::SpawnBatch,
+            OpKind::Despawn,
+        ]
+    );
+    assert_eq!(log[1].tick, 1);
+    assert_eq!(log[2].tick, 2);
+    assert_eq!(log[2].entity, Some(a));
+    assert_eq!(log[2].types[0].id(), TypeId::of::<bool>());
+    assert_eq!(log[3].types[0].id(), TypeId::of::<f32>());
+    assert_eq!(log[4].entity, None);
+    assert_eq!(log[4].types.len(), 2);
+    assert_eq!(log[5].types.len(), 2);
+    assert!(log[5].to_string().starts_with("[tick 2] despawn"));
+
+    frame.set_event_log_capacity(2);
+    assert_eq!(frame.event_log().len(), 2);
+    frame.clear();
+    let log = frame.event_log();
+    assert_eq!(log.len(), 2);
+    assert_eq!(log[1].kind, OpKind::Clear);
+
+    frame.set_event_log_capacity(0);
+    frame.spawn((3,));
+    assert!(frame.event_log().is_empty());
+}
+
+#[test]
+fn query_as_shared() {
+    fn sum(view: &View<&i32>, entities: &[Entity]) -> i32 {
+        entities.iter().filter_map(|&e| view.get(e)).sum()
+    }
+
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let b = frame.spawn((2, true));
+    let c = frame.spawn((4,));
+    let mut query = frame.query_mut::<(&mut i32, &bool)>();
+    {
+        let shared = query.as_shared::<&i32>();
+        assert!(!shared.contains(c));
+        assert_eq!(sum(&shared, &[a, b, c]), 3);
+    }
+    let mut view = query.view();
+    let total = sum(&view.as_shared::<&i32>(), &[a, b]);
+    *view.get_mut(a).unwrap().0 += total;
+    assert_eq!(*frame.get::<&i32>(a).unwrap(), 4);
+}
+
+#[test]
+#[should_panic(expected = "shared query borrows a component not borrowed by the original")]
+fn query_as_shared_unborrowed() {
+    let mut frame = Frame::new();
+    let query = frame.query_mut::<&mut i32>();
+    query.as_shared::<&bool>();
+}
+
+#[test]
+fn component_keys() {
+    struct Health;
+
+    let mut registry = ComponentRegistry::new();
+    registry.register::<Health>().key("Health");
+    registry.register::<bool>();
+    let key = ComponentKey::new("Health");
+    assert_eq!(ComponentKey::from_bits(key.to_bits()), key);
+    assert_ne!(key, ComponentKey::new("Mana"));
+    let entry = registry.find_by_key(key).unwrap();
+    assert_eq!(entry.type_info().id(), std::any::TypeId::of::<Health>());
+    assert_eq!(entry.key(), Some(key));
+    assert!(registry
+        .get(std::any::TypeId::of::<bool>())
+        .unwrap()
+        .key()
+        .is_none());
+    assert!(registry.find_by_key(ComponentKey::new("Mana")).is_none());
+
+    // Re-keying releases the old key
+    registry.register::<Health>().key("game::Health");
+    assert!(registry.find_by_key(key).is_none());
+    let key = ComponentKey::new("game::Health");
+    assert_eq!(registry.find_by_key(key).unwrap().key(), Some(key));
+
+    let mut frame = Frame::new();
+    let a = frame.spawn((Health, true));
+    let b = frame.spawn((true,));
+    let mut query = DynamicQuery::new();
+    query.read_key(&registry, key);
+    assert!(frame.entity(a).unwrap().query_dynamic(&query).is_some());
+    assert!(frame.entity(b).unwrap().query_dynamic(&query).is_none());
+    let mut query = DynamicQuery::new();
+    query.without_key(&registry, key);
+    assert!(frame.entity(a).unwrap().query_dynamic(&query).is_none());
+    assert!(frame.entity(b).unwrap().query_dynamic(&query).is_some());
+}
+
+#[test]
+#[should_panic(expected = "already assigned")]
+fn component_key_collision() {
+    let mut registry = ComponentRegistry::new();
+    registry.register::<i32>().key("Health");
+    registry.register::<u32>().key("Health");
+}
+
+#[test]
+#[should_panic(expected = "no component type is assigned key")]
+fn dynamic_query_unknown_key() {
+    let registry = ComponentRegistry::new();
+    DynamicQuery::new().read_key(&registry, ComponentKey::new("Health"));
+}
+
+#[test]
+fn archetype_metadata() {
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct Bucket(u32);
+    #[derive(Default)]
+    struct Handle(Option<Arc<()>>);
+
+    let token = Arc::new(());
+    let mut frame = Frame::new();
+    let a = frame.spawn((1,));
+    let b = frame.spawn((1, true));
+    let (x, y) = (
+        frame.archetype_id(a).unwrap(),
+        frame.archetype_id(b).unwrap(),
+    );
+    frame.archetype_metadata_mut::<Bucket>(x).0 = 3;
+    frame.archetype_metadata_mut::<Bucket>(x).0 += 1;
+    frame.archetype_metadata_mut::<Handle>(y).0 = Some(token.clone());
+    let archetypes = frame.archetypes().collect::<Vec<_>>();
+    assert_eq!(archetypes[x as usize].metadata::<Bucket>().unwrap().0, 4);
+    assert!(archetypes[x as usize].metadata::<Handle>().is_none());
+    assert!(archetypes[y as usize].metadata::<Bucket>().is_none());
+    assert_eq!(Arc::strong_count(&token), 2);
+    drop(frame);
+    assert_eq!(Arc::strong_count(&token), 1);
+}
+
+#[test]
+fn budget() {
+    use std::sync::{Arc, Mutex};
+
+    let mut frame = Frame::new();
+    let violations = Arc::new(Mutex::new(Vec::new()));
+    let log = violations.clone();
+    frame.set_budget(
+        Budget {
+            max_per_archetype: Some(2),
+            ..Budget::default()
+        },
+        move |e| {
+            log.lock().unwrap().push(*e);
+            BudgetAction::DespawnOldest
+        },
+    );
+    let a = frame.spawn((1,));
+    let b = frame.spawn((true,));
+    let c = frame.spawn((2,));
+    let d = frame.spawn((3,));
+    assert!(!frame.contains(a));
+    assert!(frame.contains(b) && frame.contains(c) && frame.contains(d));
+    let archetype = frame.archetype_id(d).unwrap();
+    assert_eq!(
+        *violations.lock().unwrap(),
+        [BudgetExceeded::Archetype {
+            archetype,
+            limit: 2
+        }]
+    );
+
+    frame.set_budget(
+        Budget {
+            max_component_bytes: Some(12),
+            ..Budget::default()
+        },
+        |_| BudgetAction::Allow,
+    );
+    frame.spawn((4,));
+    assert_eq!(frame.len(), 4);
+
+    frame.set_budget(
+        Budget {
+            max_entities: Some(4),
+            ..Budget::default()
+        },
+        |_| BudgetAction::Reject,
+    );
+    assert_eq!(
+        frame.try_spawn((5,)),
+        Err(BudgetExceeded::Entities { limit: 4 })
+    );
+    assert_eq!(frame.len(), 4);
+    frame.clear_budget();
+    frame.spawn((5,));
+    assert_eq!(frame.len(), 5);
+}
+
+#[test]
+fn budget_batches() {
+    let reject = |max_entities| {
+        let mut frame = Frame::new();
+        frame.set_budget(
+            Budget {
+                max_entities: Some(max_entities),
+                ..Budget::default()
+            },
+            |_| BudgetAction::Reject,
+        );
+        frame
+    };
+    let rejected = |f: &mut dyn FnMut()| {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+            .unwrap_err()
+            .downcast::<String>()
+            .unwrap()
+    };
+
+    // Batches are checked as a whole before anything is spawned
+    let mut frame = reject(2);
+    let message = rejected(&mut || {
+        frame.spawn_batch((0..3).map(|i| (i,)));
+    });
+    assert_eq!(*message, "spawn rejected: more than 2 entities are live");
+    assert!(frame.is_empty());
+    let mut ty = ColumnBatchType::new();
+    ty.add::<i32>();
+    let mut batch = ty.into_batch(3);
+    let mut writer = batch.writer::<i32>().unwrap();
+    for i in 0..3 {
+        writer.push(i).unwrap();
+    }
+    let mut batch = Some(batch.build().unwrap());
+    rejected(&mut || {
+        frame.spawn_column_batch(batch.take().unwrap());
+    });
+    assert!(frame.is_empty());
+    assert_eq!(frame.spawn_batch((0..2).map(|i| (i,))).count(), 2);
+
+    // Spawning at a live entity's handle replaces it rather than adding one
+    let a = frame.iter().next().unwrap().entity();
+    frame.spawn_at(a, (true,));
+    rejected(&mut || {
+        frame.spawn_at(Entity::from_bits(1 << 32 | 7).unwrap(), (true,));
+    });
+    assert_eq!(frame.len(), 2);
+
+    // Inserts are checked against the archetype they move the entity to
+    let mut frame = Frame::new();
+    frame.set_budget(
+        Budget {
+            max_per_archetype: Some(1),
+            ..Budget::default()
+        },
+        |_| BudgetAction::Reject,
+    );
+    let a = frame.spawn((1,));
+    let b = frame.spawn((2, true));
+    frame.insert_one(b, 2u8).unwrap();
+    frame.spawn((3, true));
+    let message = rejected(&mut || {
+        frame.insert_one(a, true).unwrap();
+    });
+    assert!(message.starts_with("insert rejected"));
+    assert!(!frame.satisfies::<&bool>(a).unwrap());
+
+    // Buffered and deferred spawns that are rejected are dropped
+    let mut frame = reject(1);
+    let mut cmd = CommandBuffer::new();
+    cmd.spawn((1,));
+    cmd.spawn((2,));
+    cmd.spawn_with((3,), |_, _| unreachable!());
+    cmd.run_on(&mut frame);
+    assert_eq!(frame.len(), 1);
+    let reserved = {
+        let ops = frame.defer();
+        ops.spawn((4,))
+    };
+    frame.apply_deferred();
+    assert!(!frame.contains(reserved));
+    assert_eq!(frame.len(), 1);
+    frame.run::<&i32>(|_, _, ops| {
+        ops.spawn((5,));
+    });
+    assert_eq!(frame.len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "spawn rejected: more than 0 entities are live")]
+fn budget_rejected_spawn() {
+    let mut frame = Frame::new();
+    frame.set_budget(
+        Budget {
+            max_entities: Some(0),
+            ..Budget::default()
+        },
+        |_| BudgetAction::Reject,
+    );
+    frame.spawn((1,));
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+fn par_extend() {
+    use rayon::prelude::*;
+
+    let mut frame = Frame::new();
+    frame.spawn((0u32,));
+    let entities = frame.par_extend((0..1000u32).into_par_iter().map(|i| (i, i.to_string())));
+    assert_eq!(entities.len(), 1000);
+    assert_eq!(frame.len(), 1001);
+    for (i, &e) in entities.iter().enumerate() {
+        assert_eq!(*frame.get::<&u32>(e).unwrap(), i as u32);
+        assert_eq!(*frame.get::<&String>(e).unwrap(), i.to_string());
+    }
+    assert!(frame
+        .par_extend(Vec::<(u32,)>::new().into_par_iter())
+        .is_empty());
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+fn par_iter() {
+    use rayon::prelude::*;
+
+    let mut frame = Frame::new();
+    frame.spawn_batch((0..5000u32).map(|i| (i, 2u64)));
+    frame.spawn_batch((0..3000u32).map(|i| (i,)));
+    frame
+        .query::<(&mut u32, &u64)>()
+        .par_iter()
+        .for_each(|(_, (x, y))| *x += *y as u32);
+    let sum: u64 = frame
+        .query::<&u32>()
+        .par_iter()
+        .map(|(_, &x)| x as u64)
+        .sum();
+    let expected = (0..5000u64).map(|i| i + 2).sum::<u64>() + (0..3000u64).sum::<u64>();
+    assert_eq!(sum, expected);
+
+    frame
+        .query_mut::<&mut u32>()
+        .par_iter_mut()
+        .for_each(|(_, x)| *x = 0);
+    assert!(frame.query::<&u32>().iter().all(|(_, &x)| x == 0));
+    assert_eq!(frame.query::<&bool>().par_iter().count(), 0);
+}
+
+#[test]
+fn declare_archetype() {
+    let mut frame = Frame::new();
+    let a = frame.declare_archetype::<(i32, bool)>();
+    assert_eq!(frame.declare_archetype::<(bool, i32)>(), a);
+    let b = frame.declare_archetype_dynamic(&[TypeInfo::of::<f32>(), TypeInfo::of::<i32>()]);
+    assert_ne!(a, b);
+    assert_eq!(
+        frame.declare_archetype_dynamic(&[TypeInfo::of::<i32>(), TypeInfo::of::<bool>()]),
+        a
+    );
+    let generation = frame.archetypes_generation();
+    let mut query = PreparedQuery::<&i32>::new();
+    assert_eq!(query.query(&frame).iter().count(), 0);
+    let x = frame.spawn((1, true));
+    let y = frame.spawn((2, 3.0f32));
+    assert_eq!(frame.archetype_id(x).unwrap(), a);
+    assert_eq!(frame.archetype_id(y).unwrap(), b);
+    assert_eq!(frame.archetypes_generation(), generation);
+    assert_eq!(query.query(&frame).iter().count(), 2);
+}
+
+#[test]
+fn prepared_query_matches_changed() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let mut query = PreparedQuery::<&i32>::new();
+    query.set_change_hook(move || {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+
+    let mut frame = Frame::new();
+    assert!(query.matches_changed(&frame));
+    assert!(!query.matches_changed(&frame));
+    frame.spawn((true,));
+    assert_eq!(query.query(&frame).iter().count(), 0);
+    assert!(!query.matches_changed(&frame));
+    frame.spawn((1,));
+    assert_eq!(query.query(&frame).iter().count(), 1);
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+    assert!(query.matches_changed(&frame));
+
+    let other = Frame::new();
+    assert!(query.matches_changed(&other));
+    assert_eq!(calls.load(Ordering::Relaxed), 3);
+    query.clear_change_hook();
+    assert!(query.matches_changed(&frame));
+    assert_eq!(calls.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+fn dynamic_column() {
+    use std::any::TypeId;
+
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let b = frame.spawn((2, false));
+    let archetype = frame.archetypes().find(|x| x.has::<i32>()).unwrap();
+    assert!(archetype.dynamic_column(TypeId::of::<f32>()).is_none());
+    {
+        let mut column = archetype.dynamic_column_mut(TypeId::of::<i32>()).unwrap();
+        assert_eq!(column.type_info(), TypeInfo::of::<i32>());
+        assert_eq!(column.len(), 2);
+        assert!(column.get_raw_mut(2).is_none());
+        for i in 0..column.len() {
+            unsafe {
+                *column.get_raw_mut(i).unwrap().cast::<i32>().as_mut() *= 10;
+            }
+        }
+    }
+    let column = archetype.dynamic_column(TypeId::of::<i32>()).unwrap();
+    let values = (0..column.len())
+        .map(|i| *column.get_raw(i).unwrap().downcast_ref::<i32>().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(values, [10, 20]);
+    assert!(column.get_raw(2).is_none());
+    let _shared = archetype.get::<&i32>().unwrap();
+    drop(column);
+    assert_eq!(*frame.get::<&i32>(a).unwrap(), 10);
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 20);
+}
+
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn dynamic_column_mut_borrowed() {
+    use std::any::TypeId;
+
+    let mut frame = Frame::new();
+    frame.spawn((1,));
+    let archetype = frame.archetypes().find(|x| x.has::<i32>()).unwrap();
+    let _column = archetype.get::<&i32>().unwrap();
+    archetype.dynamic_column_mut(TypeId::of::<i32>());
+}
+
+#[test]
+fn cached_matches() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let b = frame.spawn((5, true));
+    let mut cache = CachedMatches::<&i32>::new();
+    let mut runs = 0;
+    let mut refresh = |cache: &mut CachedMatches<&i32>, frame: &Frame| {
+        cache
+            .refresh(frame, |_, &x| {
+                runs += 1;
+                x > 2
+            })
+            .to_vec()
+    };
+    assert!(!cache.is_valid(&frame));
+    assert_eq!(refresh(&mut cache, &frame), [b]);
+    assert_eq!(refresh(&mut cache, &frame), [b]);
+
+    // Unrelated changes leave the cache valid
+    frame.spawn((true,));
+    frame.insert_one(a, 'x').unwrap();
+    assert!(!cache.is_valid(&frame));
+    let _ = refresh(&mut cache, &frame);
+    frame.query_mut::<&mut bool>().into_iter().for_each(|_| ());
+    assert!(cache.is_valid(&frame));
+
+    // Relevant writes, spawns and despawns invalidate it
+    *frame.get::<&mut i32>(a).unwrap() = 3;
+    assert!(!cache.is_valid(&frame));
+    let mut found = refresh(&mut cache, &frame);
+    found.sort();
+    assert_eq!(found, [a, b]);
+    frame.despawn(b).unwrap();
+    assert!(!cache.is_valid(&frame));
+    assert_eq!(refresh(&mut cache, &frame), [a]);
+    frame.spawn((2.0f32, 10));
+    assert!(!cache.is_valid(&frame));
+    assert_eq!(refresh(&mut cache, &frame).len(), 2);
+
+    cache.invalidate();
+    assert!(!cache.is_valid(&frame));
+    assert!(!cache.is_valid(&Frame::new()));
+    assert_eq!(cache.entities().len(), 2);
+    assert_eq!(runs, 9);
+}
+
+#[test]
+fn cached_matches_compacted() {
+    let mut frame = Frame::new();
+    let entities = (0..4).map(|i| frame.spawn((i,))).collect::<Vec<_>>();
+    frame.despawn(entities[0]).unwrap();
+    let mut cache = CachedMatches::<&i32>::new();
+    let mut found = cache.refresh(&frame, |_, &x| x == 3).to_vec();
+    assert_eq!(found, [entities[3]]);
+
+    // Renumbering entities invalidates cached handles
+    frame.compact_entities(|_, _| {});
+    assert!(!cache.is_valid(&frame));
+    found = cache.refresh(&frame, |_, &x| x == 3).to_vec();
+    assert_eq!(found.len(), 1);
+    assert_ne!(found[0], entities[3]);
+    assert_eq!(*frame.get::<&i32>(found[0]).unwrap(), 3);
+}
+
+#[test]
+fn spawn_with() {
+    struct Link {
+        this: Option<Entity>,
+    }
+
+    let mut frame = Frame::new();
+    let mut seen = None;
+    let e = frame.spawn_with((Link { this: None }, 5), |entity| {
+        assert_eq!(*entity.get::<&i32>().unwrap(), 5);
+        entity.get::<&mut Link>().unwrap().this = Some(entity.entity());
+        seen = Some(entity.entity());
+    });
+    assert_eq!(seen, Some(e));
+    assert_eq!(frame.get::<&Link>(e).unwrap().this, Some(e));
+    assert_eq!(frame.len(), 1);
+}
+
+#[test]
+fn strict_registry() {
+    use std::sync::Arc;
+
+    let mut registry = ComponentRegistry::new();
+    registry.register::<i32>();
+    registry.register::<bool>();
+    let registry = Arc::new(registry);
+
+    let mut frame = Frame::new();
+    let e = frame.spawn((1.0f32,));
+    assert_eq!(
+        frame.set_strict_registry(Some(registry.clone())),
+        Err(UnregisteredComponent {
+            ty: TypeInfo::of::<f32>()
+        })
+    );
+    // Empty archetypes still count
+    frame.despawn(e).unwrap();
+    assert!(frame.set_strict_registry(Some(registry.clone())).is_err());
+
+    let mut frame = Frame::new();
+    frame.set_strict_registry(Some(registry)).unwrap();
+    let e = frame.spawn((1, true));
+    assert!(frame.check_registered(&(2,)).is_ok());
+    assert_eq!(
+        frame.check_registered(&(2, 'x')),
+        Err(UnregisteredComponent {
+            ty: TypeInfo::of::<char>()
+        })
+    );
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        frame.insert_one(e, 'x').unwrap();
+    }));
+    assert!(result.is_err());
+    let mut batch = ColumnBatchType::new();
+    batch.add::<u8>();
+    let batch = batch.into_batch(0).build().unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        frame.spawn_column_batch(batch);
+    }));
+    assert!(result.is_err());
+    frame.set_strict_registry(None).unwrap();
+    frame.insert_one(e, 'x').unwrap();
+}
+
+#[test]
+fn columns_mut() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let b = frame.spawn((2,));
+    frame.spawn(("abc",));
+    let c = frame.spawn((3, "def"));
+    frame.despawn(c).unwrap();
+    assert_eq!(frame.columns_mut::<i32>().count(), 2);
+    for column in frame.columns_mut::<i32>() {
+        for x in column {
+            *x += 10;
+        }
+    }
+    assert_eq!(*frame.get::<&i32>(a).unwrap(), 11);
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 12);
+    assert_eq!(frame.columns_mut::<f32>().count(), 0);
+}
+
+#[test]
+fn query_visits_archetypes_having_required_type() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let b = frame.spawn((2,));
+    frame.spawn(("abc",));
+    frame.spawn((1.5f32, false));
+    for (_, x) in frame.query_mut::<&mut i32>() {
+        *x += 10;
+    }
+    assert_eq!(*frame.get::<&i32>(a).unwrap(), 11);
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 12);
+
+    let mut query = frame.query::<(Option<&f32>, &bool)>();
+    assert_eq!(query.iter().len(), 2);
+    let mut query = frame.query::<Without<&i32, &bool>>();
+    assert_eq!(query.iter().map(|(e, _)| e).collect::<Vec<_>>(), [b]);
+    let mut query = frame.query::<With<(), &bool>>();
+    assert_eq!(query.iter().len(), 2);
+    assert_eq!(frame.query::<&u8>().iter().len(), 0);
+}
+
+#[test]
+fn view_get_live_unchecked() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let b = frame.spawn((2,));
+    let c = frame.spawn((true,));
+    let mut query = PreparedQuery::<&mut i32>::new();
+    {
+        let view = query.view_mut(&mut frame);
+        unsafe {
+            *view.get_live_unchecked(a).unwrap() += 10;
+            *view.get_live_unchecked(b).unwrap() += 10;
+            assert!(view.get_live_unchecked(c).is_none());
+        }
+    }
+    let view = frame.view::<&i32>();
+    unsafe {
+        assert_eq!(*view.get_live_unchecked(a).unwrap(), 11);
+        assert_eq!(*view.get_live_unchecked(b).unwrap(), 12);
+    }
+}
+
+#[test]
+fn frame_builder() {
+    let allocator = SharedAllocator::new();
+    let mut a = Frame::builder()
+        .entity_capacity(100)
+        .archetype_capacity(8)
+        .allocator(allocator.clone())
+        .handle_quarantine(3)
+        .build();
+    let mut b = Frame::builder().allocator(allocator).build();
+    assert_eq!(a.handle_quarantine(), 3);
+    let x = a.spawn((1,));
+    let y = b.spawn((2,));
+    assert_ne!(x.id(), y.id());
+
+    let mut frame = Frame::with_capacity(10, 2);
+    let e = frame.spawn((true,));
+    assert!(*frame.get::<&bool>(e).unwrap());
+}
+
+#[test]
+fn compare_systems() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let b = frame.spawn((2,));
+    frame.spawn((true,));
+    let mismatches = frame.compare_systems::<i32>(
+        |frame| {
+            for (_, x) in frame.query_mut::<&mut i32>() {
+                *x += 1;
+            }
+        },
+        |frame| {
+            for (_, x) in frame.query_mut::<With<&mut i32, &bool>>() {
+                *x += 1;
+            }
+        },
+    );
+    assert_eq!(
+        mismatches,
+        [WriteMismatch {
+            entity: b,
+            old: 3,
+            new: 2
+        }]
+    );
+    assert_eq!(*frame.get::<&i32>(a).unwrap(), 2);
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 3);
+
+    // Only the writes of `old` count as changes
+    frame.track_changes::<i32>();
+    frame.clear_trackers();
+    frame.compare_systems::<i32>(
+        |frame| {
+            for (_, x) in frame.query_mut::<With<&mut i32, &bool>>() {
+                *x += 1;
+            }
+        },
+        |frame| {
+            for (_, x) in frame.query_mut::<&mut i32>() {
+                *x += 1;
+            }
+        },
+    );
+    let mut changed = frame
+        .query_mut::<Changed<&i32>>()
+        .into_iter()
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    changed.sort_by_key(|e| e.id());
+    assert_eq!(changed, [a]);
+
+    // Results are paired up by entity even if a system reorders them
+    #[derive(PartialEq, Eq, PartialOrd, Ord)]
+    struct Key(u32);
+    let mut frame = Frame::new();
+    let entities = (0..4)
+        .map(|i| frame.spawn((i, Key(4 - i as u32))))
+        .collect::<Vec<_>>();
+    frame.set_group_key::<Key>();
+    let mismatches = frame.compare_systems::<i32>(
+        |frame| {
+            for (_, x) in frame.query_mut::<&mut i32>() {
+                *x *= 10;
+            }
+        },
+        |frame| {
+            for (_, x) in frame.query_mut::<&mut i32>() {
+                *x *= if *x == 2 { 5 } else { 10 };
+            }
+            assert_eq!(frame.regroup(), 1);
+        },
+    );
+    assert_eq!(
+        mismatches,
+        [WriteMismatch {
+            entity: entities[2],
+            old: 20,
+            new: 10
+        }]
+    );
+    for (i, &e) in entities.iter().enumerate() {
+        assert_eq!(*frame.get::<&i32>(e).unwrap(), i as i32 * 10);
+    }
+}
+
+#[test]
+fn visit_sorted() {
+    let mut registry = ComponentRegistry::new();
+    registry.register::<u8>().name("c");
+    registry.register::<bool>().name("a");
+    registry.register::<u64>().name("b");
+    let mut frame = Frame::new();
+    let a = frame.spawn((1u64, true, 3u8, 4.0f32));
+    let b = frame.spawn((3u8, 1u64, true));
+    for e in [a, b] {
+        let mut names = Vec::new();
+        frame
+            .entity(e)
+            .unwrap()
+            .visit_sorted(&registry, &mut |c: DynamicComponent<'_>| {
+                names.push(registry.get(c.type_info().id()).unwrap().name());
+            });
+        assert_eq!(names, ["a", "b", "c"]);
+    }
+
+    // Types sharing a name are ordered by registration, not by column
+    use std::any::TypeId;
+    registry.register::<i16>().name("dup");
+    registry.register::<u16>().name("dup");
+    let a = frame.spawn((1u16, 2i16));
+    let b = frame.spawn((2i16, 1u16, true));
+    for e in [a, b] {
+        let mut types = Vec::new();
+        frame
+            .entity(e)
+            .unwrap()
+            .visit_sorted(&registry, &mut |c: DynamicComponent<'_>| {
+                types.push(c.type_info().id());
+            });
+        assert_eq!(
+            types[types.len() - 2..],
+            [TypeId::of::<i16>(), TypeId::of::<u16>()]
+        );
+    }
+}
+
+#[test]
+fn change_detection() {
+    let changed = |frame: &mut Frame| {
+        let mut out = frame
+            .query_mut::<(Option<Changed<&i32>>, Option<Added<&i32>>)>()
+            .into_iter()
+            .map(|(e, (c, a))| (e, (c.is_some(), a.is_some())))
+            .filter(|(_, (c, a))| *c || *a)
+            .collect::<Vec<_>>();
+        out.sort_by_key(|(e, _)| e.id());
+        out
+    };
+
+    let mut frame = Frame::new();
+    frame.track_changes::<i32>();
+    let a = frame.spawn((1,));
+    let b = frame.spawn((2, true));
+    let c = frame.spawn((3,));
+    assert_eq!(
+        changed(&mut frame),
+        [(a, (true, true)), (b, (true, true)), (c, (true, true))]
+    );
+
+    frame.clear_trackers();
+    assert_eq!(changed(&mut frame), []);
+
+    // Writes through queries and `get` are tracked per entity
+    for (_, x) in frame.query_mut::<With<&mut i32, &bool>>() {
+        *x += 1;
+    }
+    *frame.get::<&mut i32>(c).unwrap() += 1;
+    assert_eq!(
+        changed(&mut frame),
+        [(b, (true, false)), (c, (true, false))]
+    );
+
+    // Ticks move with entities between archetypes and within them
+    frame.clear_trackers();
+    frame.insert_one(a, 1.0f32).unwrap();
+    frame.remove_one::<bool>(b).unwrap();
+    frame.despawn(a).unwrap();
+    assert_eq!(changed(&mut frame), []);
+    frame.insert_one(c, 4).unwrap();
+    assert_eq!(changed(&mut frame), [(c, (true, false))]);
+
+    // Shared access isn't a change
+    frame.clear_trackers();
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 3);
+    frame.query::<&i32>().iter().for_each(|_| ());
+    assert_eq!(changed(&mut frame), []);
+
+    // Whole-column access marks every entity in the column. `d` reuses `a`'s ID.
+    let d = frame.spawn((5,));
+    for column in frame.columns_mut::<i32>() {
+        column[0] += 0;
+    }
+    assert_eq!(
+        changed(&mut frame),
+        [(d, (true, true)), (b, (true, false)), (c, (true, false))]
+    );
+
+    // Unchanged entities are skipped, and iterators still know how many remain
+    frame.clear_trackers();
+    *frame.get::<&mut i32>(b).unwrap() = 7;
+    let mut query = frame.query::<Changed<&i32>>();
+    let mut iter = query.iter();
+    assert_eq!(iter.len(), 1);
+    assert_eq!(iter.next(), Some((b, &7)));
+    assert_eq!(iter.len(), 0);
+    drop(query);
+    assert!(frame.query_one_mut::<Changed<&i32>>(c).is_err());
+    assert_eq!(*frame.query_one_mut::<Changed<&i32>>(b).unwrap(), 7);
+    assert!(frame.view::<Changed<&i32>>().get(c).is_none());
+    for (_, x) in frame.query_mut::<Changed<&mut i32>>() {
+        *x += 1;
+    }
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 8);
+    assert_eq!(*frame.get::<&i32>(c).unwrap(), 4);
+
+    // Tracking is opt-in per type
+    let untracked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        frame.query_mut::<Changed<&bool>>().into_iter().count()
+    }));
+    assert!(untracked.is_err());
+}
+
+#[test]
+fn clear_entity() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1i32, "a"));
+    let b = frame.spawn((2i32, "b"));
+    frame.clear_entity(a).unwrap();
+    assert!(frame.contains(a));
+    assert_eq!(frame.entity(a).unwrap().len(), 0);
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 2);
+    assert_eq!(*frame.get::<&&str>(b).unwrap(), "b");
+
+    let mut cmd = CommandBuffer::new();
+    cmd.clear_entity(b);
+    cmd.insert_one(b, true);
+    assert_eq!(cmd.iter().next().unwrap().kind(), CommandKind::Clear);
+    assert!(cmd.dry_run(&frame).is_empty());
+    cmd.run_on(&mut frame);
+    assert!(frame.satisfies::<&bool>(b).unwrap());
+    assert!(frame.get::<&i32>(b).is_err());
+
+    frame.despawn(a).unwrap();
+    assert!(frame.clear_entity(a).is_err());
+}
+
+#[test]
+fn entity_get_many() {
+    let mut frame = Frame::new();
+    let e = frame.spawn((1i32, 2u8, "x"));
+    let entity = frame.entity(e).unwrap();
+    {
+        let mut guard = entity.get_many::<(&i32, &mut u8, &&str)>().unwrap();
+        let (a, b, c) = guard.get();
+        *b += *a as u8;
+        assert_eq!(*c, "x");
+    }
+    assert_eq!(*entity.get::<&u8>().unwrap(), 3);
+    assert!(entity.get_many::<(&i32, &bool)>().is_none());
+    let _guard = entity.get_many::<(&i32, &u8)>().unwrap();
+    assert_eq!(*entity.get::<&u8>().unwrap(), 3);
+}
+
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn entity_get_many_conflict() {
+    let mut frame = Frame::new();
+    let e = frame.spawn((1i32,));
+    let entity = frame.entity(e).unwrap();
+    let _guard = entity.get_many::<(&mut i32,)>().unwrap();
+    entity.get::<&i32>();
+}
+
+#[test]
+fn fork_frame() {
+    let mut registry = ComponentRegistry::new();
+    registry.register::<i32>().copyable();
+    registry.register::<u8>().copyable();
+    registry.register::<String>().cloneable();
+
+    let mut frame = Frame::new();
+    let a = frame.spawn((1i32, 10u8, "a".to_string()));
+    let b = frame.spawn((2i32, 20u8, "b".to_string()));
+    let c = frame.spawn((3i32,));
+
+    let mut first = registry.fork_frame(&mut frame);
+    let second = registry.fork_frame(&mut frame);
+    let mut nested = registry.fork_frame(&mut first);
+
+    for (_, x) in first.query_mut::<&mut i32>() {
+        *x *= 10;
+    }
+    *frame.get::<&mut u8>(b).unwrap() = 21;
+    frame.despawn(a).unwrap();
+    nested.insert_one(c, 4u8).unwrap();
+    drop(frame);
+
+    let values = |frame: &Frame, e| {
+        let e = frame.entity(e).unwrap();
+        (*e.get::<&i32>().unwrap(), e.get::<&u8>().map(|x| *x))
+    };
+    assert_eq!(values(&first, a), (10, Some(10)));
+    assert_eq!(values(&first, b), (20, Some(20)));
+    assert_eq!(values(&first, c), (30, None));
+    assert_eq!(values(&second, a), (1, Some(10)));
+    assert_eq!(values(&second, b), (2, Some(20)));
+    assert_eq!(*second.get::<&String>(b).unwrap(), "b");
+    assert_eq!(values(&nested, b), (2, Some(20)));
+    assert_eq!(values(&nested, c), (3, Some(4)));
+
+    drop(first);
+    let upgraded = Ref::try_upgrade(second.get::<&i32>(b).unwrap());
+    *upgraded.ok().unwrap() = 5;
+    assert_eq!(values(&second, b), (5, Some(20)));
+    let mut second = second;
+    second.spawn_batch((0..100).map(|i| (i, 0u8, String::new())));
+    assert_eq!(values(&second, a), (1, Some(10)));
+    assert_eq!(second.query::<&i32>().iter().count(), 103);
+}
+
+#[test]
+fn remove_dynamic() {
+    use std::any::TypeId;
+    use std::sync::Arc;
+
+    let mut frame = Frame::new();
+    let shared = Arc::new(());
+    let a = frame.spawn((1i32, shared.clone(), true));
+    let b = frame.spawn((2i32, shared.clone(), false));
+    frame.remove_dynamic(a, TypeId::of::<Arc<()>>()).unwrap();
+    assert_eq!(Arc::strong_count(&shared), 2);
+    assert_eq!(frame.entity(a).unwrap().len(), 2);
+    assert!(*frame.get::<&bool>(a).unwrap());
+    assert!(!*frame.get::<&bool>(b).unwrap());
+    assert!(matches!(
+        frame.remove_dynamic(a, TypeId::of::<Arc<()>>()),
+        Err(ComponentError::MissingComponent(_))
+    ));
+
+    let mut cmd = CommandBuffer::new();
+    cmd.remove_dynamic(b, TypeId::of::<Arc<()>>());
+    assert_eq!(cmd.iter().next().unwrap().kind(), CommandKind::Remove);
+    cmd.run_on(&mut frame);
+    assert_eq!(Arc::strong_count(&shared), 1);
+    assert_eq!(*frame.get::<&i32>(b).unwrap(), 2);
+}
+
+#[test]
+fn relations() {
+    #[derive(Clone, PartialEq, Debug)]
+    struct ChildOf(Entity);
+    impl Relation for ChildOf {
+        fn target(&self) -> Entity {
+            self.0
+        }
+    }
+    impl From<Entity> for ChildOf {
+        fn from(parent: Entity) -> Self {
+            Self(parent)
+        }
+    }
+
+    let mut frame = Frame::new();
+    let root = frame.spawn(("root",));
+    let other = frame.spawn(("other",));
+    let a = frame.spawn(("a", 1));
+    let b = frame.spawn(("b", 2));
+    let c = frame.spawn(("c",));
+    frame.attach::<ChildOf>(a, root).unwrap();
+    frame.attach::<ChildOf>(b, root).unwrap();
+    frame.attach::<ChildOf>(c, other).unwrap();
+
+    let mut children = frame
+        .query::<Related<&&str, ChildOf>>()
+        .iter()
+        .filter(|&(_, (parent, _))| parent == root)
+        .map(|(_, (_, &name))| name)
+        .collect::<Vec<_>>();
+    children.sort_unstable();
+    assert_eq!(children, ["a", "b"]);
+
+    assert_eq!(frame.detach::<ChildOf>(b).unwrap(), ChildOf(root));
+    assert!(frame.detach::<ChildOf>(b).is_err());
+    frame.despawn(root).unwrap();
+    assert!(!frame.satisfies::<&ChildOf>(a).unwrap());
+    assert_eq!(*frame.get::<&ChildOf>(c).unwrap(), ChildOf(other));
+    assert!(frame.attach::<ChildOf>(c, root).is_err());
+    assert_eq!(frame.related::<ChildOf>(other).collect::<Vec<_>>(), [c]);
+
+    // Relations added without `attach` are cleaned up once the type is registered
+    let mut frame = Frame::new();
+    frame.register_relation::<ChildOf>();
+    let parent = frame.spawn(());
+    let spawned = frame.spawn((ChildOf(parent),));
+    let batch = frame
+        .spawn_batch((0..2).map(|_| (ChildOf(parent), true)))
+        .collect::<Vec<_>>();
+    let mut related = frame.related::<ChildOf>(parent).collect::<Vec<_>>();
+    related.sort();
+    assert_eq!(related, [spawned, batch[0], batch[1]]);
+    frame.despawn(parent).unwrap();
+    assert_eq!(frame.query_mut::<&ChildOf>().into_iter().count(), 0);
+
+    // ...with or without an index, and when the target is taken or transferred
+    for indexed in [false, true] {
+        let mut frame = Frame::new();
+        if indexed {
+            frame.index_references::<ChildOf>();
+        }
+        frame.register_relation::<ChildOf>();
+        let parent = frame.spawn(());
+        let child = frame.spawn((ChildOf(parent),));
+        assert_eq!(
+            frame.related::<ChildOf>(parent).collect::<Vec<_>>(),
+            [child]
+        );
+        drop(frame.take(parent).unwrap());
+        assert!(!frame.satisfies::<&ChildOf>(child).unwrap());
+
+        let parent = frame.spawn(());
+        frame.insert_one(child, ChildOf(parent)).unwrap();
+        // A relation to itself moves with the entity
+        frame.insert_one(parent, ChildOf(parent)).unwrap();
+        let mut dest = Frame::new();
+        frame.transfer(parent, &mut dest).unwrap();
+        assert!(!frame.satisfies::<&ChildOf>(child).unwrap());
+        assert!(dest.satisfies::<&ChildOf>(parent).unwrap());
+    }
+
+    // Registrations are carried over by clones and merges
+    let mut registry = ComponentRegistry::new();
+    registry.register::<ChildOf>().cloneable();
+    let mut frame = Frame::new();
+    frame.register_relation::<ChildOf>();
+    let parent = frame.spawn(());
+    let child = frame.spawn((ChildOf(parent),));
+    let mut cloned = registry.clone_frame(&frame);
+    cloned.despawn(parent).unwrap();
+    assert!(!cloned.satisfies::<&ChildOf>(child).unwrap());
+    let mut merged = Frame::new();
+    let map = registry.merge_frame(&frame, &mut merged);
+    merged.despawn(map.get(parent).unwrap()).unwrap();
+    assert!(!merged
+        .satisfies::<&ChildOf>(map.get(child).unwrap())
+        .unwrap());
+}
+
+#[test]
+fn visit_all() {
+    #[derive(Default)]
+    struct Log(Vec<String>);
+    impl FrameVisitor for Log {
+        fn begin_archetype(&mut self, archetype: &Archetype) -> bool {
+            !archetype.has::<bool>()
+        }
+        fn begin_column(&mut self, component: &RegisteredComponent) -> bool {
+            self.0.push(format!("[{}", component.name()));
+            true
+        }
+        fn visit(&mut self, _: Entity, component: DynamicComponent<'_>) {
+            if let Some(&x) = component.downcast_ref::<i32>() {
+                self.0.push(x.to_string());
+            } else if let Some(&x) = component.downcast_ref::<u8>() {
+                self.0.push(x.to_string());
+            }
+        }
+        fn end_column(&mut self, _: &RegisteredComponent) {
+            self.0.push("]".into());
+        }
+    }
+
+    let mut registry = ComponentRegistry::new();
+    registry.register::<i32>().name("b");
+    registry.register::<u8>().name("a");
+    registry.register::<bool>();
+    let mut frame = Frame::new();
+    frame.spawn((1i32, 2u8, "skipped"));
+    frame.spawn((3i32, 4u8));
+    frame.spawn((5i32, true));
+    let e = frame.spawn((6u8,));
+    frame.despawn(e).unwrap();
+
+    let mut log = Log::default();
+    frame.visit_all(&registry, &mut log);
+    let mut lines = log.0;
+    lines.sort();
+    assert_eq!(
+        lines,
+        ["1", "2", "3", "4", "[a", "[a", "[b", "[b", "]", "]", "]", "]"]
+    );
+
+    // Columns are released between visits
+    let _ = frame.query_mut::<&mut i32>();
+}
+
+#[test]
+fn merge_frame() {
+    #[derive(Clone, PartialEq)]
+    struct Parent(Entity);
+    impl MapEntities for Parent {
+        fn map_entities(&mut self, map: &EntityMap) {
+            self.0.map_entities(map);
+        }
+    }
+    impl Relation for Parent {
+        fn target(&self) -> Entity {
+            self.0
+        }
+    }
+
+    let mut registry = ComponentRegistry::new();
+    registry.register::<i32>().copyable();
+    registry.register::<Parent>().cloneable().map_entities();
+
+    let mut prefab = Frame::new();
+    let root = prefab.spawn((0,));
+    let child = prefab.spawn((1, Parent(root), "uncloned"));
+
+    let mut frame = Frame::new();
+    frame.index_references::<Parent>();
+    let outsider = frame.spawn((2, Parent(root)));
+    let first = registry.merge_frame(&prefab, &mut frame);
+    let second = registry.merge_frame(&prefab, &mut frame);
+    assert_eq!(first.len(), 2);
+    assert_eq!(frame.len(), 5);
+    for map in [&first, &second] {
+        let (new_root, new_child) = (map.get(root).unwrap(), map.get(child).unwrap());
+        assert_eq!(*frame.get::<&i32>(new_root).unwrap(), 0);
+        assert_eq!(*frame.get::<&i32>(new_child).unwrap(), 1);
+        assert_eq!(frame.get::<&Parent>(new_child).unwrap().0, new_root);
+        assert!(!frame.satisfies::<&&str>(new_child).unwrap());
+        // The index sees the remapped handles
+        assert_eq!(frame.referencing(new_root).collect::<Vec<_>>(), [new_child]);
+    }
+    assert_ne!(first.get(root), second.get(root));
+    // Entities that were already present aren't remapped
+    assert_eq!(frame.get::<&Parent>(outsider).unwrap().0, root);
+    assert_eq!(frame.referencing(root).collect::<Vec<_>>(), [outsider]);
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn derive_map_entities() {
+    #[derive(MapEntities)]
+    struct Links {
+        #[map_entities]
+        targets: Vec<Entity>,
+        #[map_entities]
+        parent: Option<Entity>,
+        unmapped: Entity,
+    }
+
+    #[derive(MapEntities)]
+    enum Target {
+        Entity(#[map_entities] Entity),
+        Point,
+    }
+
+    let mut frame = Frame::new();
+    let a = frame.spawn(());
+    let b = frame.spawn(());
+    let mut map = EntityMap::new();
+    map.insert(a, b);
+    let mut links = Links {
+        targets: vec![a, b],
+        parent: Some(a),
+        unmapped: a,
+    };
+    links.map_entities(&map);
+    assert_eq!(links.targets, [b, b]);
+    assert_eq!(links.parent, Some(b));
+    assert_eq!(links.unmapped, a);
+    let mut target = Target::Entity(a);
+    target.map_entities(&map);
+    assert!(matches!(target, Target::Entity(x) if x == b));
+    Target::Point.map_entities(&map);
+}
+
+#[test]
+fn len() {
+    let mut frame = Frame::new();
+    let ent = frame.spawn(());
+    frame.spawn(());
+    frame.spawn(());
+    assert_eq!(frame.len(), 3);
+    frame.despawn(ent).unwrap();
+    assert_eq!(frame.len(), 2);
+    frame.clear();
+    assert_eq!(frame.len(), 0);
+}
+
+#[test]
+fn take() {
+    let mut frame_a = Frame::new();
+    let e = frame_a.spawn(("abc".to_string(), 42));
+    let f = frame_a.spawn(("def".to_string(), 17));
+    let mut frame_b = Frame::new();
+    let e2 = frame_b.spawn(frame_a.take(e).unwrap());
+    assert!(!frame_a.contains(e));
+    assert_eq!(*frame_b.get::<&String>(e2).unwrap(), "abc");
+    assert_eq!(*frame_b.get::<&i32>(e2).unwrap(), 42);
+    assert_eq!(*frame_a.get::<&String>(f).unwrap(), "def");
+    assert_eq!(*frame_a.get::<&i32>(f).unwrap(), 17);
+    frame_b.take(e2).unwrap();
+    assert!(!frame_b.contains(e2));
+}
+
+#[test]
+fn empty_archetype_conflict() {
+    let mut frame = Frame::new();
+    let _ = frame.spawn((42, true));
+    let _ = frame.spawn((17, "abc"));
+    let e = frame.spawn((12, false, "def"));
+    frame.despawn(e).unwrap();
+    for _ in frame.query::<(&mut i32, &&str)>().iter() {
+        for _ in frame.query::<(&mut i32, &bool)>().iter() {}
+    }
+}
+
+#[test]
+fn component_ref_map() {
+    struct TestComponent {
+        id: i32,
+    }
+
+    let mut frame = Frame::new();
+    let e = frame.spawn((TestComponent { id: 21 },));
+
+    let e_ref = frame.entity(e).unwrap();
+    {
+        let comp = e_ref.get::<&'_ TestComponent>().unwrap();
+        // Test that no unbalanced releases occur when cloning refs.
+        let _comp2 = comp.clone();
+        let id = Ref::map(comp, |c| &c.id);
+        assert_eq!(*id, 21);
+    }
+
+    {
+        let comp = e_ref.get::<&'_ mut TestComponent>().unwrap();
+        let mut id = RefMut::map(comp, |c| &mut c.id);
+        *id = 31;
+    }
+
+    {
+        let comp = e_ref.get::<&'_ TestComponent>().unwrap();
+        let id = Ref::map(comp, |c| &c.id);
+        assert_eq!(*id, 31);
+    }
+}
+
+#[test]
+fn query_many() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((42, true));
+    let b = frame.spawn((17,));
+    assert_eq!(frame.query_many_mut::<&i32, 2>([a, b]), [Ok(&42), Ok(&17)]);
+}
+
+#[test]
+#[should_panic]
+fn query_many_duplicate() {
+    let mut frame = Frame::new();
+    let e = frame.spawn(());
+    _ = frame.query_many_mut::<(), 2>([e, e]);
+}
+
+#[test]
+fn frame_hooks() {
+    use std::sync::{Arc, Mutex};
+
+    let mut frame = Frame::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let hook = {
+        let log = log.clone();
+        frame.add_hook(move |event| {
+            log.lock().unwrap().push(match event {
+                FrameEvent::Cleared => None,
+                FrameEvent::DespawnedBatch(entities) => Some(entities.to_vec()),
+                FrameEvent::Merged(entities) => Some(entities.to_vec()),
+                FrameEvent::ArchetypeCreated(_) => return,
+                _ => unreachable!(),
+            })
+        })
+    };
+    let a = frame.spawn((1,));
+    let b = frame.spawn((2,));
+    let c = frame.spawn((3,));
+    frame.despawn(c).unwrap();
+    assert_eq!(frame.despawn_batch(&[a, c]), 1);
+    assert_eq!(frame.despawn_batch(&[c]), 0);
+    let mut registry = ComponentRegistry::new();
+    registry.register::<i32>().copyable();
+    let mut source = Frame::new();
+    let d = source.spawn((4,));
+    let merged = registry.merge_frame(&source, &mut frame).get(d).unwrap();
+    registry.merge_frame(&Frame::new(), &mut frame);
+    frame.clear();
+    assert!(frame.remove_hook(hook));
+    assert!(!frame.remove_hook(hook));
+    frame.clear();
+    assert_eq!(
+        *log.lock().unwrap(),
+        [Some(vec![a]), Some(vec![merged]), None]
+    );
+    assert!(!frame.contains(b));
+}
+
+#[test]
+fn growth_hooks() {
+    use std::any::TypeId;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, PartialEq)]
+    enum Growth {
+        Archetype(Vec<TypeId>),
+        Column(TypeId, usize),
+    }
+
+    let mut frame = Frame::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    {
+        let log = log.clone();
+        frame.add_hook(move |event| {
+            log.lock().unwrap().push(match *event {
+                FrameEvent::ArchetypeCreated(types) => {
+                    Growth::Archetype(types.iter().map(|ty| ty.id()).collect())
+                }
+                FrameEvent::ColumnGrown { ty, bytes } => Growth::Column(ty.id(), bytes),
+                _ => return,
+            })
+        });
+    }
+    let take = || std::mem::take(&mut *log.lock().unwrap());
+
+    // Column growth isn't reported without a threshold
+    let e = frame.spawn((1u64,));
+    assert_eq!(take(), [Growth::Archetype(vec![TypeId::of::<u64>()])]);
+    frame.spawn((2u64,));
+    assert_eq!(take(), []);
+
+    frame.set_growth_threshold(Some(1024));
+    frame.insert_one(e, true).unwrap();
+    let mut types = vec![TypeId::of::<u64>(), TypeId::of::<bool>()];
+    match &take()[..] {
+        [Growth::Archetype(created)] => {
+            let mut created = created.clone();
+            created.sort();
+            types.sort();
+            assert_eq!(created, types);
+        }
+        x => panic!("unexpected events {:?}", x),
+    }
+
+    // Spawning beyond the initial capacity of 64 entities reallocates
+    frame.spawn_batch((0..62).map(|i| (i as u64,)));
+    assert_eq!(take(), []);
+    frame.spawn_batch((0..100).map(|i| (i as u64,)));
+    let log = take();
+    assert_eq!(log.len(), 1);
+    assert!(
+        matches!(log[0], Growth::Column(ty, bytes) if ty == TypeId::of::<u64>() && bytes >= 1024)
+    );
+
+    frame.set_growth_threshold(None);
+    frame.spawn_batch((0..1000).map(|i| (i as u64,)));
+    assert_eq!(take(), []);
+}
+
+#[test]
+fn shared_allocator() {
+    let allocator = SharedAllocator::new();
+    let mut a = Frame::with_allocator(allocator.clone());
+    let mut b = Frame::with_allocator(allocator.clone());
+    assert!(a.allocator().unwrap().ptr_eq(&allocator));
+    assert!(Frame::new().allocator().is_none());
+
+    let x = a.spawn((1,));
+    let y = b.spawn((2,));
+    let z = a.spawn((3,));
+    assert_eq!(allocator.len(), 3);
+    assert!(x.id() != y.id() && y.id() != z.id() && x.id() != z.id());
+
+    // Transferred entities keep their IDs
+    a.transfer(x, &mut b).unwrap();
+    assert!(!a.contains(x));
+    assert_eq!(*b.get::<&i32>(x).unwrap(), 1);
+    assert!(allocator.contains(x));
+    let w = b.spawn((4,));
+    assert!(![x, y, z].iter().any(|e| e.id() == w.id()));
+
+    // IDs are released by despawning, taking, and clearing
+    b.despawn(x).unwrap();
+    assert!(!allocator.contains(x));
+    drop(a.take(z).unwrap());
+    assert!(!allocator.contains(z));
+    b.clear();
+    assert!(allocator.is_empty());
+
+    // Released IDs are reused with a new generation
+    let v = a.spawn(());
+    assert!([x, y, z, w].iter().any(|e| e.id() == v.id()));
+    assert!(![x, y, z, w].contains(&v));
+    assert!(allocator.contains(v));
+}
+
+#[test]
+#[should_panic(expected = "reserve_entity is unsupported by frames with a shared allocator")]
+fn shared_allocator_reserve() {
+    let frame = Frame::with_allocator(SharedAllocator::new());
+    frame.reserve_entity();
+}
+
+#[test]
+fn insert_replacing() {
+    let mut frame = Frame::new();
+    let e = frame.spawn(("abc".to_owned(), 123));
+
+    let old = frame.insert_replacing(e, ("def".to_owned(), true)).unwrap();
+    assert_eq!(*old.get::<&String>().unwrap(), "abc");
+    assert!(!old.has::<i32>());
+    assert!(!old.has::<bool>());
+    assert_eq!(*frame.get::<&String>(e).unwrap(), "def");
+    assert!(frame.satisfies::<(&i32, &bool)>(e).unwrap());
+
+    // No archetype change
+    let mut old = frame.insert_replacing(e, (456,)).unwrap();
+    assert_eq!(*old.get::<&i32>().unwrap(), 123);
+    frame.insert(e, old.build()).unwrap();
+    assert_eq!(*frame.get::<&i32>(e).unwrap(), 123);
+
+    assert_eq!(
+        frame.insert_one_replacing(e, "ghi".to_owned()).unwrap(),
+        Some("def".to_owned())
+    );
+    assert_eq!(frame.insert_one_replacing(e, 'x').unwrap(), None);
+    assert_eq!(*frame.get::<&char>(e).unwrap(), 'x');
+
+    frame.despawn(e).unwrap();
+    assert!(frame.insert_replacing(e, (1,)).is_err());
+    assert!(frame.insert_one_replacing(e, 1).is_err());
+}
+
+#[test]
+fn insert_one_replacing_notifies() {
+    let mut registry = ComponentRegistry::new();
+    registry.register::<i32>().validate(|&x| x >= 0);
+    let mut frame = Frame::new();
+    frame.set_validator(Some(std::sync::Arc::new(registry)));
+    frame.track_changes::<i32>();
+    let e = frame.spawn((1, true));
+    let changed = |frame: &mut Frame| frame.query_mut::<Changed<&i32>>().into_iter().count();
+    assert_eq!(changed(&mut frame), 1);
+    frame.clear_trackers();
+    assert_eq!(changed(&mut frame), 0);
+
+    // Overwriting in place is tracked like any other insertion
+    assert_eq!(frame.insert_one_replacing(e, 2).unwrap(), Some(1));
+    assert_eq!(changed(&mut frame), 1);
+
+    if cfg!(debug_assertions) {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            frame.insert_one_replacing(e, -1).unwrap();
+        }));
+        assert!(result.is_err());
+    }
+}
+
+#[test]
+fn referencing() {
+    #[derive(Clone, PartialEq)]
+    struct Targeting(Entity);
+
+    impl Relation for Targeting {
+        fn target(&self) -> Entity {
+            self.0
+        }
+    }
+
+    impl From<Entity> for Targeting {
+        fn from(target: Entity) -> Self {
+            Self(target)
+        }
+    }
+
+    let sorted = |frame: &mut Frame, target| {
+        let mut out = frame.referencing(target).collect::<Vec<_>>();
+        out.sort();
+        out
+    };
+
+    let mut frame = Frame::new();
+    let x = frame.spawn(());
+    let y = frame.spawn(());
+    let a = frame.spawn((Targeting(x),));
+    let b = frame.spawn((Targeting(x), true));
+    assert_eq!(frame.referencing(x).len(), 0);
+
+    // Existing relations are indexed on registration
+    frame.index_references::<Targeting>();
+    assert_eq!(sorted(&mut frame, x), [a, b]);
+    assert_eq!(frame.referencing(a).len(), 0);
+
+    // Spawning, inserting in place and moving between archetypes
+    let c = frame.spawn((Targeting(y),));
+    let batch = frame
+        .spawn_batch((0..2).map(|_| (Targeting(y), 1u8)))
+        .collect::<Vec<_>>();
+    frame.insert_one(a, Targeting(y)).unwrap();
+    frame.insert(b, (Targeting(y), 1.0f32)).unwrap();
+    assert_eq!(frame.referencing(x).len(), 0);
+    let mut columns = ColumnBatchType::new();
+    columns.add::<Targeting>();
+    let mut columns = columns.into_batch(1);
+    columns
+        .writer::<Targeting>()
+        .unwrap()
+        .push(Targeting(x))
+        .ok()
+        .unwrap();
+    let column = frame
+        .spawn_column_batch(columns.build().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(sorted(&mut frame, x), column);
+    frame.despawn(column[0]).unwrap();
+    let mut expected = vec![a, b, c, batch[0], batch[1]];
+    expected.sort();
+    assert_eq!(sorted(&mut frame, y), expected);
+
+    // Removing and despawning
+    frame.remove_one::<Targeting>(c).unwrap();
+    frame.despawn(b).unwrap();
+    frame.despawn_batch(&batch);
+    assert_eq!(sorted(&mut frame, y), [a]);
+
+    // Relations detached when their target is despawned
+    let d = frame.spawn(());
+    frame.attach::<Targeting>(d, a).unwrap();
+    assert_eq!(sorted(&mut frame, a), [d]);
+    frame.despawn(a).unwrap();
+    assert_eq!(frame.referencing(a).len(), 0);
+    assert!(!frame.satisfies::<&Targeting>(d).unwrap());
+
+    // Retargeting in place
+    let p1 = frame.spawn(());
+    let p2 = frame.spawn(());
+    let child = frame.spawn(());
+    frame.attach::<Targeting>(child, p1).unwrap();
+    for (_, relation) in frame.query_mut::<&mut Targeting>() {
+        if relation.0 == p1 {
+            relation.0 = p2;
+        }
+    }
+    frame.despawn(p1).unwrap();
+    assert!(frame.get::<&Targeting>(child).unwrap().0 == p2);
+    assert_eq!(sorted(&mut frame, p2), [child]);
+    frame.despawn(p2).unwrap();
+    assert!(!frame.satisfies::<&Targeting>(child).unwrap());
+
+    // Renumbering
+    let e = frame.spawn((Targeting(d),));
+    let f = frame.spawn((Targeting(e),));
+    let mut moved = std::collections::HashMap::new();
+    frame.compact_entities(|old, new| {
+        moved.insert(old, new);
+    });
+    let (d, e, f) = (
+        moved.get(&d).copied().unwrap_or(d),
+        moved.get(&e).copied().unwrap_or(e),
+        moved.get(&f).copied().unwrap_or(f),
+    );
+    assert_eq!(sorted(&mut frame, d), [e]);
+    assert_eq!(sorted(&mut frame, e), [f]);
+
+    frame.clear();
+    assert_eq!(frame.referencing(e).len(), 0);
+}
+
+#[test]
+fn transfer() {
+    let mut a = Frame::new();
+    let mut b = Frame::new();
+    let x = a.spawn(("x".to_owned(), 1));
+    let y = a.spawn(("y".to_owned(),));
+    let z = b.spawn((true,));
+    assert_eq!(x.id(), z.id());
+
+    assert_eq!(a.transfer(x, &mut b), Err(TransferError::Occupied));
+    assert!(a.contains(x));
+
+    a.transfer(y, &mut b).unwrap();
+    assert!(!a.contains(y));
+    assert_eq!(*b.get::<&String>(y).unwrap(), "y");
+    assert_eq!(a.transfer(y, &mut b), Err(TransferError::NoSuchEntity));
+
+    b.despawn(z).unwrap();
+    a.transfer(x, &mut b).unwrap();
+    assert!(b.satisfies::<(&String, &i32)>(x).unwrap());
+    assert!(a.is_empty());
+    assert_eq!(b.len(), 2);
+
+    // Handles allocated afterwards don't collide with transferred ones
+    let w = b.spawn(());
+    assert!(w != x && w != y);
+
+    // Only between frames that allocate from the same allocator, if any
+    let allocator = SharedAllocator::new();
+    let mut shared = Frame::with_allocator(allocator.clone());
+    let s = shared.spawn((1,));
+    assert_eq!(shared.transfer(s, &mut b), Err(TransferError::Allocator));
+    assert_eq!(b.transfer(w, &mut shared), Err(TransferError::Allocator));
+    let mut other = Frame::with_allocator(SharedAllocator::new());
+    assert_eq!(
+        shared.transfer(s, &mut other),
+        Err(TransferError::Allocator)
+    );
+    assert!(shared.contains(s) && b.contains(w));
+    let mut sibling = Frame::with_allocator(allocator.clone());
+    shared.transfer(s, &mut sibling).unwrap();
+    assert!(sibling.contains(s) && allocator.contains(s));
+}
+
+#[test]
+fn built_entity_clone_to_builder() {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Position(f32);
+
+    let mut builder = EntityBuilderClone::new();
+    builder.add(7u8).add(Position(1.0)).add("abc".to_owned());
+    let prefab = builder.build();
+
+    let mut variant = prefab.to_builder();
+    assert_eq!(*variant.get::<&u8>().unwrap(), 7);
+    assert_eq!(*variant.get::<&Position>().unwrap(), Position(1.0));
+    assert_eq!(*variant.get::<&String>().unwrap(), "abc");
+    variant.get_mut::<&mut Position>().unwrap().0 = 2.0;
+    let variant = variant.build();
+
+    let mut frame = Frame::new();
+    let a = frame.spawn(&prefab);
+    let b = frame.spawn(&variant);
+    let c = frame.spawn(prefab.map(|p: &mut Position| p.0 += 0.5));
+    let d = frame.spawn(prefab.map(|_: &mut bool| unreachable!()));
+    assert_eq!(*frame.get::<&Position>(a).unwrap(), Position(1.0));
+    assert_eq!(*frame.get::<&Position>(b).unwrap(), Position(2.0));
+    assert_eq!(*frame.get::<&Position>(c).unwrap(), Position(1.5));
+    assert_eq!(*frame.get::<&Position>(d).unwrap(), Position(1.0));
+    assert_eq!(*frame.get::<&String>(c).unwrap(), "abc");
+}
+
+#[test]
+fn downgrade_upgrade_borrows() {
+    #[derive(Debug)]
+    struct Foo {
+        bar: i32,
+    }
+
+    let mut frame = Frame::new();
+    let a = frame.spawn((Foo { bar: 1 },));
+    let b = frame.spawn((Foo { bar: 2 },));
+    let a_ref = frame.entity(a).unwrap();
+    let b_ref = frame.entity(b).unwrap();
+
+    let mut foo = a_ref.get::<&mut Foo>().unwrap();
+    foo.bar = 3;
+    let foo = RefMut::downgrade(foo);
+    // Shared borrows of the same column may now coexist
+    let other = b_ref.get::<&Foo>().unwrap();
+    assert_eq!(other.bar, 2);
+    let foo = Ref::try_upgrade(foo).unwrap_err();
+    assert_eq!(foo.bar, 3);
+    drop(other);
+    let bar = Ref::map(foo, |x| &x.bar);
+    assert_eq!(*bar, 3);
+    // Mapped borrows can't be upgraded
+    let bar = Ref::try_upgrade(bar).unwrap_err();
+    drop(bar);
+
+    let foo = a_ref.get::<&Foo>().unwrap();
+    let mut foo = Ref::try_upgrade(foo).unwrap();
+    foo.bar = 4;
+    drop(foo);
+    assert_eq!(a_ref.get::<&Foo>().unwrap().bar, 4);
+}
+
+#[test]
+fn split_borrows() {
+    struct Body {
+        position: [f32; 2],
+        velocity: [f32; 2],
+    }
+
+    let mut frame = Frame::new();
+    let e = frame.spawn((Body {
+        position: [0.0; 2],
+        velocity: [1.0, 2.0],
+    },));
+    let entity = frame.entity(e).unwrap();
+
+    let body = entity.get::<&mut Body>().unwrap();
+    let (mut position, velocity) = RefMut::map_split(body, |b| (&mut b.position, &mut b.velocity));
+    position[0] += velocity[0];
+    position[1] += velocity[1];
+    // Still uniquely borrowed while the other half lives
+    let velocity = RefMut::downgrade(velocity);
+    let velocity = Ref::try_upgrade(velocity).unwrap_err();
+    drop(position);
+    assert_eq!(velocity[1], 2.0);
+    let copy = velocity.clone();
+    let velocity = Ref::try_upgrade(velocity).unwrap_err();
+    drop(copy);
+    // The last holder of a split borrow can reclaim it
+    let mut velocity = Ref::try_upgrade(velocity).ok().unwrap();
+    velocity[1] = 3.0;
+    drop(velocity);
+    assert_eq!(entity.get::<&Body>().unwrap().velocity, [1.0, 3.0]);
+    entity.get::<&mut Body>().unwrap().velocity[1] = 2.0;
+
+    let body = entity.get::<&Body>().unwrap();
+    let (position, velocity) = Ref::map_split(body, |b| (&b.position, &b.velocity));
+    assert_eq!(*position, [1.0, 2.0]);
+    drop(position);
+    let second = Ref::filter_map(velocity, |v| v.get(1)).unwrap();
+    assert_eq!(*second, 2.0);
+    let second = Ref::filter_map(second, |_| None::<&u8>).unwrap_err();
+    drop(second);
+
+    let body = entity.get::<&mut Body>().unwrap();
+    let body = RefMut::filter_map(body, |_| None::<&mut u8>).unwrap_err();
+    drop(body);
+    assert!(entity.get::<&mut Body>().is_some());
+}
+
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn split_borrow_held_by_other_half() {
+    let mut frame = Frame::new();
+    let e = frame.spawn(((1, 2),));
+    let entity = frame.entity(e).unwrap();
+    let pair = entity.get::<&mut (i32, i32)>().unwrap();
+    let (_a, b) = RefMut::map_split(pair, |p| (&mut p.0, &mut p.1));
+    let _b = RefMut::downgrade(b);
+    let _ = entity.get::<&(i32, i32)>();
+}
+
+#[test]
+fn visit_components() {
+    let mut registry = ComponentRegistry::new();
+    registry.register::<i32>().debug();
+    registry.register::<&'static str>().name("label");
+    assert_eq!(registry.len(), 2);
+
+    let mut frame = Frame::new();
+    let e = frame.spawn((7, "abc", true));
+    let entity = frame.entity(e).unwrap();
+    let mut seen = Vec::new();
+    entity.visit(&mut |component: DynamicComponent<'_>| {
+        let id = component.type_info().id();
+        if let Some(entry) = registry.get(id) {
+            let debug = entry.debug(component).map(|x| format!("{:?}", x));
+            seen.push((entry.name(), debug));
+        } else {
+            assert_eq!(component.downcast_ref::<bool>(), Some(&true));
+        }
+    });
+    seen.sort();
+    assert_eq!(seen, [("i32", Some("7".into())), ("label", None)]);
+
+    // A registration can't format values of other types
+    let i32_entry = registry.get(std::any::TypeId::of::<i32>()).unwrap();
+    entity.visit(&mut |component: DynamicComponent<'_>| {
+        if component.type_info().id() != std::any::TypeId::of::<i32>() {
+            assert!(i32_entry.debug(component).is_none());
+        }
+    });
+}
+
+#[test]
+#[should_panic(expected = "already borrowed uniquely")]
+fn visit_uniquely_borrowed() {
+    let mut frame = Frame::new();
+    let e = frame.spawn((7,));
+    let entity = frame.entity(e).unwrap();
+    let _x = entity.get::<&mut i32>().unwrap();
+    entity.visit(&mut |_: DynamicComponent<'_>| {});
+}
+
+#[test]
+fn registry_clone_frame() {
+    let mut registry = ComponentRegistry::new();
+    registry.register::<[f32; 3]>().copyable();
+    registry.register::<String>().cloneable();
+    registry.register::<u8>().debug();
+    assert!(registry
+        .get(std::any::TypeId::of::<[f32; 3]>())
+        .unwrap()
+        .is_copyable());
+    assert!(!registry
+        .get(std::any::TypeId::of::<u8>())
+        .unwrap()
+        .is_cloneable());
+
+    let mut frame = Frame::new();
+    let particles = (0..100)
+        .map(|i| frame.spawn(([i as f32, 0.0, 1.0],)))
+        .collect::<Vec<_>>();
+    let gap = frame.spawn(());
+    frame.despawn(gap).unwrap();
+    let named = frame.spawn(([0.5f32, 0.5, 0.5], "abc".to_string(), 7u8));
+
+    let cloned = registry.clone_frame(&frame);
+    assert_eq!(cloned.len(), frame.len());
+    assert!(!cloned.contains(gap));
+    for (i, &e) in particles.iter().enumerate() {
+        assert_eq!(*cloned.get::<&[f32; 3]>(e).unwrap(), [i as f32, 0.0, 1.0]);
+    }
+    let named = cloned.entity(named).unwrap();
+    assert_eq!(*named.get::<&[f32; 3]>().unwrap(), [0.5; 3]);
+    assert_eq!(*named.get::<&String>().unwrap(), "abc");
+    assert!(!named.has::<u8>());
+}
+
+#[test]
+fn deferred_ops() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let b = frame.spawn((2, false));
+    let d = frame.spawn(());
+    let spawned;
+    {
+        let ops = frame.defer();
+        let mut query = frame.query::<(&i32, &bool)>();
+        for (e, (_, &flag)) in query.iter() {
+            if flag {
+                ops.insert_one(e, "flagged");
+            } else {
+                ops.remove_one::<i32>(e);
+            }
+            assert!(frame.get::<&&str>(e).is_err());
+        }
+        spawned = frame.defer().spawn((3,));
+        frame.defer().despawn(d);
+    }
+    // Flushing reserved entities leaves deferred operations pending
+    let c = frame.spawn(());
+    frame.flush();
+    assert!(frame.contains(d));
+    assert!(frame.entity(b).unwrap().has::<i32>());
+
+    frame.apply_deferred();
+    assert!(!frame.contains(d));
+    assert_eq!(*frame.get::<&&str>(a).unwrap(), "flagged");
+    assert!(!frame.entity(b).unwrap().has::<i32>());
+    assert_eq!(*frame.get::<&i32>(spawned).unwrap(), 3);
+    assert!(frame.contains(c));
+
+    // Discarded by `clear`
+    frame.defer().spawn((4,));
+    frame.clear();
+    frame.apply_deferred();
+    assert_eq!(frame.len(), 0);
+}
+
+#[test]
+fn set_many() {
+    let mut frame = Frame::new();
+    let entities = (0..10)
+        .map(|i| {
+            if i % 2 == 0 {
+                frame.spawn((i,))
+            } else {
+                frame.spawn((i, true))
+            }
+        })
+        .collect::<Vec<_>>();
+    let other = frame.spawn(("abc",));
+    let dead = frame.spawn((0,));
+    frame.despawn(dead).unwrap();
+
+    let mut updates = entities
+        .iter()
+        .map(|&e| (e, 100))
+        .chain([(other, 1), (dead, 2)])
+        .collect::<Vec<_>>();
+    updates.push((entities[3], 300));
+    assert_eq!(frame.set_many(&updates), 11);
+    for (i, &e) in entities.iter().enumerate() {
+        let expected = if i == 3 { 300 } else { 100 };
+        assert_eq!(*frame.get::<&i32>(e).unwrap(), expected);
+    }
+    assert!(frame.get::<&i32>(other).is_err());
+}
+
+#[test]
+fn pinned_archetype() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut frame = Frame::new();
+    frame.reserve::<(u32,)>(8);
+    let a = frame.spawn((1u32,));
+    let b = frame.spawn((2u32,));
+    let archetype = frame.archetypes().find(|x| x.has::<u32>()).unwrap();
+    let pin = archetype.pin();
+    assert!(archetype.is_pinned());
+    let base = archetype.get::<&u32>().unwrap().as_ptr();
+
+    // Spawning within existing capacity and modifying in place are fine
+    let c = frame.spawn((3u32,));
+    *frame.get::<&mut u32>(a).unwrap() = 10;
+
+    // Operations moving components fail cleanly
+    let moves: [&mut dyn FnMut(&mut Frame); 5] = [
+        &mut |frame| {
+            frame.despawn(a).unwrap();
+        },
+        &mut |frame| {
+            frame.insert_one(b, true).unwrap();
+        },
+        &mut |frame| {
+            frame.remove_one::<u32>(c).unwrap();
+        },
+        &mut |frame| {
+            frame.spawn_batch((0..100u32).map(|x| (x,)));
+        },
+        &mut |frame| frame.clear(),
+    ];
+    for f in moves {
+        assert!(catch_unwind(AssertUnwindSafe(|| f(&mut frame))).is_err());
+        assert_eq!(frame.len(), 3);
+        let archetype = frame.archetypes().find(|x| x.has::<u32>()).unwrap();
+        assert_eq!(archetype.len(), 3);
+        assert_eq!(archetype.get::<&u32>().unwrap().as_ptr(), base);
+    }
+    assert_eq!(*frame.get::<&u32>(a).unwrap(), 10);
+    assert_eq!(*frame.get::<&u32>(b).unwrap(), 2);
+    assert_eq!(*frame.get::<&u32>(c).unwrap(), 3);
+    assert!(!frame.entity(b).unwrap().has::<bool>());
+
+    drop(pin);
+    frame.despawn(a).unwrap();
+    assert_eq!(frame.remove_one::<u32>(c).unwrap(), 3);
+}
+
+#[test]
+fn transactions() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut frame = Frame::new();
+    let e = frame.spawn((1,));
+    let read = frame.read_transaction();
+    let nested = frame.read_transaction();
+    assert!(frame.in_read_transaction());
+    for (_, x) in frame.query_mut::<&mut i32>() {
+        *x += 1;
+    }
+    frame.set_many(&[(e, 5)]);
+    assert!(catch_unwind(AssertUnwindSafe(|| {
+        frame.write_transaction();
+    }))
+    .is_err());
+    if cfg!(debug_assertions) {
+        assert!(catch_unwind(AssertUnwindSafe(|| frame.insert_one(e, true))).is_err());
+        assert!(catch_unwind(AssertUnwindSafe(|| frame.spawn(()))).is_err());
+        assert!(catch_unwind(AssertUnwindSafe(|| frame.clear())).is_err());
+    }
+    drop(read);
+    assert!(frame.in_read_transaction());
+    drop(nested);
+    assert!(!frame.in_read_transaction());
+
+    let mut write = frame.write_transaction();
+    write.insert_one(e, true).unwrap();
+    assert_eq!(*write.get::<&i32>(e).unwrap(), 5);
+}
+
+#[test]
+fn previous_values() {
+    let mut frame = Frame::new();
+    let a = frame.spawn((1, true));
+    let b = frame.spawn((2,));
+    frame.track_previous::<i32>();
+    let c = frame.spawn((3,));
+    for (_, x) in frame.query_mut::<&mut i32>() {
+        *x *= 10;
+    }
+    // Moving between archetypes doesn't lose the previous value
+    frame.remove_one::<bool>(a).unwrap();
+
+    let mut seen = frame
+        .view_with_previous::<i32>()
+        .iter()
+        .map(|(e, &current, previous)| (e, current, previous.copied()))
+        .collect::<Vec<_>>();
+    seen.sort_by_key(|x| x.1);
+    assert_eq!(seen, [(a, 10, Some(1)), (b, 20, Some(2)), (c, 30, None)]);
+
+    frame.save_previous();
+    frame.despawn(b).unwrap();
+    let d = frame.spawn((4,));
+    assert_eq!(d.id(), b.id());
+    assert_eq!(frame.previous::<i32>(c), Some(&30));
+    assert_eq!(frame.previous::<i32>(d), None);
+
+    frame.clear();
+    let e = frame.spawn((5,));
+    assert_eq!(frame.previous::<i32>(e), None);
 }